@@ -0,0 +1,62 @@
+//! Record-decode throughput on the bundled GPS sample and a generated gyro frame, to check that
+//! parser changes don't slow decoding down. `cargo bench` runs them; `cargo bench --
+//! --save-baseline before` and then `--baseline before` compare a change with the code before it.
+
+use std::hint::black_box;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use ginsta::{
+    FrameType, GPS_RECORD_SIZE, GYRO_RECORD_SIZE, gps_record_refs, parse_gps_frame,
+    parse_gyro_frame,
+};
+
+/// GPS records as they are in a GPS frame, one after another.
+const GPS_SAMPLE: &[u8] = include_bytes!("../src/testdata/Gps_1752824363158.insgps");
+
+fn gps(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gps");
+    group.throughput(Throughput::Bytes(GPS_SAMPLE.len() as u64));
+    group.bench_function("parse_gps_frame", |b| {
+        b.iter(|| parse_gps_frame(black_box(GPS_SAMPLE)).map(|(_, frame)| frame.records.len()))
+    });
+    group.bench_function("gps_record_refs", |b| {
+        b.iter(|| {
+            gps_record_refs(black_box(GPS_SAMPLE))
+                .map(|record| record.to_owned())
+                .collect::<Vec<_>>()
+        })
+    });
+    let layout = FrameType::Gps.record_layout().unwrap();
+    group.bench_function("layout_view", |b| {
+        b.iter(|| {
+            black_box(GPS_SAMPLE)
+                .chunks_exact(GPS_RECORD_SIZE)
+                .filter_map(|record| layout.view(record))
+                .map(|view| view.value("latitude").as_f64())
+                .sum::<f64>()
+        })
+    });
+    group.finish();
+}
+
+/// A large gyro frame, as cameras write at 1 kHz: a little over 20 MB, 17 minutes of recording.
+fn gyro(c: &mut Criterion) {
+    let frame: Vec<u8> = (0..1_000_000u64)
+        .flat_map(|i| {
+            let axes =
+                (0..6).flat_map(move |axis| ((i as i16).wrapping_mul(axis + 1)).to_le_bytes());
+            i.to_le_bytes().into_iter().chain(axes)
+        })
+        .collect();
+    assert_eq!(frame.len(), 1_000_000 * GYRO_RECORD_SIZE);
+    let mut group = c.benchmark_group("gyro");
+    group.throughput(Throughput::Bytes(frame.len() as u64));
+    group.sample_size(20);
+    group.bench_function("parse_gyro_frame", |b| {
+        b.iter(|| parse_gyro_frame(black_box(&frame)).map(|(_, frame)| frame.records.len()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, gps, gyro);
+criterion_main!(benches);
@@ -1,5 +1,14 @@
 use std::io::Result;
 fn main() -> Result<()> {
-    prost_build::compile_protos(&["src/proto/extra_metadata.proto"], &["src/proto/"])?;
+    // `Serialize` lets `ginsta info --get` select fields by name.
+    #[cfg(feature = "prost")]
+    prost_build::Config::new()
+        .type_attribute(
+            ".",
+            r#"#[cfg_attr(feature = "serde", derive(serde::Serialize))]"#,
+        )
+        .compile_protos(&["src/proto/extra_metadata.proto"], &["src/proto/"])?;
+    #[cfg(feature = "grpc")]
+    tonic_prost_build::compile_protos("src/proto/ginsta.proto")?;
     Ok(())
 }
@@ -0,0 +1,398 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    FrameType, GpsRecord, GyroRecord, Telemetry, frames_of_type,
+    layout::Layout,
+    mp4::VideoTrack,
+    units::{Degrees, Meters, MetersPerSecond},
+};
+
+/// Telemetry interpolated to a single video frame's presentation time.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FrameTelemetry {
+    pub frame: usize,
+    /// Presentation time in seconds from the start of the video.
+    pub pts: f64,
+    pub latitude: Option<Degrees>,
+    pub longitude: Option<Degrees>,
+    pub speed: Option<MetersPerSecond>,
+    pub track: Option<Degrees>,
+    pub altitude: Option<Meters>,
+    /// Raw accelerometer axes, interpolated.
+    pub accel_x: Option<f64>,
+    pub accel_y: Option<f64>,
+    pub accel_z: Option<f64>,
+    /// Raw gyroscope axes, interpolated.
+    pub gyro_x: Option<f64>,
+    pub gyro_y: Option<f64>,
+    pub gyro_z: Option<f64>,
+    /// Camera orientation from the Euler frame, in degrees, interpolated.
+    pub yaw: Option<f64>,
+    pub pitch: Option<f64>,
+    pub roll: Option<f64>,
+}
+
+/// A camera orientation from the Euler frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EulerSample {
+    /// Camera time in milliseconds, as for gyro records.
+    pub timestamp: f64,
+    pub yaw: f64,
+    pub pitch: f64,
+    pub roll: f64,
+}
+
+/// The samples in an Euler frame read through `layout`, which needs `timestamp`, `yaw`, `pitch`
+/// and `roll` fields in degrees, as the frame's records have no built-in layout. Timestamps
+/// count `ticks_per_second`, 1000 when the layout doesn't say, on the gyro's clock. `None` if the
+/// layout lacks a field.
+pub fn euler_samples(frame: &[u8], layout: &'static Layout) -> Option<Vec<EulerSample>> {
+    for field in ["timestamp", "yaw", "pitch", "roll"] {
+        layout.span(field)?;
+    }
+    let ticks_per_second = layout.ticks_per_second.unwrap_or(1000.0);
+    let (records, _) = layout.records_in(frame.len());
+    let samples = (0..records)
+        .filter_map(|i| layout.view(&frame[i * layout.size()..]))
+        .map(|record| EulerSample {
+            timestamp: record.value("timestamp").as_f64() / ticks_per_second * 1000.0,
+            yaw: record.value("yaw").as_f64(),
+            pitch: record.value("pitch").as_f64(),
+            roll: record.value("roll").as_f64(),
+        })
+        .collect();
+    Some(samples)
+}
+
+/// The samples in every Euler frame of the camera file `data`, sorted by time, read through
+/// `layout` as `euler_samples` does. Frames outside the file are skipped. `None` if the layout
+/// lacks a field.
+pub fn file_euler_samples(data: &[u8], layout: &'static Layout) -> Option<Vec<EulerSample>> {
+    let mut samples = Vec::new();
+    for frame in frames_of_type(data, FrameType::Euler) {
+        samples.extend(euler_samples(frame, layout)?);
+    }
+    samples.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    Some(samples)
+}
+
+/// Finds the pair of samples surrounding `t` and the fraction of the way from the first to the
+/// second. `samples` must be sorted by time.
+pub fn bracket<T>(samples: &[T], t: f64, time: impl Fn(&T) -> f64) -> Option<(&T, &T, f64)> {
+    let after = samples.partition_point(|s| time(s) < t);
+    if after == samples.len() {
+        return None;
+    }
+    let next = &samples[after];
+    if time(next) == t {
+        return Some((next, next, 0.0));
+    }
+    let prev = samples.get(after.checked_sub(1)?)?;
+    let span = time(next) - time(prev);
+    Some((prev, next, (t - time(prev)) / span))
+}
+
+pub fn lerp(a: f64, b: f64, fraction: f64) -> f64 {
+    a + (b - a) * fraction
+}
+
+/// Interpolates between two bearings in degrees, going the short way around.
+pub fn lerp_degrees(a: f64, b: f64, fraction: f64) -> f64 {
+    let delta = (b - a + 540.0).rem_euclid(360.0) - 180.0;
+    (a + delta * fraction).rem_euclid(360.0)
+}
+
+pub fn interpolate_gps(records: &[GpsRecord], t: f64) -> Option<GpsRecord> {
+    let (a, b, f) = bracket(records, t, |r| r.timestamp as f64)?;
+    Some(GpsRecord {
+        timestamp: t as u64,
+        latitude: Degrees(lerp(a.latitude.0, b.latitude.0, f)),
+        longitude: Degrees(lerp(a.longitude.0, b.longitude.0, f)),
+        speed: MetersPerSecond(lerp(a.speed.0, b.speed.0, f)),
+        track: Degrees(lerp_degrees(a.track.0, b.track.0, f)),
+        altitude: Meters(lerp(a.altitude.0, b.altitude.0, f)),
+        fix: a.fix.min(b.fix),
+    })
+}
+
+/// Interpolated raw accelerometer and gyroscope axes at camera time `t` (milliseconds).
+pub fn interpolate_gyro(records: &[GyroRecord], t: f64) -> Option<([f64; 3], [f64; 3])> {
+    let (a, b, f) = bracket(records, t, |r| r.timestamp as f64)?;
+    let axes =
+        |x: [i16; 3], y: [i16; 3]| std::array::from_fn(|i| lerp(x[i] as f64, y[i] as f64, f));
+    Some((
+        axes(a.accel_raw(), b.accel_raw()),
+        axes(a.gyro_raw(), b.gyro_raw()),
+    ))
+}
+
+/// Yaw, pitch and roll at camera time `t` (milliseconds), each going the short way around and
+/// kept within ±180°. `samples` are in time order.
+pub fn interpolate_euler(samples: &[EulerSample], t: f64) -> Option<[f64; 3]> {
+    let (a, b, f) = bracket(samples, t, |sample| sample.timestamp)?;
+    let angle = |x: f64, y: f64| {
+        let angle = lerp_degrees(x, y, f);
+        if angle > 180.0 { angle - 360.0 } else { angle }
+    };
+    Some([
+        angle(a.yaw, b.yaw),
+        angle(a.pitch, b.pitch),
+        angle(a.roll, b.roll),
+    ])
+}
+
+/// Samples every stream at each video frame, with `euler` from `euler_samples` if the file's
+/// Euler frame could be read.
+///
+/// GPS timestamps are absolute, so frames are placed using the movie creation time. Gyro and
+/// Euler timestamps use the camera clock, which is assumed to be milliseconds with the first
+/// video frame at the Info frame's `FirstFrameTimestamp`. Without the `prost` feature the Info
+/// frame isn't decoded, so gyro and Euler columns stay empty.
+pub fn align_to_frames(
+    track: &VideoTrack,
+    telemetry: &Telemetry,
+    euler: &[EulerSample],
+) -> Vec<FrameTelemetry> {
+    #[cfg(feature = "prost")]
+    let first_frame_timestamp = telemetry
+        .info
+        .as_ref()
+        .and_then(|info| info.first_frame_timestamp);
+    #[cfg(not(feature = "prost"))]
+    let first_frame_timestamp: Option<u64> = None;
+    (0..track.presentation_times.len())
+        .map(|frame| {
+            let pts = track.frame_time(frame);
+            let mut row = FrameTelemetry {
+                frame,
+                pts,
+                ..Default::default()
+            };
+            if let Some(gps) = interpolate_gps(&telemetry.gps, track.creation_time as f64 + pts) {
+                row.latitude = Some(gps.latitude);
+                row.longitude = Some(gps.longitude);
+                row.speed = Some(gps.speed);
+                row.track = Some(gps.track);
+                row.altitude = Some(gps.altitude);
+            }
+            let camera_time = first_frame_timestamp.map(|first| first as f64 + pts * 1000.0);
+            if let Some((accel, gyro)) =
+                camera_time.and_then(|t| interpolate_gyro(&telemetry.gyro, t))
+            {
+                [row.accel_x, row.accel_y, row.accel_z] = accel.map(Some);
+                [row.gyro_x, row.gyro_y, row.gyro_z] = gyro.map(Some);
+            }
+            if let Some(angles) = camera_time.and_then(|t| interpolate_euler(euler, t)) {
+                [row.yaw, row.pitch, row.roll] = angles.map(Some);
+            }
+            row
+        })
+        .collect()
+}
+
+/// How the camera's clocks map onto the video's timeline: GPS Unix time through the movie's
+/// creation time, and the gyro's millisecond clock through the Info frame's
+/// `FirstFrameTimestamp`, both at the first frame. A timelapse covers `speed` seconds of
+/// recording per second of video.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct VideoClock {
+    /// Unix time of the first frame.
+    pub start: f64,
+    /// Camera clock in milliseconds at the first frame.
+    pub first_frame_ms: Option<f64>,
+    /// Presentation time of the first frame in seconds.
+    pub first_pts: f64,
+    pub speed: f64,
+}
+
+impl VideoClock {
+    /// The clock of `track`, whose frames were captured `timelapse_interval_ms` apart if it's a
+    /// timelapse.
+    pub fn new(
+        track: &VideoTrack,
+        first_frame_ms: Option<f64>,
+        timelapse_interval_ms: Option<f64>,
+    ) -> VideoClock {
+        let duration = track.duration as f64 / track.timescale as f64;
+        let frame_rate = track.presentation_times.len() as f64 / duration;
+        let speed = match timelapse_interval_ms {
+            Some(interval) if interval > 0.0 && frame_rate.is_finite() => {
+                interval / 1000.0 * frame_rate
+            }
+            _ => 1.0,
+        };
+        VideoClock {
+            start: track.creation_time as f64,
+            first_frame_ms,
+            first_pts: (track.presentation_times.first())
+                .map_or(0.0, |&pts| pts as f64 / track.timescale as f64),
+            speed,
+        }
+    }
+
+    /// The clock of `track` with the first frame's camera time and the timelapse interval from
+    /// the Info frame. Without the `prost` feature it isn't decoded, so the gyro can't be placed.
+    pub fn from_telemetry(track: &VideoTrack, telemetry: &Telemetry) -> VideoClock {
+        #[cfg(feature = "prost")]
+        let (first_frame_ms, interval) = match &telemetry.info {
+            Some(info) => (
+                info.first_frame_timestamp.map(|ms| ms as f64),
+                info.timelapse_interval.map(|ms| ms as f64),
+            ),
+            None => (None, None),
+        };
+        #[cfg(not(feature = "prost"))]
+        let (first_frame_ms, interval) = {
+            let _ = telemetry;
+            (None, None)
+        };
+        VideoClock::new(track, first_frame_ms, interval)
+    }
+
+    /// Seconds into the video at Unix time `t`.
+    pub fn video_time(&self, t: f64) -> f64 {
+        self.first_pts + (t - self.start) / self.speed
+    }
+
+    /// Seconds into the video at camera time `ms`.
+    pub fn video_time_ms(&self, ms: f64) -> Option<f64> {
+        Some(self.first_pts + (ms - self.first_frame_ms?) / 1000.0 / self.speed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        HEADER_SIZE,
+        layout::{Field, FieldKind},
+        tests::build_insv,
+    };
+
+    #[test]
+    fn test_interpolate_gps() {
+        let record = |timestamp, track| GpsRecord {
+            timestamp,
+            latitude: Degrees(10.0),
+            longitude: Degrees(timestamp as f64),
+            speed: MetersPerSecond(1.0),
+            track: Degrees(track),
+            altitude: Meters(100.0),
+            fix: None,
+        };
+        let records = [record(100, 350.0), record(101, 10.0)];
+
+        let mid = interpolate_gps(&records, 100.5).unwrap();
+        assert_eq!(mid.longitude.0, 100.5);
+        assert_eq!(mid.track.0, 0.0);
+        assert_eq!(interpolate_gps(&records, 101.0).unwrap().longitude.0, 101.0);
+        assert!(interpolate_gps(&records, 99.0).is_none());
+        assert!(interpolate_gps(&records, 101.5).is_none());
+    }
+
+    #[test]
+    fn test_interpolate_euler() {
+        let sample = |timestamp, yaw, pitch, roll| EulerSample {
+            timestamp,
+            yaw,
+            pitch,
+            roll,
+        };
+        let samples = [
+            sample(1000.0, 170.0, -10.0, 350.0),
+            sample(1100.0, -170.0, 10.0, 10.0),
+        ];
+        assert_eq!(interpolate_euler(&samples, 1050.0), Some([180.0, 0.0, 0.0]));
+        assert_eq!(
+            interpolate_euler(&samples, 1025.0),
+            Some([175.0, -5.0, -5.0])
+        );
+        assert_eq!(interpolate_euler(&samples, 999.0), None);
+    }
+
+    #[test]
+    fn test_euler_samples() {
+        static LAYOUT: Layout = Layout {
+            fields: &[
+                Field::new("timestamp", FieldKind::U64),
+                Field::new("yaw", FieldKind::F32),
+                Field::new("pitch", FieldKind::F32),
+                Field::new("roll", FieldKind::F32),
+            ],
+            ticks_per_second: Some(1_000_000.0),
+        };
+        static NO_ROLL: Layout = Layout {
+            fields: &[
+                Field::new("timestamp", FieldKind::U64),
+                Field::new("yaw", FieldKind::F32),
+                Field::new("pitch", FieldKind::F32),
+            ],
+            ticks_per_second: None,
+        };
+        let frame = [
+            &2_500_000u64.to_le_bytes()[..],
+            &90f32.to_le_bytes(),
+            &(-5f32).to_le_bytes(),
+            &1.5f32.to_le_bytes(),
+        ]
+        .concat();
+        assert_eq!(
+            euler_samples(&frame, &LAYOUT).unwrap(),
+            [EulerSample {
+                timestamp: 2500.0,
+                yaw: 90.0,
+                pitch: -5.0,
+                roll: 1.5
+            }]
+        );
+        assert_eq!(euler_samples(&frame, &NO_ROLL), None);
+
+        // Frames are read in the order the index lists them, then sorted by time.
+        let later = [&3_000_000u64.to_le_bytes()[..], &[0; 12]].concat();
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Euler, 1, later),
+                (FrameType::Gyro, 1, vec![0; 20]),
+                (FrameType::Euler, 1, frame.clone()),
+            ],
+        );
+        let samples = file_euler_samples(&data, &LAYOUT).unwrap();
+        let timestamps: Vec<f64> = samples.iter().map(|sample| sample.timestamp).collect();
+        assert_eq!(timestamps, [2500.0, 3000.0]);
+        assert_eq!(file_euler_samples(&data, &NO_ROLL), None);
+        assert_eq!(file_euler_samples(b"video", &LAYOUT), Some(Vec::new()));
+        // A frame the index places past the end of the file is skipped.
+        let mut truncated = build_insv(b"video", &[(FrameType::Euler, 1, frame)]);
+        let entry = truncated.len() - HEADER_SIZE as usize - 10;
+        truncated[entry + 2..entry + 6].copy_from_slice(&1000u32.to_le_bytes());
+        assert_eq!(file_euler_samples(&truncated, &LAYOUT), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_video_clock() {
+        let track = VideoTrack {
+            creation_time: 1000,
+            timescale: 30,
+            duration: 300,
+            presentation_times: (0..300).collect(),
+        };
+        let clock = VideoClock::new(&track, Some(5000.0), None);
+        assert_eq!(clock.video_time(1002.5), 2.5);
+        assert_eq!(clock.video_time_ms(6500.0), Some(1.5));
+        assert_eq!(
+            VideoClock::new(&track, None, None).video_time_ms(6500.0),
+            None
+        );
+
+        // A frame every 2 s played at 30 fps.
+        let timelapse = VideoClock::new(&track, Some(5000.0), Some(2000.0));
+        assert_eq!(timelapse.speed, 60.0);
+        assert_eq!(timelapse.video_time(1120.0), 2.0);
+        assert_eq!(timelapse.video_time_ms(65_000.0), Some(1.0));
+    }
+}
@@ -0,0 +1,168 @@
+//! Allan deviation of IMU axes, the usual way to read sensor noise off a long recording with the
+//! camera kept still.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{GyroRecord, imu::ImuScale};
+
+/// Fewest clusters an averaging time is estimated from; longer times are too noisy to plot.
+const MIN_CLUSTERS: usize = 9;
+
+/// `sqrt(2 ln 2 / π)`, the flat floor of the Allan deviation per unit of bias instability.
+const BIAS_INSTABILITY_FLOOR: f64 = 0.664;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct AllanPoint {
+    /// Averaging time in seconds.
+    pub tau: f64,
+    pub deviation: f64,
+}
+
+/// Overlapping Allan deviation of evenly spaced `samples` taken `rate` times a second, at
+/// averaging times doubling from one sample.
+pub fn allan_deviation(samples: &[f64], rate: f64) -> Vec<AllanPoint> {
+    let n = samples.len();
+    let mut integral = Vec::with_capacity(n + 1);
+    integral.push(0.0);
+    for sample in samples {
+        integral.push(integral.last().unwrap() + sample / rate);
+    }
+
+    let mut curve = Vec::new();
+    let mut m = 1;
+    while n / m >= MIN_CLUSTERS {
+        let tau = m as f64 / rate;
+        let terms = n + 1 - 2 * m;
+        let sum: f64 = (0..terms)
+            .map(|k| (integral[k + 2 * m] - 2.0 * integral[k + m] + integral[k]).powi(2))
+            .sum();
+        let variance = sum / (2.0 * tau * tau * terms as f64);
+        curve.push(AllanPoint {
+            tau,
+            deviation: variance.sqrt(),
+        });
+        m *= 2;
+    }
+    curve
+}
+
+/// Noise figures read off an Allan deviation curve.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct NoiseParameters {
+    /// White noise density in units per root hertz: angle random walk for a gyro, velocity
+    /// random walk for an accelerometer. Read where the curve falls closest to a -1/2 slope.
+    pub random_walk: Option<f64>,
+    /// Bias instability in the samples' units, from the curve's minimum.
+    pub bias_instability: Option<f64>,
+}
+
+pub fn noise_parameters(curve: &[AllanPoint]) -> NoiseParameters {
+    let random_walk = curve
+        .windows(2)
+        .map(|pair| {
+            let slope =
+                (pair[1].deviation / pair[0].deviation).ln() / (pair[1].tau / pair[0].tau).ln();
+            (pair[0], (slope + 0.5).abs())
+        })
+        .filter(|(_, error)| error.is_finite())
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(point, _)| point.deviation * point.tau.sqrt());
+    let bias_instability = curve
+        .iter()
+        .map(|point| point.deviation)
+        .min_by(f64::total_cmp)
+        .map(|deviation| deviation / BIAS_INSTABILITY_FLOOR);
+    NoiseParameters {
+        random_walk,
+        bias_instability,
+    }
+}
+
+/// The Allan deviation of one sensor axis and the noise figures read from it.
+#[derive(Debug)]
+pub struct AxisNoise {
+    /// `gyro` or `accel`.
+    pub sensor: &'static str,
+    /// `x`, `y` or `z`.
+    pub axis: &'static str,
+    /// Units of the samples: degrees per second for the gyro, g for the accelerometer.
+    pub unit: &'static str,
+    pub curve: Vec<AllanPoint>,
+    pub noise: NoiseParameters,
+}
+
+/// Allan deviation of every gyro and accelerometer axis. The sample rate is taken from the
+/// camera-clock millisecond timestamps, which are assumed evenly spaced.
+pub fn imu_noise(records: &[GyroRecord], scale: &ImuScale) -> Vec<AxisNoise> {
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return Vec::new();
+    };
+    let span = last.timestamp.saturating_sub(first.timestamp) as f64 / 1000.0;
+    if span <= 0.0 {
+        return Vec::new();
+    }
+    let rate = (records.len() - 1) as f64 / span;
+
+    let gyro: Vec<[f64; 3]> = records
+        .iter()
+        .map(|record| scale.gyro_rad_s(record).map(f64::to_degrees))
+        .collect();
+    let accel: Vec<[f64; 3]> = records.iter().map(|record| scale.accel_g(record)).collect();
+    let mut axes = Vec::new();
+    for (sensor, unit, samples) in [("gyro", "deg/s", &gyro), ("accel", "g", &accel)] {
+        for (i, axis) in ["x", "y", "z"].into_iter().enumerate() {
+            let values: Vec<f64> = samples.iter().map(|sample| sample[i]).collect();
+            let curve = allan_deviation(&values, rate);
+            axes.push(AxisNoise {
+                sensor,
+                axis,
+                unit,
+                noise: noise_parameters(&curve),
+                curve,
+            });
+        }
+    }
+    axes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allan_deviation() {
+        // Alternating ±1 differs by 2 between single samples and averages to zero over pairs.
+        let samples: Vec<f64> = (0..4096)
+            .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        let curve = allan_deviation(&samples, 100.0);
+        assert_eq!(curve[0].tau, 0.01);
+        assert!((curve[0].deviation - 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(curve[1].deviation, 0.0);
+        assert_eq!(curve.len(), 9);
+
+        // A constant has no noise.
+        let curve = allan_deviation(&[5.0; 100], 10.0);
+        assert!(curve.iter().all(|point| point.deviation.abs() < 1e-12));
+
+        let noise = noise_parameters(&[
+            AllanPoint {
+                tau: 1.0,
+                deviation: 0.1,
+            },
+            AllanPoint {
+                tau: 4.0,
+                deviation: 0.05,
+            },
+            AllanPoint {
+                tau: 16.0,
+                deviation: 0.0332,
+            },
+        ]);
+        assert_eq!(noise.random_walk, Some(0.1));
+        assert!((noise.bias_instability.unwrap() - 0.05).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,359 @@
+//! Editing the Insta360 metadata by appending to the file instead of rewriting it.
+//!
+//! Frame offsets count from the start of the metadata, which the trailer places `metadata_size`
+//! bytes before the end of the file. An amendment appends the changed frames, then a new index
+//! frame and trailer whose `metadata_size` reaches back to the same start, so the new index can
+//! point unchanged frames at their original bytes. However large the video, an edit writes only
+//! the frames it changes, and the bytes before it stay as they were, the old index and trailer
+//! and the frames replaced left in the metadata unreferenced. `vacuum` rebuilds the metadata
+//! without them.
+
+use crate::{FrameType, HEADER_SIZE, IndexFrameTrailer, Trailer, read_index};
+
+/// An entry of the index frame: the frame's type byte, version, size and offset from the start
+/// of the metadata.
+pub(crate) type IndexEntry = (u8, u8, u32, u32);
+
+/// The index frame listing `entries`, followed by a trailer regenerated from `trailer` at
+/// `version_num`, for metadata whose frames take `frames_len` bytes.
+pub(crate) fn index_and_trailer(
+    trailer: &Trailer,
+    entries: &[IndexEntry],
+    frames_len: usize,
+    version_num: i32,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (frame_type, version, size, offset) in entries {
+        out.extend_from_slice(&[*frame_type, *version]);
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&offset.to_le_bytes());
+    }
+    let index_size = out.len() as u32;
+    let metadata_size = (frames_len + out.len() + HEADER_SIZE as usize) as u32;
+    let index_version = trailer.index_frame_trailer().frame_version;
+    let trailer = trailer.regenerate(index_version, index_size, metadata_size, version_num);
+    out.extend(trailer.to_bytes());
+    out
+}
+
+/// The type byte of `frame`, ending at `end` in `data`. Unknown types keep the byte their own
+/// trailer gives.
+pub(crate) fn frame_type_byte(data: &[u8], frame: &IndexFrameTrailer, end: usize) -> u8 {
+    match frame.frame_type {
+        FrameType::Raw => data.get(end + 1).copied().unwrap_or(u8::MAX),
+        frame_type => frame_type as u8,
+    }
+}
+
+/// Appends `bytes` as a frame with its trailer to `metadata`.
+pub(crate) fn push_frame(metadata: &mut Vec<u8>, bytes: &[u8], version: u8, frame_type: u8) {
+    metadata.extend_from_slice(bytes);
+    metadata.extend_from_slice(&[version, frame_type]);
+    metadata.extend_from_slice(&(bytes.len() as i32).to_le_bytes());
+}
+
+/// A frame for `amendment` to add.
+#[derive(Clone, Copy, Debug)]
+pub struct NewFrame<'a> {
+    pub frame_type: FrameType,
+    pub version: u8,
+    pub bytes: &'a [u8],
+}
+
+/// The bytes to append to `data` for the frames `edit` changes to read as it returns, given each
+/// frame's type and bytes, and for `added` to replace any frames of their types, with the trailer
+/// at `version_num` or the file's own version. Empty if that changes nothing, and `None` if
+/// `data` has no Insta360 metadata.
+pub fn amendment(
+    data: &[u8],
+    version_num: Option<i32>,
+    mut edit: impl FnMut(FrameType, &[u8]) -> Option<Vec<u8>>,
+    added: &[NewFrame],
+) -> Option<Vec<u8>> {
+    let (trailer, index) = read_index(data)?;
+    let version_num = version_num.unwrap_or(trailer.version_num);
+    let metadata_start = data.len() - trailer.metadata_size as usize;
+    let mut appended = Vec::new();
+    let mut entries = Vec::new();
+    let replaced = |frame: &IndexFrameTrailer| {
+        (added.iter())
+            .any(|new| new.frame_type != FrameType::Raw && new.frame_type == frame.frame_type)
+    };
+    for frame in index.frames.iter().filter(|frame| !replaced(frame)) {
+        let start = metadata_start + frame.frame_offset as usize;
+        let end = start + frame.frame_size as usize;
+        let frame_type = frame_type_byte(data, frame, end);
+        let edited = data
+            .get(start..end)
+            .and_then(|bytes| edit(frame.frame_type, bytes));
+        let Some(bytes) = edited else {
+            entries.push((
+                frame_type,
+                frame.frame_version,
+                frame.frame_size,
+                frame.frame_offset,
+            ));
+            continue;
+        };
+        let offset = (data.len() - metadata_start + appended.len()) as u32;
+        push_frame(&mut appended, &bytes, frame.frame_version, frame_type);
+        entries.push((frame_type, frame.frame_version, bytes.len() as u32, offset));
+    }
+    for new in added {
+        let offset = (data.len() - metadata_start + appended.len()) as u32;
+        let frame_type = new.frame_type as u8;
+        push_frame(&mut appended, new.bytes, new.version, frame_type);
+        entries.push((frame_type, new.version, new.bytes.len() as u32, offset));
+    }
+    if appended.is_empty() && version_num == trailer.version_num {
+        return Some(Vec::new());
+    }
+    let frames_len = data.len() - metadata_start + appended.len();
+    appended.extend(index_and_trailer(
+        &trailer,
+        &entries,
+        frames_len,
+        version_num,
+    ));
+    Some(appended)
+}
+
+/// The bytes of the last frame of `frame_type` that the index of `data` lists.
+pub fn frame_bytes(data: &[u8], frame_type: FrameType) -> Option<&[u8]> {
+    let (trailer, index) = read_index(data)?;
+    let metadata_start = data.len() - trailer.metadata_size as usize;
+    let frame = (index.frames.iter()).rfind(|frame| frame.frame_type == frame_type)?;
+    let start = metadata_start + frame.frame_offset as usize;
+    data.get(start..start + frame.frame_size as usize)
+}
+
+/// Bytes of the metadata of `data` that neither a frame the index lists, nor the index and
+/// trailer, take up: what amendments left behind. `None` if `data` has no Insta360 metadata.
+pub fn superseded_len(data: &[u8]) -> Option<u64> {
+    let (trailer, index) = read_index(data)?;
+    let live: u64 = (index.frames.iter())
+        .map(|frame| frame.extent())
+        .map(|extent| extent.end - extent.start)
+        .sum();
+    // Everything amendments left behind comes before the index.
+    Some(trailer.index_frame_offset().saturating_sub(live))
+}
+
+/// Where the metadata of `data` starts, and the metadata rebuilt from the frames its index
+/// lists, without what amendments left behind, with the trailer at `version_num` or the file's
+/// own version. Frames that run past the end of the file are left out. `None` if `data` has no
+/// Insta360 metadata.
+pub fn vacuum(data: &[u8], version_num: Option<i32>) -> Option<(usize, Vec<u8>)> {
+    rebuild(data, version_num, |_| true)
+}
+
+/// The metadata of `data` with only the frames whose types `keep` accepts, to append to another
+/// file. `None` if `data` has no Insta360 metadata.
+pub fn extract(data: &[u8], keep: impl Fn(FrameType) -> bool) -> Option<Vec<u8>> {
+    Some(rebuild(data, None, keep)?.1)
+}
+
+/// Where the metadata of `data` starts, and the metadata rebuilt compactly from the frames its
+/// index lists whose types `keep` accepts. See `vacuum`.
+fn rebuild(
+    data: &[u8],
+    version_num: Option<i32>,
+    keep: impl Fn(FrameType) -> bool,
+) -> Option<(usize, Vec<u8>)> {
+    let (trailer, index) = read_index(data)?;
+    let version_num = version_num.unwrap_or(trailer.version_num);
+    let metadata_start = data.len() - trailer.metadata_size as usize;
+    let mut metadata = Vec::new();
+    let mut entries = Vec::new();
+    for frame in index.frames.iter().filter(|frame| keep(frame.frame_type)) {
+        let start = metadata_start + frame.frame_offset as usize;
+        let end = start + frame.frame_size as usize;
+        let Some(bytes) = data.get(start..end) else {
+            continue;
+        };
+        let frame_type = frame_type_byte(data, frame, end);
+        let offset = metadata.len() as u32;
+        push_frame(&mut metadata, bytes, frame.frame_version, frame_type);
+        entries.push((frame_type, frame.frame_version, bytes.len() as u32, offset));
+    }
+    let frames_len = metadata.len();
+    metadata.extend(index_and_trailer(
+        &trailer,
+        &entries,
+        frames_len,
+        version_num,
+    ));
+    Some((metadata_start, metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_telemetry, tests::build_insv, trailer_problems};
+
+    #[test]
+    fn test_amend_and_vacuum() {
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Magnetic, 1, vec![1, 2, 3]),
+                (FrameType::Exposure, 1, vec![0; 16]),
+            ],
+        );
+        assert_eq!(amendment(&data, None, |_, _| None, &[]), Some(Vec::new()));
+        assert_eq!(superseded_len(&data), Some(0));
+
+        let appended = amendment(
+            &data,
+            None,
+            |frame_type, bytes| {
+                (frame_type == FrameType::Magnetic).then(|| bytes.iter().map(|b| b * 10).collect())
+            },
+            &[],
+        )
+        .unwrap();
+        let amended = [data.clone(), appended].concat();
+        let (trailer, index) = read_index(&amended).unwrap();
+        let metadata_start = amended.len() - trailer.metadata_size as usize;
+        assert_eq!(metadata_start, 5);
+        let frame = |frame_type: FrameType| {
+            let frame = (index.frames.iter())
+                .find(|frame| frame.frame_type == frame_type)
+                .unwrap();
+            let start = metadata_start + frame.frame_offset as usize;
+            &amended[start..start + frame.frame_size as usize]
+        };
+        assert_eq!(frame(FrameType::Magnetic), [10, 20, 30]);
+        assert_eq!(frame(FrameType::Exposure), [0; 16]);
+        assert!(amended.starts_with(&data));
+        assert!(read_telemetry(&amended).diagnostics.is_empty());
+        assert_eq!(
+            trailer_problems(&amended, &trailer, &index),
+            Vec::<String>::new()
+        );
+        // The first Magnetic frame, the old index of two entries and the old trailer.
+        let superseded = 3 + 6 + 2 * 10 + HEADER_SIZE as u64;
+        assert_eq!(superseded_len(&amended), Some(superseded));
+
+        let (start, metadata) = vacuum(&amended, None).unwrap();
+        assert_eq!(start, 5);
+        let vacuumed = [&amended[..start], &metadata].concat();
+        assert_eq!(vacuumed.len() as u64, amended.len() as u64 - superseded);
+        assert_eq!(superseded_len(&vacuumed), Some(0));
+        let (trailer, index) = read_index(&vacuumed).unwrap();
+        assert_eq!(
+            trailer_problems(&vacuumed, &trailer, &index),
+            Vec::<String>::new()
+        );
+        let magnetic = &index.frames[0];
+        let start = 5 + magnetic.frame_offset as usize;
+        assert_eq!(vacuumed[start..start + 3], [10, 20, 30]);
+
+        assert_eq!(
+            amendment(b"not a camera file", None, |_, _| None, &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_damaged_metadata() {
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Magnetic, 1, vec![1, 2, 3]),
+                (FrameType::Exposure, 1, vec![0; 16]),
+            ],
+        );
+        let index_start = data.len() - HEADER_SIZE as usize - 20;
+
+        // A frame running past the end of the file is left out of a rebuild.
+        let mut overrunning = data.clone();
+        overrunning[index_start + 12..index_start + 16].copy_from_slice(&1000u32.to_le_bytes());
+        let (start, metadata) = vacuum(&overrunning, None).unwrap();
+        assert_eq!(start, 5);
+        let (_, index) = read_index(&metadata).unwrap();
+        assert_eq!(index.frames.len(), 1);
+        assert_eq!(index.frames[0].frame_type, FrameType::Magnetic);
+        assert_eq!(frame_bytes(&overrunning, FrameType::Exposure), None);
+        // Editing it finds nothing to edit, so its entry is carried over as it was.
+        let amended = amendment(&overrunning, None, |_, _| Some(vec![9]), &[]).unwrap();
+        let (_, index) = read_index(&[&overrunning[..], &amended].concat()).unwrap();
+        assert_eq!(index.frames[1].frame_size, 1000);
+
+        // A trailer claiming more metadata than the file holds has none to amend.
+        let mut oversized = data.clone();
+        let at = data.len() - HEADER_SIZE as usize + 38;
+        oversized[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(amendment(&oversized, None, |_, _| None, &[]), None);
+        assert_eq!(vacuum(&oversized, None), None);
+        assert_eq!(extract(&oversized, |_| true), None);
+        assert_eq!(superseded_len(&oversized), None);
+        assert_eq!(crate::minimize::minimize(&oversized, 1, false), None);
+    }
+
+    #[test]
+    fn test_trailer_version() {
+        let data = build_insv(b"video", &[(FrameType::Magnetic, 1, vec![1, 2, 3])]);
+        // Only the index and trailer are written again.
+        let appended = amendment(&data, Some(4), |_, _| None, &[]).unwrap();
+        assert_eq!(appended.len(), 10 + HEADER_SIZE as usize);
+        let bumped = [data.clone(), appended].concat();
+        let (trailer, index) = read_index(&bumped).unwrap();
+        assert_eq!(trailer.version_num, 4);
+        assert_eq!(trailer.metadata_size as usize, bumped.len() - 5);
+        assert_eq!(
+            trailer_problems(&bumped, &trailer, &index),
+            Vec::<String>::new()
+        );
+        assert_eq!(read_telemetry(&bumped).diagnostics, []);
+
+        let (start, metadata) = vacuum(&bumped, Some(3)).unwrap();
+        assert_eq!([&bumped[..start], &metadata].concat(), data);
+    }
+
+    #[test]
+    fn test_added_frame() {
+        let data = build_insv(b"video", &[(FrameType::Magnetic, 1, vec![1, 2, 3])]);
+        let add = |data: &[u8], bytes: &[u8]| {
+            let frame = NewFrame {
+                frame_type: FrameType::User,
+                version: 1,
+                bytes,
+            };
+            let appended = amendment(data, None, |_, _| None, &[frame]).unwrap();
+            [data, &appended].concat()
+        };
+        let first = add(&data, br#"{"take":1}"#);
+        assert_eq!(
+            frame_bytes(&first, FrameType::User),
+            Some(&br#"{"take":1}"#[..])
+        );
+        let second = add(&first, br#"{"take":2}"#);
+        assert_eq!(
+            frame_bytes(&second, FrameType::User),
+            Some(&br#"{"take":2}"#[..])
+        );
+        assert_eq!(
+            frame_bytes(&second, FrameType::Magnetic),
+            Some(&[1, 2, 3][..])
+        );
+        let (trailer, index) = read_index(&second).unwrap();
+        assert_eq!(index.frames.len(), 2);
+        assert_eq!(
+            trailer_problems(&second, &trailer, &index),
+            Vec::<String>::new()
+        );
+        assert_eq!(frame_bytes(&data, FrameType::User), None);
+
+        // Moved onto another video with only the User frame.
+        let metadata = extract(&second, |frame_type| frame_type == FrameType::User).unwrap();
+        let moved = [&b"other video"[..], &metadata].concat();
+        let (trailer, index) = read_index(&moved).unwrap();
+        assert_eq!(moved.len() - trailer.metadata_size as usize, 11);
+        assert_eq!(index.frames.len(), 1);
+        assert_eq!(
+            frame_bytes(&moved, FrameType::User),
+            Some(&br#"{"take":2}"#[..])
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Pseudonyms for the identifiers in the Info frame, for sharing sample files and their exports
+//! without giving away whose camera recorded them.
+//!
+//! Each identifier is replaced by a hash of itself, rendered as hex digits to the same length, so
+//! files from one camera still group together, and a rewritten Info frame keeps its size and
+//! every offset in the trailer stays valid. Serial numbers are short enough to recover by trying
+//! every candidate, so a pseudonym hides an identifier from a casual reader, not a determined one.
+
+/// Paths of the `ExtraMetadata` fields that identify a camera or its owner, by field number:
+/// `SerialNumber`, `Ip` and `FileGroupInfo.Identify`.
+const IDENTIFYING_FIELDS: &[&[u64]] = &[&[1], &[6], &[26, 3]];
+
+/// FNV-1a, a hash that doesn't change between builds or platforms.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Hex digits of a hash of `value`, as many as `value` has bytes.
+pub fn pseudonym(value: &[u8]) -> String {
+    let mut digits = String::new();
+    let mut round = 0u8;
+    while digits.len() < value.len() {
+        let hash = fnv1a(&[value, &[round]].concat());
+        digits += &format!("{hash:016x}");
+        round += 1;
+    }
+    digits.truncate(value.len());
+    digits
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Replaces the length-delimited fields of the protobuf message in `message` at the end of any of
+/// `paths` with their pseudonyms, returning how many were replaced, or `None` if the message
+/// doesn't decode.
+fn replace_fields(message: &mut [u8], paths: &[&[u64]]) -> Option<usize> {
+    let mut replaced = 0;
+    let mut pos = 0;
+    while pos < message.len() {
+        let key = read_varint(message, &mut pos)?;
+        let (field, wire_type) = (key >> 3, key & 7);
+        match wire_type {
+            0 => {
+                read_varint(message, &mut pos)?;
+            }
+            1 => pos += 8,
+            5 => pos += 4,
+            2 => {
+                let len = read_varint(message, &mut pos)? as usize;
+                let value = message.get_mut(pos..pos.checked_add(len)?)?;
+                let matching = paths.iter().filter(|path| path.first() == Some(&field));
+                let nested: Vec<&[u64]> = matching.clone().map(|path| &path[1..]).collect();
+                if matching.clone().any(|path| path.len() == 1) {
+                    value.copy_from_slice(pseudonym(value).as_bytes());
+                    replaced += 1;
+                } else if nested.iter().any(|path| !path.is_empty()) {
+                    replaced += replace_fields(value, &nested)?;
+                }
+                pos += len;
+            }
+            _ => return None,
+        }
+    }
+    (pos == message.len()).then_some(replaced)
+}
+
+/// Replaces the identifiers in the bytes of an Info frame in place, returning how many there
+/// were, or `None` if the frame doesn't decode, in which case it may be partly rewritten.
+pub fn anonymize_info_frame(frame: &mut [u8]) -> Option<usize> {
+    replace_fields(frame, IDENTIFYING_FIELDS)
+}
+
+/// Replaces the identifiers in a decoded Info frame.
+#[cfg(feature = "prost")]
+pub fn anonymize_info(info: &mut crate::insvtools::frames::ExtraMetadata) {
+    for value in [
+        info.serial_number.as_mut(),
+        (info.file_group_info.as_mut()).and_then(|group| group.identify.as_mut()),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *value = pseudonym(value.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pseudonym() {
+        let serial = pseudonym(b"IXSE123");
+        assert_eq!(serial.len(), 7);
+        assert_eq!(serial, pseudonym(b"IXSE123"));
+        assert_ne!(serial, pseudonym(b"IXSE124"));
+        assert_eq!(pseudonym(&[b'a'; 40]).len(), 40);
+    }
+
+    #[test]
+    fn test_anonymize_info_frame() {
+        // SerialNumber "IXSE123", CameraType "X3", FrameRate 30, and FileGroupInfo with Type 1
+        // and Identify "abc".
+        let mut frame = [
+            &[0x0a, 7][..],
+            b"IXSE123",
+            &[0x12, 2],
+            b"X3",
+            &[0xa0, 0x01, 30],
+            &[0xd2, 0x01, 7, 0x08, 1, 0x1a, 3],
+            b"abc",
+        ]
+        .concat();
+        let original = frame.clone();
+        assert_eq!(anonymize_info_frame(&mut frame), Some(2));
+        assert_eq!(frame.len(), original.len());
+        assert_eq!(&frame[2..9], pseudonym(b"IXSE123").as_bytes());
+        assert_eq!(&frame[9..23], &original[9..23]);
+        assert_eq!(&frame[23..], pseudonym(b"abc").as_bytes());
+
+        assert_eq!(anonymize_info_frame(&mut [0x0a, 9, b'x']), None);
+    }
+
+    #[cfg(feature = "prost")]
+    #[test]
+    fn test_anonymize_info() {
+        let mut info = crate::insvtools::frames::ExtraMetadata {
+            serial_number: Some("IXSE123".to_string()),
+            camera_type: Some("Insta360 X3".to_string()),
+            ..Default::default()
+        };
+        anonymize_info(&mut info);
+        assert_eq!(info.serial_number, Some(pseudonym(b"IXSE123")));
+        assert_eq!(info.camera_type.as_deref(), Some("Insta360 X3"));
+    }
+}
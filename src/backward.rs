@@ -0,0 +1,235 @@
+//! Reading a file from its end, where Insta360 metadata is, in a few large reads rather than a
+//! seek and a read for every structure on the way back from the trailer. Each read is a round
+//! trip on network storage, so the end of the file is read a chunk at a time and kept, and reads
+//! within it are served from memory.
+//!
+//! Reads are positioned, `pread` on Unix, so they don't move a shared file offset and threads can
+//! read one file handle at once without seeking it back and forth under each other.
+
+use std::{fs::File, io};
+
+use crate::{HEADER_SIZE, header_parser};
+
+/// Reading at an offset without a file position, so through a shared reference.
+pub trait ReadAt {
+    /// Fills `buf` from the bytes at `offset`, failing if there aren't enough.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+}
+
+impl ReadAt for File {
+    #[cfg(unix)]
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.seek_read(buf, offset) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => {
+                    buf = &mut buf[read..];
+                    offset += read as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Elsewhere a reference to a file seeks it, like a handle of its own would.
+    #[cfg(not(any(unix, windows)))]
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        use std::io::{Read, Seek, SeekFrom};
+        let mut file = self;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let bytes = usize::try_from(offset)
+            .ok()
+            .and_then(|start| self.get(start..start.checked_add(buf.len())?))
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self[..].read_exact_at(buf, offset)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        (**self).read_exact_at(buf, offset)
+    }
+}
+
+/// Bytes read from the end of the file at a time.
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// A file read from its end through a buffer of its last bytes, which grows back towards the
+/// start by whole chunks as earlier bytes are asked for.
+///
+/// Bytes well before the buffer, such as a frame at the start of metadata hundreds of megabytes
+/// long, are read on their own instead, so the buffer never holds more than the reads near the end
+/// need.
+#[derive(Debug)]
+pub struct BackwardReader<R> {
+    inner: R,
+    len: u64,
+    chunk_size: usize,
+    /// Where in the file `buffer` starts; it runs to the end.
+    start: u64,
+    buffer: Vec<u8>,
+    /// The last read from before the buffer.
+    scratch: Vec<u8>,
+}
+
+impl<R: ReadAt> BackwardReader<R> {
+    /// A reader of `inner`, which is `len` bytes long.
+    pub fn new(inner: R, len: u64) -> Self {
+        Self::with_chunk_size(inner, len, DEFAULT_CHUNK_SIZE)
+    }
+
+    pub fn with_chunk_size(inner: R, len: u64, chunk_size: usize) -> Self {
+        BackwardReader {
+            inner,
+            len,
+            chunk_size: chunk_size.max(1),
+            start: len,
+            buffer: Vec::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The `len` bytes at `offset`.
+    pub fn read_at(&mut self, offset: u64, len: usize) -> io::Result<&[u8]> {
+        let end = (offset.checked_add(len as u64))
+            .filter(|end| *end <= self.len)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{len} bytes at {offset} run past the end of the file"),
+                )
+            })?;
+        if end + (self.chunk_size as u64) < self.start {
+            self.scratch.resize(len, 0);
+            self.inner.read_exact_at(&mut self.scratch, offset)?;
+            return Ok(&self.scratch);
+        }
+        if offset < self.start {
+            let chunk = self.chunk_size as u64;
+            let start = offset.min(self.start.saturating_sub(chunk));
+            let mut earlier = vec![0; (self.start - start) as usize];
+            self.inner.read_exact_at(&mut earlier, start)?;
+            earlier.extend_from_slice(&self.buffer);
+            self.buffer = earlier;
+            self.start = start;
+        }
+        let at = (offset - self.start) as usize;
+        Ok(&self.buffer[at..at + len])
+    }
+
+    /// The end of the file from the index frame on, which is all `read_index` needs, or `None` if
+    /// the file doesn't end in an Insta360 trailer.
+    pub fn index_tail(&mut self) -> io::Result<Option<&[u8]>> {
+        let header_size = HEADER_SIZE as u64;
+        let Some(trailer_start) = self.len.checked_sub(header_size) else {
+            return Ok(None);
+        };
+        let trailer = self.read_at(trailer_start, header_size as usize)?;
+        let Ok((_, header)) = header_parser(trailer) else {
+            return Ok(None);
+        };
+        let Some(tail_start) = trailer_start.checked_sub(header.metadata[0].size as u64) else {
+            return Ok(None);
+        };
+        self.read_at(tail_start, (self.len - tail_start) as usize)
+            .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// Counts the reads that reach the file.
+    struct Counted {
+        data: Vec<u8>,
+        reads: Cell<usize>,
+    }
+
+    impl ReadAt for Counted {
+        fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            self.reads.set(self.reads.get() + 1);
+            self.data[..].read_exact_at(buf, offset)
+        }
+    }
+
+    #[test]
+    fn test_backward_reader() {
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let file = Counted {
+            data: data.clone(),
+            reads: Cell::new(0),
+        };
+        let mut reader = BackwardReader::with_chunk_size(&file, 1000, 100);
+        assert_eq!(reader.read_at(990, 10).unwrap(), &data[990..]);
+        assert_eq!(reader.read_at(920, 30).unwrap(), &data[920..950]);
+        assert_eq!(file.reads.get(), 1);
+
+        // Just before the buffer: it grows by a chunk.
+        assert_eq!(reader.read_at(850, 20).unwrap(), &data[850..870]);
+        assert_eq!((reader.start, file.reads.get()), (800, 2));
+        // Far before it: read on its own.
+        assert_eq!(reader.read_at(10, 5).unwrap(), &data[10..15]);
+        assert_eq!((reader.start, file.reads.get()), (800, 3));
+
+        assert_eq!(
+            reader.read_at(995, 10).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        let mut buf = [0; 4];
+        assert!(data[..].read_exact_at(&mut buf, 998).is_err());
+    }
+
+    #[test]
+    fn test_index_tail() {
+        let data = crate::tests::build_insv(b"video", &[(crate::FrameType::Gyro, 1, vec![0; 40])]);
+        let tail_len = HEADER_SIZE as usize + 10;
+        let len = data.len() as u64;
+        let tail = |data: &[u8]| {
+            (BackwardReader::new(data, data.len() as u64).index_tail())
+                .unwrap()
+                .map(<[u8]>::to_vec)
+        };
+        assert_eq!(tail(&data), Some(data[data.len() - tail_len..].to_vec()));
+
+        // No trailer, too short for one, or an index frame bigger than the file.
+        assert_eq!(tail(b"video"), None);
+        assert_eq!(tail(&data[..data.len() - 1]), None);
+        assert_eq!(tail(&data[data.len() - HEADER_SIZE as usize + 1..]), None);
+        let mut overlong = data.clone();
+        let at = data.len() - HEADER_SIZE as usize + 2;
+        overlong[at..at + 4].copy_from_slice(&(len as u32).to_le_bytes());
+        assert_eq!(tail(&overlong), None);
+    }
+}
@@ -0,0 +1,238 @@
+//! The stages `ginsta bench` times: reading the trailer and the index, decoding each stream, and
+//! writing what was decoded, so slowdowns between releases show up on users' own files.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    FrameType, HEADER_SIZE, columnar::telemetry_tables, header_parser, imu::ImuScale,
+    metadata_start, oversized_metadata, parse_exposure_frame, parse_gps_frame, parse_gyro_frame,
+    parse_info_frame, read_index, read_telemetry, rounding::Rounded,
+};
+
+/// Why a file can't be benchmarked.
+#[derive(Debug, PartialEq)]
+pub enum BenchError {
+    NoMetadata,
+    /// The trailer claims more metadata than the file holds.
+    OversizedMetadata {
+        metadata_size: u32,
+        len: usize,
+    },
+}
+
+impl fmt::Display for BenchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BenchError::NoMetadata => write!(f, "no Insta360 metadata"),
+            BenchError::OversizedMetadata { metadata_size, len } => write!(
+                f,
+                "its trailer claims {metadata_size} bytes of metadata, but the file is only {len} \
+                 bytes long"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BenchError {}
+
+/// How long a stage took.
+#[derive(Debug)]
+pub struct Stage {
+    pub name: &'static str,
+    /// Read for parsing and decoding stages, written for the others.
+    pub bytes: usize,
+    pub records: Option<usize>,
+    pub time: Duration,
+}
+
+/// The fastest of `runs` runs of `stage`, and what the last one returned.
+pub fn time_runs<T>(runs: u32, mut stage: impl FnMut() -> T) -> (Duration, T) {
+    let mut fastest = Duration::MAX;
+    let mut result = None;
+    for _ in 0..runs {
+        let start = Instant::now();
+        let value = std::hint::black_box(stage());
+        fastest = fastest.min(start.elapsed());
+        result = Some(value);
+    }
+    (fastest, result.expect("runs is at least 1"))
+}
+
+/// Decodes a frame, returning how many records it held.
+type Decoder = fn(&[u8]) -> usize;
+
+/// Times each stage of reading the camera file `data`, taking the fastest of `runs` runs, at
+/// least one. Stages writing CSV round floats to `decimals` places as `--deterministic` does.
+/// Streams the file has none of are left out.
+pub fn bench_stages(
+    data: &[u8],
+    runs: u32,
+    decimals: Option<u32>,
+) -> Result<Vec<Stage>, BenchError> {
+    let Some(metadata_start) = metadata_start(data) else {
+        return Err(match oversized_metadata(data, data.len() as u64) {
+            Some(metadata_size) => BenchError::OversizedMetadata {
+                metadata_size,
+                len: data.len(),
+            },
+            None => BenchError::NoMetadata,
+        });
+    };
+    let (trailer, index) = read_index(data).ok_or(BenchError::NoMetadata)?;
+    let mut stages = Vec::new();
+    let mut stage = |name, bytes, records, time| {
+        stages.push(Stage {
+            name,
+            bytes,
+            records,
+            time,
+        })
+    };
+
+    let header_size = HEADER_SIZE as usize;
+    let (time, _) = time_runs(runs, || {
+        header_parser(&data[data.len() - header_size..]).is_ok()
+    });
+    stage("trailer", header_size, None, time);
+    let index_size = trailer.metadata[0].size as usize;
+    let (time, _) = time_runs(runs, || read_index(data).is_some());
+    stage("index", index_size, Some(index.frames.len()), time);
+
+    let frames = |frame_type| -> Vec<&[u8]> {
+        (index.frames.iter())
+            .filter(|frame| frame.frame_type == frame_type)
+            .filter_map(|frame| {
+                let start = metadata_start + frame.frame_offset as usize;
+                data.get(start..start + frame.frame_size as usize)
+            })
+            .collect()
+    };
+    let decoders: [(&str, FrameType, Decoder); 4] = [
+        ("decode gps", FrameType::Gps, |frame| {
+            parse_gps_frame(frame).map_or(0, |(_, frame)| frame.records.len())
+        }),
+        ("decode gyro", FrameType::Gyro, |frame| {
+            parse_gyro_frame(frame).map_or(0, |(_, frame)| frame.records.len())
+        }),
+        ("decode exposure", FrameType::Exposure, |frame| {
+            parse_exposure_frame(frame).map_or(0, |(_, frame)| frame.records.len())
+        }),
+        ("decode info", FrameType::Info, |frame| {
+            parse_info_frame(frame).map_or(0, |_| 1)
+        }),
+    ];
+    for (name, frame_type, decode) in decoders {
+        let frames = frames(frame_type);
+        if frames.is_empty() {
+            continue;
+        }
+        let bytes = frames.iter().map(|frame| frame.len()).sum();
+        let (time, records) = time_runs(runs, || {
+            frames.iter().map(|frame| decode(frame)).sum::<usize>()
+        });
+        stage(name, bytes, Some(records), time);
+    }
+    let (time, telemetry) = time_runs(runs, || read_telemetry(data));
+    let records = telemetry.gps.len() + telemetry.gyro.len() + telemetry.exposure.len();
+    stage(
+        "decode all",
+        trailer.metadata_size as usize,
+        Some(records),
+        time,
+    );
+
+    let (time, csv) = time_runs(runs, || {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for value in &telemetry.gps {
+            writer
+                .serialize(Rounded { value, decimals })
+                .expect("writing to memory");
+        }
+        writer.into_inner().unwrap_or_default()
+    });
+    stage("write csv", csv.len(), Some(telemetry.gps.len()), time);
+    let scale = ImuScale::from_info(telemetry.info.as_ref());
+    let (time, csv) = time_runs(runs, || {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        for record in &telemetry.gyro {
+            let value = &scale.sample(record);
+            writer
+                .serialize(Rounded { value, decimals })
+                .expect("writing to memory");
+        }
+        writer.into_inner().unwrap_or_default()
+    });
+    stage("write imu", csv.len(), Some(telemetry.gyro.len()), time);
+    let (time, bin) = time_runs(runs, || {
+        let mut bytes = Vec::new();
+        for table in telemetry_tables(&telemetry.gps, &telemetry.gyro, scale) {
+            table.write(&mut bytes);
+        }
+        bytes
+    });
+    let records = telemetry.gps.len() + telemetry.gyro.len();
+    stage("write bin", bin.len(), Some(records), time);
+    Ok(stages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GPS_RECORD_SIZE, tests::build_insv};
+
+    #[test]
+    fn test_bench_stages() {
+        let gps = include_bytes!("testdata/Gps_1752824363158.insgps");
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Gps, 1, gps[..10 * GPS_RECORD_SIZE].to_vec()),
+                (FrameType::Gyro, 1, vec![0; 3 * 20]),
+            ],
+        );
+        let stages = bench_stages(&data, 2, Some(6)).unwrap();
+        let summary: Vec<(&str, Option<usize>)> = (stages.iter())
+            .map(|stage| (stage.name, stage.records))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("trailer", None),
+                ("index", Some(2)),
+                ("decode gps", Some(10)),
+                ("decode gyro", Some(3)),
+                ("decode all", Some(13)),
+                ("write csv", Some(10)),
+                ("write imu", Some(3)),
+                ("write bin", Some(13)),
+            ]
+        );
+        assert_eq!(stages[2].bytes, 10 * GPS_RECORD_SIZE);
+        assert_eq!(stages[4].bytes, data.len() - 5);
+
+        assert_eq!(
+            bench_stages(b"video", 1, None).unwrap_err(),
+            BenchError::NoMetadata
+        );
+        // A trailer claiming more metadata than the file holds is an error, not an underflow.
+        let mut oversized = data.clone();
+        let at = data.len() - HEADER_SIZE as usize + 38;
+        oversized[at..at + 4].copy_from_slice(&0xFFFFFF00u32.to_le_bytes());
+        let error = bench_stages(&oversized, 1, None).unwrap_err();
+        assert_eq!(
+            error,
+            BenchError::OversizedMetadata {
+                metadata_size: 0xFFFFFF00,
+                len: data.len()
+            }
+        );
+        assert!(
+            error
+                .to_string()
+                .starts_with("its trailer claims 4294967040 bytes")
+        );
+    }
+}
@@ -1,60 +1,497 @@
-use nom::{
-    number::{be_f64, be_i64, be_u64, le_f64, le_i64, le_u64},
-    Parser,
+use base64::Engine;
+use chrono::DateTime;
+use clap::{Parser, ValueEnum};
+use nom::number::{
+    be_f32, be_f64, be_i16, be_i32, be_i64, be_u16, be_u32, be_u64, le_f32, le_f64, le_i16, le_i32,
+    le_i64, le_u16, le_u32, le_u64,
 };
-use std::env::args;
-
-fn try_various_parsers_64(
-    bytes: &[u8],
-) -> (
-    Option<f64>,
-    Option<f64>,
-    Option<u64>,
-    Option<u64>,
-    Option<i64>,
-    Option<i64>,
-) {
-    let float64_le = le_f64::<&[u8], nom::error::Error<&[u8]>>()
-        .parse(bytes)
-        .ok()
-        .map(|(_, v)| v);
-    let float64_be = be_f64::<&[u8], nom::error::Error<&[u8]>>()
-        .parse(bytes)
-        .ok()
-        .map(|(_, v)| v);
-    let uint64_le = le_u64::<&[u8], nom::error::Error<&[u8]>>()
-        .parse(bytes)
-        .ok()
-        .map(|(_, v)| v);
-    let uint64_be = be_u64::<&[u8], nom::error::Error<&[u8]>>()
-        .parse(bytes)
-        .ok()
-        .map(|(_, v)| v);
-    let int64_le = le_i64::<&[u8], nom::error::Error<&[u8]>>()
-        .parse(bytes)
-        .ok()
-        .map(|(_, v)| v);
-    let int64_be = be_i64::<&[u8], nom::error::Error<&[u8]>>()
-        .parse(bytes)
-        .ok()
-        .map(|(_, v)| v);
+use std::{
+    io::{IsTerminal, Read},
+    path::PathBuf,
+};
+
+type Error<'a> = nom::error::Error<&'a [u8]>;
+
+/// An interpretation as (little endian, big endian).
+type Endians<T> = (Option<T>, Option<T>);
+
+fn parse<'a, T>(
+    mut parser: impl nom::Parser<&'a [u8], Output = T, Error = Error<'a>>,
+    bytes: &'a [u8],
+) -> Option<T> {
+    parser.parse(bytes).ok().map(|(_, v)| v)
+}
 
+/// Float, unsigned and signed interpretations, each as (little endian, big endian).
+type Interpretations64 = (Endians<f64>, Endians<u64>, Endians<i64>);
+
+fn try_various_parsers_64(bytes: &[u8]) -> Interpretations64 {
     (
-        float64_le, float64_be, uint64_le, uint64_be, int64_le, int64_be,
+        (parse(le_f64(), bytes), parse(be_f64(), bytes)),
+        (parse(le_u64(), bytes), parse(be_u64(), bytes)),
+        (parse(le_i64(), bytes), parse(be_i64(), bytes)),
     )
 }
 
+type Interpretations32 = (Endians<f32>, Endians<u32>, Endians<i32>);
+
+fn try_various_parsers_32(bytes: &[u8]) -> Interpretations32 {
+    (
+        (parse(le_f32(), bytes), parse(be_f32(), bytes)),
+        (parse(le_u32(), bytes), parse(be_u32(), bytes)),
+        (parse(le_i32(), bytes), parse(be_i32(), bytes)),
+    )
+}
+
+type Interpretations16 = (Endians<u16>, Endians<i16>);
+
+fn try_various_parsers_16(bytes: &[u8]) -> Interpretations16 {
+    (
+        (parse(le_u16(), bytes), parse(be_u16(), bytes)),
+        (parse(le_i16(), bytes), parse(be_i16(), bytes)),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    Float(f64),
+    Unsigned(u64),
+    Signed(i64),
+}
+
+/// Every interpretation of the first `width` bytes, labelled with type and endianness.
+fn interpretations(bytes: &[u8], width: usize) -> Vec<(&'static str, Value)> {
+    let mut out = Vec::new();
+    let mut push = |label, value: Option<Value>| out.extend(value.map(|v| (label, v)));
+    let float = |v: Option<f32>| v.map(|v| Value::Float(v.into()));
+    match width {
+        1 => {
+            push("u8", Some(Value::Unsigned(bytes[0].into())));
+            push("i8", Some(Value::Signed((bytes[0] as i8).into())));
+        }
+        2 => {
+            let ((leu16, beu16), (lei16, bei16)) = try_various_parsers_16(bytes);
+            push("u16 LE", leu16.map(|v| Value::Unsigned(v.into())));
+            push("u16 BE", beu16.map(|v| Value::Unsigned(v.into())));
+            push("i16 LE", lei16.map(|v| Value::Signed(v.into())));
+            push("i16 BE", bei16.map(|v| Value::Signed(v.into())));
+        }
+        4 => {
+            let ((lef32, bef32), (leu32, beu32), (lei32, bei32)) = try_various_parsers_32(bytes);
+            push("f32 LE", float(lef32));
+            push("f32 BE", float(bef32));
+            push("u32 LE", leu32.map(|v| Value::Unsigned(v.into())));
+            push("u32 BE", beu32.map(|v| Value::Unsigned(v.into())));
+            push("i32 LE", lei32.map(|v| Value::Signed(v.into())));
+            push("i32 BE", bei32.map(|v| Value::Signed(v.into())));
+        }
+        8 => {
+            let ((lef64, bef64), (leu64, beu64), (lei64, bei64)) = try_various_parsers_64(bytes);
+            push("f64 LE", lef64.map(Value::Float));
+            push("f64 BE", bef64.map(Value::Float));
+            push("u64 LE", leu64.map(Value::Unsigned));
+            push("u64 BE", beu64.map(Value::Unsigned));
+            push("i64 LE", lei64.map(Value::Signed));
+            push("i64 BE", bei64.map(Value::Signed));
+        }
+        _ => {}
+    }
+    out
+}
+
+/// Floats in degree range with a fractional part.
+fn plausible_latlon(value: Value) -> bool {
+    match value {
+        Value::Float(v) => (0.01..=180.0).contains(&v.abs()) && v.fract() != 0.0,
+        _ => false,
+    }
+}
+
+/// Integers that are a time between 2000 and 2100 in Unix seconds, milliseconds or
+/// microseconds, returned as seconds.
+fn plausible_timestamp(value: Value) -> Option<f64> {
+    let v = match value {
+        Value::Unsigned(v) => v as f64,
+        Value::Signed(v) => v as f64,
+        Value::Float(_) => return None,
+    };
+    [1.0, 1e3, 1e6]
+        .into_iter()
+        .map(|scale| v / scale)
+        .find(|seconds| (946_684_800.0..4_102_444_800.0).contains(seconds))
+}
+
+fn scan(data: &[u8], args: &Args) {
+    let filtering = args.plausible_latlon || args.plausible_timestamp;
+    for offset in 0..data.len() {
+        for width in [1, 2, 4, 8] {
+            let Some(bytes) = data.get(offset..offset + width) else {
+                continue;
+            };
+            for (label, value) in interpretations(bytes, width) {
+                let time = plausible_timestamp(value)
+                    .filter(|_| args.plausible_timestamp)
+                    .and_then(|seconds| DateTime::from_timestamp(seconds as i64, 0));
+                let latlon = args.plausible_latlon && plausible_latlon(value);
+                if filtering && time.is_none() && !latlon {
+                    continue;
+                }
+                let value = match value {
+                    Value::Float(v) => format!("{v:?}"),
+                    Value::Unsigned(v) => v.to_string(),
+                    Value::Signed(v) => v.to_string(),
+                };
+                match time {
+                    Some(time) => println!("{offset:>6}  {label:<6}  {value}  ({time})"),
+                    None => println!("{offset:>6}  {label:<6}  {value}"),
+                }
+            }
+        }
+    }
+}
+
+/// How well a payload splits into records of one size.
+#[derive(Debug)]
+struct StrideGuess {
+    stride: usize,
+    records: usize,
+    /// Mean over byte positions of the share of records repeating the previous record's byte.
+    stability: f64,
+    /// Offset and type of a field that increases from record to record, as `monotonic_field`
+    /// finds it.
+    timestamp: Option<(usize, &'static str)>,
+    score: f64,
+}
+
+/// Finds an unsigned 32 or 64-bit field that never decreases from record to record and
+/// increases across them, as a timestamp does even when several records share one.
+fn monotonic_field(records: &[&[u8]]) -> Option<(usize, &'static str)> {
+    let stride = records[0].len();
+    (0..stride).find_map(|offset| {
+        [4, 8].into_iter().find_map(|width| {
+            if offset + width > stride {
+                return None;
+            }
+            let field = |record: &[u8]| interpretations(&record[offset..offset + width], width);
+            // Each unsigned interpretation still in the running, with its last value and whether
+            // it has increased yet.
+            let mut candidates: Vec<(&'static str, u64, bool)> = field(records[0])
+                .into_iter()
+                .filter_map(|(label, value)| match value {
+                    Value::Unsigned(v) => Some((label, v, false)),
+                    _ => None,
+                })
+                .collect();
+            for record in &records[1..] {
+                if candidates.is_empty() {
+                    return None;
+                }
+                let values = field(record);
+                candidates.retain_mut(|(label, last, increased)| {
+                    match values.iter().find(|(other, _)| other == label) {
+                        Some(&(_, Value::Unsigned(v))) if v >= *last => {
+                            *increased |= v > *last;
+                            *last = v;
+                            true
+                        }
+                        _ => false,
+                    }
+                });
+            }
+            (candidates.into_iter())
+                .find(|(_, _, increased)| *increased)
+                .map(|(label, ..)| (offset, label))
+        })
+    })
+}
+
+/// Tries every record size that divides the payload into at least three records and ranks
+/// them, best first. Correct strides line fields up, so bytes change less between records,
+/// and usually expose an increasing timestamp.
+fn guess_strides(data: &[u8]) -> Vec<StrideGuess> {
+    let guesses: Vec<StrideGuess> = (2..=data.len() / 3)
+        .filter(|stride| data.len().is_multiple_of(*stride))
+        .map(|stride| {
+            let records: Vec<&[u8]> = data.chunks_exact(stride).collect();
+            let repeats: usize = records
+                .windows(2)
+                .map(|pair| pair[0].iter().zip(pair[1]).filter(|(a, b)| a == b).count())
+                .sum();
+            let stability = repeats as f64 / ((records.len() - 1) * stride) as f64;
+            let timestamp = monotonic_field(&records);
+            StrideGuess {
+                stride,
+                records: records.len(),
+                stability,
+                timestamp,
+                score: stability + if timestamp.is_some() { 0.5 } else { 0.0 },
+            }
+        })
+        .collect();
+    // Multiples of the true stride line up just as well and can repeat slow patterns in the
+    // data, so they only count when clearly more stable than the stride they're a multiple of.
+    let dominated: Vec<bool> = guesses
+        .iter()
+        .map(|guess| {
+            guesses.iter().any(|smaller| {
+                smaller.stride < guess.stride
+                    && guess.stride.is_multiple_of(smaller.stride)
+                    && smaller.timestamp.is_some() == guess.timestamp.is_some()
+                    && smaller.stability >= guess.stability - 0.1
+            })
+        })
+        .collect();
+    let mut guesses: Vec<StrideGuess> = guesses
+        .into_iter()
+        .zip(dominated)
+        .filter_map(|(guess, dominated)| (!dominated).then_some(guess))
+        .collect();
+    guesses.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.stride.cmp(&b.stride)));
+    guesses
+}
+
+fn print_stride_guesses(data: &[u8]) {
+    let guesses = guess_strides(data);
+    println!("stride  records  stability  increasing field");
+    for guess in guesses.iter().take(5) {
+        let timestamp = guess.timestamp.map_or("-".to_string(), |(offset, label)| {
+            format!("{label} at {offset}")
+        });
+        println!(
+            "{:>6}  {:>7}  {:>9.3}  {timestamp}",
+            guess.stride, guess.records, guess.stability
+        );
+    }
+    if let Some(best) = guesses.first() {
+        println!();
+        for record in data.chunks_exact(best.stride).take(3) {
+            println!("{}", hex::encode(record));
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum InputFormat {
+    /// `\x` escapes if present, then hex, then base64. Standard input that isn't printable
+    /// text is taken as raw bytes.
+    Auto,
+    /// Hex digits, optionally with `0x` prefixes and space, colon or dash separators.
+    Hex,
+    Base64,
+    /// A string with `\xNN` escapes, such as a Python or C byte string literal.
+    Escaped,
+    /// Bytes as they are.
+    Raw,
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, hex::FromHexError> {
+    let digits: String = text
+        .split(|c: char| c.is_whitespace() || matches!(c, ':' | '-' | ','))
+        .map(|word| word.trim_start_matches("0x"))
+        .collect();
+    hex::decode(digits)
+}
+
+/// Decodes `\xNN` escapes and the common single-character escapes; other characters stand for
+/// their own UTF-8 bytes. Any other escape is an error.
+fn decode_escaped(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix("b'")
+        .and_then(|t| t.strip_suffix('\''))
+        .or_else(|| text.strip_prefix("b\"").and_then(|t| t.strip_suffix('"')))
+        .unwrap_or(text);
+    let mut out = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&digits, 16)
+                    .map_err(|_| format!("invalid escape \\x{digits}"))?;
+                out.push(byte);
+            }
+            Some('n') => out.push(b'\n'),
+            Some('r') => out.push(b'\r'),
+            Some('t') => out.push(b'\t'),
+            Some('0') => out.push(0),
+            Some(c @ ('\\' | '\'' | '"')) => out.push(c as u8),
+            Some(c) => return Err(format!("unknown escape \\{c}")),
+            None => return Err("trailing backslash".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+fn decode_text(text: &str, format: InputFormat) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let base64 = |text: &str| {
+        let text: String = text.split_whitespace().collect();
+        base64::engine::general_purpose::STANDARD.decode(text)
+    };
+    Ok(match format {
+        InputFormat::Hex => decode_hex(text)?,
+        InputFormat::Base64 => base64(text)?,
+        InputFormat::Escaped => decode_escaped(text)?,
+        InputFormat::Raw => text.as_bytes().to_vec(),
+        InputFormat::Auto if text.contains("\\x") => decode_escaped(text)?,
+        InputFormat::Auto => match decode_hex(text) {
+            Ok(data) => data,
+            Err(_) => base64(text)?,
+        },
+    })
+}
+
+/// Reads the bytes to decode from the argument, a file or standard input.
+fn read_input(args: &Args) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if let Some(text) = &args.hex {
+        return decode_text(text, args.input_format);
+    }
+    let data = match &args.file {
+        Some(path) => std::fs::read(path)?,
+        None if std::io::stdin().is_terminal() => return Err("No hex data given".into()),
+        None => {
+            let mut data = Vec::new();
+            std::io::stdin().read_to_end(&mut data)?;
+            data
+        }
+    };
+    let printable = |data: &[u8]| {
+        data.iter()
+            .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+    };
+    match args.input_format {
+        InputFormat::Raw => Ok(data),
+        InputFormat::Auto if args.file.is_some() || !printable(&data) => Ok(data),
+        format => decode_text(std::str::from_utf8(&data)?, format),
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(about = "Shows the numbers a run of bytes could encode")]
+struct Args {
+    /// Bytes as hex, base64 or an escaped string. 1, 2, 4 or 8 bytes are decoded in full;
+    /// anything longer is scanned. Without this or `--file`, bytes are read from standard input.
+    hex: Option<String>,
+
+    /// Read the bytes from a file instead. Files are raw bytes unless `--input-format` says
+    /// otherwise.
+    #[arg(long, conflicts_with = "hex")]
+    file: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = InputFormat::Auto)]
+    input_format: InputFormat,
+
+    /// Scan every offset even when the input is a single number.
+    #[arg(long)]
+    scan: bool,
+
+    /// Guess the record size of a frame payload made of fixed-size records.
+    #[arg(long, conflicts_with = "scan")]
+    stride: bool,
+
+    /// When scanning, only show floats that could be a latitude or longitude in degrees.
+    #[arg(long)]
+    plausible_latlon: bool,
+
+    /// When scanning, only show integers that could be a Unix time in seconds, milliseconds or
+    /// microseconds.
+    #[arg(long)]
+    plausible_timestamp: bool,
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
-    let hex_data = args().nth(1).expect("No hex data given");
-    let data = hex::decode(hex_data).expect("Failed to decode hex data");
+    let args = Args::parse();
+    let data = read_input(&args)?;
 
-    if data.len() == 8 {
-        let (lef64, bef64, leu64, beu64, lei64, bei64) = try_various_parsers_64(data.as_slice());
-        println!("64-bit float: LE: {:?}, BE: {:?}", lef64, bef64);
-        println!("64-bit uint:  LE: {:?}, BE: {:?}", leu64, beu64);
-        println!("64-bit int:   LE: {:?}, BE: {:?}", lei64, bei64);
+    if args.stride {
+        print_stride_guesses(&data);
+        return Ok(());
+    }
+    if args.scan || args.plausible_latlon || args.plausible_timestamp {
+        scan(&data, &args);
+        return Ok(());
+    }
+
+    match data.len() {
+        1 => {
+            println!("8-bit uint:  {}", data[0]);
+            println!("8-bit int:   {}", data[0] as i8);
+        }
+        2 => {
+            let ((leu16, beu16), (lei16, bei16)) = try_various_parsers_16(data.as_slice());
+            println!("16-bit uint:  LE: {:?}, BE: {:?}", leu16, beu16);
+            println!("16-bit int:   LE: {:?}, BE: {:?}", lei16, bei16);
+        }
+        4 => {
+            let ((lef32, bef32), (leu32, beu32), (lei32, bei32)) =
+                try_various_parsers_32(data.as_slice());
+            println!("32-bit float: LE: {:?}, BE: {:?}", lef32, bef32);
+            println!("32-bit uint:  LE: {:?}, BE: {:?}", leu32, beu32);
+            println!("32-bit int:   LE: {:?}, BE: {:?}", lei32, bei32);
+        }
+        8 => {
+            let ((lef64, bef64), (leu64, beu64), (lei64, bei64)) =
+                try_various_parsers_64(data.as_slice());
+            println!("64-bit float: LE: {:?}, BE: {:?}", lef64, bef64);
+            println!("64-bit uint:  LE: {:?}, BE: {:?}", leu64, beu64);
+            println!("64-bit int:   LE: {:?}, BE: {:?}", lei64, bei64);
+        }
+        _ => scan(&data, &args),
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_strides() {
+        let gps = include_bytes!("../testdata/Gps_1752824363158.insgps");
+        let best = &guess_strides(gps)[0];
+        assert_eq!(best.stride, 53);
+        assert_eq!(best.records, 14915);
+        // Seconds, shared by the several records of each second.
+        assert_eq!(best.timestamp, Some((0, "u32 LE")));
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("0a0B ff").unwrap(), [0x0a, 0x0b, 0xff]);
+        assert_eq!(decode_hex("0x01:0x02-03,04").unwrap(), [1, 2, 3, 4]);
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_decode_escaped() {
+        assert_eq!(
+            decode_escaped(r#"b'\x01\xfFa\n\\\'"'"#).unwrap(),
+            [1, 0xff, b'a', b'\n', b'\\', b'\'', b'"']
+        );
+        assert_eq!(decode_escaped("é").unwrap(), "é".as_bytes());
+        assert_eq!(decode_escaped(r"\xg1").unwrap_err(), r"invalid escape \xg1");
+        assert_eq!(decode_escaped(r"\q").unwrap_err(), r"unknown escape \q");
+        assert_eq!(decode_escaped(r"\é").unwrap_err(), r"unknown escape \é");
+        assert_eq!(decode_escaped("a\\").unwrap_err(), "trailing backslash");
+    }
+
+    #[test]
+    fn test_decode_text() {
+        let decode = |text, format| decode_text(text, format).unwrap();
+        assert_eq!(decode(r"\x01\x02", InputFormat::Auto), [1, 2]);
+        assert_eq!(decode("0102", InputFormat::Auto), [1, 2]);
+        assert_eq!(decode("AQI=", InputFormat::Auto), [1, 2]);
+        assert_eq!(decode("AQ I=", InputFormat::Base64), [1, 2]);
+        assert_eq!(decode("0102", InputFormat::Raw), *b"0102");
+        assert!(decode_text("AQI=", InputFormat::Hex).is_err());
+        assert!(decode_text(r"\x01\q", InputFormat::Auto).is_err());
+    }
+}
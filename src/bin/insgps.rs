@@ -21,8 +21,8 @@ struct GpsRecord {
     altitude: f64, // Probably metres.
 }
 
-const NS: &[u8] = &[b'N', b'S'];
-const EW: &[u8] = &[b'E', b'W'];
+const NS: &[u8] = b"NS";
+const EW: &[u8] = b"EW";
 
 fn parse_gps_record(frame: &[u8]) -> IResult<&[u8], GpsRecord> {
     let timestamp = le_u32();
@@ -0,0 +1,318 @@
+//! A cache of the metadata at the end of each video: the trailer, the index frame and the Info
+//! frame. Reading them seeks to the end of the file and back to the index, which adds up on large
+//! archives and network storage; with an entry for the video's current size and modification
+//! time, the video isn't opened at all.
+
+use std::{
+    fs::{self, File},
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use log::debug;
+use nom::{
+    IResult, Parser,
+    bytes::complete::tag,
+    multi::length_data,
+    number::{le_u32, le_u64, le_u128},
+};
+use prost::Message;
+
+use crate::{
+    FrameType, HEADER_SIZE, IndexFrame, Trailer, backward::BackwardReader,
+    insvtools::frames::ExtraMetadata, read_index_tail,
+};
+
+const MAGIC: &[u8] = b"ginsta-idx 1\n";
+
+/// Where cache entries are kept.
+#[derive(Clone, Debug)]
+pub enum CacheLocation {
+    /// A `.ginsta.idx` file next to each video, such as `clip.insv.ginsta.idx`.
+    Sidecar,
+    /// A file per video in this directory, named after a hash of the video's path.
+    Dir(PathBuf),
+}
+
+impl CacheLocation {
+    /// `$XDG_CACHE_HOME/ginsta`, or `~/.cache/ginsta` without it.
+    pub fn user_cache() -> Option<Self> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(Self::Dir(cache_home.join("ginsta")))
+    }
+
+    /// The entry for `video`, an absolute path.
+    pub fn entry_path(&self, video: &Path) -> PathBuf {
+        match self {
+            Self::Sidecar => {
+                let mut name = video.as_os_str().to_owned();
+                name.push(".ginsta.idx");
+                PathBuf::from(name)
+            }
+            Self::Dir(dir) => {
+                let mut hasher = DefaultHasher::new();
+                video.hash(&mut hasher);
+                dir.join(format!("{:016x}.idx", hasher.finish()))
+            }
+        }
+    }
+}
+
+/// The metadata read from the end of a video.
+#[derive(Debug)]
+pub struct CachedIndex {
+    pub trailer: Trailer,
+    pub index: IndexFrame,
+    /// The first Info frame, if the index lists one that decodes.
+    pub info: Option<ExtraMetadata>,
+}
+
+/// The bytes an entry stores. `tail` is the index frame and trailer as they end the video, empty
+/// when it has no Insta360 metadata; `info` is the first Info frame.
+struct Entry {
+    tail: Vec<u8>,
+    info: Vec<u8>,
+}
+
+impl Entry {
+    /// Parses the entry of a video `len` bytes long.
+    fn parse(&self, len: u64) -> Option<CachedIndex> {
+        let (trailer, index) = read_index_tail(&self.tail, len)?;
+        let info = if self.info.is_empty() {
+            None
+        } else {
+            ExtraMetadata::decode(self.info.as_slice()).ok()
+        };
+        Some(CachedIndex {
+            trailer,
+            index,
+            info,
+        })
+    }
+}
+
+/// Identifies the version of a video an entry was made from.
+#[derive(PartialEq)]
+struct Key<'a> {
+    path: &'a [u8],
+    size: u64,
+    modified: u128, // Nanoseconds since the Unix epoch.
+}
+
+/// Reads the trailer, index frame and Info frame of the video at `path`, or `None` if it has no
+/// Insta360 metadata. They come from the cache when it has an entry for the video's current size
+/// and modification time; otherwise they're read from the video and stored. An entry that can't be
+/// stored is skipped, so a read-only location only loses the speed-up.
+pub fn read_index_cached(path: &Path, location: &CacheLocation) -> io::Result<Option<CachedIndex>> {
+    let video = fs::canonicalize(path)?;
+    let metadata = fs::metadata(&video)?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+    let Some(modified) = modified else {
+        return Ok(read_entry(File::open(&video)?, metadata.len())?.parse(metadata.len()));
+    };
+    let key = Key {
+        path: video.as_os_str().as_encoded_bytes(),
+        size: metadata.len(),
+        modified: modified.as_nanos(),
+    };
+
+    let entry_path = location.entry_path(&video);
+    if let Ok(stored) = fs::read(&entry_path)
+        && let Ok((_, (stored_key, entry))) = parse_stored(&stored)
+        && stored_key == key
+    {
+        debug!("Using cached index {}", entry_path.display());
+        return Ok(entry.parse(key.size));
+    }
+
+    let entry = read_entry(File::open(&video)?, key.size)?;
+    if let Err(err) = store(&entry_path, &key, &entry) {
+        debug!("Not caching index in {}: {err}", entry_path.display());
+    }
+    Ok(entry.parse(key.size))
+}
+
+/// Reads the index frame, trailer and first Info frame from the end of a video `len` bytes long.
+fn read_entry(file: File, len: u64) -> io::Result<Entry> {
+    let mut entry = Entry {
+        tail: Vec::new(),
+        info: Vec::new(),
+    };
+    let mut reader = BackwardReader::new(file, len);
+    let Some(tail) = reader.index_tail()? else {
+        return Ok(entry);
+    };
+    let Some((header, index)) = read_index_tail(tail, len) else {
+        return Ok(entry);
+    };
+    entry.tail = tail.to_vec();
+
+    let trailer_start = len - HEADER_SIZE as u64;
+    let metadata_start = len - header.metadata_size as u64;
+    let info = index
+        .frames
+        .iter()
+        .find(|frame| frame.frame_type == FrameType::Info && frame.frame_version == 1);
+    if let Some(info) = info {
+        let start = metadata_start + info.frame_offset as u64;
+        if start + info.frame_size as u64 <= trailer_start {
+            entry.info = reader.read_at(start, info.frame_size as usize)?.to_vec();
+        }
+    }
+    Ok(entry)
+}
+
+fn parse_stored(data: &[u8]) -> IResult<&[u8], (Key<'_>, Entry)> {
+    let mut parser = (
+        tag(MAGIC),
+        length_data(le_u32()),
+        le_u64(),
+        le_u128(),
+        length_data(le_u32()),
+        length_data(le_u32()),
+    );
+    let (rest, (_, path, size, modified, tail, info)) = parser.parse(data)?;
+    let key = Key {
+        path,
+        size,
+        modified,
+    };
+    let entry = Entry {
+        tail: tail.to_vec(),
+        info: info.to_vec(),
+    };
+    Ok((rest, (key, entry)))
+}
+
+/// Writes an entry through a temporary file, so a concurrent reader never sees half of it.
+fn store(entry_path: &Path, key: &Key, entry: &Entry) -> io::Result<()> {
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&(key.path.len() as u32).to_le_bytes());
+    data.extend_from_slice(key.path);
+    data.extend_from_slice(&key.size.to_le_bytes());
+    data.extend_from_slice(&key.modified.to_le_bytes());
+    for field in [&entry.tail, &entry.info] {
+        data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        data.extend_from_slice(field);
+    }
+
+    if let Some(dir) = entry_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temporary = entry_path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, data)?;
+    fs::rename(&temporary, entry_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::build_insv;
+
+    #[test]
+    fn test_read_index_cached() {
+        let dir = std::env::temp_dir().join(format!("ginsta-cache-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let video = dir.join("clip.insv");
+        let info = ExtraMetadata {
+            serial_number: Some("IXSE123".to_string()),
+            ..Default::default()
+        };
+        let mut data = build_insv(b"video", &[(FrameType::Info, 1, info.encode_to_vec())]);
+        fs::write(&video, &data).unwrap();
+
+        let cached = read_index_cached(&video, &CacheLocation::Sidecar)
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached.index.frames.len(), 1);
+        assert_eq!(
+            cached.info.unwrap().serial_number.as_deref(),
+            Some("IXSE123")
+        );
+        assert!(dir.join("clip.insv.ginsta.idx").exists());
+
+        // Same size and modification time: the entry is used without reading the video.
+        let modified = fs::metadata(&video).unwrap().modified().unwrap();
+        let len = data.len();
+        data[len - 1] ^= 0xff;
+        fs::write(&video, &data).unwrap();
+        File::options()
+            .write(true)
+            .open(&video)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(
+            read_index_cached(&video, &CacheLocation::Sidecar)
+                .unwrap()
+                .is_some()
+        );
+
+        // A changed file replaces the entry.
+        data.push(0);
+        fs::write(&video, &data).unwrap();
+        assert!(
+            read_index_cached(&video, &CacheLocation::Sidecar)
+                .unwrap()
+                .is_none()
+        );
+        let cache = CacheLocation::Dir(dir.join("cache"));
+        assert!(read_index_cached(&video, &cache).unwrap().is_none());
+        assert_eq!(fs::read_dir(dir.join("cache")).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_damaged_entries() {
+        let dir =
+            std::env::temp_dir().join(format!("ginsta-cache-damaged-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let video = dir.join("clip.insv");
+        let entry_path = dir.join("clip.insv.ginsta.idx");
+        fs::write(
+            &video,
+            build_insv(b"video", &[(FrameType::Gyro, 1, vec![0; 40])]),
+        )
+        .unwrap();
+        read_index_cached(&video, &CacheLocation::Sidecar).unwrap();
+        let stored = fs::read(&entry_path).unwrap();
+        assert!(parse_stored(&stored).is_ok());
+
+        // An entry cut short, or of another format, is read again from the video and replaced.
+        for damaged in [
+            &stored[..stored.len() - 1],
+            &stored[..MAGIC.len()],
+            b"ginsta-idx 0\n",
+        ] {
+            assert!(parse_stored(damaged).is_err());
+            fs::write(&entry_path, damaged).unwrap();
+            let cached = read_index_cached(&video, &CacheLocation::Sidecar)
+                .unwrap()
+                .unwrap();
+            assert_eq!(cached.index.frames.len(), 1);
+            assert_eq!(fs::read(&entry_path).unwrap(), stored);
+        }
+
+        // A trailer claiming more metadata than the video holds isn't read, or cached as read.
+        let mut oversized = fs::read(&video).unwrap();
+        let at = oversized.len() - HEADER_SIZE as usize + 38;
+        oversized[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&video, &oversized).unwrap();
+        assert!(
+            read_index_cached(&video, &CacheLocation::Sidecar)
+                .unwrap()
+                .is_none()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
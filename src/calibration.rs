@@ -0,0 +1,107 @@
+//! The lens offset string in the Info frame, the per-unit calibration that stitchers need to
+//! join the two fisheye images of a 360° camera.
+//!
+//! It reads `<lenses>_<lens>..._<width>_<height>[_<more>...]`, where each lens is six numbers
+//! separated by underscores: the centre of its image circle in pixels, the circle's radius, and
+//! the lens's yaw, pitch and roll in degrees. `width` and `height` are the dimensions of the
+//! image the numbers refer to; whatever follows them isn't known and is kept as it is.
+
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LensCalibration {
+    pub center_x: f64,
+    pub center_y: f64,
+    pub radius: f64,
+    pub yaw: f64,
+    pub pitch: f64,
+    pub roll: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LensOffset {
+    pub lenses: Vec<LensCalibration>,
+    pub width: u32,
+    pub height: u32,
+    /// Fields after the dimensions.
+    pub rest: Vec<String>,
+}
+
+impl FromStr for LensOffset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("not a lens offset: {s:?}");
+        let mut fields = s.trim().split('_');
+        let count: usize = fields
+            .next()
+            .and_then(|count| count.parse().ok())
+            .filter(|count| (1..=8).contains(count))
+            .ok_or_else(invalid)?;
+        let mut number = || -> Result<f64, String> {
+            fields
+                .next()
+                .and_then(|field| field.parse().ok())
+                .ok_or_else(invalid)
+        };
+        let lenses = (0..count)
+            .map(|_| {
+                Ok(LensCalibration {
+                    center_x: number()?,
+                    center_y: number()?,
+                    radius: number()?,
+                    yaw: number()?,
+                    pitch: number()?,
+                    roll: number()?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let mut dimension = || -> Result<u32, String> {
+            fields
+                .next()
+                .and_then(|field| field.parse().ok())
+                .ok_or_else(invalid)
+        };
+        Ok(LensOffset {
+            lenses,
+            width: dimension()?,
+            height: dimension()?,
+            rest: fields.map(str::to_string).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lens_offset() {
+        let offset: LensOffset = "2_1445.860_1530.820_1532.530_0.000_0.000_0.000_\
+                                  1446.520_4589.230_1532.620_0.340_-0.330_179.750_6080_3040_25"
+            .parse()
+            .unwrap();
+        assert_eq!(offset.lenses.len(), 2);
+        assert_eq!(
+            offset.lenses[1],
+            LensCalibration {
+                center_x: 1446.52,
+                center_y: 4589.23,
+                radius: 1532.62,
+                yaw: 0.34,
+                pitch: -0.33,
+                roll: 179.75,
+            }
+        );
+        assert_eq!((offset.width, offset.height), (6080, 3040));
+        assert_eq!(offset.rest, ["25"]);
+
+        assert!("2_1234_5678".parse::<LensOffset>().is_err());
+        assert!("".parse::<LensOffset>().is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! A catalog of an archive's clips: when and where each was shot, on which camera, and the
+//! statistics of its GPS track, for search tools and media managers to read instead of the clips.
+
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    Telemetry,
+    mp4::VideoTrack,
+    segment::split_at_gaps,
+    stats::{ElevationGain, TrackStats, track_stats},
+    track::BoundingBox,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct CatalogEntry {
+    pub file: String,
+    /// Unix time the recording started, in seconds.
+    pub capture_time: Option<u64>,
+    /// Length of the video, or of the GPS track for files without one, in seconds.
+    pub duration: Option<f64>,
+    pub serial: Option<String>,
+    /// Extent of the GPS track.
+    pub bounding_box: Option<BoundingBox>,
+    /// Statistics of the GPS track with gaps left out.
+    pub stats: TrackStats,
+}
+
+/// Describes one file from its telemetry and video track. GPS records more than `gap` seconds
+/// apart are treated as separate segments.
+pub fn catalog_entry(
+    file: &str,
+    telemetry: &Telemetry,
+    video: Option<&VideoTrack>,
+    gap: u64,
+) -> CatalogEntry {
+    let (first, last) = (telemetry.gps.first(), telemetry.gps.last());
+    let capture_time = video
+        .map(|video| video.creation_time)
+        .or(first.map(|record| record.timestamp));
+    let duration = video
+        .filter(|video| video.timescale > 0)
+        .map(|video| video.duration as f64 / video.timescale as f64)
+        .or(first
+            .zip(last)
+            .map(|(first, last)| last.timestamp.saturating_sub(first.timestamp) as f64));
+    #[cfg(feature = "prost")]
+    let serial = telemetry
+        .info
+        .as_ref()
+        .and_then(|info| info.serial_number.clone());
+    #[cfg(not(feature = "prost"))]
+    let serial = None;
+    CatalogEntry {
+        file: file.to_string(),
+        capture_time,
+        duration,
+        serial,
+        bounding_box: BoundingBox::of(&telemetry.gps),
+        stats: track_stats(&split_at_gaps(&telemetry.gps, gap), ElevationGain::Raw),
+    }
+}
+
+/// Adds `entries` to the `clips` table of the SQLite database at `path`, creating either as
+/// needed and replacing earlier entries for the same files.
+#[cfg(feature = "sqlite")]
+pub fn write_sqlite(path: &Path, entries: &[CatalogEntry]) -> rusqlite::Result<()> {
+    let mut connection = rusqlite::Connection::open(path)?;
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS clips (
+            file TEXT PRIMARY KEY,
+            capture_time INTEGER,
+            duration REAL,
+            serial TEXT,
+            min_latitude REAL,
+            min_longitude REAL,
+            max_latitude REAL,
+            max_longitude REAL,
+            track_duration REAL NOT NULL,
+            distance REAL NOT NULL,
+            average_speed REAL NOT NULL,
+            max_speed REAL NOT NULL,
+            ascent REAL NOT NULL,
+            descent REAL NOT NULL,
+            pace_min_per_km REAL,
+            pace_min_per_mi REAL,
+            grade_percent REAL,
+            max_grade_percent REAL,
+            grade_adjusted_pace_min_per_km REAL
+        )",
+    )?;
+    let transaction = connection.transaction()?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT OR REPLACE INTO clips VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+        )?;
+        for entry in entries {
+            let bounds = entry.bounding_box;
+            let stats = &entry.stats;
+            insert.execute(rusqlite::params![
+                entry.file,
+                entry.capture_time.map(|time| time as i64),
+                entry.duration,
+                entry.serial,
+                bounds.map(|b| b.min_latitude),
+                bounds.map(|b| b.min_longitude),
+                bounds.map(|b| b.max_latitude),
+                bounds.map(|b| b.max_longitude),
+                stats.duration.0,
+                stats.distance.0,
+                stats.average_speed.0,
+                stats.max_speed.0,
+                stats.ascent.0,
+                stats.descent.0,
+                stats.pace_min_per_km,
+                stats.pace_min_per_mi,
+                stats.grade_percent,
+                stats.max_grade_percent,
+                stats.grade_adjusted_pace_min_per_km,
+            ])?;
+        }
+    }
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        GpsRecord,
+        units::{Degrees, Meters, MetersPerSecond},
+    };
+
+    fn telemetry() -> Telemetry {
+        Telemetry {
+            gps: (0..3)
+                .map(|i| GpsRecord {
+                    timestamp: 1000 + i,
+                    latitude: Degrees(49.0 + i as f64 * 0.001),
+                    longitude: Degrees(4.0 - i as f64 * 0.001),
+                    speed: MetersPerSecond(0.0),
+                    track: Degrees(0.0),
+                    altitude: Meters(0.0),
+                    fix: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_catalog_entry() {
+        let entry = catalog_entry("clip.insv", &telemetry(), None, 5);
+        assert_eq!(entry.capture_time, Some(1000));
+        assert_eq!(entry.duration, Some(2.0));
+        let bounds = entry.bounding_box.unwrap();
+        assert_eq!((bounds.min_latitude, bounds.max_longitude), (49.0, 4.0));
+        assert!(entry.stats.distance.0 > 200.0);
+
+        let video = VideoTrack {
+            creation_time: 999,
+            timescale: 1000,
+            duration: 3500,
+            presentation_times: Vec::new(),
+        };
+        let entry = catalog_entry("clip.insv", &telemetry(), Some(&video), 5);
+        assert_eq!((entry.capture_time, entry.duration), (Some(999), Some(3.5)));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_write_sqlite() {
+        let path =
+            std::env::temp_dir().join(format!("ginsta-catalog-{}.sqlite", std::process::id()));
+        let entry = catalog_entry("clip.insv", &telemetry(), None, 5);
+        write_sqlite(&path, std::slice::from_ref(&entry)).unwrap();
+        write_sqlite(&path, &[entry]).unwrap();
+
+        let connection = rusqlite::Connection::open(&path).unwrap();
+        let (count, capture_time): (i64, i64) = connection
+            .query_row("SELECT COUNT(*), MAX(capture_time) FROM clips", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((count, capture_time), (1, 1000));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,620 @@
+//! A compact binary format for passing telemetry between ginsta invocations and other tools,
+//! without the size and parsing cost of CSV or JSON on long IMU streams.
+//!
+//! A file is a sequence of tables. Each table starts with a header, all integers little endian:
+//!
+//! | Size     | Content                                                      |
+//! |----------|--------------------------------------------------------------|
+//! | 8        | Magic `GINSTAB` followed by the format version, currently 1 |
+//! | 1 + n    | Table name: length, then UTF-8                               |
+//! | 2        | Number of columns                                            |
+//! | 8        | Number of rows                                               |
+//! | per column | Column name as length then UTF-8, then a type byte         |
+//!
+//! The type byte is 0 for `u64`, 1 for `f64` and 2 for `i16`. The header is followed by each
+//! column's values in turn, so a reader can map a column straight into an array.
+//!
+//! ginsta writes three tables: `gps`, with the fields of `GpsRecord`; `gyro`, with the camera
+//! clock in milliseconds and the six raw IMU axes as counts; and `imu_scale`, one row with the
+//! `gyro_range_dps` and `accel_range_g` that convert those counts to physical units.
+//!
+//! `GpsColumns` and `GyroColumns` hold the first two in memory, a `Vec` per column, and
+//! `read_telemetry_columns` decodes a file's frames straight into them for tools that want arrays
+//! rather than records.
+
+use nom::{
+    IResult, Parser,
+    bytes::tag,
+    combinator::map_res,
+    multi::{count, length_data},
+    number::{le_f64, le_i16, le_u8, le_u16, le_u64},
+};
+
+#[cfg(feature = "prost")]
+use crate::insvtools::frames::{ExtraMetadata, extra_metadata::GyroConfigInfo};
+use crate::{
+    FrameType, GPS_RECORD_SIZE, GYRO_RECORD_SIZE, GpsRecord, GyroRecord, Telemetry,
+    gps_record_refs, gyro_record_refs,
+    imu::ImuScale,
+    overlapping_entries, read_index,
+    units::{Degrees, Meters, MetersPerSecond},
+};
+
+pub const MAGIC: &[u8; 8] = b"GINSTAB\x01";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnData {
+    U64(Vec<u64>),
+    F64(Vec<f64>),
+    I16(Vec<i16>),
+}
+
+impl ColumnData {
+    fn len(&self) -> usize {
+        match self {
+            ColumnData::U64(values) => values.len(),
+            ColumnData::F64(values) => values.len(),
+            ColumnData::I16(values) => values.len(),
+        }
+    }
+
+    fn type_byte(&self) -> u8 {
+        match self {
+            ColumnData::U64(_) => 0,
+            ColumnData::F64(_) => 1,
+            ColumnData::I16(_) => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub data: ColumnData,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+}
+
+impl Table {
+    fn new(name: &str, columns: Vec<(&str, ColumnData)>) -> Self {
+        Table {
+            name: name.to_string(),
+            columns: (columns.into_iter())
+                .map(|(name, data)| Column {
+                    name: name.to_string(),
+                    data,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.columns.first().map_or(0, |column| column.data.len())
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnData> {
+        (self.columns.iter())
+            .find(|column| column.name == name)
+            .map(|column| &column.data)
+    }
+
+    /// Appends the table to `out`. All columns must have the same length.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let rows = self.rows();
+        debug_assert!(self.columns.iter().all(|c| c.data.len() == rows));
+        out.extend_from_slice(MAGIC);
+        write_name(out, &self.name);
+        out.extend_from_slice(&(self.columns.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(rows as u64).to_le_bytes());
+        for column in &self.columns {
+            write_name(out, &column.name);
+            out.push(column.data.type_byte());
+        }
+        for column in &self.columns {
+            match &column.data {
+                ColumnData::U64(values) => values
+                    .iter()
+                    .for_each(|v| out.extend_from_slice(&v.to_le_bytes())),
+                ColumnData::F64(values) => values
+                    .iter()
+                    .for_each(|v| out.extend_from_slice(&v.to_le_bytes())),
+                ColumnData::I16(values) => values
+                    .iter()
+                    .for_each(|v| out.extend_from_slice(&v.to_le_bytes())),
+            }
+        }
+    }
+}
+
+/// Names longer than 255 bytes are cut short.
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    let name = &name.as_bytes()[..name.len().min(u8::MAX as usize)];
+    out.push(name.len() as u8);
+    out.extend_from_slice(name);
+}
+
+fn parse_name(input: &[u8]) -> IResult<&[u8], String> {
+    map_res(length_data(le_u8()), |name: &[u8]| {
+        String::from_utf8(name.to_vec())
+    })
+    .parse(input)
+}
+
+fn parse_column_data(input: &[u8], type_byte: u8, rows: usize) -> IResult<&[u8], ColumnData> {
+    match type_byte {
+        0 => count(le_u64(), rows).map(ColumnData::U64).parse(input),
+        1 => count(le_f64(), rows).map(ColumnData::F64).parse(input),
+        2 => count(le_i16(), rows).map(ColumnData::I16).parse(input),
+        _ => Err(error(input, nom::error::ErrorKind::Switch)),
+    }
+}
+
+fn error(input: &[u8], kind: nom::error::ErrorKind) -> nom::Err<nom::error::Error<&[u8]>> {
+    nom::Err::Error(nom::error::Error::new(input, kind))
+}
+
+pub fn parse_table(input: &[u8]) -> IResult<&[u8], Table> {
+    let (input, _) = tag(&MAGIC[..]).parse(input)?;
+    let (input, name) = parse_name(input)?;
+    let (input, column_count) = le_u16().parse(input)?;
+    let (input, rows) = le_u64().parse(input)?;
+    let (mut input, header) = count((parse_name, le_u8()), column_count as usize).parse(input)?;
+    // Refuse row counts the remaining input can't hold before allocating for them.
+    let row_size: u64 = (header.iter())
+        .map(|(_, type_byte)| if *type_byte == 2 { 2 } else { 8 })
+        .sum();
+    if rows.saturating_mul(row_size) > input.len() as u64 {
+        return Err(error(input, nom::error::ErrorKind::Eof));
+    }
+    let mut columns = Vec::with_capacity(header.len());
+    for (name, type_byte) in header {
+        let (rest, data) = parse_column_data(input, type_byte, rows as usize)?;
+        columns.push(Column { name, data });
+        input = rest;
+    }
+    Ok((input, Table { name, columns }))
+}
+
+/// Reads every table in `input`, or `None` if anything in it isn't one.
+pub fn read_tables(mut input: &[u8]) -> Option<Vec<Table>> {
+    let mut tables = Vec::new();
+    while !input.is_empty() {
+        let (rest, table) = parse_table(input).ok()?;
+        tables.push(table);
+        input = rest;
+    }
+    Some(tables)
+}
+
+/// GPS records as a column per field, the fields of `GpsRecord` in its units.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GpsColumns {
+    pub timestamp: Vec<u64>,
+    pub latitude: Vec<f64>,
+    pub longitude: Vec<f64>,
+    pub speed: Vec<f64>,
+    pub track: Vec<f64>,
+    pub altitude: Vec<f64>,
+}
+
+impl GpsColumns {
+    pub fn len(&self) -> usize {
+        self.timestamp.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamp.is_empty()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.timestamp.reserve(additional);
+        for column in [
+            &mut self.latitude,
+            &mut self.longitude,
+            &mut self.speed,
+            &mut self.track,
+            &mut self.altitude,
+        ] {
+            column.reserve(additional);
+        }
+    }
+
+    pub fn push(&mut self, record: &GpsRecord) {
+        self.timestamp.push(record.timestamp);
+        self.latitude.push(record.latitude.0);
+        self.longitude.push(record.longitude.0);
+        self.speed.push(record.speed.0);
+        self.track.push(record.track.0);
+        self.altitude.push(record.altitude.0);
+    }
+
+    /// Decodes the records of a GPS frame onto the columns, as `parse_gps_frame` would decode
+    /// them, returning the undecoded rest of the frame.
+    pub fn extend_from_frame<'a>(&mut self, frame: &'a [u8]) -> &'a [u8] {
+        self.reserve(frame.len() / GPS_RECORD_SIZE);
+        let mut decoded = 0;
+        for record in gps_record_refs(frame) {
+            self.timestamp.push(record.timestamp());
+            self.latitude.push(record.latitude().0);
+            self.longitude.push(record.longitude().0);
+            self.speed.push(record.speed().0);
+            self.track.push(record.track().0);
+            self.altitude.push(record.altitude().0);
+            decoded += 1;
+        }
+        &frame[decoded * GPS_RECORD_SIZE..]
+    }
+
+    /// The `gps` table `telemetry_tables` writes.
+    pub fn into_table(self) -> Table {
+        Table::new(
+            "gps",
+            vec![
+                ("timestamp", ColumnData::U64(self.timestamp)),
+                ("latitude", ColumnData::F64(self.latitude)),
+                ("longitude", ColumnData::F64(self.longitude)),
+                ("speed", ColumnData::F64(self.speed)),
+                ("track", ColumnData::F64(self.track)),
+                ("altitude", ColumnData::F64(self.altitude)),
+            ],
+        )
+    }
+}
+
+/// Gyro records as a column per field: the camera clock in milliseconds and the six raw IMU axes
+/// as counts, which `ImuScale` converts.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GyroColumns {
+    pub timestamp: Vec<u64>,
+    pub accel_x: Vec<i16>,
+    pub accel_y: Vec<i16>,
+    pub accel_z: Vec<i16>,
+    pub gyro_x: Vec<i16>,
+    pub gyro_y: Vec<i16>,
+    pub gyro_z: Vec<i16>,
+}
+
+impl GyroColumns {
+    pub fn len(&self) -> usize {
+        self.timestamp.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamp.is_empty()
+    }
+
+    /// The axis columns in the order of the record payload.
+    fn axes_mut(&mut self) -> [&mut Vec<i16>; 6] {
+        [
+            &mut self.accel_x,
+            &mut self.accel_y,
+            &mut self.accel_z,
+            &mut self.gyro_x,
+            &mut self.gyro_y,
+            &mut self.gyro_z,
+        ]
+    }
+
+    fn push_axes(&mut self, timestamp: u64, accel: [i16; 3], gyro: [i16; 3]) {
+        self.timestamp.push(timestamp);
+        for (column, value) in self
+            .axes_mut()
+            .into_iter()
+            .zip(accel.into_iter().chain(gyro))
+        {
+            column.push(value);
+        }
+    }
+
+    pub fn push(&mut self, record: &GyroRecord) {
+        self.push_axes(record.timestamp, record.accel_raw(), record.gyro_raw());
+    }
+
+    /// Decodes the records of a gyro frame onto the columns, as `parse_gyro_frame` would decode
+    /// them, returning the undecoded rest of the frame.
+    pub fn extend_from_frame<'a>(&mut self, frame: &'a [u8]) -> &'a [u8] {
+        let records = frame.len() / GYRO_RECORD_SIZE;
+        self.timestamp.reserve(records);
+        for column in self.axes_mut() {
+            column.reserve(records);
+        }
+        for record in gyro_record_refs(frame) {
+            self.push_axes(record.timestamp(), record.accel_raw(), record.gyro_raw());
+        }
+        &frame[records * GYRO_RECORD_SIZE..]
+    }
+
+    /// The `gyro` table `telemetry_tables` writes.
+    pub fn into_table(self) -> Table {
+        Table::new(
+            "gyro",
+            vec![
+                ("timestamp", ColumnData::U64(self.timestamp)),
+                ("accel_x", ColumnData::I16(self.accel_x)),
+                ("accel_y", ColumnData::I16(self.accel_y)),
+                ("accel_z", ColumnData::I16(self.accel_z)),
+                ("gyro_x", ColumnData::I16(self.gyro_x)),
+                ("gyro_y", ColumnData::I16(self.gyro_y)),
+                ("gyro_z", ColumnData::I16(self.gyro_z)),
+            ],
+        )
+    }
+}
+
+/// The GPS and gyro records of a file's metadata decoded straight into columns, for handing to
+/// columnar tools without building a record for each first. Frames are read as `read_telemetry`
+/// reads them, skipping those it would skip, but without its diagnostics. `None` if `data` has
+/// no Insta360 metadata.
+pub fn read_telemetry_columns(data: &[u8]) -> Option<(GpsColumns, GyroColumns)> {
+    let (trailer, index) = read_index(data)?;
+    let metadata_start = data.len() - trailer.metadata_size as usize;
+    let overlaps = overlapping_entries(&index, trailer.index_frame_offset());
+    let (mut gps, mut gyro) = (GpsColumns::default(), GyroColumns::default());
+    for (entry, frame) in index.frames.iter().enumerate() {
+        if overlaps.iter().any(|overlap| overlap.entry == entry) {
+            continue;
+        }
+        let Some(bytes) = (metadata_start.checked_add(frame.frame_offset as usize))
+            .and_then(|start| data.get(start..start.checked_add(frame.frame_size as usize)?))
+        else {
+            continue;
+        };
+        match frame.frame_type {
+            FrameType::Gps => {
+                gps.extend_from_frame(bytes);
+            }
+            FrameType::Gyro => {
+                gyro.extend_from_frame(bytes);
+            }
+            _ => {}
+        }
+    }
+    Some((gps, gyro))
+}
+
+/// The `gps`, `gyro` and `imu_scale` tables for a file's records.
+pub fn telemetry_tables(gps: &[GpsRecord], gyro: &[GyroRecord], scale: ImuScale) -> Vec<Table> {
+    let mut gps_columns = GpsColumns::default();
+    gps_columns.reserve(gps.len());
+    gps.iter().for_each(|record| gps_columns.push(record));
+    let mut gyro_columns = GyroColumns::default();
+    gyro.iter().for_each(|record| gyro_columns.push(record));
+    vec![
+        gps_columns.into_table(),
+        gyro_columns.into_table(),
+        Table::new(
+            "imu_scale",
+            vec![
+                (
+                    "gyro_range_dps",
+                    ColumnData::F64(vec![scale.gyro_range_dps]),
+                ),
+                ("accel_range_g", ColumnData::F64(vec![scale.accel_range_g])),
+            ],
+        ),
+    ]
+}
+
+/// Telemetry read back from the tables `telemetry_tables` writes.
+#[derive(Debug, Default)]
+pub struct TableTelemetry {
+    pub gps: Vec<GpsRecord>,
+    pub gyro: Vec<GyroRecord>,
+    pub scale: Option<ImuScale>,
+}
+
+impl TableTelemetry {
+    /// The records as `Telemetry`, with the IMU scale carried in an Info frame holding only the
+    /// sensor ranges, so it converts the counts as the original file's did.
+    pub fn into_telemetry(self) -> Telemetry {
+        Telemetry {
+            #[cfg(feature = "prost")]
+            info: self.scale.map(|scale| ExtraMetadata {
+                gyro_cfg_info: Some(GyroConfigInfo {
+                    acc_range: Some(scale.accel_range_g as i64),
+                    gyro_range: Some(scale.gyro_range_dps as i64),
+                }),
+                ..Default::default()
+            }),
+            gps: self.gps,
+            gyro: self.gyro,
+            ..Default::default()
+        }
+    }
+}
+
+/// Collects the records of every `gps`, `gyro` and `imu_scale` table, skipping tables with other
+/// names or missing columns.
+pub fn read_telemetry_tables(tables: &[Table]) -> TableTelemetry {
+    let mut telemetry = TableTelemetry::default();
+    for table in tables {
+        let u64_column = |name| match table.column(name) {
+            Some(ColumnData::U64(values)) => Some(values),
+            _ => None,
+        };
+        let f64_column = |name| match table.column(name) {
+            Some(ColumnData::F64(values)) => Some(values),
+            _ => None,
+        };
+        let i16_column = |name| match table.column(name) {
+            Some(ColumnData::I16(values)) => Some(values),
+            _ => None,
+        };
+        match table.name.as_str() {
+            "gps" => {
+                let columns =
+                    ["latitude", "longitude", "speed", "track", "altitude"].map(f64_column);
+                let (Some(timestamps), [Some(lat), Some(lon), Some(speed), Some(track), Some(alt)]) =
+                    (u64_column("timestamp"), columns)
+                else {
+                    continue;
+                };
+                telemetry.gps.extend((0..table.rows()).map(|i| GpsRecord {
+                    timestamp: timestamps[i],
+                    latitude: Degrees(lat[i]),
+                    longitude: Degrees(lon[i]),
+                    speed: MetersPerSecond(speed[i]),
+                    track: Degrees(track[i]),
+                    altitude: Meters(alt[i]),
+                    fix: None,
+                }));
+            }
+            "gyro" => {
+                let axes = [
+                    "accel_x", "accel_y", "accel_z", "gyro_x", "gyro_y", "gyro_z",
+                ]
+                .map(i16_column);
+                let (Some(timestamps), true) =
+                    (u64_column("timestamp"), axes.iter().all(Option::is_some))
+                else {
+                    continue;
+                };
+                telemetry.gyro.extend((0..table.rows()).map(|i| {
+                    let axes = core::array::from_fn(|axis| axes[axis].as_ref().unwrap()[i]);
+                    GyroRecord::from_raw(timestamps[i], axes)
+                }));
+            }
+            "imu_scale" => {
+                if let (Some([gyro_range_dps]), Some([accel_range_g])) = (
+                    f64_column("gyro_range_dps").map(Vec::as_slice),
+                    f64_column("accel_range_g").map(Vec::as_slice),
+                ) {
+                    telemetry.scale = Some(ImuScale {
+                        gyro_range_dps: *gyro_range_dps,
+                        accel_range_g: *accel_range_g,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    telemetry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::build_insv;
+
+    #[test]
+    fn test_round_trip() {
+        let gps = vec![GpsRecord {
+            timestamp: 1_700_000_000,
+            latitude: Degrees(49.1),
+            longitude: Degrees(-4.2),
+            speed: MetersPerSecond(3.5),
+            track: Degrees(270.0),
+            altitude: Meters(12.25),
+            fix: None,
+        }];
+        let gyro = vec![GyroRecord::from_raw(123, [1, -2, 3, -400, 500, -32768])];
+        let scale = ImuScale {
+            gyro_range_dps: 1000.0,
+            accel_range_g: 8.0,
+        };
+        let mut out = Vec::new();
+        for table in telemetry_tables(&gps, &gyro, scale) {
+            table.write(&mut out);
+        }
+        assert!(out.starts_with(MAGIC));
+        // Header of 8 + 4 + 2 + 8 bytes, column names and types, then 8 bytes per value.
+        let gps_size = 22 + [9, 8, 9, 5, 5, 8].iter().map(|n| n + 2).sum::<usize>() + 6 * 8;
+        assert_eq!(&out[gps_size..gps_size + 8], MAGIC);
+
+        let tables = read_tables(&out).unwrap();
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[1].rows(), 1);
+        let read = read_telemetry_tables(&tables);
+        let record = &read.gps[0];
+        assert_eq!(
+            (record.timestamp, record.latitude, record.altitude),
+            (1_700_000_000, Degrees(49.1), Meters(12.25))
+        );
+        assert_eq!(
+            (record.longitude, record.speed, record.track),
+            (gps[0].longitude, gps[0].speed, gps[0].track)
+        );
+        assert_eq!(read.gyro[0].payload, gyro[0].payload);
+        assert_eq!(read.scale, Some(scale));
+        #[cfg(feature = "prost")]
+        assert_eq!(
+            ImuScale::from_info(read.into_telemetry().info.as_ref()),
+            scale
+        );
+
+        assert!(read_tables(&out[..out.len() - 1]).is_none());
+        assert!(read_tables(b"GINSTAB\x02").is_none());
+    }
+
+    #[test]
+    fn test_malformed_tables() {
+        let table = Table::new(
+            "t",
+            vec![
+                ("a", ColumnData::U64(vec![1, 2])),
+                ("b", ColumnData::I16(vec![3, 4])),
+            ],
+        );
+        let mut out = Vec::new();
+        table.write(&mut out);
+        assert_eq!(read_tables(&out), Some(vec![table]));
+        assert_eq!(read_tables(&[]), Some(Vec::new()));
+
+        // Any table cut short, and anything after the last table, is rejected.
+        for len in 1..out.len() {
+            assert_eq!(read_tables(&out[..len]), None, "{len} bytes");
+        }
+        assert_eq!(read_tables(&[&out[..], b"GINSTAB"].concat()), None);
+
+        let with = |at: usize, bytes: &[u8]| {
+            let mut data = out.clone();
+            data[at..at + bytes.len()].copy_from_slice(bytes);
+            data
+        };
+        // Another format version.
+        assert!(parse_table(&with(7, &[2])).is_err());
+        // A name that isn't UTF-8.
+        assert!(parse_table(&with(9, &[0xff])).is_err());
+        // An unknown column type.
+        assert!(parse_table(&with(22, &[3])).is_err());
+        // A row count the input can't hold is refused before anything is allocated for it.
+        assert!(parse_table(&with(12, &u64::MAX.to_le_bytes())).is_err());
+        assert!(parse_table(&with(12, &3u64.to_le_bytes())).is_err());
+    }
+
+    #[test]
+    fn test_read_telemetry_columns() {
+        let gps = include_bytes!("testdata/Gps_1752824363158.insgps");
+        let gyro: Vec<u8> = (0..50u64)
+            .flat_map(|i| {
+                let axes = [1i16, -2, 3, -4, 5, -6].map(|axis| axis * i as i16);
+                [&i.to_le_bytes()[..], &axes.map(i16::to_le_bytes).concat()].concat()
+            })
+            .collect();
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Gps, 1, gps[..100 * GPS_RECORD_SIZE].to_vec()),
+                (FrameType::Gyro, 1, gyro),
+                (FrameType::Gps, 1, gps[100 * GPS_RECORD_SIZE..].to_vec()),
+            ],
+        );
+        let (gps_columns, gyro_columns) = read_telemetry_columns(&data).unwrap();
+        let telemetry = crate::read_telemetry(&data);
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        assert_eq!(gps_columns.len(), telemetry.gps.len());
+        assert_eq!(gyro_columns.len(), 50);
+        assert_eq!(
+            [gps_columns.into_table(), gyro_columns.into_table()],
+            telemetry_tables(&telemetry.gps, &telemetry.gyro, scale)[..2]
+        );
+        assert_eq!(read_telemetry_columns(b"video"), None);
+    }
+}
@@ -0,0 +1,110 @@
+//! Compressed outputs, as `--compress` writes them or an output's `.gz` or `.zst` extension
+//! implies.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+
+/// How an output is compressed.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The compression the extension of `path` stands for.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// Wraps `out` in an encoder that ends the compressed stream when dropped.
+    pub fn encoder(self, out: impl Write + 'static) -> io::Result<Box<dyn Write>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(GzEncoder::new(out, flate2::Compression::default())),
+            Compression::Zstd => Box::new(zstd::Encoder::new(out, 0)?.auto_finish()),
+        })
+    }
+}
+
+/// The compression of an output: `compress` if given, otherwise whatever the extension of
+/// `output` implies.
+pub fn output_compression(
+    compress: Option<Compression>,
+    output: Option<&Path>,
+) -> Option<Compression> {
+    compress.or(output.and_then(Compression::from_path))
+}
+
+/// Creates the file at `path`, compressing what's written to it with `compression`.
+pub fn create_output(path: &Path, compression: Option<Compression>) -> io::Result<Box<dyn Write>> {
+    let file = File::create(path)?;
+    Ok(match compression {
+        Some(compression) => Box::new(BufWriter::new(compression.encoder(file)?)),
+        None => Box::new(BufWriter::new(file)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read};
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    #[test]
+    fn test_output_compression() {
+        let compression = |output: &str| output_compression(None, Some(Path::new(output)));
+        assert_eq!(compression("trips.gpx.gz"), Some(Compression::Gzip));
+        assert_eq!(compression("out.csv.zst"), Some(Compression::Zstd));
+        assert_eq!(compression("out.csv"), None);
+        assert_eq!(compression("gz"), None);
+        assert_eq!(output_compression(None, None), None);
+        // An explicit choice wins over the extension, and applies to standard output too.
+        let zstd = Some(Compression::Zstd);
+        assert_eq!(output_compression(zstd, Some(Path::new("out.gz"))), zstd);
+        assert_eq!(output_compression(zstd, None), zstd);
+    }
+
+    #[test]
+    fn test_create_output() {
+        let dir =
+            std::env::temp_dir().join(format!("ginsta-compression-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str| {
+            let path = dir.join(name);
+            let mut out = create_output(&path, Compression::from_path(&path)).unwrap();
+            out.write_all(b"time,speed\n").unwrap();
+            drop(out);
+            fs::read(path).unwrap()
+        };
+
+        assert_eq!(write("plain.csv"), b"time,speed\n");
+        let mut gzip = String::new();
+        GzDecoder::new(&write("out.csv.gz")[..])
+            .read_to_string(&mut gzip)
+            .unwrap();
+        assert_eq!(gzip, "time,speed\n");
+        let zstd = zstd::decode_all(&write("out.csv.zst")[..]).unwrap();
+        assert_eq!(zstd, b"time,speed\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! Defaults for command line options from a TOML config file.
+
+use std::path::PathBuf;
+
+use clap::{ArgAction, ArgMatches, parser::ValueSource};
+
+/// `$XDG_CONFIG_HOME/ginsta/config.toml`, falling back to `~/.config`.
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("ginsta").join("config.toml"))
+}
+
+/// Arguments supplying config file settings for options not given on the command line.
+///
+/// Keys are long option names. Top-level keys apply to every command with that option; keys in
+/// a table named after a command, such as `[highlights]`, only to that command. The command
+/// without a subcommand reads the `[export]` table.
+pub fn config_args(
+    config: &toml::Table,
+    cli: &clap::Command,
+    name: &str,
+    matches: &ArgMatches,
+) -> Result<Vec<String>, String> {
+    let command = cli.find_subcommand(name).unwrap_or(cli);
+    let mut settings = Vec::new();
+    for (key, value) in config {
+        match value {
+            toml::Value::Table(_) if cli.find_subcommand(key).is_none() => {
+                return Err(format!("unknown command [{key}] in config"));
+            }
+            toml::Value::Table(_) => {}
+            _ => settings.push((key, value, false)),
+        }
+    }
+    // `watch` runs exports, so takes the export options too.
+    let tables: &[&str] = match name {
+        "watch" => &["export", "watch"],
+        name => &[name],
+    };
+    for table in tables {
+        if let Some(toml::Value::Table(table)) = config.get(*table) {
+            settings.extend(table.iter().map(|(key, value)| (key, value, true)));
+        }
+    }
+
+    let mut args = Vec::new();
+    for (key, value, required) in settings {
+        let long = key.replace('_', "-");
+        let find = |command: &clap::Command| {
+            command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(long.as_str()))
+                .cloned()
+        };
+        let Some(arg) = find(command) else {
+            if required
+                || !cli
+                    .get_subcommands()
+                    .chain([cli])
+                    .any(|c| find(c).is_some())
+            {
+                return Err(format!("unknown option `{key}` in config"));
+            }
+            continue;
+        };
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            match (arg.get_action(), value) {
+                (ArgAction::SetTrue, toml::Value::Boolean(true)) => args.push(format!("--{long}")),
+                (ArgAction::SetTrue, toml::Value::Boolean(false)) => {}
+                (_, toml::Value::String(value)) => args.push(format!("--{long}={value}")),
+                (_, value) => args.push(format!("--{long}={value}")),
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{Arg, Command};
+
+    use super::*;
+
+    fn command() -> Command {
+        let format = Arg::new("format").long("format");
+        let sidecar = Arg::new("sidecar")
+            .long("sidecar")
+            .action(ArgAction::SetTrue);
+        Command::new("ginsta")
+            .args_override_self(true)
+            .arg(format.clone())
+            .arg(sidecar.clone())
+            .subcommand(Command::new("export").arg(format.clone()).arg(sidecar))
+            .subcommand(
+                Command::new("highlights")
+                    .arg(format.clone())
+                    .arg(Arg::new("min-speed").long("min-speed")),
+            )
+            .subcommand(
+                Command::new("watch")
+                    .arg(format)
+                    .arg(Arg::new("settle").long("settle")),
+            )
+    }
+
+    /// The matches for `cli` with the settings of `config` it doesn't give.
+    fn resolve(cli: &[&str], config: &str) -> Result<ArgMatches, String> {
+        let command = command();
+        let matches = command.clone().get_matches_from(cli);
+        let (name, sub_matches) = matches.subcommand().unwrap_or(("export", &matches));
+        let extra = config_args(&config.parse().unwrap(), &command, name, sub_matches)?;
+        let args = cli.iter().map(|arg| arg.to_string()).chain(extra);
+        Ok(command.get_matches_from(args))
+    }
+
+    fn value<'a>(matches: &'a ArgMatches, id: &str) -> Option<&'a str> {
+        let matches = matches.subcommand().map_or(matches, |(_, matches)| matches);
+        matches.get_one::<String>(id).map(String::as_str)
+    }
+
+    #[test]
+    fn test_config_args() {
+        let config = r#"
+            format = "gpx"
+            sidecar = true
+            min_speed = 3
+
+            [highlights]
+            format = "json"
+
+            [watch]
+            settle = 9
+        "#;
+        // A command's table beats the top level, and the command line beats both.
+        let export = resolve(&["ginsta"], config).unwrap();
+        assert_eq!(value(&export, "format"), Some("gpx"));
+        assert!(export.get_flag("sidecar"));
+        let highlights = resolve(&["ginsta", "highlights"], config).unwrap();
+        assert_eq!(value(&highlights, "format"), Some("json"));
+        assert_eq!(value(&highlights, "min-speed"), Some("3"));
+        let given = resolve(&["ginsta", "highlights", "--format=csv"], config).unwrap();
+        assert_eq!(value(&given, "format"), Some("csv"));
+
+        // `watch` takes the `[export]` table, then its own.
+        let config = "[export]\nformat = \"srt\"\n[watch]\nsettle = 9\n";
+        let watch = resolve(&["ginsta", "watch"], config).unwrap();
+        assert_eq!(value(&watch, "format"), Some("srt"));
+        assert_eq!(value(&watch, "settle"), Some("9"));
+
+        // Only the top level may hold options some other command takes.
+        assert_eq!(
+            resolve(&["ginsta"], "[export]\nmin_speed = 3\n").unwrap_err(),
+            "unknown option `min_speed` in config"
+        );
+        assert_eq!(
+            resolve(&["ginsta"], "bogus = 1\n").unwrap_err(),
+            "unknown option `bogus` in config"
+        );
+        assert_eq!(
+            resolve(&["ginsta"], "[bogus]\n").unwrap_err(),
+            "unknown command [bogus] in config"
+        );
+    }
+}
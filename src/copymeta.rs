@@ -0,0 +1,196 @@
+//! Copying Insta360 metadata onto another file, such as a re-encode or remux of the footage that
+//! lost the trailer the camera appended.
+//!
+//! The copy replaces whatever metadata the target has, or is appended if it has none. The video
+//! before it is copied unchanged, so only the metadata is checked afterwards: that it reads back
+//! with the same telemetry as the copied metadata on its own.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backward::{BackwardReader, ReadAt},
+    inplace::{append_file, replace_file},
+    oversized_metadata,
+    proxy::{Compared, compared_telemetry, telemetry_differences},
+    read_index, read_index_tail, trailer_problems,
+};
+
+/// How `copy_metadata` put the metadata on its target.
+#[derive(Debug, PartialEq)]
+pub enum MetadataCopy {
+    /// Appended to a target without metadata.
+    Appended,
+    /// In place of the target's own metadata, with the original kept at the path given.
+    Replaced(PathBuf),
+    /// To a new file, leaving the target as it was.
+    Written,
+}
+
+/// Where the video of `file`, `len` bytes long, ends: where its Insta360 metadata starts, or its
+/// end if it has none. Fails if its trailer claims more metadata than the file holds.
+pub fn video_end(file: impl ReadAt, len: u64) -> io::Result<u64> {
+    let mut reader = BackwardReader::new(file, len);
+    let Some(tail) = reader.index_tail()? else {
+        return Ok(len);
+    };
+    if let Some((trailer, _)) = read_index_tail(tail, len) {
+        return Ok(len - trailer.metadata_size as u64);
+    }
+    // Not a file without metadata to append to, but one whose trailer is wrong.
+    match oversized_metadata(tail, len) {
+        Some(metadata_size) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "its trailer claims {metadata_size} bytes of metadata, but the file is only {len} \
+                 bytes long"
+            ),
+        )),
+        None => Ok(len),
+    }
+}
+
+/// Whether the file at `written` is `video_end` bytes of video followed by `metadata`, which
+/// reads as `expected`.
+fn check_copy(
+    written: &Path,
+    video_end: u64,
+    metadata: &[u8],
+    expected: &Compared,
+) -> io::Result<Result<(), String>> {
+    let written = File::open(written)?;
+    let len = written.metadata()?.len();
+    let expected_len = video_end + metadata.len() as u64;
+    if len != expected_len {
+        return Ok(Err(format!("it is {len} bytes long, not {expected_len}")));
+    }
+    let mut copied = vec![0; metadata.len()];
+    written.read_exact_at(&mut copied, video_end)?;
+    let Some((trailer, index)) = read_index(&copied) else {
+        return Ok(Err("the metadata index doesn't parse".to_string()));
+    };
+    if let Some(problem) = trailer_problems(&copied, &trailer, &index).first() {
+        return Ok(Err(problem.clone()));
+    }
+    let telemetry = compared_telemetry(&copied);
+    let differences = telemetry_differences(&Compared::new(&copied, &telemetry), expected);
+    Ok(match differences.first() {
+        Some(difference) => Err(difference.clone()),
+        None => Ok(()),
+    })
+}
+
+/// Puts `metadata`, as `amend::extract` returns it, on the file at `target` in place of any it
+/// has, or on a copy of it at `output`. Nothing is kept unless the result reads back with the
+/// telemetry of `metadata`.
+pub fn copy_metadata(
+    target: &Path,
+    metadata: &[u8],
+    output: Option<&Path>,
+) -> io::Result<MetadataCopy> {
+    let file = File::open(target)?;
+    let len = file.metadata()?.len();
+    let video_end = video_end(&file, len)?;
+
+    let expected = compared_telemetry(metadata);
+    let expected = Compared::new(metadata, &expected);
+    let verify = |written: &Path| {
+        check_copy(written, video_end, metadata, &expected).unwrap_or_else(|e| Err(e.to_string()))
+    };
+    let write = |out: &mut dyn Write| {
+        io::copy(&mut (&file).take(video_end), out)?;
+        out.write_all(metadata)
+    };
+
+    if let Some(output) = output {
+        let mut out = BufWriter::new(File::create(output)?);
+        write(&mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        verify(output).map_err(|problem| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the copy doesn't check out: {problem}"),
+            )
+        })?;
+        Ok(MetadataCopy::Written)
+    } else if video_end == len {
+        append_file(target, metadata, verify)?;
+        Ok(MetadataCopy::Appended)
+    } else {
+        replace_file(target, write, verify).map(MetadataCopy::Replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{FrameType, HEADER_SIZE, amend::extract, inplace::backup_path, tests::build_insv};
+
+    #[test]
+    fn test_copy_metadata() {
+        let dir = std::env::temp_dir().join(format!("ginsta-copymeta-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let original = build_insv(
+            b"camera video",
+            &[
+                (FrameType::Gyro, 1, vec![1; 40]),
+                (FrameType::Exposure, 1, vec![2; 32]),
+                (FrameType::Magnetic, 1, vec![3; 5]),
+            ],
+        );
+        let metadata = extract(&original, |_| true).unwrap();
+
+        // A remux that lost the trailer gets it appended.
+        let remux = dir.join("remux.mp4");
+        fs::write(&remux, b"remuxed video").unwrap();
+        assert_eq!(
+            copy_metadata(&remux, &metadata, None).unwrap(),
+            MetadataCopy::Appended
+        );
+        assert_eq!(
+            fs::read(&remux).unwrap(),
+            [&b"remuxed video"[..], &metadata].concat()
+        );
+
+        // Copying only some frames replaces the metadata it now has, keeping the original.
+        let gyro_only = extract(&original, |frame_type| frame_type == FrameType::Gyro).unwrap();
+        let copy = copy_metadata(&remux, &gyro_only, None).unwrap();
+        assert_eq!(copy, MetadataCopy::Replaced(backup_path(&remux)));
+        let replaced = fs::read(&remux).unwrap();
+        assert_eq!(replaced, [&b"remuxed video"[..], &gyro_only].concat());
+        let (_, index) = read_index(&replaced).unwrap();
+        assert_eq!(index.frames.len(), 1);
+        assert_eq!(index.frames[0].frame_type, FrameType::Gyro);
+
+        // With an output, the target is left alone.
+        let output = dir.join("out.mp4");
+        assert_eq!(
+            copy_metadata(&remux, &metadata, Some(&output)).unwrap(),
+            MetadataCopy::Written
+        );
+        assert_eq!(fs::read(&remux).unwrap(), replaced);
+        assert_eq!(
+            fs::read(&output).unwrap(),
+            [&b"remuxed video"[..], &metadata].concat()
+        );
+
+        // A trailer claiming more metadata than the file holds is an error, not a panic.
+        let mut corrupt = original.clone();
+        let at = corrupt.len() - HEADER_SIZE as usize + 38;
+        let claimed = corrupt.len() as u32 + 100;
+        corrupt[at..at + 4].copy_from_slice(&claimed.to_le_bytes());
+        assert!(read_index(&corrupt).is_none());
+        let corrupt_path = dir.join("corrupt.insv");
+        fs::write(&corrupt_path, &corrupt).unwrap();
+        let error = copy_metadata(&corrupt_path, &metadata, None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&corrupt_path).unwrap(), corrupt);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
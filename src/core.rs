@@ -0,0 +1,1767 @@
+//! Decoders for the metadata Insta360 cameras append to their video files: the trailer, the index
+//! frame and the GPS, gyro, exposure and Info frames it points to. Needs only `alloc`, so it
+//! builds without the `std` feature.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, ops::Range};
+
+#[cfg(feature = "clap")]
+use clap::ValueEnum;
+use log::debug;
+use nom::{
+    IResult, Parser,
+    bytes::{complete::tag, take},
+    multi::count,
+    number::{le_i32, le_u16, le_u32},
+};
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::FromPrimitive;
+#[cfg(feature = "prost")]
+use prost::Message;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "prost")]
+use crate::insvtools;
+use crate::{
+    layout::{Field, FieldKind, Layout, Transform},
+    units::{Degrees, Meters, MetersPerSecond, Seconds},
+};
+
+pub const HEADER_SIZE: i64 = 78;
+
+pub const SIGNATURE: &[u8] = &[
+    0x38, 0x64, 0x62, 0x34, 0x32, 0x64, 0x36, 0x39, 0x34, 0x63, 0x63, 0x63, 0x34, 0x31, 0x38, 0x37,
+    0x39, 0x30, 0x65, 0x64, 0x66, 0x66, 0x34, 0x33, 0x39, 0x66, 0x65, 0x30, 0x32, 0x36, 0x62, 0x66,
+];
+
+/// Trailer versions camera files have been seen with. What would change in the layout for
+/// another version isn't known.
+pub const KNOWN_TRAILER_VERSIONS: &[i32] = &[3];
+
+#[derive(Debug)]
+pub struct Trailer {
+    pub version_num: i32,
+    pub signature: Vec<u8>,
+    pub metadata: Vec<TrailerMetadata>,
+    pub metadata_size: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TrailerMetadata {
+    pub id: u16,
+    pub size: u32,
+}
+
+pub fn parse_trailer_metadata(data: &[u8]) -> IResult<&[u8], TrailerMetadata> {
+    let mut parser = (le_u16(), le_u32());
+    let (rest, (id, size)) = parser.parse(data)?;
+
+    Ok((rest, TrailerMetadata { id, size }))
+}
+
+pub fn header_parser(header: &[u8]) -> IResult<&[u8], Trailer> {
+    let mut parser = (count(parse_trailer_metadata, 7), le_i32(), tag(SIGNATURE));
+    let (rest, (metadata, version_num, signature)) = parser.parse(header)?;
+
+    let metadata_size: u32 = metadata.last().unwrap().size;
+
+    Ok((
+        rest,
+        Trailer {
+            version_num,
+            signature: signature.to_vec(),
+            metadata,
+            metadata_size,
+        },
+    ))
+}
+
+/// What each of the seven trailer entries holds, where it is known. The first entry overlaps
+/// the index frame's own trailer, so its id is the index frame's version and type (low and high
+/// byte) and its size is the index frame's size. The last gives the size of all metadata. What
+/// the five between them hold hasn't been worked out, so they have no name.
+pub const TRAILER_ENTRY_NAMES: [Option<&str>; 7] = [
+    Some("index frame"),
+    None,
+    None,
+    None,
+    None,
+    None,
+    Some("metadata size"),
+];
+
+impl Trailer {
+    /// The index frame's trailer, read from the first entry.
+    pub fn index_frame_trailer(&self) -> FrameTrailer {
+        let entry = &self.metadata[0];
+        FrameTrailer {
+            frame_version: entry.id as u8,
+            frame_type: FrameType::from_u16(entry.id >> 8).unwrap_or(FrameType::Raw),
+            frame_size: entry.size as i32,
+        }
+    }
+
+    /// Offset of the index frame from the start of the metadata. Other frames end before it.
+    pub fn index_frame_offset(&self) -> u64 {
+        let index_size = self.metadata[0].size as u64;
+        (self.metadata_size as u64).saturating_sub(HEADER_SIZE as u64 + index_size)
+    }
+
+    /// The trailer for rewritten metadata of `metadata_size` bytes, ending in an index frame of
+    /// `index_size` bytes, at version `version_num`. The first and last entries are made to
+    /// match, and the unknown entries between them are kept.
+    pub fn regenerate(
+        &self,
+        index_version: u8,
+        index_size: u32,
+        metadata_size: u32,
+        version_num: i32,
+    ) -> Trailer {
+        let mut metadata = vec![TrailerMetadata {
+            id: (FrameType::Index as u16) << 8 | index_version as u16,
+            size: index_size,
+        }];
+        metadata.extend_from_slice(&self.metadata[1..6]);
+        metadata.push(TrailerMetadata {
+            id: self.metadata[6].id,
+            size: metadata_size,
+        });
+        Trailer {
+            version_num,
+            signature: SIGNATURE.to_vec(),
+            metadata,
+            metadata_size,
+        }
+    }
+
+    /// The trailer's bytes, as `header_parser` reads them.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE as usize);
+        for entry in &self.metadata {
+            out.extend_from_slice(&entry.id.to_le_bytes());
+            out.extend_from_slice(&entry.size.to_le_bytes());
+        }
+        out.extend_from_slice(&self.version_num.to_le_bytes());
+        out.extend_from_slice(&self.signature);
+        out
+    }
+}
+
+pub const FRAME_HEADER_SIZE: i64 = 6;
+
+#[repr(i8)]
+#[derive(FromPrimitive, ToPrimitive, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+pub enum FrameType {
+    Raw = -1,
+    Index = 0,
+    Info = 1,
+    Thumbnail = 2,
+    Gyro = 3,
+    Exposure = 4,
+    ThumbnailExt = 5,
+    Timelapse = 6,
+    Gps = 7,
+    StarNum = 8,
+    ThreeAInTimestamp = 9,
+    Anchors = 10,
+    ThreeASimulation = 11,
+    ExposureSecondary = 12,
+    Magnetic = 13,
+    Euler = 14,
+    GyroSecondary = 15,
+    Speed = 16,
+    Tbox = 17,
+    Editor = 18,
+    Heartrate = 19,
+    ForwardDirection = 20,
+    Upview = 21,
+    ShellRecognitionData = 22,
+    Pos = 23,
+    TimelapseQuat = 24,
+    /// JSON a production adds with `ginsta edit`, such as shoot notes or rights information.
+    /// Cameras don't write this type, which is well clear of theirs.
+    User = 100,
+}
+
+impl FrameType {
+    /// The on-disk layout of the frame types that are decoded.
+    pub fn layout(self) -> Option<String> {
+        match self {
+            FrameType::Info => Some("an ExtraMetadata protobuf message".into()),
+            _ => self.record_layout().map(|layout| layout.to_string()),
+        }
+    }
+
+    /// The layout of each record in the frame types made of fixed-size records.
+    pub fn record_layout(self) -> Option<&'static Layout> {
+        match self {
+            FrameType::Gps => Some(&GPS_RECORD),
+            FrameType::Gyro => Some(&GYRO_RECORD),
+            FrameType::Exposure => Some(&EXPOSURE_RECORD),
+            FrameType::Index => Some(&INDEX_ENTRY),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FrameTrailer {
+    pub frame_version: u8,
+    pub frame_type: FrameType,
+    pub frame_size: i32,
+}
+
+pub fn frame_trailer(frame: &[u8]) -> IResult<&[u8], FrameTrailer> {
+    let mut parser = (take(1usize), take(1usize), le_i32());
+    let (rest, (frame_ver, frame_type_code, frame_size)) = parser.parse(frame)?;
+
+    let raw_frame_type = frame_type_code[0];
+    if raw_frame_type != 0 {
+        debug!("Frame type code: {}", raw_frame_type);
+    }
+
+    Ok((
+        rest,
+        FrameTrailer {
+            frame_version: frame_ver[0],
+            frame_type: FrameType::from_u8(frame_type_code[0]).unwrap_or(FrameType::Raw),
+            frame_size,
+        },
+    ))
+}
+
+#[derive(Debug)]
+pub struct IndexFrame {
+    pub frames: Vec<IndexFrameTrailer>,
+}
+
+#[derive(Debug)]
+pub struct IndexFrameTrailer {
+    pub frame_version: u8,
+    pub frame_type: FrameType,
+    pub frame_size: u32,
+    pub frame_offset: u32, // Offset from metadata position.
+}
+
+impl IndexFrameTrailer {
+    /// Bytes of the metadata taken by the frame and its trailer.
+    pub fn extent(&self) -> Range<u64> {
+        let start = self.frame_offset as u64;
+        start..start + self.frame_size as u64 + FRAME_HEADER_SIZE as u64
+    }
+}
+
+/// An index entry whose frame shares bytes with another frame or with the index frame, as seen
+/// in some crash-recovered files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Overlap {
+    /// Position of the entry in the index.
+    pub entry: usize,
+    /// The first earlier entry it overlaps, or `None` if it reaches into the index frame.
+    pub other: Option<usize>,
+}
+
+/// Finds entries overlapping the index frame, which starts `index_offset` bytes into the
+/// metadata, or an earlier entry.
+pub fn overlapping_entries(index: &IndexFrame, index_offset: u64) -> Vec<Overlap> {
+    let extents: Vec<_> = index.frames.iter().map(|f| f.extent()).collect();
+    extents
+        .iter()
+        .enumerate()
+        .filter_map(|(entry, extent)| {
+            if extent.end > index_offset {
+                return Some(Overlap { entry, other: None });
+            }
+            extents[..entry]
+                .iter()
+                .position(|other| other.start < extent.end && extent.start < other.end)
+                .map(|other| Overlap {
+                    entry,
+                    other: Some(other),
+                })
+        })
+        .collect()
+}
+
+/// The fields `parse_index` reads.
+pub static INDEX_ENTRY: Layout = Layout {
+    fields: &[
+        Field::new("type", FieldKind::U8),
+        Field::new("version", FieldKind::U8),
+        Field::new("size", FieldKind::U32),
+        Field::new("offset", FieldKind::U32),
+    ],
+    ticks_per_second: None,
+};
+
+pub fn parse_index(input: &[u8]) -> IResult<&[u8], IndexFrameTrailer> {
+    let (rest, entry) = INDEX_ENTRY.parse(input)?;
+    let frame_type = entry.value("type").as_u64() as u8;
+
+    Ok((
+        rest,
+        IndexFrameTrailer {
+            frame_version: entry.value("version").as_u64() as u8,
+            frame_type: FrameType::from_u8(frame_type).unwrap_or(FrameType::Raw),
+            frame_size: entry.value("size").as_u64() as u32,
+            frame_offset: entry.value("offset").as_u64() as u32,
+        },
+    ))
+}
+
+/// Decodes records from the start of `frame` until one fails to decode, which includes a partial
+/// record at the end. Returns the records and the undecoded remainder.
+fn parse_records<'a, T>(
+    frame: &'a [u8],
+    mut parse_record: impl FnMut(&'a [u8]) -> IResult<&'a [u8], T>,
+) -> (&'a [u8], Vec<T>) {
+    let mut records = Vec::new();
+    let mut rest = frame;
+    while !rest.is_empty() {
+        match parse_record(rest) {
+            Ok((remainder, record)) if remainder.len() < rest.len() => {
+                records.push(record);
+                rest = remainder;
+            }
+            _ => break,
+        }
+    }
+    (rest, records)
+}
+
+/// Decodes as many entries as `frame` holds. Like the other frame parsers this never fails;
+/// bytes after the last whole entry are returned as the remainder.
+pub fn parse_index_frame(frame: &[u8]) -> IResult<&[u8], IndexFrame> {
+    let (rest, frames) = parse_records(frame, parse_index);
+    Ok((rest, IndexFrame { frames }))
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct GpsRecord {
+    /// Unix time in whole seconds.
+    pub timestamp: u64,
+    /// Positive north.
+    pub latitude: Degrees,
+    /// Positive east.
+    pub longitude: Degrees,
+    /// Ground speed.
+    pub speed: MetersPerSecond,
+    /// Course over ground, clockwise from true north.
+    pub track: Degrees,
+    pub altitude: Meters,
+    /// Whether the receiver had a fix, where the source says: Insta360 records do, while GoPro
+    /// and DJI records and those read back from the columnar format don't.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub fix: Option<FixStatus>,
+}
+
+impl GpsRecord {
+    /// Whether the record has at least the fix `min`, counting records from sources that don't
+    /// say as having it.
+    pub fn meets_fix(&self, min: FixStatus) -> bool {
+        self.fix.is_none_or(|fix| fix >= min)
+    }
+}
+
+/// Whether a GPS receiver had a position when it wrote a record, from worst to best, so that a
+/// minimum can be asked for. This is only the NMEA A/V status: the fix type (2D, 3D, DGPS) and
+/// satellite count would go here once the bytes holding them are decoded, which they aren't.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum FixStatus {
+    /// NMEA status `V`: the position is stale or a guess.
+    NoFix,
+    /// NMEA status `A`.
+    Fix,
+}
+
+impl FixStatus {
+    fn from_status(status: u8) -> Self {
+        if status == b'A' {
+            FixStatus::Fix
+        } else {
+            FixStatus::NoFix
+        }
+    }
+}
+
+/// The layout of a GPS record. The hemisphere flags must be `N`/`S` and `E`/`W`, and set the
+/// signs of the latitude and longitude.
+pub static GPS_RECORD: Layout = Layout {
+    fields: &[
+        Field::new("timestamp", FieldKind::U64),
+        Field::new("unknown", FieldKind::Unknown(2)),
+        Field::new("fix", FieldKind::Char),
+        Field::new("latitude", FieldKind::F64).transform(Transform::NegateIf {
+            flag: "N/S",
+            value: b'S',
+        }),
+        Field::new("N/S", FieldKind::Char).allowed(NS),
+        Field::new("longitude", FieldKind::F64).transform(Transform::NegateIf {
+            flag: "E/W",
+            value: b'W',
+        }),
+        Field::new("E/W", FieldKind::Char).allowed(EW),
+        Field::new("speed", FieldKind::F64),
+        Field::new("track", FieldKind::F64),
+        Field::new("altitude", FieldKind::F64),
+    ],
+    ticks_per_second: Some(1.0),
+};
+
+/// Size of one GPS record on disk.
+pub const GPS_RECORD_SIZE: usize = GPS_RECORD.size();
+
+const NS: &[u8] = b"NS";
+const EW: &[u8] = b"EW";
+
+pub fn parse_gps_record(frame: &[u8]) -> IResult<&[u8], GpsRecord> {
+    let (rest, record) = GPS_RECORD.parse(frame)?;
+
+    Ok((
+        rest,
+        GpsRecord {
+            timestamp: record.value("timestamp").as_u64(),
+            latitude: Degrees(record.value("latitude").as_f64()),
+            longitude: Degrees(record.value("longitude").as_f64()),
+            speed: MetersPerSecond(record.value("speed").as_f64()),
+            track: Degrees(record.value("track").as_f64()),
+            altitude: Meters(record.value("altitude").as_f64()),
+            fix: Some(FixStatus::from_status(record.value("fix").as_u64() as u8)),
+        },
+    ))
+}
+
+#[derive(Debug)]
+pub struct GpsFrame {
+    pub records: Vec<GpsRecord>,
+}
+
+/// Decodes records up to the first that doesn't decode, returning the rest of the frame.
+///
+/// Decodes as `parse_gps_record` does, but reads each field at its fixed offset rather than
+/// looking it up in the layout, which is several times faster on large frames.
+pub fn parse_gps_frame(frame: &[u8]) -> IResult<&[u8], GpsFrame> {
+    let mut records = Vec::with_capacity(frame.len() / GPS_RECORD_SIZE);
+    records.extend(gps_record_refs(frame).map(GpsRecordRef::to_owned));
+    let rest = &frame[records.len() * GPS_RECORD_SIZE..];
+    Ok((rest, GpsFrame { records }))
+}
+
+/// A GPS record read in place from a frame payload, for callers that don't need to keep every
+/// record.
+#[derive(Clone, Copy, Debug)]
+pub struct GpsRecordRef<'a>(&'a [u8; GPS_RECORD_SIZE]);
+
+impl<'a> GpsRecordRef<'a> {
+    /// Views the first `GPS_RECORD_SIZE` bytes of `bytes`, if they hold valid hemisphere flags.
+    pub fn new(bytes: &'a [u8]) -> Option<Self> {
+        let record: &[u8; GPS_RECORD_SIZE] = bytes.get(..GPS_RECORD_SIZE)?.try_into().ok()?;
+        (NS.contains(&record[19]) && EW.contains(&record[28])).then_some(Self(record))
+    }
+
+    /// Whether the receiver reported a fix, going by the NMEA-style status in the third byte
+    /// after the timestamp: `A` for a fix, `V` for none. The two bytes before it are still
+    /// undecoded, so the fix type and satellite count aren't known.
+    pub fn has_fix(self) -> bool {
+        self.fix() == FixStatus::Fix
+    }
+
+    pub fn fix(self) -> FixStatus {
+        FixStatus::from_status(self.0[10])
+    }
+
+    fn f64_at(self, offset: usize) -> f64 {
+        f64::from_le_bytes(self.0[offset..offset + 8].try_into().unwrap())
+    }
+
+    pub fn timestamp(self) -> u64 {
+        u64::from_le_bytes(self.0[..8].try_into().unwrap())
+    }
+
+    pub fn latitude(self) -> Degrees {
+        let latitude = self.f64_at(11);
+        Degrees(if self.0[19] == b'S' {
+            -latitude
+        } else {
+            latitude
+        })
+    }
+
+    pub fn longitude(self) -> Degrees {
+        let longitude = self.f64_at(20);
+        Degrees(if self.0[28] == b'W' {
+            -longitude
+        } else {
+            longitude
+        })
+    }
+
+    pub fn speed(self) -> MetersPerSecond {
+        MetersPerSecond(self.f64_at(29))
+    }
+
+    pub fn track(self) -> Degrees {
+        Degrees(self.f64_at(37))
+    }
+
+    pub fn altitude(self) -> Meters {
+        Meters(self.f64_at(45))
+    }
+
+    pub fn to_owned(self) -> GpsRecord {
+        GpsRecord {
+            timestamp: self.timestamp(),
+            latitude: self.latitude(),
+            longitude: self.longitude(),
+            speed: self.speed(),
+            track: self.track(),
+            altitude: self.altitude(),
+            fix: Some(self.fix()),
+        }
+    }
+}
+
+/// Iterates the records of a GPS frame without copying them, so over a memory-mapped file only
+/// the pages being read are loaded, however large the frame. Stops at the first record with
+/// invalid hemisphere flags; a partial record at the end is skipped.
+pub fn gps_record_refs(frame: &[u8]) -> impl Iterator<Item = GpsRecordRef<'_>> {
+    frame
+        .chunks_exact(GPS_RECORD_SIZE)
+        .map_while(GpsRecordRef::new)
+}
+
+#[cfg(feature = "prost")]
+#[derive(Debug)]
+pub struct InfoFrame {
+    pub extra_metadata: insvtools::frames::ExtraMetadata,
+}
+
+#[cfg(feature = "prost")]
+pub fn parse_info_frame(frame: &[u8]) -> IResult<&[u8], InfoFrame> {
+    let extra_metadata = insvtools::frames::ExtraMetadata::decode(frame).map_err(|_| {
+        nom::Err::Error(nom::error::Error::new(frame, nom::error::ErrorKind::Verify))
+    })?;
+    Ok((&frame[frame.len()..], InfoFrame { extra_metadata }))
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct GyroRecord {
+    /// Camera clock in milliseconds.
+    pub timestamp: u64,
+    pub payload: [u8; GYRO_PAYLOAD_SIZE],
+}
+
+impl GyroRecord {
+    /// A record of the raw accelerometer and gyroscope axes, in the order `accel_raw` and
+    /// `gyro_raw` read them back.
+    pub fn from_raw(timestamp: u64, axes: [i16; 6]) -> GyroRecord {
+        let mut payload = [0; GYRO_PAYLOAD_SIZE];
+        for (bytes, axis) in payload.chunks_exact_mut(2).zip(axes) {
+            bytes.copy_from_slice(&axis.to_le_bytes());
+        }
+        GyroRecord { timestamp, payload }
+    }
+
+    /// Raw accelerometer axes, assuming the payload holds three accelerometer readings followed
+    /// by three gyroscope readings as little endian i16.
+    pub fn accel_raw(&self) -> [i16; 3] {
+        self.axis_values(0)
+    }
+
+    /// Raw gyroscope axes. See `accel_raw` for the assumed layout.
+    pub fn gyro_raw(&self) -> [i16; 3] {
+        self.axis_values(3)
+    }
+
+    fn axis_values(&self, first: usize) -> [i16; 3] {
+        axis_values(&self.payload, first)
+    }
+}
+
+fn axis_values(payload: &[u8], first: usize) -> [i16; 3] {
+    core::array::from_fn(|i| {
+        let start = (first + i) * 2;
+        i16::from_le_bytes([payload[start], payload[start + 1]])
+    })
+}
+
+#[derive(Debug)]
+pub struct GyroFrame {
+    pub records: Vec<GyroRecord>,
+}
+
+/// The layout of a gyro record, with the payload split as `GyroRecord::accel_raw` and `gyro_raw`
+/// assume.
+pub static GYRO_RECORD: Layout = Layout {
+    fields: &[
+        Field::new("timestamp", FieldKind::U64),
+        Field::new("accel_x", FieldKind::I16),
+        Field::new("accel_y", FieldKind::I16),
+        Field::new("accel_z", FieldKind::I16),
+        Field::new("gyro_x", FieldKind::I16),
+        Field::new("gyro_y", FieldKind::I16),
+        Field::new("gyro_z", FieldKind::I16),
+    ],
+    ticks_per_second: Some(1000.0),
+};
+
+/// Size of one gyro record on disk: a timestamp and six axes.
+pub const GYRO_RECORD_SIZE: usize = GYRO_RECORD.size();
+
+/// Size of a gyro record's six axes, after its timestamp.
+pub const GYRO_PAYLOAD_SIZE: usize = GYRO_RECORD_SIZE - 8;
+
+pub fn parse_gyro_record(record: &[u8]) -> IResult<&[u8], GyroRecord> {
+    let (rest, view) = GYRO_RECORD.parse(record)?;
+
+    Ok((
+        rest,
+        GyroRecord {
+            timestamp: view.value("timestamp").as_u64(),
+            payload: view.bytes()[8..].try_into().unwrap(),
+        },
+    ))
+}
+
+/// See `parse_gps_frame`.
+pub fn parse_gyro_frame(frame: &[u8]) -> IResult<&[u8], GyroFrame> {
+    let mut records = Vec::with_capacity(frame.len() / GYRO_RECORD_SIZE);
+    records.extend(gyro_record_refs(frame).map(GyroRecordRef::to_owned));
+    let rest = &frame[records.len() * GYRO_RECORD_SIZE..];
+    Ok((rest, GyroFrame { records }))
+}
+
+/// A gyro record read in place from a frame payload. See `GpsRecordRef`.
+#[derive(Clone, Copy, Debug)]
+pub struct GyroRecordRef<'a>(&'a [u8; GYRO_RECORD_SIZE]);
+
+impl<'a> GyroRecordRef<'a> {
+    pub fn new(bytes: &'a [u8]) -> Option<Self> {
+        Some(Self(bytes.get(..GYRO_RECORD_SIZE)?.try_into().ok()?))
+    }
+
+    pub fn timestamp(self) -> u64 {
+        u64::from_le_bytes(self.0[..8].try_into().unwrap())
+    }
+
+    pub fn payload(self) -> &'a [u8; GYRO_PAYLOAD_SIZE] {
+        self.0[8..].try_into().unwrap()
+    }
+
+    /// See `GyroRecord::accel_raw`.
+    pub fn accel_raw(self) -> [i16; 3] {
+        axis_values(self.payload(), 0)
+    }
+
+    /// See `GyroRecord::gyro_raw`.
+    pub fn gyro_raw(self) -> [i16; 3] {
+        axis_values(self.payload(), 3)
+    }
+
+    pub fn to_owned(self) -> GyroRecord {
+        GyroRecord {
+            timestamp: self.timestamp(),
+            payload: *self.payload(),
+        }
+    }
+}
+
+/// Iterates the records of a gyro frame without copying them.
+pub fn gyro_record_refs(frame: &[u8]) -> impl Iterator<Item = GyroRecordRef<'_>> {
+    frame
+        .chunks_exact(GYRO_RECORD_SIZE)
+        .filter_map(GyroRecordRef::new)
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ExposureRecord {
+    /// Camera clock in milliseconds.
+    pub timestamp: u64,
+    /// Exposure time.
+    pub shutterspeed: Seconds,
+}
+
+#[derive(Debug)]
+pub struct ExposureFrame {
+    pub records: Vec<ExposureRecord>,
+}
+
+pub static EXPOSURE_RECORD: Layout = Layout {
+    fields: &[
+        Field::new("timestamp", FieldKind::U64),
+        Field::new("shutter_speed", FieldKind::F64),
+    ],
+    ticks_per_second: Some(1000.0),
+};
+
+pub fn parse_exposure_record(frame: &[u8]) -> IResult<&[u8], ExposureRecord> {
+    let (rest, record) = EXPOSURE_RECORD.parse(frame)?;
+
+    Ok((
+        rest,
+        ExposureRecord {
+            timestamp: record.value("timestamp").as_u64(),
+            shutterspeed: Seconds(record.value("shutter_speed").as_f64()),
+        },
+    ))
+}
+
+/// See `parse_gps_frame`.
+pub fn parse_exposure_frame(frame: &[u8]) -> IResult<&[u8], ExposureFrame> {
+    let (rest, records) = parse_records(frame, parse_exposure_record);
+    Ok((rest, ExposureFrame { records }))
+}
+
+/// All decoded streams from a single file's metadata.
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    #[cfg(feature = "prost")]
+    pub info: Option<insvtools::frames::ExtraMetadata>,
+    pub gps: Vec<GpsRecord>,
+    pub gyro: Vec<GyroRecord>,
+    pub exposure: Vec<ExposureRecord>,
+    /// Parts of the metadata that couldn't be decoded.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Something in the metadata that was skipped while decoding the rest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Diagnostic {
+    /// The trailer or the index frame couldn't be read, so no frames were.
+    UnreadableIndex,
+    /// An index entry points outside the file.
+    FrameOutOfBounds {
+        frame_type: FrameType,
+        offset: u32,
+        size: u32,
+    },
+    /// An index entry overlapping another or the index frame, whose frame was skipped.
+    OverlappingFrame { frame_type: FrameType, offset: u32 },
+    /// A frame that failed to decode at all.
+    UndecodedFrame {
+        frame_type: FrameType,
+        offset: u32,
+        size: u32,
+    },
+    /// Bytes after the last record that decoded, such as a truncated record, starting `offset`
+    /// bytes into the metadata.
+    TrailingBytes {
+        frame_type: FrameType,
+        offset: u32,
+        len: usize,
+    },
+}
+
+impl Diagnostic {
+    /// A name for the kind of problem, for counting them.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Diagnostic::UnreadableIndex => "unreadable_index",
+            Diagnostic::FrameOutOfBounds { .. } => "frame_out_of_bounds",
+            Diagnostic::OverlappingFrame { .. } => "overlapping_frame",
+            Diagnostic::UndecodedFrame { .. } => "undecoded_frame",
+            Diagnostic::TrailingBytes { .. } => "trailing_bytes",
+        }
+    }
+
+    /// Where the bytes at fault are, as offsets from the start of the metadata. The range can
+    /// run past the end of the file.
+    pub fn bytes(&self) -> Option<Range<u64>> {
+        let (offset, len) = match *self {
+            Diagnostic::UnreadableIndex | Diagnostic::OverlappingFrame { .. } => return None,
+            Diagnostic::FrameOutOfBounds { offset, size, .. }
+            | Diagnostic::UndecodedFrame { offset, size, .. } => (offset, size as u64),
+            Diagnostic::TrailingBytes { offset, len, .. } => (offset, len as u64),
+        };
+        Some(offset as u64..offset as u64 + len)
+    }
+
+    /// What the decoder expected to find, where the layout is known.
+    pub fn expected(&self) -> Option<String> {
+        match self {
+            Diagnostic::UnreadableIndex => Some(
+                "a 78-byte trailer of seven (u16 id, u32 size) entries, an i32 version and the \
+                 32-byte signature, after an index frame of 10-byte entries"
+                    .into(),
+            ),
+            Diagnostic::FrameOutOfBounds { .. } | Diagnostic::OverlappingFrame { .. } => None,
+            Diagnostic::UndecodedFrame { frame_type, .. }
+            | Diagnostic::TrailingBytes { frame_type, .. } => frame_type.layout(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnostic::UnreadableIndex => write!(f, "unreadable trailer or index frame"),
+            Diagnostic::FrameOutOfBounds {
+                frame_type,
+                offset,
+                size,
+            } => write!(
+                f,
+                "{frame_type:?} frame at offset {offset} ({size} bytes) is outside the file"
+            ),
+            Diagnostic::OverlappingFrame { frame_type, offset } => write!(
+                f,
+                "{frame_type:?} frame at offset {offset} overlaps another frame and was skipped"
+            ),
+            Diagnostic::UndecodedFrame {
+                frame_type, offset, ..
+            } => {
+                write!(f, "{frame_type:?} frame at offset {offset} didn't decode")
+            }
+            Diagnostic::TrailingBytes {
+                frame_type,
+                offset,
+                len,
+            } => write!(
+                f,
+                "trailing {len} undecoded bytes at offset {offset} in {frame_type:?} frame"
+            ),
+        }
+    }
+}
+
+/*
+* Layout:
+* |....{Metadata:[Frame{FrameTrailer}]}Trailer{MetadataPosition}|
+*
+*/
+
+/// Offset of the start of the Insta360 metadata, which is also the end of the MP4 data.
+pub fn metadata_start(data: &[u8]) -> Option<usize> {
+    let buffer = data.get(data.len().checked_sub(HEADER_SIZE as usize)?..)?;
+    let (_, header) = header_parser(buffer).ok()?;
+    data.len().checked_sub(header.metadata_size as usize)
+}
+
+/// Reads the trailer and the index frame of metadata appended to the end of `data`, or `None`
+/// if either is missing or doesn't fit in `data`. A trailer it returns never claims more
+/// metadata than `data` holds.
+pub fn read_index(data: &[u8]) -> Option<(Trailer, IndexFrame)> {
+    read_index_tail(data, data.len() as u64)
+}
+
+/// Reads the trailer and the index frame as `read_index` does, from `tail`, the end of a file
+/// `len` bytes long such as `BackwardReader::index_tail` returns.
+pub fn read_index_tail(tail: &[u8], len: u64) -> Option<(Trailer, IndexFrame)> {
+    let trailer_start = tail.len().checked_sub(HEADER_SIZE as usize)?;
+    let (_, header) = header_parser(&tail[trailer_start..]).ok()?;
+    debug!("{:?}", header);
+    if header.metadata_size as u64 > len {
+        debug!(
+            "Trailer claims {} bytes of metadata in a {len} byte file",
+            header.metadata_size
+        );
+        return None;
+    }
+
+    // The index frame ends where the trailer starts; its own trailer is the trailer's first
+    // entry.
+    let frame_trailer_buf = &tail[trailer_start..trailer_start + FRAME_HEADER_SIZE as usize];
+    let (_, frame_trailer) = frame_trailer(frame_trailer_buf).ok()?;
+    if frame_trailer.frame_type != FrameType::Index {
+        return None;
+    }
+    debug!("{:?}", frame_trailer);
+
+    let index_start = trailer_start.checked_sub(usize::try_from(frame_trailer.frame_size).ok()?)?;
+    let (_, index_frame) = parse_index_frame(&tail[index_start..trailer_start]).ok()?;
+    debug!("{:?}", index_frame);
+
+    Some((header, index_frame))
+}
+
+/// The metadata size claimed by the trailer that ends `tail`, the end of a file `len` bytes long,
+/// if it's more than the file holds, which `read_index_tail` turns the trailer down for.
+pub fn oversized_metadata(tail: &[u8], len: u64) -> Option<u32> {
+    let trailer = tail.get(tail.len().checked_sub(HEADER_SIZE as usize)?..)?;
+    let (_, header) = header_parser(trailer).ok()?;
+    (header.metadata_size as u64 > len).then_some(header.metadata_size)
+}
+
+/// The bytes of each `frame_type` frame in `data`, in index order, leaving out frames the index
+/// places outside the file. Empty if `data` has no Insta360 metadata.
+pub fn frames_of_type(data: &[u8], frame_type: FrameType) -> Vec<&[u8]> {
+    let Some((trailer, index)) = read_index(data) else {
+        return Vec::new();
+    };
+    let metadata_start = data.len() - trailer.metadata_size as usize;
+    (index.frames.iter())
+        .filter(|frame| frame.frame_type == frame_type)
+        .filter_map(|frame| {
+            let start = metadata_start + frame.frame_offset as usize;
+            data.get(start..start.checked_add(frame.frame_size as usize)?)
+        })
+        .collect()
+}
+
+/// A named range of bytes in a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub range: Range<u64>,
+}
+
+/// Absolute byte ranges of the video data, each frame and its trailer, the index frame and the
+/// trailer of a file `len` bytes long, ordered by offset. Ranges are taken from the index as
+/// written, so in a damaged file they can overlap or run past the end.
+pub fn offset_map(len: u64, trailer: &Trailer, index: &IndexFrame) -> Vec<Region> {
+    let metadata_start = len.saturating_sub(trailer.metadata_size as u64);
+    let trailer_start = len.saturating_sub(HEADER_SIZE as u64);
+    let index_start = metadata_start + trailer.index_frame_offset();
+    let mut regions = vec![
+        Region {
+            name: "Video data".into(),
+            range: 0..metadata_start,
+        },
+        Region {
+            name: "Index frame".into(),
+            range: index_start..trailer_start,
+        },
+        Region {
+            name: "Trailer".into(),
+            range: trailer_start..len,
+        },
+    ];
+    for frame in &index.frames {
+        let start = metadata_start + frame.frame_offset as u64;
+        let end = start + frame.frame_size as u64;
+        let name = format!("{:?} frame v{}", frame.frame_type, frame.frame_version);
+        regions.push(Region {
+            name: format!("{name} trailer"),
+            range: end..end + FRAME_HEADER_SIZE as u64,
+        });
+        regions.push(Region {
+            name,
+            range: start..end,
+        });
+    }
+    regions.sort_by_key(|region| (region.range.start, region.range.end));
+    regions
+}
+
+/// Cross-checks the trailer against the index frame and the frames it points to, returning a
+/// description of each inconsistency.
+pub fn trailer_problems(data: &[u8], trailer: &Trailer, index: &IndexFrame) -> Vec<String> {
+    let mut problems = Vec::new();
+    let metadata_size = trailer.metadata_size as usize;
+    if metadata_size < HEADER_SIZE as usize || metadata_size > data.len() {
+        problems.push(format!(
+            "metadata size {metadata_size} doesn't fit in a {} byte file",
+            data.len()
+        ));
+        return problems;
+    }
+    let metadata_pos = data.len() - metadata_size;
+    // Frames end where the trailer starts, less the index frame trailer it overlaps.
+    let frames_len = metadata_size - HEADER_SIZE as usize;
+
+    let index_trailer = trailer.index_frame_trailer();
+    if index_trailer.frame_type != FrameType::Index {
+        problems.push(format!(
+            "first trailer entry has frame type {:?}, expected Index",
+            index_trailer.frame_type
+        ));
+    }
+    if index_trailer.frame_size as usize != index.frames.len() * 10 {
+        problems.push(format!(
+            "index frame is {} bytes but holds {} entries",
+            index_trailer.frame_size,
+            index.frames.len()
+        ));
+    }
+
+    for frame in &index.frames {
+        let start = frame.frame_offset as usize;
+        let end = start + frame.frame_size as usize;
+        if end + FRAME_HEADER_SIZE as usize > frames_len {
+            problems.push(format!(
+                "{:?} frame at offset {start} ({} bytes) runs past the metadata",
+                frame.frame_type, frame.frame_size
+            ));
+            continue;
+        }
+        let trailer_buf =
+            &data[metadata_pos + end..metadata_pos + end + FRAME_HEADER_SIZE as usize];
+        match frame_trailer(trailer_buf) {
+            Ok((_, own))
+                if own.frame_type == frame.frame_type
+                    && own.frame_version == frame.frame_version
+                    && own.frame_size as u32 == frame.frame_size => {}
+            Ok((_, own)) => problems.push(format!(
+                "{:?} frame at offset {start} has trailer {:?} v{} ({} bytes), expected v{} ({} bytes)",
+                frame.frame_type,
+                own.frame_type,
+                own.frame_version,
+                own.frame_size,
+                frame.frame_version,
+                frame.frame_size
+            )),
+            Err(_) => problems.push(format!(
+                "{:?} frame at offset {start} has an unreadable trailer",
+                frame.frame_type
+            )),
+        }
+    }
+
+    for overlap in overlapping_entries(index, trailer.index_frame_offset()) {
+        let frame = &index.frames[overlap.entry];
+        match overlap.other {
+            Some(other) => {
+                let other = &index.frames[other];
+                problems.push(format!(
+                    "{:?} frame at offset {} overlaps {:?} frame at offset {}",
+                    frame.frame_type, frame.frame_offset, other.frame_type, other.frame_offset
+                ));
+            }
+            // Already reported above.
+            None if frame.extent().end > frames_len as u64 => {}
+            None => problems.push(format!(
+                "{:?} frame at offset {} overlaps the index frame",
+                frame.frame_type, frame.frame_offset
+            )),
+        }
+    }
+    problems
+}
+
+/// Each frame the index of `data` lists, in index order, with its bytes, or why they can't be
+/// read. `None` if the index can't be read.
+fn metadata_frames(
+    data: &[u8],
+) -> Option<impl Iterator<Item = (IndexFrameTrailer, Result<&[u8], Diagnostic>)>> {
+    let (header, index_frame) = read_index(data)?;
+    let metadata_pos = data.len() - header.metadata_size as usize;
+    let overlaps = overlapping_entries(&index_frame, header.index_frame_offset());
+    let frames = index_frame.frames.into_iter().enumerate();
+    Some(frames.map(move |(entry, frame)| {
+        let bytes = if overlaps.iter().any(|overlap| overlap.entry == entry) {
+            Err(Diagnostic::OverlappingFrame {
+                frame_type: frame.frame_type,
+                offset: frame.frame_offset,
+            })
+        } else {
+            (metadata_pos.checked_add(frame.frame_offset as usize))
+                .and_then(|start| data.get(start..start.checked_add(frame.frame_size as usize)?))
+                .ok_or(Diagnostic::FrameOutOfBounds {
+                    frame_type: frame.frame_type,
+                    offset: frame.frame_offset,
+                    size: frame.frame_size,
+                })
+        };
+        (frame, bytes)
+    }))
+}
+
+/// Splits a GPS frame into its records up to the first that doesn't decode, and the rest.
+fn split_gps_records(frame: &[u8]) -> (&[u8], &[u8]) {
+    frame.split_at(gps_record_refs(frame).count() * GPS_RECORD_SIZE)
+}
+
+/// Splits a gyro frame into its whole records and the partial one after them.
+fn split_gyro_records(frame: &[u8]) -> (&[u8], &[u8]) {
+    frame.split_at(frame.len() - frame.len() % GYRO_RECORD_SIZE)
+}
+
+/// The records of each `frame_type` frame `read_telemetry` would decode, as `records` picks them
+/// out of the frame.
+fn record_frames(data: &[u8], frame_type: FrameType, records: fn(&[u8]) -> &[u8]) -> Vec<&[u8]> {
+    (metadata_frames(data).into_iter().flatten())
+        .filter(|(frame, _)| frame.frame_type == frame_type)
+        .filter_map(|(_, bytes)| Some(records(bytes.ok()?)))
+        .collect()
+}
+
+/// The records of each GPS frame in `data` that `read_telemetry` would decode, in file order and
+/// left in place for `gps_record_refs`, without decoding any other frame.
+pub fn gps_frames(data: &[u8]) -> Vec<&[u8]> {
+    record_frames(data, FrameType::Gps, |frame| split_gps_records(frame).0)
+}
+
+/// The whole records of each gyro frame in `data`, as `gps_frames` returns those of GPS frames.
+pub fn gyro_frames(data: &[u8]) -> Vec<&[u8]> {
+    record_frames(data, FrameType::Gyro, |frame| split_gyro_records(frame).0)
+}
+
+/// Walks the metadata appended to the end of `data` and decodes every known frame. Frames that
+/// are truncated or corrupt decode as far as they can, with the rest noted in `diagnostics`.
+pub fn read_telemetry(data: &[u8]) -> Telemetry {
+    let (mut gps, mut gyro) = (Vec::new(), Vec::new());
+    let mut telemetry = read_telemetry_with(
+        data,
+        |frame| {
+            gps.reserve(frame.len() / GPS_RECORD_SIZE);
+            gps.extend(gps_record_refs(frame).map(GpsRecordRef::to_owned));
+        },
+        |frame| {
+            gyro.reserve(frame.len() / GYRO_RECORD_SIZE);
+            gyro.extend(gyro_record_refs(frame).map(GyroRecordRef::to_owned));
+        },
+    );
+    telemetry.gps = gps;
+    telemetry.gyro = gyro;
+    telemetry
+}
+
+/// Reads the metadata as `read_telemetry` does, but leaves `gps` and `gyro` empty: the whole
+/// records of each GPS and gyro frame are passed to `gps_frame` and `gyro_frame` in file order
+/// instead, as `gps_frames` and `gyro_frames` return them, for callers that write them out as
+/// they go rather than holding a long recording's hundreds of megabytes of them.
+pub fn read_telemetry_with(
+    data: &[u8],
+    mut gps_frame: impl FnMut(&[u8]),
+    mut gyro_frame: impl FnMut(&[u8]),
+) -> Telemetry {
+    let mut telemetry = Telemetry::default();
+    let Some(frames) = metadata_frames(data) else {
+        telemetry.diagnostics.push(Diagnostic::UnreadableIndex);
+        return telemetry;
+    };
+
+    for (frame, frame_buf) in frames {
+        let frame_type = frame.frame_type;
+        let frame_buf = match frame_buf {
+            Ok(frame_buf) => frame_buf,
+            Err(diagnostic) => {
+                telemetry.diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+        let rest = match frame_type {
+            FrameType::Gps => {
+                let (records, rest) = split_gps_records(frame_buf);
+                gps_frame(records);
+                Ok(rest)
+            }
+            #[cfg(feature = "prost")]
+            FrameType::Info => {
+                if frame.frame_version != 1 {
+                    debug!("Unknown info frame version: {}", frame.frame_version);
+                    continue;
+                }
+
+                parse_info_frame(frame_buf).map(|(rest, info_frame)| {
+                    debug!("Info frame: {:?}", info_frame);
+                    telemetry.info = Some(info_frame.extra_metadata);
+                    rest
+                })
+            }
+            FrameType::Gyro => {
+                debug!("Gyro frame: {:?}", frame);
+                let (records, rest) = split_gyro_records(frame_buf);
+                gyro_frame(records);
+                Ok(rest)
+            }
+            FrameType::Exposure => parse_exposure_frame(frame_buf).map(|(rest, exposure_frame)| {
+                telemetry.exposure.extend(exposure_frame.records);
+                rest
+            }),
+            _ => {
+                debug!("Other frame {:?}", frame);
+                continue;
+            }
+        };
+        match rest {
+            Ok([]) => {}
+            Ok(rest) => telemetry.diagnostics.push(Diagnostic::TrailingBytes {
+                frame_type,
+                offset: frame.frame_offset.saturating_add(frame.frame_size) - rest.len() as u32,
+                len: rest.len(),
+            }),
+            Err(_) => telemetry.diagnostics.push(Diagnostic::UndecodedFrame {
+                frame_type,
+                offset: frame.frame_offset,
+                size: frame.frame_size,
+            }),
+        }
+    }
+
+    telemetry
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Appends frames, an index frame and the trailer to `video`, as a camera would.
+    pub(crate) fn build_insv(video: &[u8], frames: &[(FrameType, u8, Vec<u8>)]) -> Vec<u8> {
+        let mut metadata = Vec::new();
+        let mut index = Vec::new();
+        for (frame_type, version, payload) in frames {
+            let offset = metadata.len() as u32;
+            let frame_type = *frame_type as u8;
+            metadata.extend_from_slice(payload);
+            metadata.extend_from_slice(&[*version, frame_type]);
+            metadata.extend_from_slice(&(payload.len() as i32).to_le_bytes());
+            index.extend_from_slice(&[frame_type, *version]);
+            index.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            index.extend_from_slice(&offset.to_le_bytes());
+        }
+        metadata.extend_from_slice(&index);
+
+        let metadata_size = (metadata.len() + HEADER_SIZE as usize) as u32;
+        let mut data = video.to_vec();
+        data.extend_from_slice(&metadata);
+        data.extend_from_slice(&[1, FrameType::Index as u8]);
+        data.extend_from_slice(&(index.len() as u32).to_le_bytes());
+        data.extend_from_slice(&[0; 30]);
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&metadata_size.to_le_bytes());
+        data.extend_from_slice(&3i32.to_le_bytes());
+        data.extend_from_slice(SIGNATURE);
+        data
+    }
+
+    fn gps_payload(timestamp: u64) -> Vec<u8> {
+        let mut record = timestamp.to_le_bytes().to_vec();
+        record.extend_from_slice(&[0, 0, b'A']);
+        record.extend_from_slice(&49.25f64.to_le_bytes());
+        record.push(b'N');
+        record.extend_from_slice(&4.03f64.to_le_bytes());
+        record.push(b'W');
+        for value in [5.0f64, 335.0, 86.0] {
+            record.extend_from_slice(&value.to_le_bytes());
+        }
+        record
+    }
+
+    #[test]
+    fn test_read_telemetry() {
+        let data = build_insv(
+            b"video",
+            &[(
+                FrameType::Gps,
+                1,
+                [gps_payload(10), gps_payload(11)].concat(),
+            )],
+        );
+        assert_eq!(metadata_start(&data), Some(5));
+
+        let (trailer, index) = read_index(&data).unwrap();
+        assert_eq!(trailer.index_frame_trailer().frame_type, FrameType::Index);
+        assert_eq!(index.frames.len(), 1);
+        assert!(trailer_problems(&data, &trailer, &index).is_empty());
+
+        let telemetry = read_telemetry(&data);
+        assert_eq!(telemetry.gps.len(), 2);
+        assert_eq!(telemetry.gps[1].timestamp, 11);
+        assert_eq!(telemetry.gps[1].longitude.0, -4.03);
+
+        // Corrupt the GPS frame's own trailer.
+        let mut corrupt = data.clone();
+        corrupt[5 + 106] = 9;
+        let problems = trailer_problems(&corrupt, &trailer, &index);
+        assert_eq!(problems.len(), 1, "{problems:?}");
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_gps_record_csv_round_trip() {
+        let data = build_insv(b"", &[(FrameType::Gps, 1, gps_payload(10))]);
+        let records = read_telemetry(&data).gps;
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(&records[0]).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "timestamp,latitude,longitude,speed,track,altitude\n10,49.25,-4.03,5.0,335.0,86.0\n"
+        );
+
+        let decoded: Vec<GpsRecord> = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded[0].speed, MetersPerSecond(5.0));
+        assert_eq!(decoded[0].altitude.feet(), Meters(86.0).feet());
+    }
+
+    #[test]
+    fn test_record_layouts() {
+        assert_eq!(GPS_RECORD_SIZE, 53);
+        assert_eq!(GYRO_RECORD_SIZE, 20);
+        assert_eq!((EXPOSURE_RECORD.size(), INDEX_ENTRY.size()), (16, 10));
+
+        // The offsets `GpsRecordRef` reads at.
+        let offset = |name| GPS_RECORD.span(name).unwrap().1.start;
+        assert_eq!(
+            [
+                "fix",
+                "latitude",
+                "N/S",
+                "longitude",
+                "E/W",
+                "speed",
+                "track",
+                "altitude"
+            ]
+            .map(offset),
+            [10, 11, 19, 20, 28, 29, 37, 45]
+        );
+
+        let mut payload = gps_payload(10);
+        payload[19] = b'S';
+        let record = GPS_RECORD.view(&payload).unwrap();
+        assert_eq!(
+            record.value("latitude").as_f64(),
+            GpsRecordRef::new(&payload).unwrap().latitude().0
+        );
+        assert!(record.value("latitude").as_f64() < 0.0);
+    }
+
+    #[test]
+    fn test_truncated_frames() {
+        let gps = [gps_payload(10), gps_payload(11)].concat();
+        for len in 0..=gps.len() {
+            let (rest, frame) = parse_gps_frame(&gps[..len]).unwrap();
+            assert_eq!(frame.records.len(), len / GPS_RECORD_SIZE);
+            assert_eq!(rest.len(), len % GPS_RECORD_SIZE);
+        }
+        let exposure: Vec<u8> = (0..3u64)
+            .flat_map(|i| [i.to_le_bytes(), 0.01f64.to_le_bytes()].concat())
+            .collect();
+        for len in 0..=exposure.len() {
+            let (rest, frame) = parse_exposure_frame(&exposure[..len]).unwrap();
+            assert_eq!(frame.records.len(), len / 16);
+            assert_eq!(rest.len(), len % 16);
+        }
+
+        let data = build_insv(
+            b"video",
+            &[(FrameType::Gps, 1, gps[..GPS_RECORD_SIZE + 20].to_vec())],
+        );
+        let telemetry = read_telemetry(&data);
+        assert_eq!(telemetry.gps.len(), 1);
+        assert_eq!(
+            telemetry.diagnostics,
+            [Diagnostic::TrailingBytes {
+                frame_type: FrameType::Gps,
+                offset: GPS_RECORD_SIZE as u32,
+                len: 20
+            }]
+        );
+        assert_eq!(
+            telemetry.diagnostics[0].to_string(),
+            "trailing 20 undecoded bytes at offset 53 in Gps frame"
+        );
+        assert_eq!(
+            telemetry.diagnostics[0].bytes(),
+            Some(GPS_RECORD_SIZE as u64..GPS_RECORD_SIZE as u64 + 20)
+        );
+        assert!(
+            telemetry.diagnostics[0]
+                .expected()
+                .unwrap()
+                .starts_with("53-byte")
+        );
+        assert_eq!(telemetry.diagnostics[0].kind(), "trailing_bytes");
+    }
+
+    #[test]
+    fn test_regenerate_trailer() {
+        let data = build_insv(b"video", &[(FrameType::Magnetic, 1, vec![1, 2, 3])]);
+        let (trailer, _) = read_index(&data).unwrap();
+        assert_eq!(
+            trailer.to_bytes(),
+            data[data.len() - HEADER_SIZE as usize..]
+        );
+
+        let regenerated = trailer.regenerate(2, 20, 1000, 4);
+        let (_, parsed) = header_parser(&regenerated.to_bytes()).unwrap();
+        assert_eq!((parsed.version_num, parsed.metadata_size), (4, 1000));
+        let index_trailer = parsed.index_frame_trailer();
+        assert_eq!(index_trailer.frame_type, FrameType::Index);
+        assert_eq!(
+            (index_trailer.frame_version, index_trailer.frame_size),
+            (2, 20)
+        );
+        assert_eq!(parsed.index_frame_offset(), 1000 - 78 - 20);
+    }
+
+    #[test]
+    fn test_offset_map() {
+        let data = build_insv(b"video", &[(FrameType::Gps, 1, gps_payload(10))]);
+        let (trailer, index) = read_index(&data).unwrap();
+        let len = data.len() as u64;
+        let regions = offset_map(len, &trailer, &index);
+        let names: Vec<_> = regions.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Video data",
+                "Gps frame v1",
+                "Gps frame v1 trailer",
+                "Index frame",
+                "Trailer"
+            ]
+        );
+        // The regions cover the file without gaps.
+        assert!(
+            regions
+                .windows(2)
+                .all(|w| w[0].range.end == w[1].range.start)
+        );
+        assert_eq!(regions[1].range, 5..5 + GPS_RECORD_SIZE as u64);
+        assert_eq!(regions[4].range.end, len);
+    }
+
+    #[test]
+    fn test_overlapping_entries() {
+        let gps = [gps_payload(10), gps_payload(11)].concat();
+        let mut data = build_insv(
+            b"video",
+            &[
+                (FrameType::Gps, 1, gps.clone()),
+                (FrameType::Gps, 1, gps.clone()),
+            ],
+        );
+        // Point the second entry at the first frame.
+        let index_start = data.len() - HEADER_SIZE as usize - 20;
+        data[index_start + 16..index_start + 20].copy_from_slice(&0u32.to_le_bytes());
+
+        let (trailer, index) = read_index(&data).unwrap();
+        assert_eq!(
+            overlapping_entries(&index, trailer.index_frame_offset()),
+            [Overlap {
+                entry: 1,
+                other: Some(0)
+            }]
+        );
+        assert!(
+            trailer_problems(&data, &trailer, &index)
+                .contains(&"Gps frame at offset 0 overlaps Gps frame at offset 0".to_string())
+        );
+        let telemetry = read_telemetry(&data);
+        assert_eq!(telemetry.gps.len(), 2);
+        assert_eq!(
+            telemetry.diagnostics,
+            [Diagnostic::OverlappingFrame {
+                frame_type: FrameType::Gps,
+                offset: 0
+            }]
+        );
+
+        // A frame reaching into the index.
+        let mut index = index;
+        index.frames[1].frame_offset = (2 * gps.len() + 10) as u32;
+        assert_eq!(
+            overlapping_entries(&index, trailer.index_frame_offset()),
+            [Overlap {
+                entry: 1,
+                other: None
+            }]
+        );
+    }
+
+    #[test]
+    fn test_damaged_files() {
+        let data = build_insv(
+            b"video",
+            &[
+                (
+                    FrameType::Gps,
+                    1,
+                    [gps_payload(10), gps_payload(11)].concat(),
+                ),
+                (FrameType::Exposure, 1, [0; 32].to_vec()),
+            ],
+        );
+        let check = |data: &[u8]| {
+            let telemetry = read_telemetry(data);
+            if let Some((trailer, index)) = read_index(data) {
+                trailer_problems(data, &trailer, &index);
+            } else {
+                assert_eq!(telemetry.diagnostics, [Diagnostic::UnreadableIndex]);
+            }
+        };
+        for len in 0..data.len() {
+            check(&data[..len]);
+            check(&data[len..]);
+            let mut corrupt = data.clone();
+            corrupt[len] ^= 0xff;
+            check(&corrupt);
+        }
+    }
+
+    #[test]
+    fn test_malformed_metadata() {
+        let data = build_insv(b"video", &[(FrameType::Gyro, 1, vec![0; 40])]);
+        let trailer_start = data.len() - HEADER_SIZE as usize;
+        let index_start = trailer_start - 10;
+        let with = |at: usize, bytes: &[u8]| {
+            let mut data = data.clone();
+            data[at..at + bytes.len()].copy_from_slice(bytes);
+            data
+        };
+
+        // A trailer cut short, or without the signature, isn't one.
+        assert!(header_parser(&data[trailer_start + 1..]).is_err());
+        let unsigned = with(data.len() - 1, b"?");
+        assert!(header_parser(&unsigned[trailer_start..]).is_err());
+        assert!(read_index(&unsigned).is_none());
+        assert_eq!(metadata_start(&unsigned), None);
+        assert!(read_index(&data[..HEADER_SIZE as usize - 1]).is_none());
+
+        // The first trailer entry must be the index frame's trailer, and fit before it.
+        assert!(read_index(&with(trailer_start + 1, &[FrameType::Gps as u8])).is_none());
+        assert!(read_index(&with(trailer_start + 2, &u32::MAX.to_le_bytes())).is_none());
+
+        // An empty index has nothing to read, and nothing wrong with it.
+        let empty = build_insv(b"video", &[]);
+        let (trailer, index) = read_index(&empty).unwrap();
+        assert!(index.frames.is_empty());
+        assert!(trailer_problems(&empty, &trailer, &index).is_empty());
+        let telemetry = read_telemetry(&empty);
+        assert!(telemetry.gyro.is_empty() && telemetry.diagnostics.is_empty());
+        let (rest, index) = parse_index_frame(&[0; 9]).unwrap();
+        assert_eq!((index.frames.len(), rest.len()), (0, 9));
+
+        // A frame size reaching past the end of the file.
+        let oversized = with(index_start + 2, &1000u32.to_le_bytes());
+        let (trailer, index) = read_index(&oversized).unwrap();
+        assert_eq!(
+            trailer_problems(&oversized, &trailer, &index),
+            ["Gyro frame at offset 0 (1000 bytes) runs past the metadata"]
+        );
+        // It reaches over the index frame first, so it's skipped as overlapping that.
+        assert_eq!(
+            read_telemetry(&oversized).diagnostics,
+            [Diagnostic::OverlappingFrame {
+                frame_type: FrameType::Gyro,
+                offset: 0
+            }]
+        );
+        // One that stops short of the frame's own trailer reads what it covers, but doesn't
+        // check out.
+        let undersized = with(index_start + 2, &30u32.to_le_bytes());
+        let (trailer, index) = read_index(&undersized).unwrap();
+        assert_eq!(trailer_problems(&undersized, &trailer, &index).len(), 1);
+        let telemetry = read_telemetry(&undersized);
+        assert_eq!(telemetry.gyro.len(), 1);
+        assert_eq!(
+            telemetry.diagnostics,
+            [Diagnostic::TrailingBytes {
+                frame_type: FrameType::Gyro,
+                offset: 20,
+                len: 10
+            }]
+        );
+
+        // GPS records stop at the first with hemisphere flags that aren't.
+        let mut gps = [gps_payload(10), gps_payload(11)].concat();
+        gps[28] = b'X';
+        assert!(parse_gps_record(&gps).is_err());
+        let (rest, frame) = parse_gps_frame(&gps).unwrap();
+        assert_eq!((frame.records.len(), rest.len()), (0, gps.len()));
+
+        #[cfg(feature = "prost")]
+        {
+            let garbage = build_insv(b"video", &[(FrameType::Info, 1, vec![0xff; 8])]);
+            let telemetry = read_telemetry(&garbage);
+            assert!(telemetry.info.is_none());
+            assert_eq!(
+                telemetry.diagnostics,
+                [Diagnostic::UndecodedFrame {
+                    frame_type: FrameType::Info,
+                    offset: 0,
+                    size: 8
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn test_oversized_metadata() {
+        let data = build_insv(b"video", &[(FrameType::Gyro, 1, vec![0; 40])]);
+        let len = data.len() as u64;
+        let claiming = |metadata_size: u32| {
+            let mut data = data.clone();
+            let at = data.len() - HEADER_SIZE as usize + 38;
+            data[at..at + 4].copy_from_slice(&metadata_size.to_le_bytes());
+            data
+        };
+
+        // Metadata can take up the whole file, but no more.
+        let whole = claiming(len as u32);
+        assert_eq!(read_index(&whole).unwrap().0.metadata_size as u64, len);
+        assert_eq!(oversized_metadata(&whole, len), None);
+        for metadata_size in [len as u32 + 1, 0xFFFFFF00] {
+            let oversized = claiming(metadata_size);
+            assert!(read_index(&oversized).is_none());
+            assert_eq!(oversized_metadata(&oversized, len), Some(metadata_size));
+            assert_eq!(metadata_start(&oversized), None);
+            assert_eq!(
+                read_telemetry(&oversized).diagnostics,
+                [Diagnostic::UnreadableIndex]
+            );
+            // The same end of a file long enough to hold it reads.
+            assert!(read_index_tail(&oversized, metadata_size as u64).is_some());
+            assert_eq!(oversized_metadata(&oversized, metadata_size as u64), None);
+        }
+        assert_eq!(oversized_metadata(&data[..10], len), None);
+    }
+
+    #[test]
+    fn test_frames_of_type() {
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Magnetic, 1, vec![1, 2]),
+                (FrameType::Gyro, 1, vec![0; 20]),
+                (FrameType::Magnetic, 1, vec![3]),
+            ],
+        );
+        assert_eq!(
+            frames_of_type(&data, FrameType::Magnetic),
+            [&[1, 2][..], &[3]]
+        );
+        assert!(frames_of_type(&data, FrameType::Gps).is_empty());
+        assert!(frames_of_type(b"video", FrameType::Magnetic).is_empty());
+        // The last index entry, the second Magnetic frame, claims to run past the end of the file.
+        let mut truncated = data.clone();
+        let entry = data.len() - HEADER_SIZE as usize - 10;
+        truncated[entry + 2..entry + 6].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            frames_of_type(&truncated, FrameType::Magnetic),
+            [&[1, 2][..]]
+        );
+    }
+
+    #[test]
+    fn test_record_refs() {
+        let gps = [gps_payload(10), gps_payload(11)].concat();
+        let refs: Vec<_> = gps_record_refs(&gps).collect();
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs[1].timestamp(), 11);
+        assert_eq!(refs[1].longitude(), Degrees(-4.03));
+        assert!(refs[1].has_fix());
+        let (_, frame) = parse_gps_frame(&gps).unwrap();
+        assert_eq!(
+            format!("{:?}", refs[0].to_owned()),
+            format!("{:?}", frame.records[0])
+        );
+
+        let mut void = gps_payload(12);
+        void[10] = b'V';
+        let (_, record) = parse_gps_record(&void).unwrap();
+        assert_eq!(record.fix, Some(FixStatus::NoFix));
+        assert_eq!(GpsRecordRef::new(&void).unwrap().fix(), FixStatus::NoFix);
+        assert!(!record.meets_fix(FixStatus::Fix));
+        assert!(record.meets_fix(FixStatus::NoFix));
+        assert!(frame.records[0].meets_fix(FixStatus::Fix));
+        let unknown = GpsRecord {
+            fix: None,
+            ..record
+        };
+        assert!(unknown.meets_fix(FixStatus::Fix));
+
+        let mut gyro = 1000u64.to_le_bytes().to_vec();
+        for axis in [1i16, 2, 3, -1, -2, -3] {
+            gyro.extend_from_slice(&axis.to_le_bytes());
+        }
+        let record = gyro_record_refs(&gyro).next().unwrap();
+        assert_eq!(record.timestamp(), 1000);
+        assert_eq!(record.gyro_raw(), [-1, -2, -3]);
+        assert_eq!(record.to_owned().accel_raw(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_frames_decode_as_records() {
+        let mut gps = include_bytes!("testdata/Gps_1752824363158.insgps").to_vec();
+        let count = gps.len() / GPS_RECORD_SIZE;
+        fn by_record(frame: &[u8]) -> (usize, String) {
+            let (rest, records) = parse_records(frame, parse_gps_record);
+            (rest.len(), format!("{records:?}"))
+        }
+        fn by_frame(frame: &[u8]) -> (usize, String) {
+            let (rest, frame) = parse_gps_frame(frame).unwrap();
+            (rest.len(), format!("{:?}", frame.records))
+        }
+        assert_eq!(by_frame(&gps[..]), by_record(&gps[..]));
+        // A record with a bad hemisphere flag ends both, as does a partial one.
+        gps[100 * GPS_RECORD_SIZE + 28] = b'X';
+        for frame in [&gps[..], &gps[..count * GPS_RECORD_SIZE - 7]] {
+            assert_eq!(by_frame(frame), by_record(frame));
+        }
+        assert_eq!(parse_gps_frame(&gps).unwrap().1.records.len(), 100);
+
+        let gyro: Vec<u8> = (0..1000u16)
+            .flat_map(|i| i.to_le_bytes().repeat(GYRO_RECORD_SIZE / 2))
+            .collect();
+        let frame = &gyro[..gyro.len() - 3];
+        let (rest, records) = parse_records(frame, parse_gyro_record);
+        let (frame_rest, frame) = parse_gyro_frame(frame).unwrap();
+        assert_eq!(frame_rest.len(), rest.len());
+        assert_eq!(format!("{:?}", frame.records), format!("{records:?}"));
+    }
+
+    #[test]
+    fn test_read_telemetry_with() {
+        let gyro = |first: u64| -> Vec<u8> {
+            (first..first + 3)
+                .flat_map(|i| [&i.to_le_bytes()[..], &[0; 12]].concat())
+                .collect()
+        };
+        let mut partial = gyro(10);
+        partial.extend_from_slice(&[1, 2, 3]);
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Gyro, 1, gyro(0)),
+                (FrameType::Gps, 1, gps_payload(5)),
+                (FrameType::Gyro, 1, partial),
+            ],
+        );
+        let (mut gps, mut streamed) = (Vec::new(), Vec::new());
+        let telemetry = read_telemetry_with(
+            &data,
+            |frame| gps.extend(gps_record_refs(frame).map(|record| record.timestamp())),
+            |frame| {
+                assert_eq!(frame.len() % GYRO_RECORD_SIZE, 0);
+                streamed.extend(gyro_record_refs(frame).map(|record| record.timestamp()));
+            },
+        );
+        assert_eq!(streamed, [0, 1, 2, 10, 11, 12]);
+        assert_eq!(gps.len(), 1);
+        assert!(telemetry.gyro.is_empty() && telemetry.gps.is_empty());
+
+        // The frames read in place are the ones handed over while reading.
+        let in_place: Vec<u64> = (gyro_frames(&data).into_iter())
+            .flat_map(gyro_record_refs)
+            .map(|record| record.timestamp())
+            .collect();
+        assert_eq!(in_place, streamed);
+        let gps_in_place: Vec<u64> = (gps_frames(&data).into_iter())
+            .flat_map(gps_record_refs)
+            .map(|record| record.timestamp())
+            .collect();
+        assert_eq!(gps_in_place, gps);
+        assert!(gyro_frames(b"video").is_empty());
+
+        let collected = read_telemetry(&data);
+        let timestamps: Vec<u64> = collected.gyro.iter().map(|r| r.timestamp).collect();
+        assert_eq!(timestamps, streamed);
+        assert_eq!(
+            format!("{:?}", collected.diagnostics),
+            format!("{:?}", telemetry.diagnostics)
+        );
+        assert_eq!(telemetry.diagnostics.len(), 1);
+    }
+}
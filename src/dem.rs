@@ -0,0 +1,134 @@
+//! Ground elevation from SRTM `.hgt` tiles, which is usually much closer to the truth than the
+//! altitude a camera's GPS reports.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+/// Marks a sample with no data, such as over water or in radar shadow.
+const VOID: i16 = -32768;
+
+/// One 1°×1° tile: big-endian heights in metres, rows from north to south.
+struct Tile {
+    size: usize,
+    heights: Vec<i16>,
+}
+
+impl Tile {
+    fn parse(data: &[u8]) -> Option<Self> {
+        // SRTM3 tiles are 1201 samples square, SRTM1 tiles 3601.
+        let size = [1201, 3601]
+            .into_iter()
+            .find(|size| data.len() == size * size * 2)?;
+        let heights = data
+            .chunks_exact(2)
+            .map(|pair| i16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        Some(Tile { size, heights })
+    }
+
+    fn height(&self, row: usize, column: usize) -> Option<f64> {
+        let height = self.heights[row * self.size + column];
+        (height != VOID).then_some(height as f64)
+    }
+}
+
+/// A directory of tiles named like `N37W122.hgt`, each loaded the first time it is needed.
+pub struct Dem {
+    dir: PathBuf,
+    tiles: HashMap<(i32, i32), Option<Tile>>,
+}
+
+/// File name of the tile whose south-west corner is at `lat`, `lon`.
+pub fn tile_name(lat: i32, lon: i32) -> String {
+    format!(
+        "{}{:02}{}{:03}.hgt",
+        if lat < 0 { 'S' } else { 'N' },
+        lat.unsigned_abs(),
+        if lon < 0 { 'W' } else { 'E' },
+        lon.unsigned_abs()
+    )
+}
+
+impl Dem {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Dem {
+            dir: dir.into(),
+            tiles: HashMap::new(),
+        }
+    }
+
+    fn load(dir: &Path, lat: i32, lon: i32) -> Option<Tile> {
+        let path = dir.join(tile_name(lat, lon));
+        let tile = fs::read(&path).ok().and_then(|data| Tile::parse(&data));
+        if tile.is_none() {
+            debug!("No usable DEM tile {}", path.display());
+        }
+        tile
+    }
+
+    /// Elevation in metres at a point, interpolated between the four surrounding samples, or
+    /// `None` where there is no tile or a surrounding sample is void.
+    pub fn elevation(&mut self, lat: f64, lon: f64) -> Option<f64> {
+        let (south, west) = (lat.floor() as i32, lon.floor() as i32);
+        let dir = &self.dir;
+        let tile = self
+            .tiles
+            .entry((south, west))
+            .or_insert_with(|| Self::load(dir, south, west))
+            .as_ref()?;
+
+        let last = (tile.size - 1) as f64;
+        let row = (south as f64 + 1.0 - lat) * last;
+        let column = (lon - west as f64) * last;
+        let (top, left) = (row.floor() as usize, column.floor() as usize);
+        let (bottom, right) = ((top + 1).min(tile.size - 1), (left + 1).min(tile.size - 1));
+        let (dy, dx) = (row - top as f64, column - left as f64);
+        let upper = tile.height(top, left)? * (1.0 - dx) + tile.height(top, right)? * dx;
+        let lower = tile.height(bottom, left)? * (1.0 - dx) + tile.height(bottom, right)? * dx;
+        Some(upper * (1.0 - dy) + lower * dy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dem_elevation() {
+        assert_eq!(tile_name(37, -122), "N37W122.hgt");
+        assert_eq!(tile_name(-5, 7), "S05E007.hgt");
+
+        // Heights rise 1 m per column eastwards and 2 m per row southwards.
+        let dir = std::env::temp_dir().join(format!("ginsta-dem-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut data = Vec::new();
+        for row in 0..1201i16 {
+            for column in 0..1201i16 {
+                let height = if (row, column) == (600, 600) {
+                    VOID
+                } else {
+                    column + 2 * row
+                };
+                data.extend_from_slice(&height.to_be_bytes());
+            }
+        }
+        fs::write(dir.join("N49W005.hgt"), data).unwrap();
+
+        let mut dem = Dem::new(&dir);
+        // A sample on the western edge one row down, then half a sample east of it.
+        let step = 1.0 / 1200.0;
+        let height = dem.elevation(50.0 - step, -5.0).unwrap();
+        assert!((height - 2.0).abs() < 1e-6);
+        let height = dem.elevation(50.0 - step, -5.0 + step / 2.0).unwrap();
+        assert!((height - 2.5).abs() < 1e-6);
+        assert_eq!(dem.elevation(49.5, -4.5), None);
+        assert_eq!(dem.elevation(10.0, 10.0), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
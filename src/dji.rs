@@ -0,0 +1,216 @@
+use chrono::NaiveDateTime;
+
+use crate::{
+    GpsRecord, Telemetry,
+    geo::{haversine_distance, initial_bearing},
+    units::{Degrees, Meters, MetersPerSecond},
+};
+
+/// A position from one subtitle block, with a fractional Unix time.
+struct Fix {
+    time: f64,
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+}
+
+/// Finds `key: number` in a line, e.g. `[latitude: 52.123456]` or `ISO:100`.
+fn field(text: &str, key: &str) -> Option<f64> {
+    text.match_indices(key).find_map(|(i, _)| {
+        let before = text[..i].chars().next_back();
+        if before.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        let value = text[i + key.len()..]
+            .trim_start()
+            .strip_prefix(':')?
+            .trim_start();
+        let end = value
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.')))
+            .unwrap_or(value.len());
+        value[..end].parse().ok()
+    })
+}
+
+/// The older `GPS(longitude, latitude, altitude)` layout.
+fn gps_tuple(text: &str) -> Option<(f64, f64, f64)> {
+    let start = text.find("GPS")?;
+    let inner = text[start + 3..].trim_start().strip_prefix('(')?;
+    let inner = &inner[..inner.find(')')?];
+    let mut values = inner.split(',').map(|v| v.trim().parse::<f64>());
+    let longitude = values.next()?.ok()?;
+    let latitude = values.next()?.ok()?;
+    let altitude = values.next().and_then(|v| v.ok()).unwrap_or_default();
+    Some((latitude, longitude, altitude))
+}
+
+/// The first `date time` pair in the block. Fractional seconds are written either as `.417` or
+/// as `,417,123` (milliseconds, microseconds).
+fn block_time(text: &str) -> Option<f64> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    words.windows(2).find_map(|pair| {
+        let date = pair[0].replace('.', "-");
+        let mut time = pair[1].replacen(',', ".", 1);
+        if let Some(extra) = time.find(',') {
+            time.truncate(extra);
+        }
+        let time = format!("{date} {time}");
+        let time = NaiveDateTime::parse_from_str(&time, "%Y-%m-%d %H:%M:%S%.f").ok()?;
+        Some(time.and_utc().timestamp_micros() as f64 / 1e6)
+    })
+}
+
+fn strip_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => (),
+        }
+    }
+    out
+}
+
+fn parse_block(block: &str) -> Option<Fix> {
+    // Skip the cue number and the `00:00:00,000 --> 00:00:00,033` timing line.
+    let text = strip_tags(&block.lines().skip(2).collect::<Vec<_>>().join("\n"));
+    let time = block_time(&text)?;
+    let (latitude, longitude, altitude) = match field(&text, "latitude") {
+        Some(latitude) => (
+            latitude,
+            field(&text, "longitude").or_else(|| field(&text, "longtitude"))?,
+            field(&text, "abs_alt")
+                .or_else(|| field(&text, "altitude"))
+                .unwrap_or_default(),
+        ),
+        None => gps_tuple(&text)?,
+    };
+    // Blocks recorded before the first fix report 0, 0.
+    (latitude != 0.0 || longitude != 0.0).then_some(Fix {
+        time,
+        latitude,
+        longitude,
+        altitude,
+    })
+}
+
+/// Parses the subtitle telemetry DJI drones and action cameras write next to each video.
+///
+/// The date in each block is the aircraft's clock, which has no zone; it is taken as UTC.
+/// There's no speed or course field, so both are derived from the position one second earlier.
+pub fn parse_dji_srt(text: &str) -> Vec<GpsRecord> {
+    let text = text.replace("\r\n", "\n");
+    let fixes: Vec<Fix> = text
+        .split("\n\n")
+        .filter(|block| !block.trim().is_empty())
+        .filter_map(parse_block)
+        .collect();
+
+    fixes
+        .iter()
+        .enumerate()
+        .map(|(i, fix)| {
+            let previous = fixes[..i]
+                .iter()
+                .rev()
+                .find(|p| fix.time - p.time >= 1.0)
+                .or(fixes.first());
+            let (speed, track) = match previous {
+                Some(p) if fix.time > p.time => (
+                    haversine_distance(p.latitude, p.longitude, fix.latitude, fix.longitude)
+                        / (fix.time - p.time),
+                    initial_bearing(p.latitude, p.longitude, fix.latitude, fix.longitude),
+                ),
+                _ => (0.0, 0.0),
+            };
+            GpsRecord {
+                timestamp: fix.time as u64,
+                latitude: Degrees(fix.latitude),
+                longitude: Degrees(fix.longitude),
+                speed: MetersPerSecond(speed),
+                track: Degrees(track),
+                altitude: Meters(fix.altitude),
+                fix: None,
+            }
+        })
+        .collect()
+}
+
+pub fn read_dji_srt(text: &str) -> Telemetry {
+    Telemetry {
+        gps: parse_dji_srt(text),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dji_srt() {
+        let text = "1\r\n00:00:00,000 --> 00:00:00,033\r\n<font size=\"28\">FrameCnt: 1, DiffTime: 33ms\r\n\
+            2023-01-19 13:22:43.417\r\n[iso: 100] [shutter: 1/1000.0] [latitude: 52.000000] \
+            [longitude: 4.000000] [rel_alt: 1.200 abs_alt: 35.496] </font>\r\n\r\n\
+            2\r\n00:00:01,000 --> 00:00:01,033\r\n<font size=\"28\">FrameCnt: 31, DiffTime: 33ms\r\n\
+            2023-01-19 13:22:44,417,123\r\n[iso: 100] [latitude: 52.000090] [longtitude: 4.000000] \
+            [rel_alt: 1.200 abs_alt: 36.000] </font>\r\n\r\n\
+            3\r\n00:00:02,000 --> 00:00:03,000\r\nHOME(4.0000,52.0000) 2017.08.05 14:11:51\r\n\
+            GPS(4.0001,52.0001,16) ISO:100 Shutter:60 EV:0 Fnum:2.2\r\n";
+        let records = parse_dji_srt(text);
+        assert_eq!(records.len(), 3);
+
+        assert_eq!(records[0].timestamp, 1674134563);
+        assert_eq!(records[0].altitude.0, 35.496);
+        assert_eq!(records[1].latitude.0, 52.00009);
+        assert!(
+            (records[1].speed.0 - 10.0).abs() < 0.1,
+            "{}",
+            records[1].speed.0
+        );
+        assert_eq!(records[1].track.0, 0.0);
+
+        assert_eq!(records[2].timestamp, 1501942311);
+        assert_eq!(records[2].latitude.0, 52.0001);
+        assert_eq!(records[2].longitude.0, 4.0001);
+        assert_eq!(records[2].altitude.0, 16.0);
+    }
+
+    #[test]
+    fn test_malformed_dji_srt() {
+        let block = |time: &str, position: &str| {
+            format!("1\n00:00:00,000 --> 00:00:00,033\n{time}\n{position}\n\n")
+        };
+        let good = block(
+            "2023-01-19 13:22:43.417",
+            "[latitude: 52.0] [longitude: 4.0]",
+        );
+        assert_eq!(parse_dji_srt(&good).len(), 1);
+
+        // Blocks without a date, a whole position or a fix are skipped, not read as zeros.
+        let text = [
+            block("no date here", "[latitude: 52.0] [longitude: 4.0]"),
+            block("2023-01-19 13:22:43.417", "[latitude: 52.0]"),
+            block(
+                "2023-01-19 13:22:43.417",
+                "[latitude: 52.0] [longitude: east]",
+            ),
+            block(
+                "2023-01-19 13:22:43.417",
+                "[latitude: 0.0] [longitude: 0.0]",
+            ),
+            block("2023-01-19 13:22:43.417", "GPS(4.0001,52.0001"),
+            "1\n00:00:00,000 --> 00:00:00,033\n".to_string(),
+            good.clone(),
+        ]
+        .concat();
+        let records = parse_dji_srt(&text);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].latitude.0, 52.0);
+        assert!(parse_dji_srt("").is_empty());
+        assert!(parse_dji_srt(&good[..good.len() / 2]).is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+#[cfg(feature = "prost")]
+use crate::insvtools::frames::ExtraMetadata;
+use crate::{ExposureRecord, GyroRecord, imu::ImuScale};
+
+/// Expected blur and rolling-shutter skew for one second of video.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ExposureSecond {
+    /// Seconds from the first video frame.
+    pub second: u64,
+    /// Longest exposure in seconds.
+    pub shutter: Option<f64>,
+    /// Peak angular speed in degrees per second.
+    pub peak_rate: f64,
+    /// Degrees swept during the longest exposure.
+    pub blur: Option<f64>,
+    /// Degrees swept while the sensor is read out.
+    pub skew: Option<f64>,
+    /// Blur or skew is over its limit.
+    pub flagged: bool,
+}
+
+/// Angles, in degrees, above which a second is flagged as likely to stabilize poorly.
+#[derive(Clone, Copy, Debug)]
+pub struct ExposureLimits {
+    pub blur: f64,
+    pub skew: f64,
+}
+
+/// Sensor readout time in seconds from the Info frame's `RollingShutterTime`. Values above one
+/// are taken to be milliseconds.
+#[cfg(feature = "prost")]
+pub fn readout_time(info: Option<&ExtraMetadata>) -> Option<f64> {
+    let time = info?.rolling_shutter_time.filter(|t| *t > 0.0)?;
+    Some(if time > 1.0 { time / 1000.0 } else { time })
+}
+
+/// Buckets exposure and gyro records into seconds from `first_frame_timestamp` (camera clock,
+/// milliseconds) and estimates how far the camera turns during each exposure and readout.
+///
+/// Rates use the peak angular speed in each second, so a single jolt is enough to flag it.
+pub fn exposure_report(
+    exposure: &[ExposureRecord],
+    gyro: &[GyroRecord],
+    first_frame_timestamp: u64,
+    scale: &ImuScale,
+    readout_time: Option<f64>,
+    limits: ExposureLimits,
+) -> Vec<ExposureSecond> {
+    let second_of = |timestamp: u64| {
+        timestamp
+            .checked_sub(first_frame_timestamp)
+            .map(|ms| ms / 1000)
+    };
+    let seconds = exposure
+        .iter()
+        .map(|r| r.timestamp)
+        .chain(gyro.iter().map(|r| r.timestamp))
+        .filter_map(second_of)
+        .max()
+        .map_or(0, |last| last + 1);
+
+    let mut shutter: Vec<Option<f64>> = vec![None; seconds as usize];
+    for record in exposure {
+        if let Some(second) = second_of(record.timestamp) {
+            let longest = &mut shutter[second as usize];
+            *longest = Some(longest.unwrap_or(0.0).max(record.shutterspeed.0));
+        }
+    }
+    let mut peak_rate = vec![0.0f64; seconds as usize];
+    for record in gyro {
+        if let Some(second) = second_of(record.timestamp) {
+            let rate = scale.angular_speed_deg_s(record);
+            peak_rate[second as usize] = peak_rate[second as usize].max(rate);
+        }
+    }
+
+    (0..seconds as usize)
+        .map(|i| {
+            let blur = shutter[i].map(|s| s * peak_rate[i]);
+            let skew = readout_time.map(|t| t * peak_rate[i]);
+            ExposureSecond {
+                second: i as u64,
+                shutter: shutter[i],
+                peak_rate: peak_rate[i],
+                blur,
+                skew,
+                flagged: blur.is_some_and(|b| b > limits.blur)
+                    || skew.is_some_and(|s| s > limits.skew),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Seconds;
+
+    #[test]
+    fn test_exposure_report() {
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        // 16384 counts is half of full scale, 1000 dps about the z axis.
+        let gyro = [
+            GyroRecord {
+                timestamp: 1500,
+                payload: [0; 12],
+            },
+            GyroRecord::from_raw(2500, [0, 0, 0, 0, 0, 16384]),
+        ];
+        let exposure = [
+            ExposureRecord {
+                timestamp: 1000,
+                shutterspeed: Seconds(1.0 / 100.0),
+            },
+            ExposureRecord {
+                timestamp: 2000,
+                shutterspeed: Seconds(1.0 / 1000.0),
+            },
+        ];
+        let limits = ExposureLimits {
+            blur: 0.5,
+            skew: 2.0,
+        };
+
+        let report = exposure_report(&exposure, &gyro, 1000, &scale, Some(0.03), limits);
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].blur, Some(0.0));
+        assert!(!report[0].flagged);
+        assert!((report[1].peak_rate - 1000.0).abs() < 1e-9);
+        assert!((report[1].blur.unwrap() - 1.0).abs() < 1e-9);
+        assert!((report[1].skew.unwrap() - 30.0).abs() < 1e-9);
+        assert!(report[1].flagged);
+    }
+}
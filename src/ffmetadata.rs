@@ -0,0 +1,136 @@
+use std::io::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::GpsRecord;
+
+#[derive(Debug, PartialEq)]
+pub struct Chapter {
+    pub start: f64, // Seconds from the start of the video.
+    pub end: f64,
+    pub title: String,
+}
+
+/// One chapter per GPS segment, clamped to the start of the video.
+pub fn segment_chapters(segments: &[&[GpsRecord]], video_start: f64) -> Vec<Chapter> {
+    segments
+        .iter()
+        .enumerate()
+        .filter_map(|(i, segment)| {
+            let start = segment.first()?.timestamp as f64 - video_start;
+            let end = segment.last()?.timestamp as f64 - video_start + 1.0;
+            (end > 0.0).then(|| Chapter {
+                start: start.max(0.0),
+                end,
+                title: format!("GPS segment {}", i + 1),
+            })
+        })
+        .collect()
+}
+
+/// Formats a position as an ISO 6709 string, as used by the MP4 `location` tag.
+pub fn iso6709(record: &GpsRecord) -> String {
+    format!(
+        "{:+08.4}{:+09.4}{:+.3}/",
+        record.latitude.0, record.longitude.0, record.altitude.0
+    )
+}
+
+/// The container tags for a video made by `camera_type` starting at the Unix time `video_start`,
+/// located where `first` places it.
+pub fn metadata_tags(
+    camera_type: Option<&str>,
+    video_start: u64,
+    first: Option<&GpsRecord>,
+) -> Vec<(&'static str, String)> {
+    let mut tags = vec![("make", "Insta360".to_string())];
+    if let Some(camera_type) = camera_type {
+        tags.push(("model", camera_type.to_string()));
+    }
+    if let Some(time) = DateTime::<Utc>::from_timestamp(video_start as i64, 0) {
+        tags.push((
+            "creation_time",
+            time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        ));
+    }
+    if let Some(first) = first {
+        tags.push(("location", iso6709(first)));
+    }
+    tags
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Writes an FFMETADATA1 file, which `ffmpeg -i video -i file -map_metadata 1` muxes in.
+pub fn write_ffmetadata(
+    mut out: impl Write,
+    tags: &[(&str, String)],
+    chapters: &[Chapter],
+) -> std::io::Result<()> {
+    writeln!(out, ";FFMETADATA1")?;
+    for (key, value) in tags {
+        writeln!(out, "{}={}", escape(key), escape(value))?;
+    }
+    for chapter in chapters {
+        writeln!(out)?;
+        writeln!(out, "[CHAPTER]")?;
+        writeln!(out, "TIMEBASE=1/1000")?;
+        writeln!(out, "START={}", (chapter.start * 1000.0).round() as u64)?;
+        writeln!(out, "END={}", (chapter.end * 1000.0).round() as u64)?;
+        writeln!(out, "title={}", escape(&chapter.title))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_write_ffmetadata() {
+        let record = GpsRecord {
+            timestamp: 110,
+            latitude: Degrees(49.25853492931603),
+            longitude: Degrees(-4.03079459928793),
+            speed: MetersPerSecond(0.0),
+            track: Degrees(0.0),
+            altitude: Meters(86.40542984008789),
+            fix: None,
+        };
+        assert_eq!(iso6709(&record), "+49.2585-004.0308+86.405/");
+
+        let segments: [&[GpsRecord]; 1] = [std::slice::from_ref(&record)];
+        let chapters = segment_chapters(&segments, 100.0);
+        let mut out = Vec::new();
+        write_ffmetadata(&mut out, &[("title", "a=b;c".to_string())], &chapters).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ";FFMETADATA1\ntitle=a\\=b\\;c\n\n[CHAPTER]\nTIMEBASE=1/1000\nSTART=10000\nEND=11000\ntitle=GPS segment 1\n"
+        );
+
+        assert_eq!(
+            metadata_tags(Some("Insta360 X4"), 1_700_000_000, Some(&record)),
+            [
+                ("make", "Insta360".to_string()),
+                ("model", "Insta360 X4".to_string()),
+                ("creation_time", "2023-11-14T22:13:20Z".to_string()),
+                ("location", "+49.2585-004.0308+86.405/".to_string()),
+            ]
+        );
+        // A start past what a timestamp can hold has no creation time.
+        assert_eq!(
+            metadata_tags(None, u64::MAX / 2, None),
+            [("make", "Insta360".to_string())]
+        );
+    }
+}
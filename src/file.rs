@@ -0,0 +1,193 @@
+//! A camera file opened once and shared between threads, such as a viewer decoding thumbnails,
+//! GPS and gyro at the same time.
+//!
+//! Opening reads the trailer and the index; frames are read when asked for, with positioned reads
+//! that don't disturb each other, so every thread can hold its own cursor over the same handle.
+
+use std::{fs::File, io, path::Path, sync::Arc};
+
+use crate::{
+    FrameType, IndexFrame, IndexFrameTrailer, Trailer,
+    backward::{BackwardReader, ReadAt},
+    read_index_tail,
+};
+
+/// A file with Insta360 metadata, with its trailer and index read.
+#[derive(Debug)]
+pub struct Insta360File<R = File> {
+    inner: R,
+    len: u64,
+    trailer: Trailer,
+    index: IndexFrame,
+}
+
+impl Insta360File {
+    /// Opens the camera file at `path`. `None` if it has no Insta360 metadata.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Option<Arc<Self>>> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Self::new(file, len)
+    }
+}
+
+impl<R: ReadAt> Insta360File<R> {
+    /// Reads the trailer and index of `inner`, which is `len` bytes long. `None` if it has no
+    /// Insta360 metadata.
+    pub fn new(inner: R, len: u64) -> io::Result<Option<Arc<Self>>> {
+        let (trailer, index) = {
+            let mut reader = BackwardReader::new(&inner, len);
+            match reader
+                .index_tail()?
+                .and_then(|tail| read_index_tail(tail, len))
+            {
+                Some(read) => read,
+                None => return Ok(None),
+            }
+        };
+        Ok(Some(Arc::new(Insta360File {
+            inner,
+            len,
+            trailer,
+            index,
+        })))
+    }
+
+    pub fn trailer(&self) -> &Trailer {
+        &self.trailer
+    }
+
+    pub fn index(&self) -> &IndexFrame {
+        &self.index
+    }
+
+    /// Where the metadata starts in the file.
+    pub fn metadata_start(&self) -> u64 {
+        self.len - self.trailer.metadata_size as u64
+    }
+
+    /// Reads the payload of `frame`, an entry of the index.
+    pub fn read_frame(&self, frame: &IndexFrameTrailer) -> io::Result<Vec<u8>> {
+        let start = self.metadata_start() + frame.frame_offset as u64;
+        if start + frame.frame_size as u64 > self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "{:?} frame at offset {} runs past the end of the file",
+                    frame.frame_type, frame.frame_offset
+                ),
+            ));
+        }
+        let mut bytes = vec![0; frame.frame_size as usize];
+        self.inner.read_exact_at(&mut bytes, start)?;
+        Ok(bytes)
+    }
+
+    /// A cursor over the payloads of the frames of `frame_type`, in index order, which reads
+    /// independently of any other and can be moved to another thread.
+    pub fn frames(self: &Arc<Self>, frame_type: FrameType) -> FrameCursor<R> {
+        FrameCursor {
+            file: Arc::clone(self),
+            frame_type,
+            entry: 0,
+        }
+    }
+}
+
+/// The frames of one type of an `Insta360File`, read as they're iterated.
+#[derive(Debug)]
+pub struct FrameCursor<R = File> {
+    file: Arc<Insta360File<R>>,
+    frame_type: FrameType,
+    /// The next index entry to look at.
+    entry: usize,
+}
+
+impl<R: ReadAt> Iterator for FrameCursor<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frames = &self.file.index.frames;
+        let found =
+            (self.entry..frames.len()).find(|&i| frames[i].frame_type == self.frame_type)?;
+        self.entry = found + 1;
+        Some(self.file.read_frame(&frames[found]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{
+        GpsRecord, HEADER_SIZE, Telemetry, columnar::Table, gyro_record_refs, parse_gps_frame,
+        tests::build_insv,
+    };
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_shared_file() {
+        assert_send_sync::<Insta360File>();
+        assert_send_sync::<FrameCursor>();
+        assert_send_sync::<(Telemetry, GpsRecord, Trailer, IndexFrame, Table)>();
+
+        let gps = include_bytes!("testdata/Gps_1752824363158.insgps");
+        let gyro: Vec<u8> = (0..100u64)
+            .flat_map(|i| [i.to_le_bytes(), [0; 8]].concat().into_iter().chain([0; 4]))
+            .collect();
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Thumbnail, 1, b"jpeg".to_vec()),
+                (FrameType::Gps, 1, gps[..530].to_vec()),
+                (FrameType::Gyro, 1, gyro),
+                (FrameType::Gps, 1, gps[530..1060].to_vec()),
+            ],
+        );
+        let len = data.len() as u64;
+        let file = Insta360File::new(data, len).unwrap().unwrap();
+        assert_eq!(file.index().frames.len(), 4);
+
+        let thumbnails = file.frames(FrameType::Thumbnail);
+        let gps = file.frames(FrameType::Gps);
+        let gyro = file.frames(FrameType::Gyro);
+        let thumbnails = thread::spawn(move || thumbnails.collect::<io::Result<Vec<_>>>());
+        let gps = thread::spawn(move || {
+            gps.map(|frame| Ok(parse_gps_frame(&frame?).unwrap().1.records.len()))
+                .sum::<io::Result<usize>>()
+        });
+        let gyro = thread::spawn(move || {
+            gyro.map(|frame| Ok(gyro_record_refs(&frame?).count()))
+                .sum::<io::Result<usize>>()
+        });
+        assert_eq!(thumbnails.join().unwrap().unwrap(), [b"jpeg"]);
+        assert_eq!(gps.join().unwrap().unwrap(), 20);
+        assert_eq!(gyro.join().unwrap().unwrap(), 100);
+
+        assert!(Insta360File::new(b"video".to_vec(), 5).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_damaged_file() {
+        let data = build_insv(b"video", &[(FrameType::Gyro, 1, vec![0; 40])]);
+        let len = data.len() as u64;
+
+        // An index entry reaching past the end of the file is an error to read, not a panic.
+        let mut oversized = data.clone();
+        let at = data.len() - HEADER_SIZE as usize - 10 + 2;
+        oversized[at..at + 4].copy_from_slice(&1000u32.to_le_bytes());
+        let file = Insta360File::new(oversized, len).unwrap().unwrap();
+        let error = file.read_frame(&file.index().frames[0]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(file.frames(FrameType::Gyro).next().unwrap().is_err());
+
+        // Nor is a trailer claiming more metadata than the file holds opened.
+        let mut claiming = data.clone();
+        let at = data.len() - HEADER_SIZE as usize + 38;
+        claiming[at..at + 4].copy_from_slice(&0xFFFFFF00u32.to_le_bytes());
+        assert!(Insta360File::new(claiming, len).unwrap().is_none());
+        // A file shorter than it's said to be can't be read at all.
+        assert!(Insta360File::new(data, len + 1).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Replays a recording's channels to Foxglove Studio over the Foxglove WebSocket protocol
+//! (`foxglove.websocket.v1`), paced by the log times so telemetry plays back as it was
+//! recorded.
+//!
+//! The server advertises the channels of `mcap::telemetry_channels`, sends each message to the
+//! clients subscribed to its channel, and broadcasts the playback time so panels stay in step.
+
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{Value, json};
+use tungstenite::{
+    Message, WebSocket,
+    handshake::server::{ErrorResponse, Request, Response},
+};
+
+use crate::mcap::McapWriter;
+
+pub const SUBPROTOCOL: &str = "foxglove.websocket.v1";
+
+/// Binary opcodes sent by the server.
+const MESSAGE_DATA: u8 = 0x01;
+const TIME: u8 = 0x02;
+
+/// How often to check for requests from the client.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Accepts the handshake, agreeing on the Foxglove subprotocol when the client offers it.
+// The callback's error type is tungstenite's.
+#[allow(clippy::result_large_err)]
+fn accept(stream: TcpStream) -> tungstenite::Result<WebSocket<TcpStream>> {
+    let callback = |request: &Request, mut response: Response| {
+        let offered = (request.headers().get_all("Sec-WebSocket-Protocol").iter())
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|protocol| protocol.trim() == SUBPROTOCOL);
+        if offered {
+            response.headers_mut().insert(
+                "Sec-WebSocket-Protocol",
+                SUBPROTOCOL.parse().expect("A valid header value"),
+            );
+        }
+        Ok::<_, ErrorResponse>(response)
+    };
+    tungstenite::accept_hdr(stream, callback).map_err(|e| match e {
+        tungstenite::HandshakeError::Failure(e) => e,
+        tungstenite::HandshakeError::Interrupted(_) => {
+            tungstenite::Error::Io(ErrorKind::WouldBlock.into())
+        }
+    })
+}
+
+fn advertise(log: &McapWriter) -> Value {
+    let channels: Vec<Value> = (1..)
+        .zip(&log.channels)
+        .map(|(id, channel)| {
+            json!({
+                "id": id,
+                "topic": channel.topic,
+                "encoding": "json",
+                "schemaName": channel.schema_name,
+                "schema": channel.schema.to_string(),
+                "schemaEncoding": "jsonschema",
+            })
+        })
+        .collect();
+    json!({ "op": "advertise", "channels": channels })
+}
+
+/// Applies `subscribe` and `unsubscribe` requests to `subscriptions`, a map from subscription id
+/// to channel id. Other operations are ignored.
+fn handle_request(text: &str, subscriptions: &mut HashMap<u32, u16>) {
+    let Ok(request) = serde_json::from_str::<Value>(text) else {
+        return;
+    };
+    match request["op"].as_str() {
+        Some("subscribe") => {
+            for subscription in request["subscriptions"].as_array().into_iter().flatten() {
+                if let (Some(id), Some(channel)) = (
+                    subscription["id"].as_u64(),
+                    subscription["channelId"].as_u64(),
+                ) {
+                    subscriptions.insert(id as u32, channel as u16);
+                }
+            }
+        }
+        Some("unsubscribe") => {
+            for id in request["subscriptionIds"].as_array().into_iter().flatten() {
+                if let Some(id) = id.as_u64() {
+                    subscriptions.remove(&(id as u32));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads whatever the client has sent, without waiting for more.
+fn poll(
+    socket: &mut WebSocket<TcpStream>,
+    subscriptions: &mut HashMap<u32, u16>,
+) -> tungstenite::Result<()> {
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => handle_request(&text, subscriptions),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The binary frame for a message on one subscription.
+fn message_data(subscription: u32, log_time: u64, data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(13 + data.len());
+    frame.push(MESSAGE_DATA);
+    frame.extend_from_slice(&subscription.to_le_bytes());
+    frame.extend_from_slice(&log_time.to_le_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+fn time(log_time: u64) -> Vec<u8> {
+    let mut frame = vec![TIME];
+    frame.extend_from_slice(&log_time.to_le_bytes());
+    frame
+}
+
+/// Plays `log` to the client on `stream` at `speed` times real time, returning once every
+/// message has been sent or the client has gone.
+pub fn replay(stream: TcpStream, log: &McapWriter, speed: f64) -> tungstenite::Result<()> {
+    let mut socket = accept(stream)?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(1)))?;
+    let server_info = json!({
+        "op": "serverInfo",
+        "name": concat!("ginsta ", env!("CARGO_PKG_VERSION")),
+        "capabilities": ["time"],
+        "supportedEncodings": [],
+        "metadata": {},
+    });
+    socket.send(Message::text(server_info.to_string()))?;
+    socket.send(Message::text(advertise(log).to_string()))?;
+
+    let mut order: Vec<usize> = (0..log.messages.len()).collect();
+    order.sort_by_key(|&i| log.messages[i].0);
+    let Some(&first) = order.first() else {
+        return Ok(());
+    };
+    let first_time = log.messages[first].0;
+    let mut subscriptions = HashMap::new();
+    let mut last_poll = Instant::now();
+    // Give the client a moment to subscribe before the first messages go out.
+    let start = Instant::now() + POLL_INTERVAL * 10;
+    for i in order {
+        let (log_time, channel, data) = &log.messages[i];
+        let due = start + Duration::from_secs_f64((log_time - first_time) as f64 / 1e9 / speed);
+        loop {
+            // Reading waits out the timeout when the client is quiet, so not for every message.
+            if last_poll.elapsed() >= POLL_INTERVAL {
+                poll(&mut socket, &mut subscriptions)?;
+                last_poll = Instant::now();
+            }
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+            thread::sleep((due - now).min(POLL_INTERVAL));
+        }
+        socket.write(Message::binary(time(*log_time)))?;
+        for (subscription, _) in subscriptions.iter().filter(|(_, c)| *c == channel) {
+            socket.write(Message::binary(message_data(
+                *subscription,
+                *log_time,
+                data,
+            )))?;
+        }
+        socket.flush()?;
+    }
+    socket.close(None)?;
+    // Wait for the client to acknowledge the close.
+    while socket.read().is_ok() {}
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol() {
+        let mut subscriptions = HashMap::new();
+        handle_request(
+            r#"{"op":"subscribe","subscriptions":[{"id":0,"channelId":2},{"id":1,"channelId":1}]}"#,
+            &mut subscriptions,
+        );
+        handle_request(
+            r#"{"op":"unsubscribe","subscriptionIds":[1]}"#,
+            &mut subscriptions,
+        );
+        handle_request("not json", &mut subscriptions);
+        assert_eq!(subscriptions, HashMap::from([(0, 2)]));
+
+        let frame = message_data(7, 1_000, b"{}");
+        assert_eq!(frame, [1, 7, 0, 0, 0, 232, 3, 0, 0, 0, 0, 0, 0, b'{', b'}']);
+
+        let mut log = McapWriter::default();
+        log.add_channel("/gps", "foxglove.LocationFix", json!({}));
+        let advertised = advertise(&log);
+        assert_eq!(advertised["channels"][0]["id"], 1);
+        assert_eq!(advertised["channels"][0]["schema"], "{}");
+    }
+}
@@ -0,0 +1,102 @@
+//! Gyroflow's IMU log, `.gcsv`: a few `key,value` header lines, then the raw gyroscope and
+//! accelerometer counts with the factors that turn them into rad/s and g.
+//!
+//! Times are camera-clock milliseconds from the first video frame, so Gyroflow lines the log up
+//! with the video without searching for the offset.
+
+use std::io::Write;
+
+use crate::{GyroRecord, imu::ImuScale};
+
+/// What the header says about the recording, where known.
+#[derive(Clone, Debug, Default)]
+pub struct GcsvHeader<'a> {
+    pub camera: Option<&'a str>,
+    pub firmware: Option<&'a str>,
+    /// Unix time of the first video frame.
+    pub timestamp: Option<u64>,
+    pub video_file: &'a str,
+    /// Sensor readout time in milliseconds.
+    pub frame_readout_ms: Option<f64>,
+}
+
+/// Writes `gyro` as a Gyroflow log, with times counted from `first_frame_ms` on the camera clock,
+/// or from the first record when that isn't known.
+pub fn write_gcsv(
+    mut out: impl Write,
+    gyro: &[GyroRecord],
+    scale: &ImuScale,
+    first_frame_ms: Option<u64>,
+    header: &GcsvHeader,
+) -> std::io::Result<()> {
+    writeln!(out, "GYROFLOW IMU LOG")?;
+    writeln!(out, "version,1.3")?;
+    writeln!(out, "id,ginsta")?;
+    writeln!(out, "orientation,XYZ")?;
+    writeln!(out, "vendor,insta360")?;
+    if let Some(camera) = header.camera {
+        writeln!(out, "note,{camera}")?;
+    }
+    if let Some(firmware) = header.firmware {
+        writeln!(out, "fwversion,{firmware}")?;
+    }
+    if let Some(timestamp) = header.timestamp {
+        writeln!(out, "timestamp,{timestamp}")?;
+    }
+    writeln!(out, "videofilename,{}", header.video_file)?;
+    if let Some(readout) = header.frame_readout_ms {
+        writeln!(out, "frame_readout_time,{readout}")?;
+        writeln!(out, "frame_readout_direction,0")?;
+    }
+    writeln!(out, "tscale,0.001")?;
+    writeln!(out, "gscale,{}", 1.0 / scale.gyro_counts_per_rad_s())?;
+    writeln!(out, "ascale,{}", scale.accel_range_g / 32768.0)?;
+    writeln!(out, "t,gx,gy,gz,ax,ay,az")?;
+    let Some(start) = first_frame_ms.or(gyro.first().map(|record| record.timestamp)) else {
+        return Ok(());
+    };
+    for record in gyro {
+        let [gx, gy, gz] = record.gyro_raw();
+        let [ax, ay, az] = record.accel_raw();
+        let t = record.timestamp as i64 - start as i64;
+        writeln!(out, "{t},{gx},{gy},{gz},{ax},{ay},{az}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_gcsv() {
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        let gyro: Vec<GyroRecord> = (0..2)
+            .map(|i| {
+                let axes = [1i16, 2, 2048, -4, 5, 6].map(|axis| axis * (i + 1));
+                GyroRecord::from_raw(990 + i as u64 * 10, axes)
+            })
+            .collect();
+        let header = GcsvHeader {
+            video_file: "VID_1.insv",
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        write_gcsv(&mut out, &gyro, &scale, Some(1000), &header).unwrap();
+        let log = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines[0], "GYROFLOW IMU LOG");
+        assert!(lines.contains(&"ascale,0.00048828125"));
+        assert_eq!(
+            lines[lines.len() - 3..],
+            [
+                "t,gx,gy,gz,ax,ay,az",
+                "-10,-4,5,6,1,2,2048",
+                "0,-8,10,12,2,4,4096"
+            ]
+        );
+    }
+}
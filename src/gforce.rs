@@ -0,0 +1,228 @@
+//! Longitudinal and lateral acceleration in g, from the GPS as the change in speed and the speed
+//! times the turn rate, and from the accelerometer with gravity taken out.
+//!
+//! Gravity is tracked through the gyro: the up direction is turned with the camera at each sample
+//! and pulled toward the accelerometer only while the GPS shows the vehicle neither speeding up
+//! nor turning, so braking or a long corner isn't taken for the camera tilting. How the camera
+//! was mounted isn't known, so forward is taken as the horizontal direction whose acceleration
+//! follows the GPS's change in speed best, assuming the camera stays fixed to the vehicle.
+//! Acceleration and turns to the right are positive.
+
+use crate::{GpsRecord, GyroRecord, imu::ImuScale, lean::STANDARD_GRAVITY, motion::turning};
+
+/// Largest GPS acceleration in g either way at which the accelerometer is trusted to show
+/// gravity.
+pub const STEADY_LIMIT: f64 = 0.05;
+
+/// Time constant in seconds of the pull of the up direction toward the accelerometer.
+const GRAVITY_TIME_CONSTANT: f64 = 2.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GForce {
+    pub gps_longitudinal: Option<f64>,
+    pub gps_lateral: Option<f64>,
+    /// Averaged over the second around the record.
+    pub accel_longitudinal: Option<f64>,
+    pub accel_lateral: Option<f64>,
+}
+
+/// The up direction and the acceleration without gravity at one gyro record, in the camera's
+/// axes.
+struct Sample {
+    /// Unix time.
+    time: f64,
+    up: [f64; 3],
+    linear: [f64; 3],
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f64; 3]) -> Option<[f64; 3]> {
+    let norm = dot(v, v).sqrt();
+    (norm > 0.0).then(|| v.map(|v| v / norm))
+}
+
+/// Longitudinal and lateral acceleration at each GPS record.
+fn gps_accelerations(gps: &[GpsRecord]) -> Vec<(Option<f64>, Option<f64>)> {
+    let turns = turning(gps);
+    (0..gps.len())
+        .map(|i| {
+            let (before, after) = (i.saturating_sub(1), (i + 1).min(gps.len() - 1));
+            let elapsed = gps[after].timestamp as f64 - gps[before].timestamp as f64;
+            let longitudinal = (elapsed != 0.0)
+                .then(|| (gps[after].speed.0 - gps[before].speed.0) / elapsed / STANDARD_GRAVITY);
+            let lateral = (turns[i].turn_rate)
+                .map(|rate| gps[i].speed.0 * rate.0.to_radians() / STANDARD_GRAVITY);
+            (longitudinal, lateral)
+        })
+        .collect()
+}
+
+/// The acceleration at each GPS record. Gyro timestamps are camera-clock milliseconds;
+/// `camera_epoch` is the Unix time at which that clock read zero, `None` to go by the GPS alone.
+pub fn g_forces(
+    gps: &[GpsRecord],
+    gyro: &[GyroRecord],
+    camera_epoch: Option<f64>,
+    scale: &ImuScale,
+) -> Vec<GForce> {
+    let from_gps = gps_accelerations(gps);
+    let steady_at = |t: f64| {
+        let after = gps.partition_point(|record| (record.timestamp as f64) < t);
+        [after.checked_sub(1), Some(after)]
+            .into_iter()
+            .flatten()
+            .filter_map(|i| from_gps.get(i))
+            .all(|(longitudinal, lateral)| {
+                [longitudinal, lateral]
+                    .into_iter()
+                    .flatten()
+                    .all(|g| g.abs() <= STEADY_LIMIT)
+            })
+    };
+
+    let mut samples: Vec<Sample> = Vec::new();
+    if let Some(epoch) = camera_epoch {
+        let mut up: Option<[f64; 3]> = None;
+        let mut previous: Option<u64> = None;
+        for record in gyro {
+            let t = epoch + record.timestamp as f64 / 1000.0;
+            let accel = scale.accel_g(record);
+            let dt = previous.map_or(0.0, |p| record.timestamp.saturating_sub(p) as f64 / 1000.0);
+            previous = Some(record.timestamp);
+            let current = match up {
+                None => normalize(accel),
+                Some(up) => {
+                    // A direction fixed in the world turns against the camera.
+                    let turn = cross(scale.gyro_rad_s(record), up);
+                    let mut turned: [f64; 3] = core::array::from_fn(|i| up[i] - turn[i] * dt);
+                    if steady_at(t) {
+                        let alpha = dt / (GRAVITY_TIME_CONSTANT + dt);
+                        if let Some(measured) = normalize(accel) {
+                            for (turned, measured) in turned.iter_mut().zip(measured) {
+                                *turned += alpha * (measured - *turned);
+                            }
+                        }
+                    }
+                    normalize(turned)
+                }
+            };
+            up = current.or(up);
+            if let Some(up) = up {
+                samples.push(Sample {
+                    time: t,
+                    up,
+                    linear: core::array::from_fn(|i| accel[i] - up[i]),
+                });
+            }
+        }
+    }
+
+    // Horizontal acceleration and up around each GPS record.
+    let around: Vec<Option<([f64; 3], [f64; 3])>> = gps
+        .iter()
+        .map(|record| {
+            let t = record.timestamp as f64;
+            let start = samples.partition_point(|sample| sample.time < t - 0.5);
+            let end = samples.partition_point(|sample| sample.time <= t + 0.5);
+            let near = &samples[start..end];
+            if near.is_empty() {
+                return None;
+            }
+            let mean = |value: fn(&Sample) -> [f64; 3]| -> [f64; 3] {
+                core::array::from_fn(|i| {
+                    near.iter().map(|s| value(s)[i]).sum::<f64>() / near.len() as f64
+                })
+            };
+            let up = normalize(mean(|sample| sample.up))?;
+            let linear = mean(|sample| sample.linear);
+            let vertical = dot(linear, up);
+            let horizontal = core::array::from_fn(|i| linear[i] - vertical * up[i]);
+            Some((horizontal, up))
+        })
+        .collect();
+
+    let mut correlation = [0.0; 3];
+    for ((longitudinal, _), around) in from_gps.iter().zip(&around) {
+        if let (Some(longitudinal), Some((horizontal, _))) = (longitudinal, around) {
+            for (sum, h) in correlation.iter_mut().zip(horizontal) {
+                *sum += longitudinal * h;
+            }
+        }
+    }
+    let forward = normalize(correlation);
+
+    from_gps
+        .into_iter()
+        .zip(around)
+        .map(|((gps_longitudinal, gps_lateral), around)| {
+            let measured = forward.zip(around).and_then(|(forward, (horizontal, up))| {
+                let right = normalize(cross(forward, up))?;
+                Some((dot(horizontal, forward), dot(horizontal, right)))
+            });
+            GForce {
+                gps_longitudinal,
+                gps_lateral,
+                accel_longitudinal: measured.map(|m| m.0),
+                accel_lateral: measured.map(|m| m.1),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_g_forces() {
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        // Level and going straight, speeding up at 0.3 g for four seconds along the camera's y.
+        let speed = |t: f64| 10.0 + 0.3 * STANDARD_GRAVITY * (t - 4.0).clamp(0.0, 4.0);
+        let gps: Vec<GpsRecord> = (0..=12)
+            .map(|i| GpsRecord {
+                timestamp: 1000 + i,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(speed(i as f64)),
+                track: Degrees(90.0),
+                altitude: Meters(0.0),
+                fix: None,
+            })
+            .collect();
+        let gyro: Vec<GyroRecord> = (0..=120)
+            .map(|i| {
+                let y: i16 = if (40..80).contains(&i) { 614 } else { 0 };
+                GyroRecord::from_raw(5000 + i * 100, [0, y, 2048, 0, 0, 0])
+            })
+            .collect();
+
+        let forces = g_forces(&gps, &gyro, Some(995.0), &scale);
+        let at = forces[6];
+        assert!((at.gps_longitudinal.unwrap() - 0.3).abs() < 1e-9, "{at:?}");
+        assert_eq!(at.gps_lateral, Some(0.0));
+        assert!(
+            (at.accel_longitudinal.unwrap() - 0.3).abs() < 0.01,
+            "{at:?}"
+        );
+        assert!(at.accel_lateral.unwrap().abs() < 0.01, "{at:?}");
+        assert!(forces[11].accel_longitudinal.unwrap().abs() < 0.01);
+
+        let forces = g_forces(&gps, &gyro, None, &scale);
+        assert_eq!(forces[6].accel_longitudinal, None);
+    }
+}
@@ -0,0 +1,474 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use nom::{
+    IResult, Parser,
+    bytes::take,
+    multi::many0,
+    number::{be_u8, be_u16},
+};
+
+use crate::{
+    GpsRecord, GyroRecord, Telemetry,
+    geo::initial_bearing,
+    imu::ImuScale,
+    mp4::{Mp4Box, TrackSamples, find_track_samples},
+    units::{Degrees, Meters, MetersPerSecond},
+};
+
+/// GPMF is a big endian key-length-value format. Each entry is a FourCC key, a type character,
+/// the size of one structure, a repeat count, then the data padded to a multiple of four bytes.
+fn klv(out: &mut Vec<u8>, key: &[u8; 4], value_type: u8, struct_size: u8, data: &[u8]) {
+    let repeat = data.len() / struct_size as usize;
+    out.extend_from_slice(key);
+    out.push(value_type);
+    out.push(struct_size);
+    out.extend_from_slice(&(repeat as u16).to_be_bytes());
+    out.extend_from_slice(data);
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+/// A nested entry containing other entries.
+fn nested(out: &mut Vec<u8>, key: &[u8; 4], children: &[u8]) {
+    // Children are always padded, so a four byte structure lets nests exceed 64 KiB.
+    let struct_size = if children.len() > u16::MAX as usize {
+        4
+    } else {
+        1
+    };
+    klv(out, key, 0, struct_size, children);
+}
+
+fn string(out: &mut Vec<u8>, key: &[u8; 4], value: &str) {
+    klv(out, key, b'c', 1, value.as_bytes());
+}
+
+fn u32s(out: &mut Vec<u8>, key: &[u8; 4], values: &[u32]) {
+    let data: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+    klv(out, key, b'L', 4, &data);
+}
+
+/// Scale factors for the GPS5 fields: latitude, longitude, altitude, 2D speed and 3D speed.
+const GPS5_SCALE: [i32; 5] = [10_000_000, 10_000_000, 1000, 1000, 100];
+
+/// A GPS5 stream, the layout GoPro cameras before the HERO11 write.
+pub fn gps_stream(records: &[GpsRecord]) -> Vec<u8> {
+    let mut stream = Vec::new();
+    string(
+        &mut stream,
+        b"STNM",
+        "GPS (Lat., Long., Alt., 2D speed, 3D speed)",
+    );
+    // No fix information is decoded yet, so records are reported as 3D fixes.
+    u32s(&mut stream, b"GPSF", &[3]);
+    if let Some(time) = records
+        .first()
+        .and_then(|r| DateTime::from_timestamp(r.timestamp as i64, 0))
+    {
+        klv(
+            &mut stream,
+            b"GPSU",
+            b'U',
+            16,
+            time.format("%y%m%d%H%M%S.000").to_string().as_bytes(),
+        );
+    }
+    klv(&mut stream, b"UNIT", b'c', 3, b"degdegm\0\0m/sm/s");
+    let scale: Vec<u8> = GPS5_SCALE.iter().flat_map(|v| v.to_be_bytes()).collect();
+    klv(&mut stream, b"SCAL", b'l', 4, &scale);
+
+    let mut samples = Vec::with_capacity(records.len() * 20);
+    for record in records {
+        let values = [
+            record.latitude.0,
+            record.longitude.0,
+            record.altitude.0,
+            record.speed.0,
+            record.speed.0,
+        ];
+        for (value, scale) in values.iter().zip(GPS5_SCALE) {
+            samples.extend_from_slice(&((value * scale as f64).round() as i32).to_be_bytes());
+        }
+    }
+    klv(&mut stream, b"GPS5", b'l', 20, &samples);
+
+    let mut out = Vec::new();
+    nested(&mut out, b"STRM", &stream);
+    out
+}
+
+/// A GYRO stream in rad/s. Raw counts are written unchanged with a matching SCAL.
+pub fn gyro_stream(records: &[GyroRecord], scale: &ImuScale) -> Vec<u8> {
+    let mut stream = Vec::new();
+    string(&mut stream, b"STNM", "Gyroscope");
+    string(&mut stream, b"SIUN", "rad/s");
+    let counts = scale.gyro_counts_per_rad_s().round() as i16;
+    klv(&mut stream, b"SCAL", b's', 2, &counts.to_be_bytes());
+
+    let samples: Vec<u8> = records
+        .iter()
+        .flat_map(|record| record.gyro_raw())
+        .flat_map(|v| v.to_be_bytes())
+        .collect();
+    klv(&mut stream, b"GYRO", b's', 6, &samples);
+
+    let mut out = Vec::new();
+    nested(&mut out, b"STRM", &stream);
+    out
+}
+
+/// Wraps streams in a device entry, which forms one complete GPMF sample.
+pub fn device(name: &str, streams: &[Vec<u8>]) -> Vec<u8> {
+    let mut device = Vec::new();
+    u32s(&mut device, b"DVID", &[1]);
+    string(&mut device, b"DVNM", name);
+    streams.iter().for_each(|s| device.extend_from_slice(s));
+
+    let mut out = Vec::new();
+    nested(&mut out, b"DEVC", &device);
+    out
+}
+
+/// Builds one GPMF sample per second of video. GPS records are placed using their absolute
+/// time relative to `video_start`, gyro records using the camera clock relative to
+/// `first_frame_timestamp` (milliseconds). Gyro is omitted when `first_frame_timestamp` is `None`.
+pub fn samples_per_second(
+    camera: &str,
+    gps: &[GpsRecord],
+    video_start: u64,
+    gyro: &[GyroRecord],
+    first_frame_timestamp: Option<u64>,
+    scale: &ImuScale,
+    seconds: u64,
+) -> Vec<Vec<u8>> {
+    (0..seconds)
+        .map(|second| {
+            let start = video_start + second;
+            let gps = &gps[gps.partition_point(|r| r.timestamp < start)
+                ..gps.partition_point(|r| r.timestamp < start + 1)];
+            let mut streams = Vec::new();
+            if !gps.is_empty() {
+                streams.push(gps_stream(gps));
+            }
+            if let Some(first) = first_frame_timestamp {
+                let start = first + second * 1000;
+                let gyro = &gyro[gyro.partition_point(|r| r.timestamp < start)
+                    ..gyro.partition_point(|r| r.timestamp < start + 1000)];
+                if !gyro.is_empty() {
+                    streams.push(gyro_stream(gyro, scale));
+                }
+            }
+            device(camera, &streams)
+        })
+        .collect()
+}
+
+/// The `gpmd` sample entry GoPro uses for its metadata track.
+pub fn gpmd_sample_entry() -> Mp4Box {
+    // Six reserved bytes, data reference index 1, then four reserved bytes.
+    Mp4Box::Leaf(*b"gpmd", vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0])
+}
+
+#[derive(Debug)]
+pub struct Klv<'a> {
+    pub key: [u8; 4],
+    pub value_type: u8,
+    pub struct_size: u8,
+    pub repeat: u16,
+    pub data: &'a [u8],
+}
+
+pub fn parse_klv(input: &[u8]) -> IResult<&[u8], Klv<'_>> {
+    let (rest, (key, value_type, struct_size, repeat)) =
+        (take(4usize), be_u8(), be_u8(), be_u16()).parse_complete(input)?;
+    let len = struct_size as usize * repeat as usize;
+    let (rest, data) = take(len).parse_complete(rest)?;
+    // The final entry's padding is sometimes missing.
+    let padding = (len.next_multiple_of(4) - len).min(rest.len());
+    let (rest, _) = take(padding).parse_complete(rest)?;
+    Ok((
+        rest,
+        Klv {
+            key: key.try_into().unwrap(),
+            value_type,
+            struct_size,
+            repeat,
+            data,
+        },
+    ))
+}
+
+pub fn parse_klvs(input: &[u8]) -> Vec<Klv<'_>> {
+    many0(parse_klv)
+        .parse_complete(input)
+        .map(|(_, klvs)| klvs)
+        .unwrap_or_default()
+}
+
+impl<'a> Klv<'a> {
+    pub fn children(&self) -> Vec<Klv<'a>> {
+        if self.value_type == 0 {
+            parse_klvs(self.data)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Every value as f64, for the numeric types.
+    pub fn numbers(&self) -> Vec<f64> {
+        let width = match self.value_type {
+            b'b' | b'B' => 1,
+            b's' | b'S' => 2,
+            b'l' | b'L' | b'f' => 4,
+            b'd' | b'j' | b'J' => 8,
+            _ => return Vec::new(),
+        };
+        self.data
+            .chunks_exact(width)
+            .map(|c| match self.value_type {
+                b'b' => c[0] as i8 as f64,
+                b'B' => c[0] as f64,
+                b's' => i16::from_be_bytes(c.try_into().unwrap()) as f64,
+                b'S' => u16::from_be_bytes(c.try_into().unwrap()) as f64,
+                b'l' => i32::from_be_bytes(c.try_into().unwrap()) as f64,
+                b'L' => u32::from_be_bytes(c.try_into().unwrap()) as f64,
+                b'f' => f32::from_be_bytes(c.try_into().unwrap()) as f64,
+                b'd' => f64::from_be_bytes(c.try_into().unwrap()),
+                b'j' => i64::from_be_bytes(c.try_into().unwrap()) as f64,
+                _ => u64::from_be_bytes(c.try_into().unwrap()) as f64,
+            })
+            .collect()
+    }
+
+    pub fn string(&self) -> String {
+        String::from_utf8_lossy(self.data)
+            .trim_end_matches('\0')
+            .to_string()
+    }
+}
+
+fn parse_gpsu(value: &str) -> Option<f64> {
+    let time = NaiveDateTime::parse_from_str(value, "%y%m%d%H%M%S%.3f").ok()?;
+    Some(time.and_utc().timestamp_millis() as f64 / 1000.0)
+}
+
+/// A GPS sample before the track is derived, with a fractional Unix time.
+struct GpsFix {
+    time: f64,
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+    speed: f64,
+}
+
+/// Decodes a GPS5 or GPS9 stream. GPS5 carries a single GPSU time for the whole sample, so
+/// its fixes are spread evenly over `duration` seconds.
+fn stream_fixes(stream: &[Klv], sample_duration: f64) -> Vec<GpsFix> {
+    let scale = stream
+        .iter()
+        .find(|k| &k.key == b"SCAL")
+        .map(|k| k.numbers())
+        .unwrap_or_default();
+    let scale_at = |i: usize| match scale.len() {
+        0 => 1.0,
+        1 => scale[0],
+        _ => scale.get(i).copied().unwrap_or(1.0),
+    };
+
+    if let Some(gps9) = stream.iter().find(|k| &k.key == b"GPS9") {
+        let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let epoch = epoch.and_utc().timestamp() as f64;
+        return gps9
+            .data
+            .chunks_exact(gps9.struct_size as usize)
+            .filter(|c| c.len() >= 28)
+            .map(|c| {
+                let v: Vec<f64> = (0..7)
+                    .map(|i| {
+                        i32::from_be_bytes(c[i * 4..i * 4 + 4].try_into().unwrap()) as f64
+                            / scale_at(i)
+                    })
+                    .collect();
+                GpsFix {
+                    time: epoch + v[5] * 86400.0 + v[6],
+                    latitude: v[0],
+                    longitude: v[1],
+                    altitude: v[2],
+                    speed: v[3],
+                }
+            })
+            .collect();
+    }
+
+    let Some(gps5) = stream.iter().find(|k| &k.key == b"GPS5") else {
+        return Vec::new();
+    };
+    let Some(start) = stream
+        .iter()
+        .find(|k| &k.key == b"GPSU")
+        .and_then(|k| parse_gpsu(&k.string()))
+    else {
+        return Vec::new();
+    };
+    let values = gps5.numbers();
+    let fixes = values.len() / 5;
+    values
+        .chunks_exact(5)
+        .enumerate()
+        .map(|(i, v)| GpsFix {
+            time: start + sample_duration * i as f64 / fixes as f64,
+            latitude: v[0] / scale_at(0),
+            longitude: v[1] / scale_at(1),
+            altitude: v[2] / scale_at(2),
+            speed: v[3] / scale_at(3),
+        })
+        .collect()
+}
+
+/// Decodes the GPS streams from every sample of a GPMF track. GPMF has no course over ground,
+/// so `track` is the bearing from the previous fix.
+pub fn gps_records(track: &TrackSamples) -> Vec<GpsRecord> {
+    let mut fixes = Vec::new();
+    for sample in &track.samples {
+        let duration = sample.duration as f64 / track.timescale as f64;
+        for device in parse_klvs(sample.data).iter().filter(|k| &k.key == b"DEVC") {
+            for stream in device.children().iter().filter(|k| &k.key == b"STRM") {
+                fixes.extend(stream_fixes(&stream.children(), duration));
+            }
+        }
+    }
+
+    let bearing =
+        |a: &GpsFix, b: &GpsFix| initial_bearing(a.latitude, a.longitude, b.latitude, b.longitude);
+    (0..fixes.len())
+        .map(|i| {
+            let fix = &fixes[i];
+            let track = match i {
+                0 => fixes.get(1).map_or(0.0, |next| bearing(fix, next)),
+                _ => bearing(&fixes[i - 1], fix),
+            };
+            GpsRecord {
+                timestamp: fix.time as u64,
+                latitude: Degrees(fix.latitude),
+                longitude: Degrees(fix.longitude),
+                speed: MetersPerSecond(fix.speed),
+                track: Degrees(track),
+                altitude: Meters(fix.altitude),
+                fix: None,
+            }
+        })
+        .collect()
+}
+
+/// Reads GPS from a GoPro MP4's `gpmd` track.
+pub fn read_gopro_telemetry(data: &[u8]) -> Option<Telemetry> {
+    let track = find_track_samples(data, b"gpmd")?;
+    Some(Telemetry {
+        gps: gps_records(&track),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gps_stream() {
+        let record = GpsRecord {
+            timestamp: 1752824362,
+            latitude: Degrees(49.25853492931603),
+            longitude: Degrees(-4.03079459928793),
+            speed: MetersPerSecond(1.5),
+            track: Degrees(0.0),
+            altitude: Meters(86.40542984008789),
+            fix: None,
+        };
+        let stream = gps_stream(&[record]);
+        assert_eq!(&stream[..4], b"STRM");
+        assert_eq!(stream.len() % 4, 0);
+
+        let gpsu = stream.windows(4).position(|w| w == b"GPSU").unwrap();
+        assert_eq!(&stream[gpsu + 8..gpsu + 24], b"250718073922.000");
+
+        let gps5 = stream.windows(4).position(|w| w == b"GPS5").unwrap();
+        assert_eq!(&stream[gps5 + 4..gps5 + 8], &[b'l', 20, 0, 1]);
+        let values: Vec<i32> = stream[gps5 + 8..gps5 + 28]
+            .chunks(4)
+            .map(|c| i32::from_be_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, vec![492585349, -40307946, 86405, 1500, 150]);
+    }
+
+    #[test]
+    fn test_malformed_klvs() {
+        // Two bytes of data, padded to four; the padding may be missing at the end.
+        let klv = [&b"TEST"[..], &[b'S', 2, 0, 1], &[1, 2, 0, 0]].concat();
+        let (rest, parsed) = parse_klv(&klv).unwrap();
+        assert_eq!((parsed.data, rest.len()), (&[1, 2][..], 0));
+        assert_eq!(parse_klv(&klv[..10]).unwrap().1.data, [1, 2]);
+
+        // A header cut short, or data shorter than the header claims.
+        assert!(parse_klv(&klv[..7]).is_err());
+        assert!(parse_klv(&klv[..9]).is_err());
+        let mut overlong = klv.clone();
+        overlong[6..8].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(parse_klv(&overlong).is_err());
+
+        // A list keeps the entries before one that doesn't parse.
+        let stream = [&klv[..], &klv[..], &klv[..9]].concat();
+        assert_eq!(parse_klvs(&stream).len(), 2);
+        let nested = Klv {
+            key: *b"STRM",
+            value_type: 0,
+            struct_size: 1,
+            repeat: 9,
+            data: &klv[..9],
+        };
+        assert!(nested.children().is_empty());
+
+        let track = TrackSamples {
+            timescale: 1000,
+            samples: vec![crate::mp4::Sample {
+                time: 0,
+                duration: 1000,
+                data: &stream,
+            }],
+        };
+        assert!(gps_records(&track).is_empty());
+        assert!(read_gopro_telemetry(b"not a GoPro video").is_none());
+    }
+
+    #[test]
+    fn test_gps_round_trip() {
+        let records: Vec<GpsRecord> = (0..4)
+            .map(|i| GpsRecord {
+                timestamp: 1752824362,
+                latitude: Degrees(49.2585 + i as f64 * 0.0001),
+                longitude: Degrees(4.0307),
+                speed: MetersPerSecond(2.0),
+                track: Degrees(0.0),
+                altitude: Meters(86.5),
+                fix: None,
+            })
+            .collect();
+        let sample = device("Test", &[gps_stream(&records)]);
+        let track = TrackSamples {
+            timescale: 1000,
+            samples: vec![crate::mp4::Sample {
+                time: 0,
+                duration: 1000,
+                data: &sample,
+            }],
+        };
+
+        let decoded = gps_records(&track);
+        assert_eq!(decoded.len(), 4);
+        assert_eq!(decoded[3].timestamp, 1752824362);
+        assert_eq!(decoded[3].latitude.0, 49.2588);
+        assert_eq!(decoded[3].altitude.0, 86.5);
+        assert_eq!(decoded[3].speed.0, 2.0);
+        assert_eq!(decoded[3].track.0, 0.0);
+    }
+}
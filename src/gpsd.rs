@@ -0,0 +1,125 @@
+//! The JSON protocol gpsd speaks to its clients, for serving a recorded track as if it came from
+//! a live receiver.
+//!
+//! A client is greeted with a `VERSION` object and receives a `TPV` (time, position, velocity)
+//! object per GPS record once it has sent `?WATCH={"enable":true}`. Objects are one per line.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::{FixStatus, GpsRecord};
+
+/// The gpsd protocol version implemented.
+const PROTO_MAJOR: u32 = 3;
+const PROTO_MINOR: u32 = 14;
+
+/// The device path reported to clients.
+pub const DEVICE: &str = "ginsta";
+
+pub fn version() -> String {
+    format!(
+        "{{\"class\":\"VERSION\",\"release\":\"ginsta {}\",\"rev\":\"{}\",\
+         \"proto_major\":{PROTO_MAJOR},\"proto_minor\":{PROTO_MINOR}}}\n",
+        env!("CARGO_PKG_VERSION"),
+        env!("CARGO_PKG_VERSION"),
+    )
+}
+
+fn devices() -> String {
+    format!(
+        "{{\"class\":\"DEVICES\",\"devices\":[{{\"class\":\"DEVICE\",\"path\":\"{DEVICE}\"}}]}}\n"
+    )
+}
+
+/// A 3D fix, as the camera records altitude with every position, or no fix for records the
+/// receiver wrote without one.
+pub fn tpv(record: &GpsRecord) -> String {
+    let time = DateTime::<Utc>::from_timestamp(record.timestamp as i64, 0).unwrap_or_default();
+    let mode = if record.fix == Some(FixStatus::NoFix) {
+        1
+    } else {
+        3
+    };
+    format!(
+        "{{\"class\":\"TPV\",\"device\":\"{DEVICE}\",\"mode\":{mode},\"time\":\"{}\",\
+         \"lat\":{},\"lon\":{},\"alt\":{},\"track\":{},\"speed\":{}}}\n",
+        time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        record.latitude.0,
+        record.longitude.0,
+        record.altitude.0,
+        record.track.0,
+        record.speed.0,
+    )
+}
+
+/// A client's state: whether it has asked for reports.
+#[derive(Debug, Default)]
+pub struct Session {
+    pub watching: bool,
+}
+
+impl Session {
+    /// Applies one line from the client, such as `?WATCH={"enable":true,"json":true};`, and
+    /// returns the reply. Unknown commands get an `ERROR` object.
+    pub fn handle(&mut self, line: &str) -> String {
+        let line = line.trim().trim_end_matches(';');
+        let (command, argument) = line.split_once('=').unwrap_or((line, ""));
+        match command {
+            "?WATCH" => {
+                let argument: String = argument.split_whitespace().collect();
+                self.watching = !argument.contains("\"enable\":false");
+                format!(
+                    "{}{{\"class\":\"WATCH\",\"enable\":{},\"json\":true}}\n",
+                    devices(),
+                    self.watching
+                )
+            }
+            "?VERSION" => version(),
+            "?DEVICES" => devices(),
+            "" => String::new(),
+            _ => format!(
+                "{{\"class\":\"ERROR\",\"message\":\"Unrecognized request '{}'\"}}\n",
+                command.trim_start_matches('?').replace(['"', '\\'], "")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_session() {
+        let record = GpsRecord {
+            // 2025-07-18 07:39:22 UTC.
+            timestamp: 1752824362,
+            latitude: Degrees(49.25),
+            longitude: Degrees(-4.0305),
+            speed: MetersPerSecond(5.0),
+            track: Degrees(335.0),
+            altitude: Meters(86.0),
+            fix: None,
+        };
+        assert_eq!(
+            tpv(&record),
+            "{\"class\":\"TPV\",\"device\":\"ginsta\",\"mode\":3,\
+             \"time\":\"2025-07-18T07:39:22.000Z\",\"lat\":49.25,\"lon\":-4.0305,\"alt\":86,\
+             \"track\":335,\"speed\":5}\n"
+        );
+
+        let mut session = Session::default();
+        assert!(
+            session
+                .handle("?VERSION;\n")
+                .starts_with("{\"class\":\"VERSION\"")
+        );
+        assert!(!session.watching);
+        let reply = session.handle("?WATCH={\"enable\": true, \"json\": true};\n");
+        assert!(reply.ends_with("{\"class\":\"WATCH\",\"enable\":true,\"json\":true}\n"));
+        assert!(session.watching);
+        session.handle("?WATCH={\"enable\":false};");
+        assert!(!session.watching);
+        assert!(session.handle("?FOO;").contains("\"class\":\"ERROR\""));
+    }
+}
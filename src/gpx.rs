@@ -0,0 +1,193 @@
+use std::io::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::{
+    GpsRecord,
+    align::{bracket, lerp},
+    layout::Layout,
+};
+
+/// Longest gap between heart-rate samples that is interpolated across, in seconds.
+pub const MAX_HEART_RATE_GAP: f64 = 5.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeartRateSample {
+    pub timestamp: f64, // Unix time in seconds.
+    pub bpm: f64,
+}
+
+/// Heart rate at `time`, interpolated between the samples either side of it, or `None` outside
+/// the samples or across a gap longer than `MAX_HEART_RATE_GAP`. `samples` are in time order.
+pub fn heart_rate_at(samples: &[HeartRateSample], time: f64) -> Option<f64> {
+    let (previous, next, fraction) = bracket(samples, time, |sample| sample.timestamp)?;
+    (next.timestamp - previous.timestamp <= MAX_HEART_RATE_GAP)
+        .then(|| lerp(previous.bpm, next.bpm, fraction))
+}
+
+/// The samples in a Heartrate frame read through `layout`, which needs `timestamp` and `bpm`
+/// fields, as the frame's records have no built-in layout. Timestamps count `ticks_per_second`,
+/// 1000 when the layout doesn't say, on the camera clock, which read zero at Unix time
+/// `camera_epoch`. `None` if the layout lacks a field.
+pub fn heart_rate_samples(
+    frame: &[u8],
+    layout: &'static Layout,
+    camera_epoch: f64,
+) -> Option<Vec<HeartRateSample>> {
+    layout.span("timestamp")?;
+    layout.span("bpm")?;
+    let ticks_per_second = layout.ticks_per_second.unwrap_or(1000.0);
+    let (records, _) = layout.records_in(frame.len());
+    let samples = (0..records)
+        .filter_map(|i| layout.view(&frame[i * layout.size()..]))
+        .map(|record| HeartRateSample {
+            timestamp: camera_epoch + record.value("timestamp").as_f64() / ticks_per_second,
+            bpm: record.value("bpm").as_f64(),
+        })
+        .collect();
+    Some(samples)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes the start of a GPX 1.1 document, to be followed by `write_gpx_track` for each track
+/// and closed with `write_gpx_footer`.
+pub fn write_gpx_header(mut out: impl Write) -> std::io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<gpx version="1.1" creator="ginsta" xmlns="http://www.topografix.com/GPX/1/1" xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">"#
+    )
+}
+
+/// Writes a track with one `trkseg` per GPS segment. Points get a Garmin TrackPointExtension
+/// `hr` where `heart_rate` covers their time, which is what Strava and Garmin Connect read.
+pub fn write_gpx_track(
+    mut out: impl Write,
+    name: &str,
+    segments: &[&[GpsRecord]],
+    heart_rate: &[HeartRateSample],
+) -> std::io::Result<()> {
+    writeln!(out, "  <trk>")?;
+    writeln!(out, "    <name>{}</name>", escape(name))?;
+    for segment in segments {
+        writeln!(out, "    <trkseg>")?;
+        for record in *segment {
+            writeln!(
+                out,
+                r#"      <trkpt lat="{:.7}" lon="{:.7}">"#,
+                record.latitude.0, record.longitude.0
+            )?;
+            writeln!(out, "        <ele>{:.1}</ele>", record.altitude.0)?;
+            if let Some(time) = DateTime::<Utc>::from_timestamp(record.timestamp as i64, 0) {
+                writeln!(
+                    out,
+                    "        <time>{}</time>",
+                    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+                )?;
+            }
+            if let Some(bpm) = heart_rate_at(heart_rate, record.timestamp as f64) {
+                writeln!(
+                    out,
+                    "        <extensions><gpxtpx:TrackPointExtension><gpxtpx:hr>{}</gpxtpx:hr></gpxtpx:TrackPointExtension></extensions>",
+                    bpm.round() as u32
+                )?;
+            }
+            writeln!(out, "      </trkpt>")?;
+        }
+        writeln!(out, "    </trkseg>")?;
+    }
+    writeln!(out, "  </trk>")
+}
+
+pub fn write_gpx_footer(mut out: impl Write) -> std::io::Result<()> {
+    writeln!(out, "</gpx>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layout::{Field, FieldKind},
+        units::{Degrees, Meters, MetersPerSecond},
+    };
+
+    #[test]
+    fn test_write_gpx() {
+        let record = |timestamp| GpsRecord {
+            timestamp,
+            latitude: Degrees(49.25),
+            longitude: Degrees(-4.03),
+            speed: MetersPerSecond(0.0),
+            track: Degrees(0.0),
+            altitude: Meters(86.0),
+            fix: None,
+        };
+        let records = [record(1_600_000_000), record(1_600_000_010)];
+        let heart_rate = [
+            HeartRateSample {
+                timestamp: 1_599_999_998.0,
+                bpm: 120.0,
+            },
+            HeartRateSample {
+                timestamp: 1_600_000_002.0,
+                bpm: 130.0,
+            },
+        ];
+        assert_eq!(heart_rate_at(&heart_rate, 1_600_000_000.0), Some(125.0));
+        assert_eq!(heart_rate_at(&heart_rate, 1_600_000_010.0), None);
+
+        let mut gpx = Vec::new();
+        write_gpx_header(&mut gpx).unwrap();
+        write_gpx_track(&mut gpx, "a & b", &[&records], &heart_rate).unwrap();
+        write_gpx_footer(&mut gpx).unwrap();
+        let gpx = String::from_utf8(gpx).unwrap();
+        assert!(gpx.contains("<name>a &amp; b</name>"));
+        assert!(gpx.contains(
+            "<trkpt lat=\"49.2500000\" lon=\"-4.0300000\">\n        <ele>86.0</ele>\n        \
+             <time>2020-09-13T12:26:40Z</time>\n        <extensions>"
+        ));
+        assert_eq!(gpx.matches("<gpxtpx:hr>125</gpxtpx:hr>").count(), 1);
+        assert!(gpx.ends_with("  </trk>\n</gpx>\n"));
+    }
+
+    #[test]
+    fn test_heart_rate_samples() {
+        static LAYOUT: Layout = Layout {
+            fields: &[
+                Field::new("timestamp", FieldKind::U64),
+                Field::new("bpm", FieldKind::U8),
+            ],
+            ticks_per_second: None,
+        };
+        static NO_BPM: Layout = Layout {
+            fields: &[Field::new("timestamp", FieldKind::U64)],
+            ticks_per_second: None,
+        };
+        let frame: Vec<u8> = [(1500u64, 120u8), (2500, 124)]
+            .iter()
+            .flat_map(|(ms, bpm)| [&ms.to_le_bytes()[..], &[*bpm]].concat())
+            .chain([0; 3])
+            .collect();
+        assert_eq!(
+            heart_rate_samples(&frame, &LAYOUT, 1_600_000_000.0).unwrap(),
+            [
+                HeartRateSample {
+                    timestamp: 1_600_000_001.5,
+                    bpm: 120.0
+                },
+                HeartRateSample {
+                    timestamp: 1_600_000_002.5,
+                    bpm: 124.0
+                },
+            ]
+        );
+        assert_eq!(heart_rate_samples(&frame, &NO_BPM, 0.0), None);
+    }
+}
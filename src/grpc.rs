@@ -0,0 +1,165 @@
+//! A gRPC service that decodes files the server can read and streams their records back, for
+//! media asset management systems that already speak gRPC.
+
+use std::{fs::File, path::Path, pin::Pin};
+
+use memmap::MmapOptions;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    Telemetry, dji::read_dji_srt, gpmf::read_gopro_telemetry, metadata_start, read_telemetry,
+    time::TimeBase,
+};
+
+pub mod proto {
+    tonic::include_proto!("ginsta");
+}
+
+pub use proto::telemetry_server::TelemetryServer;
+
+type RecordStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// Serves files from the local filesystem by path.
+#[derive(Debug, Default)]
+pub struct TelemetryService;
+
+/// Reads whichever kind of telemetry the file at `path` holds, with GPS times in UTC.
+fn load(path: &str, time_base: TimeBase) -> Result<Telemetry, Status> {
+    let not_found = |e: std::io::Error| Status::not_found(format!("{path}: {e}"));
+    let file = File::open(path).map_err(not_found)?;
+    let mmap = unsafe { MmapOptions::new().map(&file) }.map_err(not_found)?;
+
+    let is_srt = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("srt"));
+    let mut telemetry = if is_srt {
+        read_dji_srt(&String::from_utf8_lossy(&mmap))
+    } else if metadata_start(&mmap).is_some() {
+        read_telemetry(&mmap)
+    } else {
+        read_gopro_telemetry(&mmap).ok_or_else(|| {
+            Status::invalid_argument(format!("no Insta360 or GoPro telemetry in {path}"))
+        })?
+    };
+    for record in telemetry.gps.iter_mut() {
+        record.timestamp = time_base.to_utc(record.timestamp);
+    }
+    Ok(telemetry)
+}
+
+impl TelemetryService {
+    async fn load(request: Request<proto::ExportRequest>) -> Result<Telemetry, Status> {
+        let request = request.into_inner();
+        let time_base = match request.time_base() {
+            proto::TimeBase::Utc => TimeBase::Utc,
+            proto::TimeBase::Gps => TimeBase::Gps,
+        };
+        tokio::task::spawn_blocking(move || load(&request.path, time_base))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+    }
+}
+
+fn stream<T: Send + 'static>(records: impl Iterator<Item = T>) -> Response<RecordStream<T>> {
+    let records: Vec<_> = records.map(Ok).collect();
+    Response::new(Box::pin(tokio_stream::iter(records)))
+}
+
+#[tonic::async_trait]
+impl proto::telemetry_server::Telemetry for TelemetryService {
+    type ExportGpsStream = RecordStream<proto::GpsRecord>;
+    type ExportGyroStream = RecordStream<proto::GyroRecord>;
+    type ExportExposureStream = RecordStream<proto::ExposureRecord>;
+
+    async fn export_gps(
+        &self,
+        request: Request<proto::ExportRequest>,
+    ) -> Result<Response<Self::ExportGpsStream>, Status> {
+        let telemetry = Self::load(request).await?;
+        Ok(stream(telemetry.gps.into_iter().map(|record| {
+            proto::GpsRecord {
+                timestamp: record.timestamp,
+                latitude: record.latitude.0,
+                longitude: record.longitude.0,
+                speed: record.speed.0,
+                track: record.track.0,
+                altitude: record.altitude.0,
+            }
+        })))
+    }
+
+    async fn export_gyro(
+        &self,
+        request: Request<proto::ExportRequest>,
+    ) -> Result<Response<Self::ExportGyroStream>, Status> {
+        let telemetry = Self::load(request).await?;
+        Ok(stream(telemetry.gyro.into_iter().map(|record| {
+            let [accel_x, accel_y, accel_z] = record.accel_raw().map(i32::from);
+            let [gyro_x, gyro_y, gyro_z] = record.gyro_raw().map(i32::from);
+            proto::GyroRecord {
+                timestamp: record.timestamp,
+                accel_x,
+                accel_y,
+                accel_z,
+                gyro_x,
+                gyro_y,
+                gyro_z,
+            }
+        })))
+    }
+
+    async fn export_exposure(
+        &self,
+        request: Request<proto::ExportRequest>,
+    ) -> Result<Response<Self::ExportExposureStream>, Status> {
+        let telemetry = Self::load(request).await?;
+        Ok(stream(telemetry.exposure.into_iter().map(|record| {
+            proto::ExposureRecord {
+                timestamp: record.timestamp,
+                shutter_speed: record.shutterspeed.0,
+            }
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::telemetry_server::Telemetry as _;
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn test_export_gps() {
+        let path = std::env::temp_dir().join("ginsta-grpc-test.srt");
+        std::fs::write(
+            &path,
+            "1\n00:00:00,000 --> 00:00:01,000\nHOME(4.0000,52.0000) 2017.08.05 14:11:51\n\
+             GPS(4.0001,52.0001,16) ISO:100 Shutter:60 EV:0 Fnum:2.2\n",
+        )
+        .unwrap();
+        let request = |path: &Path| {
+            Request::new(proto::ExportRequest {
+                path: path.to_string_lossy().into_owned(),
+                time_base: proto::TimeBase::Utc.into(),
+            })
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let records: Vec<_> = runtime.block_on(async {
+            let response = TelemetryService.export_gps(request(&path)).await.unwrap();
+            response.into_inner().collect().await
+        });
+        std::fs::remove_file(&path).unwrap();
+        let records: Vec<_> = records.into_iter().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].timestamp, 1501942311);
+        assert_eq!(records[0].latitude, 52.0001);
+
+        let missing = runtime
+            .block_on(TelemetryService.export_gps(request(&path)))
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+    }
+}
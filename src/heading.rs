@@ -0,0 +1,330 @@
+//! Compares the GPS course with the heading integrated from the gyro and the heading read from
+//! the magnetometer, to show how far they drift apart and where, such as a compass needing
+//! calibration.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    GpsRecord, GyroRecord,
+    align::{bracket, lerp, lerp_degrees},
+    imu::ImuScale,
+    magnetometer::MagSample,
+    units::Degrees,
+};
+
+/// Slowest speed in m/s at which the GPS course is trusted.
+pub const MIN_HEADING_SPEED: f64 = 2.0;
+
+/// Time constant in seconds of the low-pass filter taking gravity from the accelerometer.
+const GRAVITY_TIME_CONSTANT: f64 = 1.0;
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HeadingComparison {
+    /// Unix time in whole seconds, from the GPS record.
+    pub timestamp: u64,
+    /// GPS course over ground.
+    pub gps_track: Degrees,
+    /// Gyro heading, started from the GPS course at the first record fast enough to trust it.
+    pub gyro_heading: Option<Degrees>,
+    /// `gyro_heading` minus `gps_track`, in (-180, 180]. Empty below `MIN_HEADING_SPEED`.
+    pub gyro_delta: Option<f64>,
+    /// Magnetometer heading, turned by the mount's offset from the GPS course at the first record
+    /// fast enough to trust it. Empty without magnetometer samples.
+    pub mag_heading: Option<Degrees>,
+    /// `mag_heading` minus `gps_track`, in (-180, 180]. Empty below `MIN_HEADING_SPEED`.
+    pub mag_delta: Option<f64>,
+    /// `mag_heading` minus `gyro_heading`, in (-180, 180], which needs no GPS course, so shows
+    /// compass errors while turning on the spot too.
+    pub mag_gyro_delta: Option<f64>,
+}
+
+/// The direction of up in the camera frame at each record, low-pass filtered from the
+/// accelerometer, and not normalised.
+pub fn gravity(records: &[GyroRecord], scale: &ImuScale) -> Vec<[f64; 3]> {
+    let mut up = [0.0; 3];
+    let mut previous: Option<u64> = None;
+    records
+        .iter()
+        .map(|record| {
+            let accel = scale.accel_g(record);
+            let alpha = match previous {
+                None => 1.0,
+                Some(t) => {
+                    let dt = record.timestamp.saturating_sub(t) as f64 / 1000.0;
+                    dt / (GRAVITY_TIME_CONSTANT + dt)
+                }
+            };
+            for (up, accel) in up.iter_mut().zip(accel) {
+                *up += alpha * (accel - *up);
+            }
+            previous = Some(record.timestamp);
+            up
+        })
+        .collect()
+}
+
+/// Heading change in degrees integrated from the gyro up to each record, unwrapped. The gyro is
+/// projected onto gravity from the accelerometer, so the result doesn't depend on how the camera
+/// is mounted.
+pub fn integrate_yaw(records: &[GyroRecord], scale: &ImuScale) -> Vec<(u64, f64)> {
+    let mut yaw = Vec::with_capacity(records.len());
+    let mut heading = 0.0;
+    let mut previous: Option<u64> = None;
+    for (record, up) in records.iter().zip(gravity(records, scale)) {
+        let dt = previous.map_or(0.0, |t| record.timestamp.saturating_sub(t) as f64 / 1000.0);
+        let norm = up.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            let rate = scale.gyro_rad_s(record);
+            // Counter-clockwise about up is a turn to the left, so heading falls.
+            let yaw_rate = -rate.iter().zip(up).map(|(r, u)| r * u).sum::<f64>() / norm;
+            heading += yaw_rate.to_degrees() * dt;
+        }
+        yaw.push((record.timestamp, heading));
+        previous = Some(record.timestamp);
+    }
+    yaw
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// `v` with its component along the unit vector `axis` removed.
+fn reject(v: [f64; 3], axis: [f64; 3]) -> [f64; 3] {
+    let along = dot(v, axis);
+    core::array::from_fn(|i| v[i] - along * axis[i])
+}
+
+fn normalised(v: [f64; 3]) -> Option<[f64; 3]> {
+    let norm = dot(v, v).sqrt();
+    (norm > 1e-9).then(|| v.map(|v| v / norm))
+}
+
+/// The magnetic heading in degrees of the camera at each of `samples` that the gyro records
+/// span, with its camera-clock time in milliseconds. The magnetometer is taken to share the
+/// IMU's axes. Heading is that of the camera axis furthest from vertical at the first sample,
+/// which stays fixed for the clip, so only differences between headings are meaningful.
+pub fn mag_headings(
+    samples: &[MagSample],
+    gyro: &[GyroRecord],
+    scale: &ImuScale,
+) -> Vec<(f64, f64)> {
+    let ups: Vec<(u64, [f64; 3])> = (gyro.iter())
+        .map(|record| record.timestamp)
+        .zip(gravity(gyro, scale))
+        .collect();
+    let up_at = |t: f64| {
+        let ((_, a), (_, b), f) = bracket(&ups, t, |(ms, _)| *ms as f64)?;
+        normalised(core::array::from_fn(|k| lerp(a[k], b[k], f)))
+    };
+    let mut forward = None;
+    samples
+        .iter()
+        .filter_map(|sample| {
+            let up = up_at(sample.timestamp)?;
+            let forward = *forward.get_or_insert_with(|| {
+                let axes = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+                (axes.into_iter())
+                    .min_by(|a, b| dot(*a, up).abs().total_cmp(&dot(*b, up).abs()))
+                    .unwrap()
+            });
+            let north = normalised(reject(sample.reading(), up))?;
+            // East is north × up for a right-handed frame with up out of the ground.
+            let east = [
+                north[1] * up[2] - north[2] * up[1],
+                north[2] * up[0] - north[0] * up[2],
+                north[0] * up[1] - north[1] * up[0],
+            ];
+            let forward = reject(forward, up);
+            let heading = dot(forward, east).atan2(dot(forward, north)).to_degrees();
+            Some((sample.timestamp, heading.rem_euclid(360.0)))
+        })
+        .collect()
+}
+
+fn wrap_degrees(angle: f64) -> f64 {
+    let wrapped = (angle + 180.0).rem_euclid(360.0) - 180.0;
+    if wrapped == -180.0 { 180.0 } else { wrapped }
+}
+
+/// Compares each GPS record's course with the gyro heading and the magnetometer heading of `mag`
+/// at the same time. Gyro and magnetometer timestamps are camera-clock milliseconds;
+/// `camera_epoch` is the Unix time at which that clock read zero.
+pub fn compare_headings(
+    gps: &[GpsRecord],
+    gyro: &[GyroRecord],
+    mag: &[MagSample],
+    camera_epoch: f64,
+    scale: &ImuScale,
+) -> Vec<HeadingComparison> {
+    let yaw = integrate_yaw(gyro, scale);
+    let camera_time = |timestamp: u64| (timestamp as f64 - camera_epoch) * 1000.0;
+    let yaw_at = |timestamp: u64| {
+        bracket(&yaw, camera_time(timestamp), |(ms, _)| *ms as f64)
+            .map(|(a, b, f)| lerp(a.1, b.1, f))
+    };
+    let mag = mag_headings(mag, gyro, scale);
+    let mag_at = |timestamp: u64| {
+        bracket(&mag, camera_time(timestamp), |(ms, _)| *ms)
+            .map(|(a, b, f)| lerp_degrees(a.1, b.1, f))
+    };
+
+    let (mut gyro_offset, mut mag_offset) = (None, None);
+    gps.iter()
+        .map(|record| {
+            let moving = record.speed.0 >= MIN_HEADING_SPEED;
+            let yaw = yaw_at(record.timestamp);
+            let mag = mag_at(record.timestamp);
+            if moving && gyro_offset.is_none() {
+                gyro_offset = yaw.map(|yaw| record.track.0 - yaw);
+            }
+            if moving && mag_offset.is_none() {
+                mag_offset = mag.map(|mag| record.track.0 - mag);
+            }
+            let heading = |value: Option<f64>, offset: Option<f64>| {
+                value
+                    .zip(offset)
+                    .map(|(value, offset)| (value + offset).rem_euclid(360.0))
+            };
+            let gyro_heading = heading(yaw, gyro_offset);
+            let mag_heading = heading(mag, mag_offset);
+            let gps_delta = |heading: Option<f64>| {
+                heading
+                    .filter(|_| moving)
+                    .map(|heading| wrap_degrees(heading - record.track.0))
+            };
+            HeadingComparison {
+                timestamp: record.timestamp,
+                gps_track: record.track,
+                gyro_heading: gyro_heading.map(Degrees),
+                gyro_delta: gps_delta(gyro_heading),
+                mag_heading: mag_heading.map(Degrees),
+                mag_delta: gps_delta(mag_heading),
+                mag_gyro_delta: mag_heading
+                    .zip(gyro_heading)
+                    .map(|(mag, gyro)| wrap_degrees(mag - gyro)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        magnetometer::MagCalibration,
+        units::{Meters, MetersPerSecond},
+    };
+
+    #[test]
+    fn test_compare_headings() {
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        // Level, turning clockwise seen from above at 10 degrees a second for 10 seconds.
+        let counts = |value: f64| value.round() as i16;
+        let gyro: Vec<GyroRecord> = (0..=100)
+            .map(|i| {
+                let z = counts(-10f64.to_radians() * scale.gyro_counts_per_rad_s());
+                GyroRecord::from_raw(5000 + i * 100, [0, 0, counts(32768.0 / 16.0), 0, 0, z])
+            })
+            .collect();
+        let gps: Vec<GpsRecord> = (0..=10)
+            .map(|i| GpsRecord {
+                timestamp: 1000 + i,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(if i == 0 { 0.0 } else { 5.0 }),
+                track: Degrees((350.0 + i as f64 * 10.0) % 360.0),
+                altitude: Meters(0.0),
+                fix: None,
+            })
+            .collect();
+
+        let comparison = compare_headings(&gps, &gyro, &[], 995.0, &scale);
+        assert_eq!(comparison[0].gyro_delta, None);
+        assert_eq!(comparison[1].gyro_heading, Some(Degrees(0.0)));
+        let last = comparison.last().unwrap();
+        assert!(last.gyro_delta.unwrap().abs() < 0.5, "{last:?}");
+        assert_eq!(last.mag_heading, None);
+
+        // A field pointing north and down, read by a camera whose x axis points along the course.
+        let mag_at = |hard_iron: f64| -> Vec<MagSample> {
+            (0..=20)
+                .map(|k| {
+                    let heading = (350.0 + k as f64 * 5.0).to_radians();
+                    MagSample {
+                        timestamp: 5000.0 + k as f64 * 500.0,
+                        x: 20.0 * heading.cos() + hard_iron,
+                        y: 20.0 * heading.sin(),
+                        z: -40.0,
+                    }
+                })
+                .collect()
+        };
+        let comparison = compare_headings(&gps, &gyro, &mag_at(0.0), 995.0, &scale);
+        assert_eq!(comparison[0].mag_delta, None);
+        assert!(comparison[0].mag_gyro_delta.is_none());
+        for row in &comparison[1..] {
+            assert!(row.mag_delta.unwrap().abs() < 1e-6, "{row:?}");
+            assert!(row.mag_gyro_delta.unwrap().abs() < 0.5, "{row:?}");
+        }
+
+        // An uncorrected hard-iron offset makes the compass swing against the course as the
+        // camera turns, until it's calibrated out.
+        let biased = mag_at(15.0);
+        let comparison = compare_headings(&gps, &gyro, &biased, 995.0, &scale);
+        let worst = (comparison.iter())
+            .filter_map(|row| row.mag_delta)
+            .map(f64::abs)
+            .fold(0.0, f64::max);
+        assert!(worst > 10.0, "{comparison:?}");
+        let calibration = MagCalibration {
+            offset: [15.0, 0.0, 0.0],
+            soft_iron: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            field_strength: 20.0,
+        };
+        let calibrated: Vec<MagSample> = biased.iter().map(|s| calibration.calibrate(s)).collect();
+        let comparison = compare_headings(&gps, &gyro, &calibrated, 995.0, &scale);
+        assert!(
+            comparison[1..]
+                .iter()
+                .all(|row| row.mag_delta.unwrap().abs() < 1e-6)
+        );
+    }
+
+    #[test]
+    fn test_mag_headings() {
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        // Standing on its side, with y up, so the heading is that of x, the first axis
+        // furthest from vertical.
+        let gyro: Vec<GyroRecord> = (0..=10)
+            .map(|i| GyroRecord::from_raw(i * 100, [0, 2048, 0, 0, 0, 0]))
+            .collect();
+        let sample = |timestamp, [x, y, z]: [f64; 3]| MagSample { timestamp, x, y, z };
+        let headings = mag_headings(
+            &[
+                sample(0.0, [1.0, -2.0, 0.0]),
+                sample(500.0, [0.0, -2.0, 1.0]),
+                // After the last gyro record, so without a known up.
+                sample(1500.0, [1.0, -2.0, 0.0]),
+                // Straight down, so without a north.
+                sample(600.0, [0.0, -2.0, 0.0]),
+            ],
+            &gyro,
+            &scale,
+        );
+        assert_eq!(headings.len(), 2);
+        assert_eq!(headings[0], (0.0, 0.0));
+        assert_eq!(headings[1].0, 500.0);
+        // North along z puts x, with up along y, to the west.
+        assert!((headings[1].1 - 270.0).abs() < 1e-9, "{headings:?}");
+    }
+}
@@ -0,0 +1,304 @@
+use std::{io::Write, path::Path};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{GpsRecord, GyroRecord, Telemetry, imu::ImuScale};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum HighlightKind {
+    /// Ground speed well above the clip's average.
+    Speed,
+    /// Rapid change of GPS course while moving.
+    Turn,
+    /// High angular rate from the gyroscope.
+    Rotation,
+}
+
+impl HighlightKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            HighlightKind::Speed => "speed peak",
+            HighlightKind::Turn => "rapid turn",
+            HighlightKind::Rotation => "fast rotation",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Highlight {
+    pub kind: HighlightKind,
+    /// Seconds from the start of the video.
+    pub start: f64,
+    /// Seconds.
+    pub duration: f64,
+    /// m/s for speed, degrees per second for turns and rotation.
+    pub peak: f64,
+    /// Standard deviations above the clip's mean.
+    pub score: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HighlightOptions {
+    /// Minimum standard deviations above the mean for a second to count as interesting.
+    pub min_score: f64,
+    /// Seconds added before and after each highlight.
+    pub padding: f64,
+    /// Course changes are ignored below this speed in m/s, where GPS heading is mostly noise.
+    pub min_turn_speed: f64,
+    pub top: usize,
+}
+
+/// A value per second, keyed by seconds from the start of the video.
+type Signal = Vec<(f64, f64)>;
+
+pub fn speed_signal(records: &[GpsRecord], video_start: f64) -> Signal {
+    records
+        .iter()
+        .map(|r| (r.timestamp as f64 - video_start, r.speed.0))
+        .filter(|(t, _)| *t >= 0.0)
+        .collect()
+}
+
+/// Rate of course change in degrees per second between consecutive GPS records.
+pub fn turn_signal(records: &[GpsRecord], video_start: f64, min_speed: f64) -> Signal {
+    records
+        .windows(2)
+        .filter(|pair| pair[0].speed.0 >= min_speed && pair[1].speed.0 >= min_speed)
+        .filter_map(|pair| {
+            let dt = pair[1].timestamp.checked_sub(pair[0].timestamp)? as f64;
+            let t = pair[1].timestamp as f64 - video_start;
+            let change = (pair[1].track.0 - pair[0].track.0 + 540.0).rem_euclid(360.0) - 180.0;
+            (dt > 0.0 && t >= 0.0).then_some((t, change.abs() / dt))
+        })
+        .collect()
+}
+
+/// Peak angular speed in each second. Gyro timestamps are camera-clock milliseconds with the
+/// first video frame at `first_frame_timestamp`.
+pub fn rotation_signal(
+    records: &[GyroRecord],
+    first_frame_timestamp: u64,
+    scale: &ImuScale,
+) -> Signal {
+    let mut signal: Signal = Vec::new();
+    for record in records {
+        let Some(ms) = record.timestamp.checked_sub(first_frame_timestamp) else {
+            continue;
+        };
+        let t = (ms / 1000) as f64;
+        let rate = scale.angular_speed_deg_s(record);
+        match signal.last_mut() {
+            Some((last, peak)) if *last == t => *peak = peak.max(rate),
+            _ => signal.push((t, rate)),
+        }
+    }
+    signal
+}
+
+/// Finds runs of consecutive samples scoring at least `min_score` standard deviations above
+/// the signal's mean.
+fn detect(kind: HighlightKind, signal: &[(f64, f64)], min_score: f64) -> Vec<Highlight> {
+    if signal.is_empty() {
+        return Vec::new();
+    }
+    let n = signal.len() as f64;
+    let mean = signal.iter().map(|(_, v)| v).sum::<f64>() / n;
+    let std = (signal.iter().map(|(_, v)| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+    if std == 0.0 {
+        return Vec::new();
+    }
+
+    signal
+        .chunk_by(|a, b| {
+            let score = |v: f64| (v - mean) / std;
+            (score(a.1) >= min_score) == (score(b.1) >= min_score) && b.0 - a.0 <= 1.0
+        })
+        .filter(|run| (run[0].1 - mean) / std >= min_score)
+        .map(|run| {
+            let peak = run.iter().map(|(_, v)| *v).fold(f64::MIN, f64::max);
+            Highlight {
+                kind,
+                start: run[0].0,
+                duration: run[run.len() - 1].0 - run[0].0 + 1.0,
+                peak,
+                score: (peak - mean) / std,
+            }
+        })
+        .collect()
+}
+
+/// Detects highlights in every available stream and returns the best `options.top`, highest
+/// score first. Gyro is only used when `first_frame_timestamp` places it on the video timeline.
+pub fn find_highlights(
+    telemetry: &Telemetry,
+    video_start: Option<f64>,
+    first_frame_timestamp: Option<u64>,
+    scale: &ImuScale,
+    options: &HighlightOptions,
+) -> Vec<Highlight> {
+    let mut highlights = Vec::new();
+    if let Some(video_start) = video_start {
+        let speed = speed_signal(&telemetry.gps, video_start);
+        highlights.extend(detect(HighlightKind::Speed, &speed, options.min_score));
+        let turns = turn_signal(&telemetry.gps, video_start, options.min_turn_speed);
+        highlights.extend(detect(HighlightKind::Turn, &turns, options.min_score));
+    }
+    if let Some(first_frame_timestamp) = first_frame_timestamp {
+        let rotation = rotation_signal(&telemetry.gyro, first_frame_timestamp, scale);
+        highlights.extend(detect(
+            HighlightKind::Rotation,
+            &rotation,
+            options.min_score,
+        ));
+    }
+
+    for highlight in highlights.iter_mut() {
+        let start = (highlight.start - options.padding).max(0.0);
+        highlight.duration += highlight.start - start + options.padding;
+        highlight.start = start;
+    }
+    highlights.sort_by(|a, b| b.score.total_cmp(&a.score));
+    highlights.truncate(options.top);
+    highlights
+}
+
+/// SMPTE non-drop-frame timecode at an integer frame rate.
+pub fn timecode(seconds: f64, fps: u32) -> String {
+    let frames = (seconds * fps as f64).round() as u64;
+    let fps = fps as u64;
+    let whole = frames / fps;
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        whole / 3600,
+        whole / 60 % 60,
+        whole % 60,
+        frames % fps
+    )
+}
+
+/// Writes a CMX3600 edit decision list with one event per highlight, cut back to back.
+pub fn write_edl(
+    mut out: impl Write,
+    title: &str,
+    clip_name: &str,
+    highlights: &[Highlight],
+    fps: u32,
+) -> std::io::Result<()> {
+    writeln!(out, "TITLE: {title}")?;
+    writeln!(out, "FCM: NON-DROP FRAME")?;
+    let mut record_start = 0.0;
+    for (i, highlight) in highlights.iter().enumerate() {
+        let end = highlight.start + highlight.duration;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{:03}  AX       V     C        {} {} {} {}",
+            i + 1,
+            timecode(highlight.start, fps),
+            timecode(end, fps),
+            timecode(record_start, fps),
+            timecode(record_start + highlight.duration, fps),
+        )?;
+        writeln!(out, "* FROM CLIP NAME: {clip_name}")?;
+        writeln!(out, "* COMMENT: {}", highlight.kind.label())?;
+        record_start += highlight.duration;
+    }
+    Ok(())
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Writes a shell script that cuts each highlight into its own file without re-encoding.
+pub fn write_ffmpeg_script(
+    mut out: impl Write,
+    input: &Path,
+    highlights: &[Highlight],
+) -> std::io::Result<()> {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    writeln!(out, "#!/bin/sh")?;
+    for (i, highlight) in highlights.iter().enumerate() {
+        writeln!(
+            out,
+            "# {}: {} ({:.1})",
+            i + 1,
+            highlight.kind.label(),
+            highlight.peak
+        )?;
+        writeln!(
+            out,
+            "ffmpeg -ss {:.3} -t {:.3} -i {} -map 0 -c copy {}",
+            highlight.start,
+            highlight.duration,
+            shell_quote(&input.to_string_lossy()),
+            shell_quote(&format!("{stem}-highlight-{:02}.mp4", i + 1)),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_find_highlights() {
+        let gps: Vec<GpsRecord> = (0..20)
+            .map(|i| GpsRecord {
+                timestamp: 1000 + i,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(if (10..12).contains(&i) { 20.0 } else { 5.0 }),
+                track: Degrees(if i >= 15 { 90.0 } else { 0.0 }),
+                altitude: Meters(0.0),
+                fix: None,
+            })
+            .collect();
+        let telemetry = Telemetry {
+            gps,
+            ..Default::default()
+        };
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        let options = HighlightOptions {
+            min_score: 2.0,
+            padding: 1.0,
+            min_turn_speed: 1.0,
+            top: 10,
+        };
+
+        let highlights = find_highlights(&telemetry, Some(1000.0), None, &scale, &options);
+        assert_eq!(highlights.len(), 2);
+        assert_eq!(highlights[0].kind, HighlightKind::Turn);
+        assert_eq!((highlights[0].start, highlights[0].duration), (14.0, 3.0));
+        assert_eq!(highlights[0].peak, 90.0);
+        assert_eq!(highlights[1].kind, HighlightKind::Speed);
+        assert_eq!((highlights[1].start, highlights[1].duration), (9.0, 4.0));
+
+        let mut edl = Vec::new();
+        write_edl(&mut edl, "test", "a.insv", &highlights, 30).unwrap();
+        let edl = String::from_utf8(edl).unwrap();
+        assert!(edl.contains(
+            "001  AX       V     C        00:00:14:00 00:00:17:00 00:00:00:00 00:00:03:00\n"
+        ));
+        assert!(edl.contains(
+            "002  AX       V     C        00:00:09:00 00:00:13:00 00:00:03:00 00:00:07:00\n"
+        ));
+
+        let mut script = Vec::new();
+        write_ffmpeg_script(&mut script, Path::new("it's.insv"), &highlights[..1]).unwrap();
+        assert!(String::from_utf8(script).unwrap().ends_with(
+            "ffmpeg -ss 14.000 -t 3.000 -i 'it'\\''s.insv' -map 0 -c copy 'it'\\''s-highlight-01.mp4'\n"
+        ));
+    }
+}
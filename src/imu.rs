@@ -0,0 +1,186 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::GyroRecord;
+#[cfg(feature = "prost")]
+use crate::insvtools::frames::ExtraMetadata;
+
+/// Full-scale ranges assumed when the Info frame doesn't carry `GyroCfgInfo`.
+pub const DEFAULT_GYRO_RANGE_DPS: f64 = 2000.0;
+pub const DEFAULT_ACCEL_RANGE_G: f64 = 16.0;
+
+/// Gyroscope full-scale range in degrees per second. `GyroCfgInfo.GyroRange` is taken to be in
+/// dps when it looks like a standard sensor range.
+#[cfg(feature = "prost")]
+pub fn gyro_range_dps(info: Option<&ExtraMetadata>) -> f64 {
+    info.and_then(|info| info.gyro_cfg_info)
+        .and_then(|cfg| cfg.gyro_range)
+        .filter(|range| (125..=4000).contains(range))
+        .map_or(DEFAULT_GYRO_RANGE_DPS, |range| range as f64)
+}
+
+/// Accelerometer full-scale range in g, from `GyroCfgInfo.AccRange`.
+#[cfg(feature = "prost")]
+pub fn accel_range_g(info: Option<&ExtraMetadata>) -> f64 {
+    info.and_then(|info| info.gyro_cfg_info)
+        .and_then(|cfg| cfg.acc_range)
+        .filter(|range| (2..=32).contains(range))
+        .map_or(DEFAULT_ACCEL_RANGE_G, |range| range as f64)
+}
+
+/// Scales raw sensor readings to physical units given the sensor's full-scale ranges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImuScale {
+    pub gyro_range_dps: f64,
+    pub accel_range_g: f64,
+}
+
+impl ImuScale {
+    #[cfg(feature = "prost")]
+    pub fn from_info(info: Option<&ExtraMetadata>) -> Self {
+        ImuScale {
+            gyro_range_dps: gyro_range_dps(info),
+            accel_range_g: accel_range_g(info),
+        }
+    }
+
+    /// Raw gyroscope counts per radian per second.
+    pub fn gyro_counts_per_rad_s(&self) -> f64 {
+        32768.0 / self.gyro_range_dps.to_radians()
+    }
+
+    /// Angular velocity in radians per second.
+    pub fn gyro_rad_s(&self, record: &GyroRecord) -> [f64; 3] {
+        record
+            .gyro_raw()
+            .map(|raw| raw as f64 / self.gyro_counts_per_rad_s())
+    }
+
+    /// Magnitude of the angular velocity in degrees per second.
+    pub fn angular_speed_deg_s(&self, record: &GyroRecord) -> f64 {
+        let [x, y, z] = self.gyro_rad_s(record);
+        (x * x + y * y + z * z).sqrt().to_degrees()
+    }
+
+    /// Acceleration in g.
+    pub fn accel_g(&self, record: &GyroRecord) -> [f64; 3] {
+        record
+            .accel_raw()
+            .map(|raw| raw as f64 * self.accel_range_g / 32768.0)
+    }
+
+    /// A record in physical units.
+    pub fn sample(&self, record: &GyroRecord) -> ImuSample {
+        let [gyro_x, gyro_y, gyro_z] = self.gyro_rad_s(record);
+        let [accel_x, accel_y, accel_z] = self.accel_g(record);
+        ImuSample {
+            timestamp: record.timestamp,
+            gyro_x,
+            gyro_y,
+            gyro_z,
+            accel_x,
+            accel_y,
+            accel_z,
+        }
+    }
+}
+
+/// A gyro record in radians per second and g.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ImuSample {
+    /// Camera clock in milliseconds.
+    pub timestamp: u64,
+    pub gyro_x: f64,
+    pub gyro_y: f64,
+    pub gyro_z: f64,
+    pub accel_x: f64,
+    pub accel_y: f64,
+    pub accel_z: f64,
+}
+
+/// The six raw axes of a record: accelerometer then gyroscope, in payload order.
+fn raw_axes(record: &GyroRecord) -> [f64; 6] {
+    let (accel, gyro) = (record.accel_raw(), record.gyro_raw());
+    core::array::from_fn(|i| if i < 3 { accel[i] } else { gyro[i - 3] } as f64)
+}
+
+/// Resamples records, sorted by time, to `rate` per second on their millisecond clock. Each new
+/// record averages the records within half a period of its time, which filters out what the
+/// lower rate can't represent; where fewer than two are that close, as when upsampling, it is
+/// interpolated between the records either side.
+pub fn resample(records: &[GyroRecord], rate: f64) -> Vec<GyroRecord> {
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return Vec::new();
+    };
+    let period = 1000.0 / rate;
+    let count = ((last.timestamp - first.timestamp) as f64 / period).floor() as usize + 1;
+    let mut resampled = Vec::with_capacity(count);
+    let mut start = 0;
+    for k in 0..count {
+        let time = first.timestamp as f64 + k as f64 * period;
+        let in_window = |record: &GyroRecord| (record.timestamp as f64) < time + period / 2.0;
+        while start < records.len() && (records[start].timestamp as f64) < time - period / 2.0 {
+            start += 1;
+        }
+        let end = start + records[start..].partition_point(in_window);
+        let axes = if end - start >= 2 {
+            let sums = records[start..end]
+                .iter()
+                .map(raw_axes)
+                .fold([0.0; 6], |sum, axes| {
+                    core::array::from_fn(|i| sum[i] + axes[i])
+                });
+            sums.map(|sum| sum / (end - start) as f64)
+        } else {
+            let after = records.partition_point(|record| record.timestamp as f64 <= time);
+            let before = &records[after - 1];
+            match records.get(after) {
+                Some(next) => {
+                    let fraction = (time - before.timestamp as f64)
+                        / (next.timestamp - before.timestamp) as f64;
+                    let (a, b) = (raw_axes(before), raw_axes(next));
+                    core::array::from_fn(|i| a[i] + (b[i] - a[i]) * fraction)
+                }
+                None => raw_axes(before),
+            }
+        };
+        resampled.push(GyroRecord::from_raw(
+            time.round() as u64,
+            axes.map(|axis| axis.round() as i16),
+        ));
+    }
+    resampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: u64, value: i16) -> GyroRecord {
+        GyroRecord::from_raw(timestamp, [value, 0, 0, -value, 0, 0])
+    }
+
+    #[test]
+    fn test_resample() {
+        // 1 kHz with the first accelerometer axis counting up.
+        let records: Vec<GyroRecord> = (0..1000).map(|i| record(5000 + i, i as i16)).collect();
+
+        let decimated = resample(&records, 100.0);
+        assert_eq!(decimated.len(), 100);
+        assert_eq!(decimated[1].timestamp, 5010);
+        // The mean of records 5005 to 5014.
+        assert_eq!(decimated[1].accel_raw()[0], 10);
+        assert_eq!(decimated[1].gyro_raw()[0], -10);
+        // Only records 5000 to 5004 reach back to the first.
+        assert_eq!(decimated[0].accel_raw()[0], 2);
+
+        let sparse = [record(0, 0), record(10, 100)];
+        let upsampled = resample(&sparse, 500.0);
+        let values: Vec<i16> = upsampled.iter().map(|r| r.accel_raw()[0]).collect();
+        assert_eq!(values, [0, 20, 40, 60, 80, 100]);
+        assert_eq!(upsampled[2].timestamp, 4);
+
+        assert!(resample(&[], 100.0).is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+//! Fields of the Info frame by their path, as `ginsta info --get` reads them.
+
+use log::warn;
+use serde_json::Value;
+
+use crate::{
+    FrameType, amend::frame_bytes, calibration::LensOffset, insvtools::frames::ExtraMetadata,
+};
+
+/// Short names `info --get` takes for Info frame fields.
+pub const INFO_FIELD_ALIASES: &[(&str, &str)] = &[
+    ("serial", "serial_number"),
+    ("firmware", "fw_version"),
+    ("firmware_version", "fw_version"),
+    ("camera", "camera_type"),
+];
+
+/// The JSON in the User frame of `data`, added by `edit --user-metadata`.
+pub fn user_metadata(data: &[u8]) -> Option<Value> {
+    let bytes = frame_bytes(data, FrameType::User)?;
+    serde_json::from_slice(bytes)
+        .inspect_err(|e| warn!("The User frame isn't JSON: {e}"))
+        .ok()
+}
+
+/// The field of `info` at a dot-separated `path` of field names and list indexes, null where a
+/// message along the way is missing. `lens_offset` is `offset` parsed and `user_metadata` the
+/// User frame's JSON.
+pub fn info_field(
+    info: &ExtraMetadata,
+    user_metadata: Option<Value>,
+    path: &str,
+) -> Result<Value, String> {
+    let path = (INFO_FIELD_ALIASES.iter())
+        .find(|(alias, _)| *alias == path)
+        .map_or(path, |(_, field)| field);
+    let mut value = serde_json::to_value(info).map_err(|e| e.to_string())?;
+    let lens_offset = (info.offset.as_deref()).and_then(|offset| offset.parse::<LensOffset>().ok());
+    if let Value::Object(fields) = &mut value {
+        let lens_offset = serde_json::to_value(lens_offset).map_err(|e| e.to_string())?;
+        fields.insert("lens_offset".to_string(), lens_offset);
+        fields.insert(
+            "user_metadata".to_string(),
+            user_metadata.unwrap_or_default(),
+        );
+    }
+    for key in path.split('.') {
+        value = match value {
+            Value::Null => Some(Value::Null),
+            Value::Object(mut fields) => fields.remove(key),
+            Value::Array(mut items) => (key.parse::<usize>().ok())
+                .filter(|&i| i < items.len())
+                .map(|i| items.swap_remove(i)),
+            _ => None,
+        }
+        .ok_or_else(|| format!("No field `{path}` in the Info frame"))?;
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::tests::build_insv;
+
+    #[test]
+    fn test_info_field() {
+        let info = ExtraMetadata {
+            serial_number: Some("IXSE123".to_string()),
+            offset: Some(
+                "2_1445.860_1530.820_1532.530_0.000_0.000_0.000_\
+                 1446.520_4589.230_1532.620_0.340_-0.330_179.750_6080_3040_25"
+                    .to_string(),
+            ),
+            ..Default::default()
+        };
+        let user = Some(json!({ "rider": "A. N. Other", "laps": [1, 2] }));
+        let field = |path| info_field(&info, user.clone(), path);
+        assert_eq!(field("serial_number"), Ok(json!("IXSE123")));
+        assert_eq!(field("serial"), Ok(json!("IXSE123")));
+        assert_eq!(field("lens_offset.width"), Ok(json!(6080)));
+        assert_eq!(field("lens_offset.lenses.1.roll"), Ok(json!(179.75)));
+        assert_eq!(field("user_metadata.laps.1"), Ok(json!(2)));
+        // Missing messages and fields are null, but names the message doesn't have are errors.
+        assert_eq!(field("fw_version"), Ok(Value::Null));
+        assert_eq!(field("gps.latitude"), Ok(Value::Null));
+        assert_eq!(
+            field("colour"),
+            Err("No field `colour` in the Info frame".to_string())
+        );
+        assert!(field("lens_offset.lenses.2").is_err());
+        assert!(field("serial_number.first").is_err());
+        assert_eq!(
+            info_field(&info, None, "user_metadata.rider"),
+            Ok(Value::Null)
+        );
+        let no_offset = ExtraMetadata {
+            offset: Some("garbled".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            info_field(&no_offset, None, "lens_offset.width"),
+            Ok(Value::Null)
+        );
+    }
+
+    #[test]
+    fn test_user_metadata() {
+        let data = build_insv(b"video", &[(FrameType::User, 1, br#"{"a": 1}"#.to_vec())]);
+        assert_eq!(user_metadata(&data), Some(json!({ "a": 1 })));
+        let data = build_insv(b"video", &[(FrameType::User, 1, b"{not json".to_vec())]);
+        assert_eq!(user_metadata(&data), None);
+        assert_eq!(user_metadata(b"video"), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! Changing a camera file without putting the footage at risk.
+//!
+//! A rewritten copy is written to a temporary file next to the original and synced to disk, then
+//! checked by the caller. Only then is the original linked to `<name>.bak` and the copy renamed
+//! over it, which is atomic on the same file system, so a crash at any point leaves either the
+//! original or the checked copy in place, and the original stays in the backup afterwards.
+//!
+//! Edits that only add to the end of the file, such as those of `amend`, don't need a copy: the
+//! bytes are appended and synced, and the file is truncated back to its old length if the caller
+//! doesn't accept the result.
+
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// `path` with `suffix` added to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.file_name().unwrap_or_default().to_owned();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Where `replace_file` keeps the original of `path`.
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// Replaces `path` with what `write` writes, once `verify` accepts the temporary file it was
+/// written to, returning where the original was kept.
+///
+/// Fails without touching `path` if a backup from an earlier edit is in the way, as it may hold
+/// the only unedited copy.
+pub fn replace_file(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    verify: impl FnOnce(&Path) -> Result<(), String>,
+) -> io::Result<PathBuf> {
+    let backup = backup_path(path);
+    if backup.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", backup.display()),
+        ));
+    }
+    let temporary = with_suffix(path, ".tmp");
+    let written = (|| {
+        let mut out = BufWriter::new(File::create(&temporary)?);
+        write(&mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        verify(&temporary).map_err(|message| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the rewritten file doesn't check out: {message}"),
+            )
+        })
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+
+    // A hard link keeps the original at `path` until the rename; file systems without them get
+    // a copy.
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+        File::open(&backup)?.sync_all()?;
+    }
+    fs::rename(&temporary, path)?;
+    // Make the rename itself durable, where directories can be synced.
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+    Ok(backup)
+}
+
+/// Appends `bytes` to `path` and syncs it, then truncates it to its old length again if
+/// `verify` doesn't accept the result.
+pub fn append_file(
+    path: &Path,
+    bytes: &[u8],
+    verify: impl FnOnce(&Path) -> Result<(), String>,
+) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    let len = file.metadata()?.len();
+    let appended = (|| {
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        verify(path).map_err(|message| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the edited file doesn't check out: {message}"),
+            )
+        })
+    })();
+    if let Err(e) = appended {
+        file.set_len(len)?;
+        file.sync_all()?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_file() {
+        let dir = std::env::temp_dir().join(format!("ginsta-inplace-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clip.insv");
+        fs::write(&path, b"original").unwrap();
+
+        let rejected = replace_file(
+            &path,
+            |out| out.write_all(b"broken"),
+            |_| Err("no trailer".to_string()),
+        );
+        assert!(rejected.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+        assert!(!dir.join("clip.insv.tmp").exists());
+        assert!(!backup_path(&path).exists());
+
+        let backup = replace_file(
+            &path,
+            |out| out.write_all(b"edited"),
+            |written| match fs::read(written) {
+                Ok(data) if data == b"edited" => Ok(()),
+                _ => Err("not written".to_string()),
+            },
+        )
+        .unwrap();
+        assert_eq!(backup, dir.join("clip.insv.bak"));
+        assert_eq!(fs::read(&path).unwrap(), b"edited");
+        assert_eq!(fs::read(&backup).unwrap(), b"original");
+
+        // The backup of the first edit is never overwritten.
+        let again = replace_file(&path, |out| out.write_all(b"twice"), |_| Ok(()));
+        assert_eq!(again.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&backup).unwrap(), b"original");
+
+        append_file(&path, b" more", |_| Ok(())).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"edited more");
+        let rejected = append_file(&path, b" broken", |_| Err("no trailer".to_string()));
+        assert!(rejected.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"edited more");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
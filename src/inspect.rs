@@ -0,0 +1,308 @@
+//! What `ginsta inspect` and decoding warnings show of the bytes in a file: records field by
+//! field, and hexdumps around the bytes a decoder choked on, each at its offset in the file.
+
+use std::{
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+};
+
+use anstyle::{AnsiColor, Style};
+
+use crate::{Diagnostic, FrameType, layout::Layout, metadata_start, read_index};
+
+pub const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+pub const LOCATION: Style = AnsiColor::Cyan.on_default();
+const FAULTY_BYTES: Style = AnsiColor::Red.on_default().bold();
+const CONTEXT_BYTES: Style = Style::new().dimmed();
+
+/// Rows of 16 bytes shown before and after the faulty bytes in a diagnostic.
+const HEXDUMP_CONTEXT_ROWS: u64 = 1;
+/// Rows of faulty bytes shown at most.
+const HEXDUMP_MAX_ROWS: u64 = 4;
+
+/// The versions and byte ranges in `data` of its frames of `frame_type`, in index order. The
+/// ranges can run past the end of the file. `None` if `data` has no Insta360 metadata.
+pub fn frame_ranges(data: &[u8], frame_type: FrameType) -> Option<Vec<(u8, Range<u64>)>> {
+    let (trailer, index) = read_index(data)?;
+    let metadata_start = (data.len() - trailer.metadata_size as usize) as u64;
+    if frame_type == FrameType::Index {
+        let start = metadata_start + trailer.index_frame_offset();
+        let index_trailer = trailer.index_frame_trailer();
+        return Some(vec![(
+            index_trailer.frame_version,
+            start..start + index_trailer.frame_size as u64,
+        )]);
+    }
+    Some(
+        (index.frames.iter())
+            .filter(|frame| frame.frame_type == frame_type)
+            .map(|frame| {
+                let start = metadata_start + frame.frame_offset as u64;
+                (frame.frame_version, start..start + frame.frame_size as u64)
+            })
+            .collect(),
+    )
+}
+
+/// Writes `records` records of `layout` from the `frame_type` frames of `data`, after skipping
+/// `skip`, with each field's offset, bytes and value, and what a `char` field should hold where
+/// it holds something else.
+pub fn write_records(
+    out: &mut dyn Write,
+    data: &[u8],
+    frame_type: FrameType,
+    layout: &'static Layout,
+    frames: &[(u8, Range<u64>)],
+    skip: usize,
+    records: usize,
+) -> io::Result<()> {
+    let size = layout.size() as u64;
+    let name_width = (layout.fields.iter())
+        .map(|field| field.name.len())
+        .max()
+        .unwrap_or(0);
+    if frames.is_empty() {
+        writeln!(out, "  no {frame_type:?} frames")?;
+    }
+    let (mut skip, mut remaining) = (skip as u64, records as u64);
+    for (version, range) in frames {
+        if remaining == 0 {
+            break;
+        }
+        let len = range.end - range.start;
+        writeln!(
+            out,
+            "  {frame_type:?} frame v{version} at {LOCATION}{:#x}{LOCATION:#}: {len} bytes, {} \
+             records and {} trailing bytes",
+            range.start,
+            len / size,
+            len % size
+        )?;
+        let Some(bytes) = data.get(range.start as usize..range.end as usize) else {
+            writeln!(out, "  runs past the end of the file")?;
+            continue;
+        };
+        let records = len / size;
+        if skip >= records {
+            skip -= records;
+            continue;
+        }
+        let shown = (skip..records.min(skip + remaining))
+            .map(|i| (i, layout.view(&bytes[(i * size) as usize..])));
+        for (i, record) in shown {
+            let Some(record) = record else {
+                break;
+            };
+            let start = range.start + i * size;
+            writeln!(out, "  record {i}:")?;
+            for (field, span, value) in record.fields() {
+                let field_bytes = &record.bytes()[span.clone()];
+                let hex: Vec<String> = (field_bytes.iter())
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect();
+                write!(
+                    out,
+                    "    {LOCATION}{:08x}{LOCATION:#}  {:<23}  {:<name_width$}  {value}",
+                    start + span.start as u64,
+                    hex.join(" "),
+                    field.name,
+                )?;
+                if !field.accepts(field_bytes) {
+                    let allowed: Vec<String> = (field.allowed.iter())
+                        .map(|c| format!("{:?}", *c as char))
+                        .collect();
+                    write!(
+                        out,
+                        "  {WARNING}expected {}{WARNING:#}",
+                        allowed.join(" or ")
+                    )?;
+                }
+                writeln!(out)?;
+            }
+        }
+        remaining -= records.min(skip + remaining) - skip;
+        skip = 0;
+    }
+    Ok(())
+}
+
+/// Writes a decoding problem in `data` with its absolute byte offset, the layout the decoder
+/// expected and a hexdump of the bytes around it, with the faulty ones highlighted.
+pub fn write_diagnostic(
+    out: &mut dyn Write,
+    file_name: &Path,
+    data: &[u8],
+    diagnostic: &Diagnostic,
+) -> io::Result<()> {
+    writeln!(out, "{WARNING}warning{WARNING:#}: {diagnostic}")?;
+    let metadata_start = metadata_start(data).unwrap_or(data.len()) as u64;
+    let bytes = diagnostic
+        .bytes()
+        .map(|range| metadata_start + range.start..metadata_start + range.end);
+    match &bytes {
+        Some(range) => writeln!(
+            out,
+            "  {LOCATION}-->{LOCATION:#} {}:{:#x}",
+            file_name.display(),
+            range.start
+        )?,
+        None => writeln!(out, "  {LOCATION}-->{LOCATION:#} {}", file_name.display())?,
+    }
+    if let Some(expected) = diagnostic.expected() {
+        writeln!(out, "   = expected {expected}")?;
+    }
+    let Some(faulty) = bytes else {
+        return Ok(());
+    };
+    let len = data.len() as u64;
+    if faulty.end > len {
+        writeln!(
+            out,
+            "   = ends {} bytes past the end of the file",
+            faulty.end - len
+        )?;
+    }
+    let first_row = (faulty.start / 16).saturating_sub(HEXDUMP_CONTEXT_ROWS);
+    let faulty_rows = faulty
+        .end
+        .min(len)
+        .div_ceil(16)
+        .saturating_sub(faulty.start / 16);
+    let last_row = (faulty.start / 16 + faulty_rows.min(HEXDUMP_MAX_ROWS) + HEXDUMP_CONTEXT_ROWS)
+        .min(len.div_ceil(16));
+    for row in first_row..last_row {
+        let start = row * 16;
+        let end = (start + 16).min(len);
+        let style = |offset: u64| {
+            if faulty.contains(&offset) {
+                FAULTY_BYTES
+            } else {
+                CONTEXT_BYTES
+            }
+        };
+        write!(out, "  {LOCATION}{start:08x}{LOCATION:#} ")?;
+        for offset in start..start + 16 {
+            match data.get(offset as usize) {
+                Some(byte) => write!(out, " {}{byte:02x}{:#}", style(offset), style(offset))?,
+                None => write!(out, "   ")?,
+            }
+        }
+        write!(out, "  |")?;
+        for offset in start..end {
+            let byte = data[offset as usize];
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            write!(out, "{}{c}{:#}", style(offset), style(offset))?;
+        }
+        writeln!(out, "|")?;
+    }
+    if faulty_rows > HEXDUMP_MAX_ROWS {
+        let shown_end = (faulty.start / 16 + HEXDUMP_MAX_ROWS) * 16;
+        writeln!(
+            out,
+            "  … {} more faulty bytes",
+            faulty.end.min(len) - shown_end
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_telemetry, tests::build_insv};
+
+    fn plain(bytes: Vec<u8>) -> String {
+        anstream::adapter::strip_str(&String::from_utf8(bytes).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_write_records() {
+        let layout = FrameType::Gyro.record_layout().unwrap();
+        let size = layout.size();
+        let mut gyro = vec![0u8; 3 * size + 5];
+        gyro[size] = 1;
+        let data = build_insv(b"video", &[(FrameType::Gyro, 1, gyro)]);
+        let frames = frame_ranges(&data, FrameType::Gyro).unwrap();
+        assert_eq!(frames, [(1, 5..5 + 3 * size as u64 + 5)]);
+
+        let mut out = Vec::new();
+        write_records(&mut out, &data, FrameType::Gyro, layout, &frames, 1, 5).unwrap();
+        let text = plain(out);
+        assert!(text.starts_with(&format!(
+            "  Gyro frame v1 at 0x5: {} bytes, 3 records and 5 trailing bytes\n  record 1:\n",
+            3 * size + 5
+        )));
+        assert!(text.contains("  record 2:\n"));
+        assert!(!text.contains("record 0:") && !text.contains("record 3:"));
+        assert!(text.contains(&format!("    {:08x}  01 00 00 00 00 00 00 00", 5 + size)));
+
+        // Skipping every record shows only the frame.
+        let mut out = Vec::new();
+        write_records(&mut out, &data, FrameType::Gyro, layout, &frames, 3, 5).unwrap();
+        assert_eq!(plain(out).lines().count(), 1);
+
+        let mut out = Vec::new();
+        write_records(&mut out, &data, FrameType::Gps, layout, &[], 0, 5).unwrap();
+        assert_eq!(plain(out), "  no Gps frames\n");
+
+        // A frame the index says runs past the end of a truncated file isn't read.
+        let truncated = [(1, 5..data.len() as u64 + 100)];
+        let mut out = Vec::new();
+        write_records(&mut out, &data, FrameType::Gyro, layout, &truncated, 0, 5).unwrap();
+        assert!(plain(out).ends_with("  runs past the end of the file\n"));
+
+        let index = frame_ranges(&data, FrameType::Index).unwrap();
+        assert_eq!(index.len(), 1);
+        assert_eq!(frame_ranges(b"video", FrameType::Gyro), None);
+    }
+
+    #[test]
+    fn test_write_diagnostic() {
+        let data = build_insv(b"video", &[(FrameType::Gyro, 1, vec![0; 20 + 7])]);
+        let telemetry = read_telemetry(&data);
+        let [diagnostic] = telemetry.diagnostics[..] else {
+            panic!("expected one diagnostic, got {:?}", telemetry.diagnostics);
+        };
+        let mut out = Vec::new();
+        write_diagnostic(&mut out, Path::new("clip.insv"), &data, &diagnostic).unwrap();
+        let text = plain(out);
+        let mut lines = text.lines();
+        assert_eq!(lines.next().unwrap(), format!("warning: {diagnostic}"));
+        assert_eq!(lines.next().unwrap(), "  --> clip.insv:0x19");
+        assert!(lines.next().unwrap().starts_with("   = expected "));
+        assert_eq!(
+            lines.next().unwrap(),
+            "  00000000  76 69 64 65 6f 00 00 00 00 00 00 00 00 00 00 00  |video...........|"
+        );
+        assert!(lines.next().unwrap().starts_with("  00000010 "));
+
+        // Faulty bytes past the end of the file are counted rather than dumped.
+        // A file cut short has no trailer, so offsets count from its end.
+        let truncated = &data[..30];
+        let beyond = Diagnostic::FrameOutOfBounds {
+            frame_type: FrameType::Gyro,
+            offset: 20,
+            size: 100,
+        };
+        let mut out = Vec::new();
+        write_diagnostic(&mut out, Path::new("clip.insv"), truncated, &beyond).unwrap();
+        let text = plain(out);
+        assert!(text.contains("  --> clip.insv:0x32\n"));
+        assert!(text.contains("   = ends 120 bytes past the end of the file\n"));
+
+        let mut out = Vec::new();
+        write_diagnostic(
+            &mut out,
+            Path::new("clip.insv"),
+            &data,
+            &Diagnostic::UnreadableIndex,
+        )
+        .unwrap();
+        assert_eq!(plain(out).lines().count(), 3);
+    }
+}
@@ -0,0 +1,226 @@
+//! Lap timing on a circuit, from the moments the GPS track crosses a start/finish line.
+//!
+//! The line runs through a point across the direction of travel, reaching `width` metres to
+//! either side. Without a heading, the direction of travel is the GPS course at the record
+//! nearest the point. Crossings are placed between records by interpolation, over a flat
+//! projection around the point, which is accurate over the few metres between fixes.
+
+use std::str::FromStr;
+
+use crate::{
+    GpsRecord,
+    geo::{EARTH_RADIUS_M, haversine_distance},
+    units::Seconds,
+};
+
+/// Crossings closer together than this many seconds count once, so a car stopped on the line
+/// with its position wandering back and forth doesn't start laps.
+pub const MIN_LAP_SECONDS: f64 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StartFinish {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Direction of travel over the line, in degrees clockwise from north.
+    pub heading: Option<f64>,
+}
+
+/// Parses `latitude,longitude` or `latitude,longitude,heading` in degrees.
+impl FromStr for StartFinish {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected `latitude,longitude[,heading]`, got {s:?}");
+        let values: Vec<f64> = s
+            .split(',')
+            .map(|value| value.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        let (latitude, longitude, heading) = match values[..] {
+            [latitude, longitude] => (latitude, longitude, None),
+            [latitude, longitude, heading] => (latitude, longitude, Some(heading)),
+            _ => return Err(invalid()),
+        };
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(format!("position out of range: {s:?}"));
+        }
+        Ok(StartFinish {
+            latitude,
+            longitude,
+            heading,
+        })
+    }
+}
+
+/// A crossing of the start/finish line in the direction of travel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crossing {
+    /// The first record past the line.
+    pub index: usize,
+    /// Unix time of the crossing.
+    pub time: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Lap {
+    pub lap: u32,
+    /// Unix time the lap started.
+    pub start: f64,
+    pub time: Seconds,
+    /// How much slower than the fastest lap.
+    pub delta: Seconds,
+}
+
+/// The crossings of `line` by `records`, which are in time order.
+pub fn crossings(records: &[GpsRecord], line: &StartFinish, width: f64) -> Vec<Crossing> {
+    let heading = match line.heading {
+        Some(heading) => heading,
+        None => {
+            let distance = |record: &GpsRecord| {
+                let (latitude, longitude) = (record.latitude.0, record.longitude.0);
+                haversine_distance(line.latitude, line.longitude, latitude, longitude)
+            };
+            let nearest = records
+                .iter()
+                .min_by(|a, b| distance(a).total_cmp(&distance(b)));
+            match nearest {
+                Some(record) => record.track.0,
+                None => return Vec::new(),
+            }
+        }
+    }
+    .to_radians();
+    // Metres along the direction of travel and across it, from the point.
+    let project = |record: &GpsRecord| {
+        let north = (record.latitude.0 - line.latitude).to_radians() * EARTH_RADIUS_M;
+        let east = (record.longitude.0 - line.longitude).to_radians()
+            * EARTH_RADIUS_M
+            * line.latitude.to_radians().cos();
+        (
+            east * heading.sin() + north * heading.cos(),
+            east * heading.cos() - north * heading.sin(),
+        )
+    };
+
+    let mut crossings: Vec<Crossing> = Vec::new();
+    for (i, pair) in records.windows(2).enumerate() {
+        let ((along_a, across_a), (along_b, across_b)) = (project(&pair[0]), project(&pair[1]));
+        if !(along_a < 0.0 && along_b >= 0.0) {
+            continue;
+        }
+        let fraction = -along_a / (along_b - along_a);
+        if (across_a + (across_b - across_a) * fraction).abs() > width {
+            continue;
+        }
+        let (t_a, t_b) = (pair[0].timestamp as f64, pair[1].timestamp as f64);
+        let time = t_a + (t_b - t_a) * fraction;
+        if crossings
+            .last()
+            .is_some_and(|last| time - last.time < MIN_LAP_SECONDS)
+        {
+            continue;
+        }
+        crossings.push(Crossing { index: i + 1, time });
+    }
+    crossings
+}
+
+/// The lap of each of `records` records: 0 before the first crossing, the out lap, and one more
+/// after each crossing.
+pub fn lap_numbers(records: usize, crossings: &[Crossing]) -> Vec<u32> {
+    let mut laps = vec![0; records];
+    for (lap, crossing) in crossings.iter().enumerate() {
+        for number in &mut laps[crossing.index..] {
+            *number = lap as u32 + 1;
+        }
+    }
+    laps
+}
+
+/// The laps completed between crossings.
+pub fn lap_times(crossings: &[Crossing]) -> Vec<Lap> {
+    let times: Vec<f64> = (crossings.windows(2))
+        .map(|pair| pair[1].time - pair[0].time)
+        .collect();
+    let best = times.iter().copied().fold(f64::INFINITY, f64::min);
+    (crossings.iter().zip(times).enumerate())
+        .map(|(i, (crossing, time))| Lap {
+            lap: i as u32 + 1,
+            start: crossing.time,
+            time: Seconds(time),
+            delta: Seconds(time - best),
+        })
+        .collect()
+}
+
+/// The records of each lap with its number, starting with the out lap if there is one.
+pub fn split_laps<'a>(
+    records: &'a [GpsRecord],
+    crossings: &[Crossing],
+) -> Vec<(u32, &'a [GpsRecord])> {
+    let mut bounds: Vec<usize> = vec![0];
+    bounds.extend(crossings.iter().map(|crossing| crossing.index));
+    bounds.push(records.len());
+    (bounds.windows(2).enumerate())
+        .filter(|(_, bounds)| bounds[0] < bounds[1])
+        .map(|(lap, bounds)| (lap as u32, &records[bounds[0]..bounds[1]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_laps() {
+        // Round a 1 km circuit at 50 m/s, starting 125 m before the line.
+        let circumference = 1000.0;
+        let radius = circumference / std::f64::consts::TAU;
+        let records: Vec<GpsRecord> = (0..70)
+            .map(|i| {
+                let angle = (i as f64 * 50.0 - 125.0) / radius;
+                // Clockwise from the south of the circle's centre, heading west there.
+                let (east, north) = (-radius * angle.sin(), -radius * angle.cos());
+                GpsRecord {
+                    timestamp: 1000 + i,
+                    latitude: Degrees(north / EARTH_RADIUS_M.to_radians()),
+                    longitude: Degrees(east / EARTH_RADIUS_M.to_radians()),
+                    speed: MetersPerSecond(50.0),
+                    track: Degrees((270.0 + angle.to_degrees()).rem_euclid(360.0)),
+                    altitude: Meters(0.0),
+                    fix: None,
+                }
+            })
+            .collect();
+        let south = -radius / EARTH_RADIUS_M.to_radians();
+        let line: StartFinish = format!("{south},0").parse().unwrap();
+        let found = crossings(&records, &line, 15.0);
+        let times: Vec<f64> = found.iter().map(|c| c.time - 1000.0).collect();
+        assert_eq!(times.len(), 4);
+        for (time, expected) in times.iter().zip([2.5, 22.5, 42.5, 62.5]) {
+            assert!((time - expected).abs() < 0.01, "{times:?}");
+        }
+        assert_eq!(found[0].index, 3);
+
+        let laps = lap_times(&found);
+        assert_eq!(laps.len(), 3);
+        assert_eq!(laps[1].lap, 2);
+        assert!((laps[0].time.0 - 20.0).abs() < 0.01);
+        assert!(laps.iter().all(|lap| lap.delta.0.abs() < 0.01));
+        let numbers = lap_numbers(records.len(), &found);
+        assert_eq!((numbers[2], numbers[3], numbers[69]), (0, 1, 4));
+        let split = split_laps(&records, &found);
+        let lengths: Vec<(u32, usize)> = split.iter().map(|(lap, r)| (*lap, r.len())).collect();
+        assert_eq!(lengths, [(0, 3), (1, 20), (2, 20), (3, 20), (4, 7)]);
+
+        // Going the other way over the line isn't a crossing.
+        let backwards = StartFinish {
+            heading: Some(90.0),
+            ..line
+        };
+        assert!(crossings(&records, &backwards, 15.0).is_empty());
+        assert!("1,2,3,4".parse::<StartFinish>().is_err());
+        assert!("91,0".parse::<StartFinish>().is_err());
+    }
+}
@@ -0,0 +1,452 @@
+//! Declarative layouts of fixed-size records: each field's name, type, byte order and how its
+//! value is transformed. The record parsers in `core` and `ginsta inspect` are both driven by
+//! these, so a new frame type needs only its layout and a constructor for its record type.
+
+use alloc::{format, string::String, vec::Vec};
+use core::{fmt, ops::Range};
+
+use nom::{
+    IResult, Parser,
+    bytes::take,
+    error::{Error, ErrorKind},
+};
+
+use crate::FrameType;
+
+/// How a field is stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldKind {
+    U8,
+    U16,
+    U32,
+    U64,
+    I16,
+    I32,
+    F32,
+    F64,
+    /// A single ASCII character, such as a hemisphere flag.
+    Char,
+    /// Bytes whose meaning isn't known.
+    Unknown(usize),
+}
+
+impl FieldKind {
+    pub const fn size(self) -> usize {
+        match self {
+            FieldKind::U8 | FieldKind::Char => 1,
+            FieldKind::U16 | FieldKind::I16 => 2,
+            FieldKind::U32 | FieldKind::I32 | FieldKind::F32 => 4,
+            FieldKind::U64 | FieldKind::F64 => 8,
+            FieldKind::Unknown(size) => size,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+/// What is done to a field's stored value to get the value it stands for.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Transform {
+    #[default]
+    None,
+    /// Multiplied by this.
+    Scale(f64),
+    /// Negated when the `Char` field named `flag` holds `value`, as latitudes are for `S`.
+    NegateIf { flag: &'static str, value: u8 },
+}
+
+/// A field of a fixed-size record.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    pub endian: Endian,
+    pub transform: Transform,
+    /// Bytes a `Char` field may hold, with any allowed when empty. Records holding anything
+    /// else don't parse.
+    pub allowed: &'static [u8],
+}
+
+impl Field {
+    /// A little endian field with no transform.
+    pub const fn new(name: &'static str, kind: FieldKind) -> Self {
+        Field {
+            name,
+            kind,
+            endian: Endian::Little,
+            transform: Transform::None,
+            allowed: &[],
+        }
+    }
+
+    pub const fn big_endian(self) -> Self {
+        Field {
+            endian: Endian::Big,
+            ..self
+        }
+    }
+
+    pub const fn transform(self, transform: Transform) -> Self {
+        Field { transform, ..self }
+    }
+
+    pub const fn allowed(self, allowed: &'static [u8]) -> Self {
+        Field { allowed, ..self }
+    }
+
+    /// Whether `bytes`, this field's bytes in a record, hold an allowed value.
+    pub fn accepts(&self, bytes: &[u8]) -> bool {
+        self.allowed.is_empty() || bytes.first().is_some_and(|b| self.allowed.contains(b))
+    }
+
+    /// The value stored in `bytes`, before the transform.
+    pub fn read<'a>(&self, bytes: &'a [u8]) -> Value<'a> {
+        macro_rules! number {
+            ($type:ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                match self.endian {
+                    Endian::Little => <$type>::from_le_bytes(bytes),
+                    Endian::Big => <$type>::from_be_bytes(bytes),
+                }
+            }};
+        }
+        match self.kind {
+            FieldKind::U8 => Value::Unsigned(bytes[0] as u64),
+            FieldKind::U16 => Value::Unsigned(number!(u16) as u64),
+            FieldKind::U32 => Value::Unsigned(number!(u32) as u64),
+            FieldKind::U64 => Value::Unsigned(number!(u64)),
+            FieldKind::I16 => Value::Signed(number!(i16) as i64),
+            FieldKind::I32 => Value::Signed(number!(i32) as i64),
+            FieldKind::F32 => Value::Float(number!(f32) as f64),
+            FieldKind::F64 => Value::Float(number!(f64)),
+            FieldKind::Char => Value::Char(bytes[0]),
+            FieldKind::Unknown(_) => Value::Bytes(bytes),
+        }
+    }
+}
+
+/// A field's value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value<'a> {
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    Char(u8),
+    Bytes(&'a [u8]),
+}
+
+impl Value<'_> {
+    /// The value as a float, or NaN for bytes.
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Value::Unsigned(v) => v as f64,
+            Value::Signed(v) => v as f64,
+            Value::Float(v) => v,
+            Value::Char(c) => c as f64,
+            Value::Bytes(_) => f64::NAN,
+        }
+    }
+
+    /// The value as an unsigned integer, with floats truncated and anything negative as 0.
+    pub fn as_u64(self) -> u64 {
+        match self {
+            Value::Unsigned(v) => v,
+            Value::Signed(v) => v.max(0) as u64,
+            Value::Float(v) => v as u64,
+            Value::Char(c) => c as u64,
+            Value::Bytes(_) => 0,
+        }
+    }
+
+    fn negated(self) -> Self {
+        match self {
+            Value::Signed(v) => Value::Signed(-v),
+            Value::Float(v) => Value::Float(-v),
+            Value::Unsigned(v) => Value::Signed(-(v as i64)),
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Unsigned(v) => write!(f, "{v}"),
+            Value::Signed(v) => write!(f, "{v}"),
+            Value::Float(v) => write!(f, "{v}"),
+            Value::Char(c) if c.is_ascii_graphic() => write!(f, "{:?}", *c as char),
+            Value::Char(c) => write!(f, "{c:#04x}"),
+            Value::Bytes(_) => f.write_str("?"),
+        }
+    }
+}
+
+/// The fields of a fixed-size record, in the order they are stored.
+#[derive(Debug, PartialEq)]
+pub struct Layout {
+    pub fields: &'static [Field],
+    /// Units of the `timestamp` field per second, for records that have one.
+    pub ticks_per_second: Option<f64>,
+}
+
+impl Layout {
+    /// Size of a record in bytes.
+    pub const fn size(&self) -> usize {
+        let mut size = 0;
+        let mut i = 0;
+        while i < self.fields.len() {
+            size += self.fields[i].kind.size();
+            i += 1;
+        }
+        size
+    }
+
+    /// Each field with its byte range in a record.
+    pub fn spans(&self) -> impl Iterator<Item = (&'static Field, Range<usize>)> {
+        let fields = self.fields;
+        fields.iter().scan(0, |offset, field| {
+            let start = *offset;
+            *offset += field.kind.size();
+            Some((field, start..*offset))
+        })
+    }
+
+    /// The field called `name` and its byte range.
+    pub fn span(&self, name: &str) -> Option<(&'static Field, Range<usize>)> {
+        self.spans().find(|(field, _)| field.name == name)
+    }
+
+    /// Views the first record in `bytes` without checking its fields' values, or `None` if
+    /// `bytes` is too short.
+    pub fn view<'a>(&'static self, bytes: &'a [u8]) -> Option<RecordView<'a>> {
+        let bytes = bytes.get(..self.size())?;
+        Some(RecordView {
+            layout: self,
+            bytes,
+        })
+    }
+
+    /// Whole records in a frame of `len` bytes, and the bytes left over.
+    pub fn records_in(&self, len: usize) -> (usize, usize) {
+        (len / self.size(), len % self.size())
+    }
+
+    /// Records per second in `frame`, from the timestamps of its first and last records, or
+    /// `None` without timestamps or if they don't increase.
+    pub fn sample_rate(&'static self, frame: &[u8]) -> Option<f64> {
+        let ticks_per_second = self.ticks_per_second?;
+        let (records, _) = self.records_in(frame.len());
+        self.span("timestamp")?;
+        let timestamp = |i: usize| {
+            let record = self.view(&frame[i * self.size()..])?;
+            Some(record.value("timestamp").as_f64())
+        };
+        let span = (timestamp(records.checked_sub(1)?)? - timestamp(0)?) / ticks_per_second;
+        (span > 0.0).then(|| (records - 1) as f64 / span)
+    }
+
+    /// Parses a record, failing if `input` is too short or a field holds a value it doesn't
+    /// allow.
+    pub fn parse<'a>(&'static self, input: &'a [u8]) -> IResult<&'a [u8], RecordView<'a>> {
+        let (rest, bytes) = take(self.size()).parse(input)?;
+        for (field, range) in self.spans() {
+            if !field.accepts(&bytes[range.clone()]) {
+                return Err(nom::Err::Error(Error::new(
+                    &input[range.start..],
+                    ErrorKind::OneOf,
+                )));
+            }
+        }
+        Ok((
+            rest,
+            RecordView {
+                layout: self,
+                bytes,
+            },
+        ))
+    }
+}
+
+/// Describes the layout, such as `16-byte records: u64 timestamp, f64 shutter_speed`.
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| {
+                let kind = match field.kind {
+                    FieldKind::U8 => "u8",
+                    FieldKind::U16 => "u16",
+                    FieldKind::U32 => "u32",
+                    FieldKind::U64 => "u64",
+                    FieldKind::I16 => "i16",
+                    FieldKind::I32 => "i32",
+                    FieldKind::F32 => "f32",
+                    FieldKind::F64 => "f64",
+                    FieldKind::Char => "char",
+                    FieldKind::Unknown(size) => return format!("{size} unknown bytes"),
+                };
+                let endian = match field.endian {
+                    Endian::Little => "",
+                    Endian::Big => " big endian",
+                };
+                let allowed: Vec<String> = (field.allowed.iter())
+                    .map(|c| format!("{:?}", *c as char))
+                    .collect();
+                match allowed.as_slice() {
+                    [] => format!("{kind}{endian} {}", field.name),
+                    allowed => format!("{kind} {} ({})", field.name, allowed.join(" or ")),
+                }
+            })
+            .collect();
+        write!(f, "{}-byte records: {}", self.size(), fields.join(", "))
+    }
+}
+
+/// Record layouts by frame type: the built-in ones, and any registered at run time, such as
+/// experimental layouts of undocumented frames, which take precedence.
+#[derive(Debug, Default)]
+pub struct LayoutRegistry {
+    layouts: Vec<(FrameType, &'static Layout)>,
+}
+
+impl LayoutRegistry {
+    /// Uses `layout` for `frame_type` from now on, in place of any earlier one.
+    pub fn register(&mut self, frame_type: FrameType, layout: &'static Layout) {
+        self.layouts
+            .retain(|(registered, _)| *registered != frame_type);
+        self.layouts.push((frame_type, layout));
+    }
+
+    pub fn get(&self, frame_type: FrameType) -> Option<&'static Layout> {
+        (self.layouts.iter())
+            .find(|(registered, _)| *registered == frame_type)
+            .map(|(_, layout)| *layout)
+            .or_else(|| frame_type.record_layout())
+    }
+}
+
+/// A record's bytes, read through its layout.
+#[derive(Clone, Copy, Debug)]
+pub struct RecordView<'a> {
+    layout: &'static Layout,
+    bytes: &'a [u8],
+}
+
+impl<'a> RecordView<'a> {
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The transformed value of the field called `name`.
+    ///
+    /// # Panics
+    ///
+    /// If the layout has no such field.
+    pub fn value(&self, name: &str) -> Value<'a> {
+        let (field, range) = (self.layout.span(name))
+            .unwrap_or_else(|| panic!("no field {name} in the record layout"));
+        self.transformed(field, &self.bytes[range])
+    }
+
+    /// Each field with its byte range, bytes and transformed value.
+    pub fn fields(&self) -> impl Iterator<Item = (&'static Field, Range<usize>, Value<'a>)> {
+        let view = *self;
+        self.layout.spans().map(move |(field, range)| {
+            let value = view.transformed(field, &view.bytes[range.clone()]);
+            (field, range, value)
+        })
+    }
+
+    fn transformed(&self, field: &Field, bytes: &'a [u8]) -> Value<'a> {
+        let value = field.read(bytes);
+        match field.transform {
+            Transform::None => value,
+            Transform::Scale(scale) => Value::Float(value.as_f64() * scale),
+            Transform::NegateIf { flag, value: set } => match self.layout.span(flag) {
+                Some((_, range)) if self.bytes[range.start] == set => value.negated(),
+                _ => value,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static LAYOUT: Layout = Layout {
+        fields: &[
+            Field::new("count", FieldKind::U32).big_endian(),
+            Field::new("value", FieldKind::I16).transform(Transform::NegateIf {
+                flag: "sign",
+                value: b'-',
+            }),
+            Field::new("sign", FieldKind::Char).allowed(b"+-"),
+            Field::new("scaled", FieldKind::U8).transform(Transform::Scale(0.5)),
+            Field::new("padding", FieldKind::Unknown(2)),
+        ],
+        ticks_per_second: None,
+    };
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(LAYOUT.size(), 10);
+        let bytes = [0, 0, 1, 2, 7, 0, b'-', 9, 0xaa, 0xbb, 0xff];
+        let (rest, record) = LAYOUT.parse(&bytes).unwrap();
+        assert_eq!(rest, [0xff]);
+        assert_eq!(record.value("count"), Value::Unsigned(258));
+        assert_eq!(record.value("value"), Value::Signed(-7));
+        assert_eq!(record.value("scaled"), Value::Float(4.5));
+        assert_eq!(record.value("padding"), Value::Bytes(&[0xaa, 0xbb]));
+        let spans: Vec<Range<usize>> = record.fields().map(|(_, range, _)| range).collect();
+        assert_eq!(spans, [0..4, 4..6, 6..7, 7..8, 8..10]);
+
+        let mut bad = bytes;
+        bad[6] = b'?';
+        assert!(LAYOUT.parse(&bad).is_err());
+        assert!(LAYOUT.view(&bad).is_some());
+        assert!(LAYOUT.parse(&bytes[..9]).is_err());
+
+        assert_eq!(
+            LAYOUT.to_string(),
+            "10-byte records: u32 big endian count, i16 value, char sign ('+' or '-'), \
+             u8 scaled, 2 unknown bytes"
+        );
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        static TIMED: Layout = Layout {
+            fields: &[
+                Field::new("timestamp", FieldKind::U64),
+                Field::new("value", FieldKind::U8),
+            ],
+            ticks_per_second: Some(1000.0),
+        };
+        let frame: Vec<u8> = (0..11u64)
+            .flat_map(|i| [&(1000 + i * 5).to_le_bytes()[..], &[0]].concat())
+            .chain([0xff, 0xff])
+            .collect();
+        assert_eq!(TIMED.records_in(frame.len()), (11, 2));
+        assert_eq!(TIMED.sample_rate(&frame), Some(200.0));
+        assert_eq!(TIMED.sample_rate(&frame[..9]), None);
+        assert_eq!(LAYOUT.sample_rate(&frame), None);
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = LayoutRegistry::default();
+        assert_eq!(registry.get(FrameType::Gps), Some(&crate::GPS_RECORD));
+        assert_eq!(registry.get(FrameType::Magnetic), None);
+        registry.register(FrameType::Magnetic, &LAYOUT);
+        registry.register(FrameType::Gps, &LAYOUT);
+        assert_eq!(registry.get(FrameType::Magnetic), Some(&LAYOUT));
+        assert_eq!(registry.get(FrameType::Gps), Some(&LAYOUT));
+    }
+}
@@ -0,0 +1,265 @@
+//! Record layouts read from TOML, for frame types the built-in layouts don't cover or get wrong,
+//! as `--layout` takes them.
+
+use std::{error::Error, path::Path};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{
+    FrameType,
+    layout::{Field, FieldKind, Layout, LayoutRegistry, Transform},
+};
+
+/// A file of record layouts, with a `[[frame]]` table per frame type:
+///
+/// ```toml
+/// [[frame]]
+/// type = "magnetic"
+/// field = [
+///     { name = "timestamp", kind = "u64" },
+///     { name = "x", kind = "i16", scale = 0.01 },
+///     { name = "unknown", kind = "unknown", size = 4 },
+/// ]
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LayoutFile {
+    frame: Vec<FrameLayoutSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrameLayoutSpec {
+    /// A frame type as `--frame` takes it.
+    r#type: String,
+    /// Units of the `timestamp` field per second, for estimating sample rates.
+    ticks_per_second: Option<f64>,
+    field: Vec<FieldSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldSpec {
+    name: String,
+    /// `u8`, `u16`, `u32`, `u64`, `i16`, `i32`, `f32`, `f64`, `char` or `unknown`.
+    kind: String,
+    /// Bytes in an `unknown` field.
+    size: Option<usize>,
+    #[serde(default)]
+    big_endian: bool,
+    scale: Option<f64>,
+    /// Negate the value when a `char` field holds a character, as `{ flag = "N/S", value = "S" }`.
+    negate_if: Option<NegateIfSpec>,
+    /// Characters a `char` field may hold, such as `"NS"`.
+    allowed: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NegateIfSpec {
+    flag: String,
+    value: char,
+}
+
+/// The layouts in the TOML `text` of a layout file, with the frame types they're for. Layouts
+/// live until the program exits.
+pub fn parse_layouts(text: &str) -> Result<Vec<(FrameType, &'static Layout)>, String> {
+    let file: LayoutFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    let mut layouts = Vec::new();
+    for frame in file.frame {
+        let frame_type = <FrameType as ValueEnum>::from_str(&frame.r#type, true)?;
+        let mut fields = Vec::new();
+        for spec in &frame.field {
+            let name = &spec.name;
+            let kind = match (spec.kind.as_str(), spec.size) {
+                ("u8", None) => FieldKind::U8,
+                ("u16", None) => FieldKind::U16,
+                ("u32", None) => FieldKind::U32,
+                ("u64", None) => FieldKind::U64,
+                ("i16", None) => FieldKind::I16,
+                ("i32", None) => FieldKind::I32,
+                ("f32", None) => FieldKind::F32,
+                ("f64", None) => FieldKind::F64,
+                ("char", None) => FieldKind::Char,
+                ("unknown", Some(size)) if size > 0 => FieldKind::Unknown(size),
+                ("unknown", _) => return Err(format!("field {name} needs a size")),
+                (_, Some(_)) => return Err(format!("field {name} isn't unknown, so has no size")),
+                (kind, None) => return Err(format!("field {name} has unknown kind {kind:?}")),
+            };
+            let transform = match (spec.scale, &spec.negate_if) {
+                (None, None) => Transform::None,
+                (Some(scale), None) => Transform::Scale(scale),
+                (None, Some(NegateIfSpec { flag, value })) => {
+                    let flag_is_char = (frame.field.iter())
+                        .any(|field| field.name == *flag && field.kind == "char");
+                    if !flag_is_char || !value.is_ascii() {
+                        return Err(format!(
+                            "field {name} is negated by {flag:?}, which must be a char field, \
+                             when it holds an ASCII character"
+                        ));
+                    }
+                    Transform::NegateIf {
+                        flag: flag.clone().leak(),
+                        value: *value as u8,
+                    }
+                }
+                (Some(_), Some(_)) => {
+                    return Err(format!("field {name} has both scale and negate_if"));
+                }
+            };
+            let mut field = Field::new(name.clone().leak(), kind).transform(transform);
+            if spec.big_endian {
+                field = field.big_endian();
+            }
+            if let Some(allowed) = &spec.allowed {
+                field = field.allowed(allowed.clone().into_bytes().leak());
+            }
+            fields.push(field);
+        }
+        if fields.is_empty() {
+            return Err(format!("the {} layout has no fields", frame.r#type));
+        }
+        let layout = Box::leak(Box::new(Layout {
+            fields: fields.leak(),
+            ticks_per_second: frame.ticks_per_second,
+        }));
+        layouts.push((frame_type, &*layout));
+    }
+    Ok(layouts)
+}
+
+/// Reads the layout file at `path` into `registry`.
+pub fn load_layouts(path: &Path, registry: &mut LayoutRegistry) -> Result<(), Box<dyn Error>> {
+    let layouts = parse_layouts(&std::fs::read_to_string(path)?)
+        .map_err(|message| format!("{}: {message}", path.display()))?;
+    for (frame_type, layout) in layouts {
+        registry.register(frame_type, layout);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_layouts() {
+        let layouts = parse_layouts(
+            r#"
+            [[frame]]
+            type = "magnetic"
+            ticks_per_second = 1000.0
+            field = [
+                { name = "timestamp", kind = "u64" },
+                { name = "x", kind = "i16", scale = 0.5, big_endian = true },
+                { name = "hemisphere", kind = "char", allowed = "NS" },
+                { name = "y", kind = "i32", negate_if = { flag = "hemisphere", value = "S" } },
+                { name = "unknown", kind = "unknown", size = 3 },
+            ]
+            "#,
+        )
+        .unwrap();
+        let [(frame_type, layout)] = layouts[..] else {
+            panic!("expected one layout, got {}", layouts.len());
+        };
+        assert_eq!(frame_type, FrameType::Magnetic);
+        assert_eq!(layout.size(), 8 + 2 + 1 + 4 + 3);
+        assert_eq!(layout.ticks_per_second, Some(1000.0));
+        assert_eq!(layout.fields[1].transform, Transform::Scale(0.5));
+        assert_eq!(layout.fields[2].allowed, b"NS");
+        assert_eq!(layout.fields[4].kind, FieldKind::Unknown(3));
+
+        let mut record = vec![0; layout.size()];
+        record[8..10].copy_from_slice(&4i16.to_be_bytes());
+        record[10] = b'S';
+        record[11..15].copy_from_slice(&7i32.to_le_bytes());
+        let view = layout.view(&record).unwrap();
+        let values: Vec<String> = view
+            .fields()
+            .map(|(_, _, value)| value.to_string())
+            .collect();
+        assert_eq!(values[1], "2");
+        assert_eq!(values[3], "-7");
+
+        // A later layout for the same frame type replaces an earlier one.
+        let mut registry = LayoutRegistry::default();
+        registry.register(frame_type, layout);
+        let replacement = parse_layouts(
+            "[[frame]]\ntype = \"magnetic\"\nfield = [{ name = \"x\", kind = \"f32\" }]",
+        )
+        .unwrap();
+        registry.register(replacement[0].0, replacement[0].1);
+        assert_eq!(registry.get(FrameType::Magnetic).unwrap().size(), 4);
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        let error = |fields: &str| {
+            parse_layouts(&format!(
+                "[[frame]]\ntype = \"magnetic\"\nfield = [{fields}]"
+            ))
+            .unwrap_err()
+        };
+        assert_eq!(
+            error(r#"{ name = "a", kind = "unknown" }"#),
+            "field a needs a size"
+        );
+        assert_eq!(
+            error(r#"{ name = "a", kind = "unknown", size = 0 }"#),
+            "field a needs a size"
+        );
+        assert_eq!(
+            error(r#"{ name = "a", kind = "u8", size = 1 }"#),
+            "field a isn't unknown, so has no size"
+        );
+        assert_eq!(
+            error(r#"{ name = "a", kind = "u128" }"#),
+            "field a has unknown kind \"u128\""
+        );
+        assert_eq!(
+            error(r#"{ name = "a", kind = "i16", negate_if = { flag = "b", value = "S" } }"#),
+            "field a is negated by \"b\", which must be a char field, when it holds an ASCII \
+             character"
+        );
+        assert_eq!(
+            error(
+                r#"{ name = "b", kind = "char" },
+                { name = "a", kind = "i16", negate_if = { flag = "b", value = "é" } }"#
+            ),
+            "field a is negated by \"b\", which must be a char field, when it holds an ASCII \
+             character"
+        );
+        assert_eq!(
+            error(
+                r#"{ name = "b", kind = "char" },
+                { name = "a", kind = "i16", scale = 2.0, negate_if = { flag = "b", value = "S" } }"#
+            ),
+            "field a has both scale and negate_if"
+        );
+        assert_eq!(error(""), "the magnetic layout has no fields");
+        assert!(error(r#"{ name = "a", kind = "u8", colour = "red" }"#).contains("colour"));
+        assert!(
+            parse_layouts("[[frame]]\ntype = \"compass\"\nfield = []")
+                .unwrap_err()
+                .contains("compass")
+        );
+        assert!(parse_layouts("frame = 3").is_err());
+    }
+
+    #[test]
+    fn test_load_layouts() {
+        let dir = std::env::temp_dir().join(format!("ginsta-layouts-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bad.toml");
+        std::fs::write(&path, "[[frame]]\ntype = \"magnetic\"\nfield = []").unwrap();
+        let mut registry = LayoutRegistry::default();
+        let error = load_layouts(&path, &mut registry).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            format!("{}: the magnetic layout has no fields", path.display())
+        );
+        assert!(load_layouts(&dir.join("missing.toml"), &mut registry).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+//! Lean angle for motorcycling, from how fast the course turns at the speed travelled. In a
+//! balanced turn the bike leans until gravity and the centripetal acceleration add up along it,
+//! so `tan(lean) = speed × yaw rate / g`.
+//!
+//! The yaw rate is read from the gyro where its clock can be placed, about the up direction from
+//! the accelerometer. In a balanced turn that direction leans with the bike, so the gyro sees the
+//! yaw rate times the cosine of the lean, and `sin(lean) = speed × rate / g` instead. Elsewhere
+//! the rate comes from the GPS course, which lags. Leaning right is positive.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    GpsRecord, GyroRecord,
+    align::{bracket, lerp},
+    heading::{MIN_HEADING_SPEED, integrate_yaw},
+    imu::ImuScale,
+    motion::turning,
+    units::Degrees,
+};
+
+pub const STANDARD_GRAVITY: f64 = 9.80665;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LeanSource {
+    Gyro,
+    Gps,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lean {
+    pub angle: Degrees,
+    pub source: LeanSource,
+}
+
+/// The lean at each GPS record, `None` below `MIN_HEADING_SPEED` or where the turn rate isn't
+/// known. Gyro timestamps are camera-clock milliseconds; `camera_epoch` is the Unix time at which
+/// that clock read zero, `None` to go by the GPS alone.
+pub fn lean_angles(
+    gps: &[GpsRecord],
+    gyro: &[GyroRecord],
+    camera_epoch: Option<f64>,
+    scale: &ImuScale,
+) -> Vec<Option<Lean>> {
+    let yaw = integrate_yaw(gyro, scale);
+    let yaw_at = |t: f64| {
+        let ms = (t - camera_epoch?) * 1000.0;
+        bracket(&yaw, ms, |(ms, _)| *ms as f64).map(|(a, b, f)| lerp(a.1, b.1, f))
+    };
+    let turns = turning(gps);
+    gps.iter()
+        .zip(turns)
+        .map(|(record, turn)| {
+            let speed = record.speed.0;
+            if speed < MIN_HEADING_SPEED {
+                return None;
+            }
+            let t = record.timestamp as f64;
+            // Over the second around the record, as GPS rates are.
+            let gyro_rate = yaw_at(t - 0.5).zip(yaw_at(t + 0.5)).map(|(a, b)| b - a);
+            let (angle, source) = match gyro_rate {
+                Some(rate) => {
+                    let ratio = speed * rate.to_radians() / STANDARD_GRAVITY;
+                    (ratio.clamp(-1.0, 1.0).asin(), LeanSource::Gyro)
+                }
+                None => {
+                    let rate = turn.turn_rate?.0;
+                    let ratio = speed * rate.to_radians() / STANDARD_GRAVITY;
+                    (ratio.atan(), LeanSource::Gps)
+                }
+            };
+            Some(Lean {
+                angle: Degrees(angle.to_degrees()),
+                source,
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LeanStats {
+    /// Furthest lean to the left, as a positive angle.
+    pub max_lean_left: Option<Degrees>,
+    pub max_lean_right: Option<Degrees>,
+    /// Mean of the lean to either side.
+    pub average_lean: Option<Degrees>,
+}
+
+/// The furthest and average lean over the estimates from `lean_angles`.
+pub fn lean_stats(leans: &[Option<Lean>]) -> LeanStats {
+    let angles: Vec<f64> = leans.iter().flatten().map(|lean| lean.angle.0).collect();
+    let furthest = |angles: Vec<f64>| {
+        angles
+            .into_iter()
+            .reduce(f64::max)
+            .filter(|angle| *angle > 0.0)
+            .map(Degrees)
+    };
+    LeanStats {
+        max_lean_left: furthest(angles.iter().map(|angle| -angle).collect()),
+        max_lean_right: furthest(angles.clone()),
+        average_lean: (!angles.is_empty()).then(|| {
+            Degrees(angles.iter().map(|angle| angle.abs()).sum::<f64>() / angles.len() as f64)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Meters, MetersPerSecond};
+
+    #[test]
+    fn test_lean_angles() {
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        // At 20 m/s, turning right at 15° a second, so tan(lean) is about 0.53: 28°.
+        let gps: Vec<GpsRecord> = (0..=10)
+            .map(|i| GpsRecord {
+                timestamp: 1000 + i,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(if i == 0 { 0.0 } else { 20.0 }),
+                track: Degrees((i as f64 * 15.0) % 360.0),
+                altitude: Meters(0.0),
+                fix: None,
+            })
+            .collect();
+        let leans = lean_angles(&gps, &[], None, &scale);
+        assert_eq!(leans[0], None);
+        let lean = leans[5].unwrap();
+        assert_eq!(lean.source, LeanSource::Gps);
+        assert!((lean.angle.0 - 28.1).abs() < 0.1, "{lean:?}");
+
+        // The gyro, leaning with the bike, sees the yaw rate times cos(28°).
+        let counts = |value: f64| value.round() as i16;
+        let rate = 15f64.to_radians() * 28.1f64.to_radians().cos();
+        let gyro: Vec<GyroRecord> = (0..=100)
+            .map(|i| {
+                let z = counts(-rate * scale.gyro_counts_per_rad_s());
+                GyroRecord::from_raw(5000 + i * 100, [0, 0, counts(32768.0 / 16.0), 0, 0, z])
+            })
+            .collect();
+        let leans = lean_angles(&gps, &gyro, Some(995.0), &scale);
+        let lean = leans[5].unwrap();
+        assert_eq!(lean.source, LeanSource::Gyro);
+        assert!((lean.angle.0 - 28.1).abs() < 0.5, "{lean:?}");
+
+        let stats = lean_stats(&leans);
+        assert_eq!(stats.max_lean_left, None);
+        assert!(stats.max_lean_right.unwrap().0 > 28.0);
+        assert!(stats.average_lean.is_some());
+        assert_eq!(lean_stats(&[]), LeanStats::default());
+    }
+}
@@ -0,0 +1,144 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod align;
+#[cfg(feature = "std")]
+pub mod allan;
+#[cfg(feature = "std")]
+pub mod amend;
+#[cfg(feature = "std")]
+pub mod anonymize;
+#[cfg(feature = "std")]
+pub mod backward;
+#[cfg(feature = "cli")]
+pub mod bench;
+#[cfg(all(feature = "std", feature = "prost"))]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod calibration;
+#[cfg(feature = "std")]
+pub mod catalog;
+#[cfg(feature = "std")]
+pub mod columnar;
+#[cfg(feature = "cli")]
+pub mod compression;
+#[cfg(feature = "cli")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod copymeta;
+pub mod core;
+#[cfg(feature = "std")]
+pub mod dem;
+#[cfg(feature = "std")]
+pub mod dji;
+#[cfg(feature = "std")]
+pub mod exposure;
+#[cfg(feature = "std")]
+pub mod ffmetadata;
+#[cfg(feature = "std")]
+pub mod file;
+#[cfg(feature = "foxglove")]
+pub mod foxglove;
+#[cfg(feature = "std")]
+pub mod gcsv;
+#[cfg(feature = "std")]
+pub mod geo;
+#[cfg(feature = "std")]
+pub mod gforce;
+#[cfg(feature = "std")]
+pub mod gpmf;
+#[cfg(feature = "std")]
+pub mod gpsd;
+#[cfg(feature = "map-output")]
+pub mod gpx;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "std")]
+pub mod heading;
+#[cfg(feature = "std")]
+pub mod highlights;
+#[cfg(feature = "std")]
+pub mod imu;
+#[cfg(feature = "cli")]
+pub mod info;
+#[cfg(feature = "std")]
+pub mod inplace;
+#[cfg(feature = "cli")]
+pub mod inspect;
+#[cfg(feature = "std")]
+pub mod laps;
+pub mod layout;
+#[cfg(feature = "cli")]
+pub mod layout_file;
+#[cfg(feature = "std")]
+pub mod lean;
+#[cfg(feature = "std")]
+pub mod magnetometer;
+#[cfg(feature = "std")]
+pub mod markers;
+#[cfg(feature = "mcap")]
+pub mod mcap;
+#[cfg(feature = "std")]
+pub mod minimize;
+#[cfg(all(feature = "std", feature = "prost"))]
+pub mod models;
+#[cfg(feature = "std")]
+pub mod motion;
+#[cfg(feature = "std")]
+pub mod mp4;
+#[cfg(feature = "std")]
+pub mod naming;
+#[cfg(feature = "std")]
+pub mod nmea;
+#[cfg(all(feature = "std", feature = "serde"))]
+pub mod overlay;
+#[cfg(feature = "std")]
+pub mod places;
+#[cfg(feature = "std")]
+pub mod plotjuggler;
+#[cfg(all(feature = "std", feature = "serde"))]
+pub mod profiles;
+#[cfg(feature = "std")]
+pub mod proxy;
+#[cfg(feature = "std")]
+pub mod query;
+#[cfg(feature = "std")]
+pub mod reframe;
+#[cfg(feature = "sqlite")]
+pub mod rosbag;
+#[cfg(feature = "serde")]
+pub mod rounding;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "std")]
+pub mod segment;
+#[cfg(all(feature = "std", feature = "serde"))]
+pub mod selftest;
+#[cfg(feature = "std")]
+pub mod stationary;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod stripped;
+#[cfg(feature = "std")]
+pub mod subtitle;
+#[cfg(feature = "std")]
+pub mod time;
+#[cfg(feature = "std")]
+pub mod track;
+pub mod units;
+#[cfg(feature = "std")]
+pub mod verify;
+#[cfg(feature = "std")]
+pub mod watch;
+
+pub use self::core::*;
+
+#[cfg(feature = "prost")]
+pub mod insvtools {
+    pub mod frames {
+        include!(concat!(env!("OUT_DIR"), "/insvtools.frames.rs"));
+    }
+}
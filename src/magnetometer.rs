@@ -0,0 +1,320 @@
+//! Hard- and soft-iron calibration of magnetometer samples. Undisturbed readings taken in every
+//! orientation lie on a sphere; a magnetised part of the mount shifts it (hard iron) and nearby
+//! metal stretches it into an ellipsoid (soft iron). Fitting that ellipsoid gives the correction.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{FrameType, frames_of_type, layout::Layout};
+
+/// A reading from the Magnetic frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MagSample {
+    /// Camera time in milliseconds, as for gyro records.
+    pub timestamp: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl MagSample {
+    pub fn reading(&self) -> [f64; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+/// The samples in a Magnetic frame read through `layout`, which needs `timestamp`, `x`, `y` and
+/// `z` fields, as the frame's records have no built-in layout. Timestamps count
+/// `ticks_per_second`, 1000 when the layout doesn't say, on the gyro's clock. `None` if the
+/// layout lacks a field.
+pub fn mag_samples(frame: &[u8], layout: &'static Layout) -> Option<Vec<MagSample>> {
+    for field in ["timestamp", "x", "y", "z"] {
+        layout.span(field)?;
+    }
+    let ticks_per_second = layout.ticks_per_second.unwrap_or(1000.0);
+    let (records, _) = layout.records_in(frame.len());
+    let samples = (0..records)
+        .filter_map(|i| layout.view(&frame[i * layout.size()..]))
+        .map(|record| MagSample {
+            timestamp: record.value("timestamp").as_f64() / ticks_per_second * 1000.0,
+            x: record.value("x").as_f64(),
+            y: record.value("y").as_f64(),
+            z: record.value("z").as_f64(),
+        })
+        .collect();
+    Some(samples)
+}
+
+/// The samples in every Magnetic frame of the camera file `data`, sorted by time, read as
+/// `mag_samples` does.
+pub fn file_mag_samples(data: &[u8], layout: &'static Layout) -> Option<Vec<MagSample>> {
+    let mut samples = Vec::new();
+    for frame in frames_of_type(data, FrameType::Magnetic) {
+        samples.extend(mag_samples(frame, layout)?);
+    }
+    samples.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    Some(samples)
+}
+
+/// Maps raw magnetometer readings back onto a sphere centred on zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MagCalibration {
+    /// Hard-iron offset, subtracted from each reading.
+    pub offset: [f64; 3],
+    /// Soft-iron matrix, applied after the offset.
+    pub soft_iron: [[f64; 3]; 3],
+    /// Radius of the corrected sphere, in the readings' units: the mean field strength.
+    pub field_strength: f64,
+}
+
+impl MagCalibration {
+    pub fn apply(&self, reading: [f64; 3]) -> [f64; 3] {
+        let centred: [f64; 3] = core::array::from_fn(|i| reading[i] - self.offset[i]);
+        core::array::from_fn(|i| (0..3).map(|j| self.soft_iron[i][j] * centred[j]).sum())
+    }
+
+    /// `sample` with its reading corrected.
+    pub fn calibrate(&self, sample: &MagSample) -> MagSample {
+        let [x, y, z] = self.apply(sample.reading());
+        MagSample { x, y, z, ..*sample }
+    }
+}
+
+/// Solves `a x = b` by Gaussian elimination with partial pivoting, or `None` if `a` is singular.
+fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    for column in 0..N {
+        let pivot =
+            (column..N).max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))?;
+        if a[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(column, pivot);
+        b.swap(column, pivot);
+        let pivot_row = a[column];
+        for row in column + 1..N {
+            let factor = a[row][column] / pivot_row[column];
+            for (value, pivot) in a[row][column..].iter_mut().zip(&pivot_row[column..]) {
+                *value -= factor * pivot;
+            }
+            b[row] -= factor * b[column];
+        }
+    }
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let sum: f64 = (row + 1..N).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a symmetric matrix, by Jacobi rotations.
+fn symmetric_eigen(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..50 {
+        let (p, q) = [(0, 1), (0, 2), (1, 2)]
+            .into_iter()
+            .max_by(|&(i, j), &(k, l)| a[i][j].abs().total_cmp(&a[k][l].abs()))
+            .unwrap();
+        if a[p][q].abs() < 1e-15 {
+            break;
+        }
+        let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+        let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+        let (c, s) = (1.0 / (t * t + 1.0).sqrt(), t / (t * t + 1.0).sqrt());
+        // A = Jᵀ A J and V = V J for the rotation J in the (p, q) plane.
+        for row in a.iter_mut().chain(&mut v) {
+            let (vp, vq) = (row[p], row[q]);
+            row[p] = c * vp - s * vq;
+            row[q] = s * vp + c * vq;
+        }
+        let (row_p, row_q) = (a[p], a[q]);
+        a[p] = core::array::from_fn(|k| c * row_p[k] - s * row_q[k]);
+        a[q] = core::array::from_fn(|k| s * row_p[k] + c * row_q[k]);
+    }
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+/// Fits an ellipsoid to `readings` by least squares and returns the calibration mapping it onto
+/// a sphere, or `None` if the readings don't determine one: fewer than nine, or too little
+/// rotation to cover the ellipsoid.
+pub fn fit_calibration(readings: &[[f64; 3]]) -> Option<MagCalibration> {
+    if readings.len() < 9 {
+        return None;
+    }
+    // Centre first to keep the normal equations well conditioned.
+    let n = readings.len() as f64;
+    let mean: [f64; 3] = core::array::from_fn(|i| readings.iter().map(|r| r[i]).sum::<f64>() / n);
+
+    // a x² + b y² + c z² + 2f yz + 2g xz + 2h xy + 2p x + 2q y + 2r z = 1
+    let mut normal = [[0.0; 9]; 9];
+    let mut rhs = [0.0; 9];
+    for reading in readings {
+        let [x, y, z] = core::array::from_fn(|i| reading[i] - mean[i]);
+        let row = [
+            x * x,
+            y * y,
+            z * z,
+            2.0 * y * z,
+            2.0 * x * z,
+            2.0 * x * y,
+            2.0 * x,
+            2.0 * y,
+            2.0 * z,
+        ];
+        for i in 0..9 {
+            for j in 0..9 {
+                normal[i][j] += row[i] * row[j];
+            }
+            rhs[i] += row[i];
+        }
+    }
+    let [a, b, c, f, g, h, p, q, r] = solve(normal, rhs)?;
+    let quadric = [[a, h, g], [h, b, f], [g, f, c]];
+    let centre = solve(quadric, [-p, -q, -r])?;
+    let k = 1.0 - (p * centre[0] + q * centre[1] + r * centre[2]);
+
+    let (values, vectors) = symmetric_eigen(quadric);
+    if k <= 0.0 || values.iter().any(|&value| value <= 0.0) {
+        return None;
+    }
+    // Semi-axes are sqrt(k / value); the sphere keeps their geometric mean.
+    let field_strength = values
+        .iter()
+        .map(|value| (k / value).sqrt())
+        .product::<f64>()
+        .cbrt();
+    let scales = values.map(|value| field_strength * (value / k).sqrt());
+    let soft_iron = core::array::from_fn(|i| {
+        core::array::from_fn(|j| {
+            (0..3)
+                .map(|e| vectors[i][e] * scales[e] * vectors[j][e])
+                .sum()
+        })
+    });
+    Some(MagCalibration {
+        offset: core::array::from_fn(|i| centre[i] + mean[i]),
+        soft_iron,
+        field_strength,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layout::{Field, FieldKind, Transform},
+        tests::build_insv,
+    };
+
+    #[test]
+    fn test_fit_calibration() {
+        // A 40 unit sphere, stretched along a tilted axis and shifted.
+        let (cos, sin) = (30f64.to_radians().cos(), 30f64.to_radians().sin());
+        let mut readings = Vec::new();
+        for i in 0..12 {
+            for j in 1..12 {
+                let (theta, phi) = (i as f64 * 0.52, j as f64 * 0.26);
+                let [x, y, z] = [
+                    40.0 * phi.sin() * theta.cos(),
+                    40.0 * phi.sin() * theta.sin(),
+                    40.0 * phi.cos(),
+                ];
+                let (x, y) = (1.3 * (cos * x - sin * y), 0.8 * (sin * x + cos * y));
+                readings.push([x + 12.0, y - 7.0, 0.9 * z + 3.0]);
+            }
+        }
+
+        let calibration = fit_calibration(&readings).unwrap();
+        for (got, expected) in calibration.offset.iter().zip([12.0, -7.0, 3.0]) {
+            assert!((got - expected).abs() < 1e-6, "{calibration:?}");
+        }
+        for reading in &readings {
+            let norm = calibration
+                .apply(*reading)
+                .iter()
+                .map(|v| v * v)
+                .sum::<f64>()
+                .sqrt();
+            assert!((norm - calibration.field_strength).abs() < 1e-6);
+        }
+        assert!(fit_calibration(&readings[..5]).is_none());
+    }
+
+    #[test]
+    fn test_file_mag_samples() {
+        static LAYOUT: Layout = Layout {
+            fields: &[
+                Field::new("timestamp", FieldKind::U64),
+                Field::new("x", FieldKind::I16).transform(Transform::Scale(0.5)),
+                Field::new("y", FieldKind::I16),
+                Field::new("z", FieldKind::I16),
+            ],
+            ticks_per_second: Some(1_000_000.0),
+        };
+        static NO_Z: Layout = Layout {
+            fields: &[
+                Field::new("timestamp", FieldKind::U64),
+                Field::new("x", FieldKind::I16),
+                Field::new("y", FieldKind::I16),
+            ],
+            ticks_per_second: None,
+        };
+        let record = |timestamp: u64, [x, y, z]: [i16; 3]| {
+            [
+                &timestamp.to_le_bytes()[..],
+                &x.to_le_bytes(),
+                &y.to_le_bytes(),
+                &z.to_le_bytes(),
+            ]
+            .concat()
+        };
+        // The second frame is earlier, and ends in half a record.
+        let mut earlier = record(1_000_000, [-4, 5, 6]);
+        earlier.extend([0; 3]);
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Magnetic, 1, record(2_500_000, [10, -20, 30])),
+                (FrameType::Magnetic, 1, earlier),
+            ],
+        );
+        let samples = file_mag_samples(&data, &LAYOUT).unwrap();
+        assert_eq!(
+            samples,
+            [
+                MagSample {
+                    timestamp: 1000.0,
+                    x: -2.0,
+                    y: 5.0,
+                    z: 6.0
+                },
+                MagSample {
+                    timestamp: 2500.0,
+                    x: 5.0,
+                    y: -20.0,
+                    z: 30.0
+                },
+            ]
+        );
+        assert_eq!(file_mag_samples(&data, &NO_Z), None);
+        assert_eq!(file_mag_samples(b"video", &LAYOUT), Some(Vec::new()));
+
+        let calibration = MagCalibration {
+            offset: [1.0, 2.0, 3.0],
+            soft_iron: [[2.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.5]],
+            field_strength: 10.0,
+        };
+        assert_eq!(
+            calibration.calibrate(&samples[1]),
+            MagSample {
+                timestamp: 2500.0,
+                x: 8.0,
+                y: -22.0,
+                z: 13.5
+            }
+        );
+    }
+}
@@ -1,412 +1,3907 @@
-use std::env::args;
-
-use log::debug;
-use memmap::MmapOptions;
-use nom::{
-    IResult, Parser,
-    bytes::{complete::tag, take},
-    character::complete::one_of,
-    combinator::eof,
-    multi::{count, many_till},
-    number::{le_f64, le_i16, le_i32, le_u16, le_u32, le_u64},
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
+    fs::File,
+    io::{BufWriter, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::{Duration, Instant},
 };
-use num_derive::{FromPrimitive, ToPrimitive};
-use num_traits::FromPrimitive;
-use prost::Message;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use comfy_table::{ContentArrangement, Row, Table, presets::UTF8_FULL_CONDENSED};
+use ginsta::{
+    FixStatus, FrameType, GpsRecord, GyroRecord, KNOWN_TRAILER_VERSIONS, TRAILER_ENTRY_NAMES,
+    Telemetry,
+    align::{VideoClock, align_to_frames, file_euler_samples},
+    allan::imu_noise,
+    amend::{NewFrame, amendment, extract, superseded_len, vacuum},
+    anonymize::{anonymize_info, anonymize_info_frame},
+    bench::{BenchError, bench_stages},
+    catalog::catalog_entry,
+    columnar::{self, read_tables, read_telemetry_tables, telemetry_tables},
+    compression::{Compression, create_output, output_compression},
+    config::{config_args, default_config_path},
+    copymeta::{MetadataCopy, copy_metadata},
+    dem::Dem,
+    dji::read_dji_srt,
+    exposure::{ExposureLimits, exposure_report, readout_time},
+    ffmetadata::{metadata_tags, segment_chapters, write_ffmetadata},
+    gcsv::{GcsvHeader, write_gcsv},
+    gforce::g_forces,
+    gpmf::{gpmd_sample_entry, read_gopro_telemetry, samples_per_second},
+    gps_frames, gps_record_refs, gpsd,
+    gpx::{
+        HeartRateSample, heart_rate_samples, write_gpx_footer, write_gpx_header, write_gpx_track,
+    },
+    gyro_frames, gyro_record_refs,
+    heading::compare_headings,
+    highlights::{Highlight, HighlightOptions, find_highlights, write_edl, write_ffmpeg_script},
+    imu::{ImuScale, resample},
+    info::{info_field, user_metadata},
+    inplace::{append_file, replace_file},
+    inspect::{frame_ranges, write_diagnostic, write_records},
+    laps::{StartFinish, crossings, lap_numbers, lap_times, split_laps},
+    layout::{Layout, LayoutRegistry},
+    layout_file::load_layouts,
+    lean::{lean_angles, lean_stats},
+    magnetometer::{MagCalibration, MagSample, file_mag_samples, fit_calibration},
+    markers::{chapter_markers, highlight_markers, write_marker_csv, write_marker_edl},
+    mcap::telemetry_mcap,
+    metadata_start,
+    models::{compatibility_warnings, measured_gyro_rate},
+    motion::{derive_motion, turning},
+    mp4::{DataTrack, data_track_layout, write_rewrite_plan, write_with_data_track},
+    mp4::{VideoTrack, parse_video_track},
+    naming::{NameFields, NameTemplate, unique_path},
+    nmea::{gga, rmc},
+    offset_map,
+    overlay::overlay_bundle,
+    places::{Gazetteer, Place, PlaceProvider, infer_time_zone},
+    plotjuggler::merge_streams,
+    profiles::{dashware_rows, racerender_rows},
+    proxy::{
+        Compared, LensChoice, choose_lens, compared_telemetry, full_resolution_sibling, is_proxy,
+        lens_partner, telemetry_differences,
+    },
+    query::Query,
+    read_index, read_telemetry, read_telemetry_with,
+    reframe::{KeyframeSource, anchor_keyframes, companion_projects, parse_project, timeline},
+    rounding::{Rounded, round},
+    schema::export_schemas,
+    segment::{ActivitySplit, sort_by_time, sort_marking_repeats, split_activities, split_at_gaps},
+    selftest::{Snapshot, Summary, compare, summarize},
+    stationary::{
+        Interval, StationaryOptions, mark_stationary, retain_moving, stationary_intervals,
+    },
+    stats::{ElevationGain, track_stats},
+    stripped::stripped_trailer_diagnostic,
+    subtitle::{AssStyle, DEFAULT_TEMPLATE, Position, Template, gps_events, write_ass, write_srt},
+    time::{
+        MAX_TIME_JUMP, TimeAnomalyPolicy, TimeBase, enforce_monotonic_telemetry, rebase_telemetry,
+    },
+    track::{BoundingBox, Track},
+    trailer_problems,
+    units::{Degrees, DegreesPerSecond, Meters, MetersPerSecond},
+    verify::verify_files,
+    watch::{Settling, is_camera_file},
+};
+#[cfg(feature = "sqlite")]
+use ginsta::{catalog::write_sqlite, rosbag::write_rosbag};
+use log::warn;
+use memmap::{Mmap, MmapOptions};
+use notify::{EventKind, RecursiveMode, Watcher};
 use serde::Serialize;
 
-pub mod insvtools {
-    pub mod frames {
-        include!(concat!(env!("OUT_DIR"), "/insvtools.frames.rs"));
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Format {
+    Csv,
+    Srt,
+    Ass,
+    /// Chapters at GPS gaps plus global tags, for `ffmpeg -i video -i meta -map_metadata 1`.
+    Ffmetadata,
+    /// A GPX track per input file, split into segments at GPS gaps.
+    Gpx,
+    /// A copy of the input video with GPS (and optionally gyro) added as a GoPro GPMF track.
+    Gpmf,
+    /// Per-second CSV of expected motion blur and rolling-shutter skew from exposure and gyro.
+    Exposure,
+    /// CSV comparing each GPS record's course with the heading integrated from the gyro, and
+    /// with the magnetometer heading given a `--layout` for the Magnetic frame.
+    Heading,
+    /// CSV of the periods the camera stood still, from GPS speed and accelerometer variance.
+    Stationary,
+    /// CSV of the gyroscope in rad/s and the accelerometer in g, on the camera clock.
+    Imu,
+    /// CSV of the magnetometer readings, on the camera clock, from the Magnetic frame read
+    /// through a `--layout` for it, corrected by any `--mag-calibration`.
+    Magnetometer,
+    /// CSV of every stream on one Unix time column, with `stream/field` column names, which
+    /// PlotJuggler loads as it is.
+    Plotjuggler,
+    /// The CSV columns as a table sized to the terminal, for reading rather than processing.
+    Table,
+    /// GPS and raw IMU records in the binary columnar format described in `ginsta::columnar`,
+    /// which ginsta also reads as input.
+    Bin,
+    /// An MCAP log with GPS, IMU and exposure channels, for Foxglove, PlotJuggler and the like.
+    Mcap,
+    /// A ROS 2 bag directory of `NavSatFix` and `Imu` messages, written to `--output`.
+    Rosbag,
+    /// JSON with position, speed and g sampled at every video frame, for overlay renderers, in
+    /// the stable shape described in `ginsta::overlay`.
+    Overlay,
+    /// CSV with the columns and units DashWare recognises, and a lap column.
+    Dashware,
+    /// CSV with the columns and units RaceRender recognises, and a lap column.
+    Racerender,
+}
+
+impl Format {
+    /// Extension for output written next to the input, as `watch` does.
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Srt => "srt",
+            Format::Ass => "ass",
+            Format::Ffmetadata => "ffmetadata",
+            Format::Gpx => "gpx",
+            Format::Gpmf => "gpmf.mp4",
+            Format::Exposure => "exposure.csv",
+            Format::Heading => "heading.csv",
+            Format::Stationary => "stationary.csv",
+            Format::Imu => "imu.csv",
+            Format::Magnetometer => "mag.csv",
+            Format::Plotjuggler => "plotjuggler.csv",
+            Format::Table => "txt",
+            Format::Bin => "bin",
+            Format::Mcap => "mcap",
+            Format::Rosbag => "bag",
+            Format::Overlay => "overlay.json",
+            Format::Dashware => "dashware.csv",
+            Format::Racerender => "racerender.csv",
+        }
     }
 }
 
-const HEADER_SIZE: i64 = 78;
+/// What ground elevation from `--dem-dir` is used for.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum DemMode {
+    /// Replace the GPS altitude wherever the DEM covers the record.
+    Replace,
+    /// Keep the GPS altitude and add a `dem_altitude` column to the default CSV.
+    Column,
+}
+
+/// Zone `{time_local}` in subtitles is shown in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TimeZoneArg {
+    /// The system's own zone.
+    Local,
+    /// The zone at the first GPS fix, from `--gazetteer` or else the longitude.
+    Auto,
+    Named(Tz),
+}
 
-const SIGNATURE: &[u8] = &[
-    0x38, 0x64, 0x62, 0x34, 0x32, 0x64, 0x36, 0x39, 0x34, 0x63, 0x63, 0x63, 0x34, 0x31, 0x38, 0x37,
-    0x39, 0x30, 0x65, 0x64, 0x66, 0x66, 0x34, 0x33, 0x39, 0x66, 0x65, 0x30, 0x32, 0x36, 0x62, 0x66,
-];
+impl FromStr for TimeZoneArg {
+    type Err = String;
 
-#[derive(Debug)]
-struct Trailer {
-    version_num: i32,
-    signature: Vec<u8>,
-    metadata: Vec<TrailerMetadata>,
-    metadata_size: u32,
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(TimeZoneArg::Local),
+            "auto" => Ok(TimeZoneArg::Auto),
+            name => name
+                .parse()
+                .map(TimeZoneArg::Named)
+                .map_err(|_| format!("unknown time zone {name:?}")),
+        }
+    }
 }
 
-#[derive(Debug)]
-struct TrailerMetadata {
-    id: u16,
-    size: u32,
+/// How commands that write a row per file lay the rows out.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum RowFormat {
+    Csv,
+    /// A table sized to the terminal, for reading rather than processing.
+    Table,
 }
 
-fn parse_trailer_metadata(data: &[u8]) -> IResult<&[u8], TrailerMetadata> {
-    let mut parser = (le_u16(), le_u32());
-    let (rest, (id, size)) = parser.parse(data)?;
+/// Which columns CSV rows carry besides the record's own.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Scope {
+    /// Only the record's columns.
+    Record,
+    /// A `segment` column numbering the runs of GPS records split at `--chapter-gap`.
+    Frame,
+    /// `file` and camera `serial` columns ahead of `segment`, so CSV from many clips can be
+    /// concatenated.
+    File,
+}
 
-    Ok((rest, TrailerMetadata { id, size }))
+/// Columns identifying where a row came from.
+#[derive(Serialize)]
+struct Source<'a> {
+    file: &'a str,
+    serial: Option<&'a str>,
+    segment: Option<usize>,
 }
 
-fn header_parser(header: &[u8]) -> IResult<&[u8], Trailer> {
-    let mut parser = (count(parse_trailer_metadata, 7), le_i32(), tag(SIGNATURE));
-    let (rest, (metadata, version_num, signature)) = parser.parse(header)?;
+#[derive(Serialize)]
+struct SegmentColumn {
+    segment: Option<usize>,
+}
 
-    let metadata_size: u32 = metadata.last().unwrap().size;
+/// Columns added after a GPS record's own, each only when its option is given.
+#[derive(Default, Serialize)]
+struct GpsColumns {
+    /// From `--derive-motion`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    derived_speed: Option<Option<MetersPerSecond>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    derived_track: Option<Option<Degrees>>,
+    /// From `--unwrap-track`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unwrapped_track: Option<Degrees>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    turn_rate: Option<Option<DegreesPerSecond>>,
+    /// From `--lean`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lean: Option<Option<Degrees>>,
+    /// From `--g-force`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gps_longitudinal_g: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gps_lateral_g: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accel_longitudinal_g: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accel_lateral_g: Option<Option<f64>>,
+    /// From `--start-finish`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lap: Option<u32>,
+    /// From `--video-time`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    video_time: Option<Option<f64>>,
+    /// Whether the record passed the filters that `--mark-invalid` keeps it through.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    valid: Option<bool>,
+}
 
-    Ok((
-        rest,
-        Trailer {
-            version_num,
-            signature: signature.to_vec(),
-            metadata,
-            metadata_size,
-        },
-    ))
-}
-
-const FRAME_HEADER_SIZE: i64 = 6;
-
-#[repr(i8)]
-#[derive(FromPrimitive, ToPrimitive, Debug, PartialEq)]
-enum FrameType {
-    Raw = -1,
-    Index = 0,
-    Info = 1,
-    Thumbnail = 2,
-    Gyro = 3,
-    Exposure = 4,
-    ThumbnailExt = 5,
-    Timelapse = 6,
-    Gps = 7,
-    StarNum = 8,
-    ThreeAInTimestamp = 9,
-    Anchors = 10,
-    ThreeASimulation = 11,
-    ExposureSecondary = 12,
-    Magnetic = 13,
-    Euler = 14,
-    GyroSecondary = 15,
-    Speed = 16,
-    Tbox = 17,
-    Editor = 18,
-    Heartrate = 19,
-    ForwardDirection = 20,
-    Upview = 21,
-    ShellRecognitionData = 22,
-    Pos = 23,
-    TimelapseQuat = 24,
-}
-
-#[derive(Debug)]
-struct FrameTrailer {
-    frame_version: u8,
-    frame_type: FrameType,
-    frame_size: i32,
-}
-
-fn frame_trailer(frame: &[u8]) -> IResult<&[u8], FrameTrailer> {
-    let mut parser = (take(1usize), take(1usize), le_i32());
-    let (rest, (frame_ver, frame_type_code, frame_size)) = parser.parse(frame)?;
-
-    let raw_frame_type = frame_type_code[0];
-    if raw_frame_type != 0 {
-        debug!("Frame type code: {}", raw_frame_type);
-    }
-
-    Ok((
-        rest,
-        FrameTrailer {
-            frame_version: frame_ver[0],
-            frame_type: FrameType::from_u8(frame_type_code[0]).unwrap_or(FrameType::Raw),
-            frame_size,
-        },
-    ))
+/// The IMU rows' column from `--video-time`.
+#[derive(Serialize)]
+struct VideoTimeColumn {
+    video_time: Option<f64>,
+}
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Extracts telemetry from Insta360, GoPro and DJI video files",
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    /// Read default options from this file instead of `~/.config/ginsta/config.toml`.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Make output byte-stable for diffing: round floats in CSV and JSON to six decimals, sort
+    /// JSON object keys, and show `{time_local}` in UTC unless `--tz` names a zone.
+    #[arg(long, global = true)]
+    deterministic: bool,
+
+    /// How to report the non-fatal anomalies met while reading the inputs, such as undecoded
+    /// frames, unknown frame types and GPS records without a fix.
+    #[arg(long, global = true, value_enum, default_value_t = AnomalyReport::Text)]
+    anomalies: AnomalyReport,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Without a subcommand, arguments are those of `export`.
+    #[command(flatten)]
+    export: ExportArgs,
 }
 
-#[derive(Debug)]
-struct IndexFrame {
-    frames: Vec<IndexFrameTrailer>,
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum AnomalyReport {
+    /// A `anomalies: kind=count ...` line on standard error, when there were any.
+    Text,
+    /// A `{"anomalies": {kind: count, ...}}` line on standard error, always.
+    Json,
+    /// Nothing.
+    Off,
 }
 
-#[derive(Debug)]
-struct IndexFrameTrailer {
-    frame_version: u8,
-    frame_type: FrameType,
-    frame_size: u32,
-    frame_offset: u32, // Offset from metadata position.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert telemetry to CSV, subtitles, chapters or a GPMF track (the default).
+    Export(ExportArgs),
+    /// Rank interesting moments by speed, turning and rotation.
+    Highlights(HighlightArgs),
+    /// Highlights and GPS segment starts as markers for Resolve or Premiere.
+    Markers(MarkerArgs),
+    /// Describe each file's metadata layout, camera and streams, and check it for consistency.
+    Info(InfoArgs),
+    /// Print the first records of one frame type as a hexdump with each field labelled and
+    /// decoded, for checking guesses about a layout against real files.
+    Inspect(InspectArgs),
+    /// Distance, speed, pace and grade of each file's GPS track, as a CSV row per file.
+    Stats(StatsArgs),
+    /// Time each lap of a circuit between crossings of a start/finish line, as a CSV row per lap
+    /// with how much slower it was than the file's fastest.
+    Laps(LapsArgs),
+    /// List the files whose GPS track passes near a position or whose statistics match an
+    /// expression, with the track's statistics.
+    Search(SearchArgs),
+    /// Catalog every file's capture time, duration, camera, track extent and statistics, as
+    /// JSON, or into an SQLite database when `--output` ends in `.sqlite` or `.db`.
+    Index(IndexArgs),
+    /// Statistical analyses of a file's sensor data.
+    #[command(subcommand)]
+    Analyze(Analysis),
+    /// Fit the calibration of a sensor to each file's samples.
+    #[command(subcommand)]
+    Calibrate(Calibration),
+    /// Check each file's Insta360 metadata for inconsistencies between the trailer, the index and
+    /// the frames, and for data that doesn't decode.
+    Verify(VerifyArgs),
+    /// Write a small sample of a file for a bug report: its video boxes without the media data,
+    /// and its metadata with the first records of each frame, which parses like the original.
+    Minimize(MinimizeArgs),
+    /// Edit each file's Insta360 metadata in place by appending the changed frames with a new
+    /// index and trailer, which leaves the original bytes untouched but superseded until
+    /// `--vacuum`.
+    Edit(EditArgs),
+    /// Copy the Insta360 metadata of one file onto another, such as a re-encode of the footage
+    /// made with a tool that dropped it, replacing any metadata the other file has.
+    CopyMeta(CopyMetaArgs),
+    /// Decode every camera file under a corpus directory and compare how each decodes with a
+    /// snapshot from an earlier run, listing the files and fields that changed.
+    Selftest(SelftestArgs),
+    /// Time each stage of reading a file, from the trailer and the index to decoding each stream
+    /// and writing exports, to measure performance on real files from one release to the next.
+    Bench(BenchArgs),
+    /// Print schemas for the rows of each CSV export.
+    Schema(SchemaArgs),
+    /// Watch directories for new camera files and export each one next to it, with options from
+    /// the command line or the config file's `[export]` and `[watch]` tables.
+    Watch(WatchArgs),
+    /// The reframing timeline of each file, merging the keyframes of its Anchors frame with those
+    /// of the Insta360 Studio project saved next to it.
+    Reframe(ReframeArgs),
+    /// Stream the GPS records as NMEA sentences paced by their timestamps, as a live receiver
+    /// would, to standard output or a UDP address, or as gpsd reports to TCP clients.
+    Replay(ReplayArgs),
+    /// Serve decoded records over gRPC; see `src/proto/ginsta.proto`.
+    #[cfg(feature = "grpc")]
+    Serve(ServeArgs),
+    /// Replay a file's telemetry in real time to Foxglove Studio, which connects to the address
+    /// as a Foxglove WebSocket data source.
+    #[cfg(feature = "foxglove")]
+    Foxglove(FoxgloveArgs),
 }
 
-fn parse_index(input: &[u8]) -> IResult<&[u8], IndexFrameTrailer> {
-    let mut parser = (take(1usize), take(1usize), le_u32(), le_u32());
-    let (rest, (frame_type, version, size, offset)) = parser.parse(input)?;
+#[derive(Args, Clone, Debug)]
+struct InputArgs {
+    /// Clock the camera's GPS timestamps are recorded against. With `gps`, the leap-second
+    /// offset is removed so exported times are UTC.
+    #[arg(long, value_enum, default_value_t = TimeBase::Utc)]
+    time_base: TimeBase,
 
-    Ok((
-        rest,
-        IndexFrameTrailer {
-            frame_version: version[0],
-            frame_type: FrameType::from_u8(frame_type[0]).unwrap_or(FrameType::Raw),
-            frame_size: size,
-            frame_offset: offset,
-        },
-    ))
+    /// Write to this file instead of standard output.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Compress the output. Implied for an `--output` ending in `.gz` or `.zst`.
+    #[arg(long, value_enum)]
+    compress: Option<Compression>,
+
+    files: Vec<PathBuf>,
 }
 
-fn parse_index_frame(frame: &[u8]) -> IResult<&[u8], IndexFrame> {
-    let (rest, index_frames) = many_till(parse_index, eof).parse(frame)?;
-    Ok((
-        rest,
-        IndexFrame {
-            frames: index_frames.0,
-        },
-    ))
+impl InputArgs {
+    fn compression(&self) -> Option<Compression> {
+        output_compression(self.compress, self.output.as_deref())
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+struct ExportArgs {
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+
+    /// Emit one CSV row per video frame with telemetry interpolated to its presentation time,
+    /// instead of the raw GPS records.
+    #[arg(long)]
+    per_frame: bool,
+
+    /// Instead of one output, write a set of sidecar files next to each input for editing: its
+    /// GPX track, a Gyroflow IMU log and its catalog entry as JSON, as `clip.gpx`, `clip.gcsv`
+    /// and `clip.json` for `clip.insv`.
+    #[arg(long, conflicts_with_all = ["format", "output", "split", "per_frame"])]
+    sidecar: bool,
+
+    /// Print what `--format gpmf` would write, instead of writing it: which byte ranges of the
+    /// input are copied where, which are rewritten, and how the trailer would change.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// With `--format gpmf`, replace the input with its copy instead of writing one, keeping the
+    /// original as `<name>.bak`. The copy is synced to disk and checked to parse with the same
+    /// telemetry before it takes the original's place.
+    #[arg(long, conflicts_with_all = ["output", "output_template", "dry_run"])]
+    in_place: bool,
+
+    /// Instead of one output, write one per input named from this template, e.g.
+    /// "{stem}_{camera}_{date}.{ext}". Fields are `stem`, `ext`, `camera`, `serial`, `firmware`,
+    /// `date` and `time` of the start in UTC, and `duration`, `distance_km` and `max_speed_kmh`.
+    /// Relative names are placed next to each input, and a name already written gets `-2`,
+    /// `-3`... added.
+    #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["output", "sidecar", "split"])]
+    output_template: Option<NameTemplate>,
+
+    /// Leave out GPS records from periods the camera stood still.
+    #[arg(long)]
+    moving_only: bool,
+
+    /// Leave out GPS records the receiver wrote with less than this fix: `fix` drops those marked
+    /// void. Only the record's A/V status byte is read, as the bytes that may hold the fix type
+    /// and satellite count aren't decoded yet, so there is no `2d`, `3d` or `dgps` and no
+    /// satellite minimum. GoPro and DJI records, and records read back from `--format bin`, don't
+    /// say and are kept.
+    #[arg(long, value_enum, value_name = "FIX")]
+    min_fix: Option<FixStatus>,
+
+    /// Find where the timestamps of a stream jump back, or forward by more than an hour, as after
+    /// a GPS week rollover, the time of day wrapping at midnight or a camera clock reset, and move
+    /// the records around the jump so the stream runs on. Each move is reported.
+    #[arg(long)]
+    rebase_time: bool,
+
+    /// Check that the timestamps of every stream strictly increase, and fail or repair them if
+    /// not.
+    #[arg(long, value_enum)]
+    on_time_anomaly: Option<TimeAnomalyPolicy>,
+
+    /// Put GPS records in time order, dropping any that repeat a timestamp, for inputs merged out
+    /// of order and consumers that need strictly increasing times.
+    #[arg(long)]
+    sort_by_time: bool,
+
+    /// Keep the GPS records `--min-fix`, `--moving-only` and `--sort-by-time` would drop, and add
+    /// a `valid` column that is false for them, for studying the bad fixes themselves.
+    #[arg(long, conflicts_with = "per_frame")]
+    mark_invalid: bool,
+
+    /// Add a `video_time` column to CSV and IMU rows with the seconds into the video at each
+    /// record, for lining data up in an editor: GPS times are placed by the movie's creation
+    /// time, camera-clock times by the Info frame's first frame timestamp, and a timelapse's are
+    /// sped up to match its playback.
+    #[arg(long, conflicts_with = "per_frame")]
+    video_time: bool,
+
+    /// Add `derived_speed` and `derived_track` columns computed from the GPS positions within a
+    /// window of this many seconds centred on each record, next to the speed and track the
+    /// camera reports, which lag and wander at low speeds.
+    #[arg(long, value_name = "SECONDS", conflicts_with = "per_frame")]
+    derive_motion: Option<f64>,
+
+    /// Add an `unwrapped_track` column that carries on past 360° or below 0° instead of
+    /// wrapping, and a `turn_rate` column in degrees per second, for following corners.
+    #[arg(long, conflicts_with = "per_frame")]
+    unwrap_track: bool,
+
+    /// Add a `lean` column with the motorcycle lean estimated from the turn rate, from the gyro
+    /// where its clock can be placed and the GPS course elsewhere, in degrees with right
+    /// positive.
+    #[arg(long, conflicts_with_all = ["per_frame", "reverse"])]
+    lean: bool,
+
+    /// Add longitudinal and lateral acceleration columns in g, both from the GPS speed and
+    /// course and from the accelerometer with gravity taken out, with forward and right
+    /// positive.
+    #[arg(long, conflicts_with_all = ["per_frame", "reverse"])]
+    g_force: bool,
+
+    /// Replace the camera's serial number and other identifiers in the Info frame with stable
+    /// pseudonyms, in the `serial` column and in the trailer `--format gpmf` copies, for sharing
+    /// the output publicly.
+    #[arg(long)]
+    anonymize: bool,
+
+    /// Write GPS records last to first, to follow a route the other way.
+    #[arg(long, conflicts_with_all = ["per_frame", "split"])]
+    reverse: bool,
+
+    /// Add columns saying which file and GPS segment each CSV row came from.
+    #[arg(long, value_enum, default_value_t = Scope::Record)]
+    scope: Scope,
+
+    /// Subtitle text for each GPS record, e.g. "{speed_kmh:.1} km/h  {alt_m:.0} m {time_local}".
+    #[arg(long, default_value = DEFAULT_TEMPLATE, help_heading = "Subtitles")]
+    template: Template,
+
+    #[arg(long, value_enum, default_value_t = Position::BottomLeft, help_heading = "Subtitles")]
+    position: Position,
+
+    #[arg(long, default_value = "Arial", help_heading = "Subtitles")]
+    font: String,
+
+    /// Font size relative to the video height.
+    #[arg(long, default_value_t = 48, help_heading = "Subtitles")]
+    font_size: u32,
+
+    /// Distance in pixels from the edges of the video.
+    #[arg(long, default_value_t = 40, help_heading = "Subtitles")]
+    margin: u32,
+
+    /// Time zone for `{time_local}`: `local`, `auto` to look it up from the first GPS fix, or an
+    /// IANA name such as `Asia/Tokyo`.
+    #[arg(long, default_value = "local", help_heading = "Subtitles")]
+    tz: TimeZoneArg,
+
+    /// GeoNames dump, such as `cities500.txt`, that `--tz auto` takes zones from. Without it the
+    /// zone is estimated from the longitude.
+    #[arg(long, help_heading = "Subtitles")]
+    gazetteer: Option<PathBuf>,
+
+    /// Start a new chapter, or CSV `segment`, when GPS records are more than this many seconds
+    /// apart.
+    #[arg(long, default_value_t = 5, help_heading = "Chapters")]
+    chapter_gap: u64,
+
+    /// Write a GPX file per activity instead of one: `gap:<duration>` (such as `gap:30m`) starts
+    /// a new one after a pause in the GPS, `day` one per UTC day and `lap` one per lap of
+    /// `--start-finish`. Files are named after `--output` with the activity's start, or `lap`
+    /// and its number, added.
+    #[arg(long, help_heading = "GPX")]
+    split: Option<ActivitySplit>,
+
+    /// Start and finish line of a circuit, as `latitude,longitude` in degrees and optionally the
+    /// heading it is crossed at, in degrees clockwise from north; without one the GPS course
+    /// nearest the point is used. Adds a `lap` CSV column counting the crossings, from 0 on the
+    /// out lap, and numbers the laps of `dashware` and `racerender`, which are otherwise all 1.
+    #[arg(
+        long,
+        value_name = "LAT,LON[,HEADING]",
+        conflicts_with_all = ["per_frame", "reverse"],
+        help_heading = "Laps"
+    )]
+    start_finish: Option<StartFinish>,
+
+    /// How far the line reaches to either side of `--start-finish`, in metres or with an `m` or
+    /// `km` suffix.
+    #[arg(long, default_value = "15m", value_parser = parse_distance, help_heading = "Laps")]
+    lap_width: f64,
+
+    /// Include the gyroscope in the GPMF track.
+    #[arg(long, help_heading = "GPMF")]
+    gpmf_gyro: bool,
+
+    /// Resample the gyroscope and accelerometer to this many samples per second, up to 1000,
+    /// averaging when decimating and interpolating otherwise. Applies to `imu` and `gpmf`
+    /// output and to the analyses based on them.
+    #[arg(long, value_name = "HZ", value_parser = parse_imu_rate, help_heading = "IMU")]
+    rate: Option<f64>,
+
+    /// Directory of SRTM `.hgt` tiles, such as `N37W122.hgt`, to look up ground elevation in.
+    #[arg(long, help_heading = "Elevation")]
+    dem_dir: Option<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = DemMode::Replace, help_heading = "Elevation")]
+    dem_mode: DemMode,
+
+    /// Flag seconds where the camera turns more than this many degrees during an exposure.
+    #[arg(long, default_value_t = 0.5, help_heading = "Exposure")]
+    blur_limit: f64,
+
+    /// Flag seconds where the camera turns more than this many degrees during sensor readout.
+    #[arg(long, default_value_t = 2.0, help_heading = "Exposure")]
+    skew_limit: f64,
+
+    /// TOML file of record layouts, as for `inspect`, for frames with no built-in layout: with
+    /// one for the Heartrate frame GPX tracks carry the heart rate at each point, with one for
+    /// the Euler frame `--per-frame` rows carry the camera's orientation, and with one for the
+    /// Magnetic frame `--format heading` compares the magnetometer heading too. `--format
+    /// magnetometer` needs one for the Magnetic frame. May be repeated.
+    #[arg(long, value_name = "FILE")]
+    layout: Vec<PathBuf>,
+
+    /// JSON magnetometer calibration written by `calibrate mag`, applied to the readings of
+    /// `--format magnetometer` and `--format heading`.
+    #[arg(long, value_name = "FILE")]
+    mag_calibration: Option<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args, Debug)]
+struct WatchArgs {
+    /// Also watch subdirectories.
+    #[arg(long)]
+    recursive: bool,
+
+    /// Seconds without changes before a new file is taken to be completely copied.
+    #[arg(long, default_value_t = 5)]
+    settle: u64,
+
+    /// The directories to watch are given in place of input files.
+    #[command(flatten)]
+    export: ExportArgs,
+}
+
+#[derive(Args, Debug)]
+struct ReframeArgs {
+    #[arg(long, value_enum, default_value_t = RowFormat::Csv)]
+    format: RowFormat,
+
+    /// Studio project to read instead of the `.insprj` files found next to the video. Only with
+    /// a single input.
+    #[arg(long, value_name = "FILE", conflicts_with = "no_project")]
+    project: Option<PathBuf>,
+
+    /// Leave out project files, keeping only the Anchors frame.
+    #[arg(long)]
+    no_project: bool,
+
+    /// TOML file of record layouts, as for `inspect`, with one for the Anchors frame, which has
+    /// no built-in layout. Without one, only project keyframes are read.
+    #[arg(long, value_name = "FILE")]
+    layout: Vec<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
 }
 
-#[derive(Debug, Serialize)]
-struct GpsRecord {
-    timestamp: u64,
-    latitude: f64,
-    longitude: f64,
+#[derive(Args, Debug)]
+struct ReplayArgs {
+    /// Playback speed as a multiple of real time.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
     speed: f64,
-    track: f64,
-    altitude: f64,
-}
-
-const NS: &[u8] = &[b'N', b'S'];
-const EW: &[u8] = &[b'E', b'W'];
-
-fn parse_gps_record(frame: &[u8]) -> IResult<&[u8], GpsRecord> {
-    let timestamp = le_u64();
-    let latitude = le_f64();
-    let northsouth = one_of(NS);
-    let longitude = le_f64();
-    let eastwest = one_of(EW);
-    let speed = le_f64();
-    let track = le_f64();
-    let altitude = le_f64();
-
-    let mut parser = (
-        timestamp,
-        take(3usize),
-        latitude,
-        northsouth,
-        longitude,
-        eastwest,
-        speed,
-        track,
-        altitude,
-    );
 
-    let (rest, (timestamp, _, latitude, northsouth, longitude, eastwest, speed, track, altitude)) =
-        parser.parse(frame)?;
+    /// Send each sentence as a datagram to this address instead of writing to standard output.
+    #[arg(long, value_name = "HOST:PORT")]
+    udp: Option<String>,
 
-    Ok((
-        rest,
-        GpsRecord {
-            timestamp,
-            latitude: if northsouth == 'S' {
-                -latitude
-            } else {
-                latitude
-            },
-            longitude: if eastwest == 'W' {
-                -longitude
-            } else {
-                longitude
-            },
-            speed,
-            track,
-            altitude,
-        },
-    ))
+    /// Listen on this address for gpsd clients instead, serving each the track from the start as
+    /// gpsd JSON reports. gpsd itself listens on port 2947.
+    #[arg(long, value_name = "HOST:PORT", conflicts_with = "udp")]
+    gpsd: Option<String>,
+
+    /// Gaps between records longer than this many seconds, such as between clips, are cut to
+    /// one second.
+    #[arg(long, default_value_t = 10)]
+    max_gap: u64,
+
+    #[arg(long, value_enum, default_value_t = TimeBase::Utc)]
+    time_base: TimeBase,
+
+    files: Vec<PathBuf>,
 }
 
-#[derive(Debug)]
-struct GpsFrame {
-    records: Vec<GpsRecord>,
+#[cfg(feature = "grpc")]
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    listen: std::net::SocketAddr,
 }
 
-fn parse_gps_frame(frame: &[u8]) -> IResult<&[u8], GpsFrame> {
-    let (rest, records) = many_till(parse_gps_record, eof).parse(frame)?;
-    assert_eq!(0, rest.len());
-    Ok((rest, GpsFrame { records: records.0 }))
+#[cfg(feature = "foxglove")]
+#[derive(Args, Debug)]
+struct FoxgloveArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8765")]
+    listen: std::net::SocketAddr,
+
+    /// Playback speed as a multiple of real time.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    speed: f64,
+
+    #[arg(long, value_enum, default_value_t = TimeBase::Utc)]
+    time_base: TimeBase,
+
+    file: PathBuf,
 }
 
-#[derive(Debug)]
-struct InfoFrame {
-    extra_metadata: insvtools::frames::ExtraMetadata,
+#[derive(Args, Debug)]
+struct InfoArgs {
+    /// List the absolute byte range of the video data, every frame and its trailer, the index
+    /// frame and the trailer instead, one `start<TAB>length<TAB>name` line each with offsets in
+    /// hex, for loading as hex editor bookmarks.
+    #[arg(long)]
+    offset_map: bool,
+
+    /// GeoNames dump, such as `cities500.txt`, to name where the GPS track starts and ends.
+    #[arg(long, value_name = "GAZETTEER")]
+    annotate_places: Option<PathBuf>,
+
+    /// Replace the camera's serial number and other identifiers in the Info frame with stable
+    /// pseudonyms, for sharing the output publicly.
+    #[arg(long)]
+    anonymize: bool,
+
+    /// Print only this field of the Info frame, bare, for shell scripts: its snake_case name,
+    /// with dots into nested messages and lists as in `dimension.x`. `serial`, `firmware`,
+    /// `firmware_version` and `camera` are short for `serial_number`, `fw_version` and
+    /// `camera_type`, and `lens_offset` is the lens calibration in `offset` read into each
+    /// lens's image circle and rotation. `user_metadata` is the JSON added with `edit
+    /// --user-metadata`. Repeat for several fields, a line each; with several files each line
+    /// starts with the file name and a tab.
+    #[arg(long, value_name = "PATH", conflicts_with = "offset_map")]
+    get: Vec<String>,
+
+    /// Write each file's lens offset, the calibration stitchers need, to `<file stem>.offset`
+    /// in this directory, such as the one its frames were extracted to.
+    #[arg(long, value_name = "DIR", conflicts_with = "offset_map")]
+    offset_sidecar: Option<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
 }
 
-fn parse_info_frame(frame: &[u8]) -> IResult<&[u8], InfoFrame> {
-    let extra_metadata = insvtools::frames::ExtraMetadata::decode(frame).unwrap();
-    Ok((frame, InfoFrame { extra_metadata }))
+#[derive(Args, Debug)]
+struct InspectArgs {
+    /// Frame type whose records to show.
+    #[arg(long, value_enum, default_value_t = FrameType::Gps)]
+    frame: FrameType,
+
+    /// Number of records to show, counted across frames of that type.
+    #[arg(long, default_value_t = 3)]
+    records: usize,
+
+    /// Records to skip before the first shown.
+    #[arg(long, default_value_t = 0)]
+    skip: usize,
+
+    /// TOML file of record layouts to use instead of the built-in ones, or for frame types that
+    /// have none, such as hypotheses about an undocumented frame. May be repeated.
+    #[arg(long, value_name = "FILE")]
+    layout: Vec<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
 }
 
-#[derive(Debug)]
-struct GyroRecord {
-    timestamp: u64,
-    payload: Vec<u8>,
+#[derive(Subcommand, Debug)]
+enum Analysis {
+    /// Allan deviation of each gyro and accelerometer axis, with the random walk and bias
+    /// instability read from it, as CSV. Record the camera kept still for an hour or more.
+    Imu(ImuAnalysisArgs),
 }
 
-#[derive(Debug)]
-struct GyroFrame {
-    records: Vec<GyroRecord>,
+#[derive(Subcommand, Debug)]
+enum Calibration {
+    /// Fit the hard-iron offset and soft-iron matrix of the magnetometer to the samples of every
+    /// file together, and print them as JSON for `export --mag-calibration`. Record the camera
+    /// turned through every orientation, away from the mount's metal.
+    Mag(CalibrateMagArgs),
 }
 
-fn parse_gyro_record(record: &[u8]) -> IResult<&[u8], GyroRecord> {
-    let timestamp = le_u64();
-    let payload = take(6 * 2usize);
+#[derive(Args, Debug)]
+struct CalibrateMagArgs {
+    /// TOML file of record layouts, as for `inspect`, with one for the Magnetic frame, which has
+    /// no built-in layout, with `timestamp`, `x`, `y` and `z` fields. May be repeated.
+    #[arg(long, value_name = "FILE", required = true)]
+    layout: Vec<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
 
-    let mut parser = (timestamp, payload);
-    let (rest, (timestamp, payload)) = parser.parse(record)?;
+#[derive(Args, Debug)]
+struct ImuAnalysisArgs {
+    /// Write the Allan deviation at each averaging time instead of the noise figures.
+    #[arg(long)]
+    curves: bool,
 
-    Ok((
-        rest,
-        GyroRecord {
-            timestamp,
-            payload: payload.to_vec(),
-        },
-    ))
+    #[command(flatten)]
+    input: InputArgs,
 }
 
-fn parse_gyro_frame(frame: &[u8]) -> IResult<&[u8], GyroFrame> {
-    let (rest, records) = many_till(parse_gyro_record, eof).parse(frame)?;
-    assert_eq!(0, rest.len());
-    Ok((rest, GyroFrame { records: records.0 }))
+#[derive(Args, Debug)]
+struct StatsArgs {
+    #[arg(long, value_enum, default_value_t = RowFormat::Csv)]
+    format: RowFormat,
+
+    /// Leave out gaps between GPS records more than this many seconds apart.
+    #[arg(long, default_value_t = 5)]
+    gap: u64,
+
+    /// Leave out periods the camera stood still, so stops don't lower the averages.
+    #[arg(long)]
+    moving_only: bool,
+
+    /// How ascent and descent are summed: `raw` counts every rise and fall between fixes,
+    /// `threshold[:<metres>]` only changes of at least 3 m or the given metres, and
+    /// `smoothed[:<seconds>]` the rises and falls of altitudes averaged over 10 s or the given
+    /// seconds. GPS altitude noise makes `raw` overestimate climbing.
+    #[arg(long, value_name = "METHOD", default_value = "raw")]
+    elevation_gain: ElevationGain,
+
+    /// Add the furthest lean to each side and the average lean, estimated as `export --lean`
+    /// does.
+    #[arg(long)]
+    lean: bool,
+
+    /// GeoNames dump, such as `cities500.txt`, to add the places and countries the GPS track
+    /// starts and ends in.
+    #[arg(long, value_name = "GAZETTEER")]
+    annotate_places: Option<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
 }
 
-#[derive(Debug)]
-struct ExposureRecord {
-    timestamp: u64,
-    shutterspeed: f64,
+#[derive(Args, Debug)]
+struct LapsArgs {
+    #[arg(long, value_enum, default_value_t = RowFormat::Csv)]
+    format: RowFormat,
+
+    /// Start and finish line, as `latitude,longitude[,heading]` in degrees as for
+    /// `export --start-finish`.
+    #[arg(long, value_name = "LAT,LON[,HEADING]")]
+    start_finish: StartFinish,
+
+    /// How far the line reaches to either side of the point, in metres or with an `m` or `km`
+    /// suffix.
+    #[arg(long, default_value = "15m", value_parser = parse_distance)]
+    lap_width: f64,
+
+    #[command(flatten)]
+    input: InputArgs,
 }
 
-#[derive(Debug)]
-struct ExposureFrame {
-    records: Vec<ExposureRecord>,
+#[derive(Args, Debug)]
+struct SearchArgs {
+    /// Position to search around, as `latitude,longitude` in degrees.
+    #[arg(long, value_name = "LAT,LON", value_parser = parse_position)]
+    near: Option<(f64, f64)>,
+
+    /// How close a track must pass, in metres or with an `m` or `km` suffix.
+    #[arg(long, default_value = "200m", value_parser = parse_distance)]
+    radius: f64,
+
+    /// Expression over the `stats` columns a track must satisfy, such as
+    /// "max_speed_kmh > 60 && duration > 10min". Numbers can take a unit: `s`, `min` or `h` for
+    /// durations, `m` or `km` for distances.
+    #[arg(long = "where", value_name = "EXPRESSION")]
+    filter: Option<Query>,
+
+    /// Leave out gaps between GPS records more than this many seconds apart from the statistics.
+    #[arg(long, default_value_t = 5)]
+    gap: u64,
+
+    /// Also search the subdirectories of directories given as input.
+    #[arg(long)]
+    recursive: bool,
+
+    /// Files, or directories to search for camera files.
+    #[command(flatten)]
+    input: InputArgs,
 }
 
-fn parse_exposure_record(frame: &[u8]) -> IResult<&[u8], ExposureRecord> {
-    let timestamp = le_u64();
-    let shutterspeed = le_f64();
+fn parse_position(s: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("expected `latitude,longitude`, got {s:?}");
+    let (latitude, longitude) = s.split_once(',').ok_or_else(invalid)?;
+    let latitude: f64 = latitude.trim().parse().map_err(|_| invalid())?;
+    let longitude: f64 = longitude.trim().parse().map_err(|_| invalid())?;
+    if latitude.abs() > 90.0 || longitude.abs() > 180.0 {
+        return Err(format!("{s:?} is out of range"));
+    }
+    Ok((latitude, longitude))
+}
 
-    let mut parser = (timestamp, shutterspeed);
-    let (rest, (timestamp, shutterspeed)) = parser.parse(frame)?;
+/// The gyro clock counts milliseconds, so rates above 1 kHz can't be represented.
+fn parse_imu_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate <= 1000.0 => Ok(rate),
+        _ => Err(format!("{s:?} isn't a rate between 0 and 1000 Hz")),
+    }
+}
 
-    Ok((
-        rest,
-        ExposureRecord {
-            timestamp,
-            shutterspeed,
-        },
-    ))
+fn parse_speed(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("{s:?} isn't a positive speed")),
+    }
 }
 
-fn parse_exposure_frame(frame: &[u8]) -> IResult<&[u8], ExposureFrame> {
-    assert_eq!(frame.len() % 16, 0);
-    let (rest, records) = many_till(parse_exposure_record, eof).parse(frame)?;
-    assert_eq!(0, rest.len());
-    Ok((rest, ExposureFrame { records: records.0 }))
+fn parse_distance(s: &str) -> Result<f64, String> {
+    let (number, scale) = if let Some(km) = s.strip_suffix("km") {
+        (km, 1000.0)
+    } else {
+        (s.strip_suffix('m').unwrap_or(s), 1.0)
+    };
+    number
+        .parse::<f64>()
+        .map(|distance| distance * scale)
+        .map_err(|_| format!("invalid distance {s:?}"))
 }
 
-/*
-* Layout:
-* |....{Metadata:[Frame{FrameTrailer}]}Trailer{MetadataPosition}|
-*
-*/
+#[derive(Args, Debug)]
+struct IndexArgs {
+    /// Leave out gaps between GPS records more than this many seconds apart from the statistics.
+    #[arg(long, default_value_t = 5)]
+    gap: u64,
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::init();
+    /// Also catalog the subdirectories of directories given as input.
+    #[arg(long)]
+    recursive: bool,
 
-    let file_names = args().skip(1);
-    let mut csv_writer = csv::Writer::from_writer(std::io::stdout());
-    for file_name in file_names {
-        let file = std::fs::File::open(file_name).expect("Failed to open file");
-        let mmap = unsafe { MmapOptions::new().map(&file)? };
+    /// Replace the camera's serial number and other identifiers in the Info frame with stable
+    /// pseudonyms, for sharing the output publicly.
+    #[arg(long)]
+    anonymize: bool,
 
-        let buffer = &mmap[(mmap.len() - HEADER_SIZE as usize)..];
-        assert_eq!(buffer.len() as i64, HEADER_SIZE);
+    /// Files, or directories to catalog the camera files in.
+    #[command(flatten)]
+    input: InputArgs,
+}
 
-        let (_, header) = header_parser(&buffer).expect("Failed to parse header");
-        debug!("{:?}", header);
+#[derive(Args, Debug)]
+struct EditArgs {
+    /// Replace the camera's serial number and other identifiers in the Info frame with stable
+    /// pseudonyms. The original Info frame stays in the file until `--vacuum`.
+    #[arg(long)]
+    anonymize: bool,
 
-        let metadata_pos = mmap.len() as u64 - header.metadata_size as u64;
+    /// Add this JSON file's contents, such as shoot notes or rights information, to the metadata
+    /// in a frame of its own, replacing any added before. `info` shows it again.
+    #[arg(long, value_name = "JSON")]
+    user_metadata: Option<PathBuf>,
 
-        // Read frames one at a time backwards from just before the header/trailer.
-        let frames_end = mmap.len() - HEADER_SIZE as usize + FRAME_HEADER_SIZE as usize;
-        let last_frame_trailer_start = frames_end - FRAME_HEADER_SIZE as usize;
-        let frame_trailer_buf = &mmap[last_frame_trailer_start..frames_end];
-        assert_eq!(frame_trailer_buf.len() as i64, FRAME_HEADER_SIZE);
+    /// Write the trailer as this version instead of the file's own, with its sizes and first and
+    /// last entries regenerated to match.
+    #[arg(long, value_name = "VERSION")]
+    trailer_version: Option<i32>,
 
-        // Read frame trailer.
-        let (_, frame_trailer) =
-            frame_trailer(&frame_trailer_buf).expect("Failed to parse frame trailer");
-        assert_eq!(frame_trailer.frame_type, FrameType::Index);
-        debug!("{:?}", frame_trailer);
+    /// Rewrite the metadata without the bytes earlier edits superseded. This copies the whole
+    /// file, checks the copy and keeps the original as `<name>.bak`.
+    #[arg(long)]
+    vacuum: bool,
 
-        let last_frame_start = last_frame_trailer_start - frame_trailer.frame_size as usize;
-        let frame_buf = &mmap[last_frame_start..last_frame_trailer_start];
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
 
-        let (_, index_frame) = parse_index_frame(frame_buf).expect("Failed to parse index frame");
-        debug!("{:?}", index_frame);
+#[derive(Args, Debug)]
+struct CopyMetaArgs {
+    /// The camera file to copy the metadata of.
+    #[arg(long, value_name = "FILE")]
+    from: PathBuf,
 
-        for frame in index_frame.frames {
-            match frame.frame_type {
-                FrameType::Gps => {
-                    let file_offset = (metadata_pos + frame.frame_offset as u64) as usize;
-                    let gps_frame_buf = &mmap[file_offset..file_offset + frame.frame_size as usize];
+    /// The file to copy it onto. Metadata is appended to a file without any; a file with some is
+    /// rewritten through a checked copy, with the original kept as `<name>.bak`.
+    #[arg(long, value_name = "FILE")]
+    to: PathBuf,
 
-                    let (_, gps_frame) =
-                        parse_gps_frame(gps_frame_buf).expect("Failed to parse GPS frame");
+    /// Copy only frames of these types, with an index of its own. All frames by default.
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "TYPES")]
+    frames: Vec<FrameType>,
 
-                    gps_frame.records.iter().for_each(|record| {
-                        csv_writer.serialize(record).expect("Failed to write CSV");
-                    });
-                }
-                FrameType::Info => {
-                    if frame.frame_version != 1 {
-                        debug!("Unknown info frame version: {}", frame.frame_version);
-                        continue;
-                    }
+    /// Write the result here and leave the `--to` file as it is.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
 
-                    let file_offset = (metadata_pos + frame.frame_offset as u64) as usize;
-                    let info_frame_buf =
-                        &mmap[file_offset..file_offset + frame.frame_size as usize];
-
-                    let (_, info_frame) =
-                        parse_info_frame(info_frame_buf).expect("Failed to parse info frame");
-                    debug!("Info frame: {:?}", info_frame);
-                }
-                FrameType::Gyro => {
-                    debug!("Gyro frame: {:?}", frame);
-                    let file_offset = (metadata_pos + frame.frame_offset as u64) as usize;
-                    let gyro_frame_buf =
-                        &mmap[file_offset..file_offset + frame.frame_size as usize];
-
-                    let (_, gyro_frame) =
-                        parse_gyro_frame(gyro_frame_buf).expect("Failed to parse gyro frame");
-                    debug!(
-                        "gyro frame: {:?} {:?} {:?}",
-                        gyro_frame.records[0], gyro_frame.records[1], gyro_frame.records[2],
-                    );
-                }
-                FrameType::Exposure => {
-                    let file_offset = (metadata_pos + frame.frame_offset as u64) as usize;
-                    let exposure_frame_buf =
-                        &mmap[file_offset..file_offset + frame.frame_size as usize];
+#[derive(Args, Debug)]
+struct MinimizeArgs {
+    /// Records to keep from each frame of fixed-size records.
+    #[arg(long, default_value_t = 100)]
+    records: usize,
 
-                    let (_, exposure_frame) = parse_exposure_frame(exposure_frame_buf)
-                        .expect("Failed to parse exposure frame");
-                    debug!(
-                        "Exposure frame: {:?} {:?}",
-                        exposure_frame.records[0], exposure_frame.records[1]
-                    );
-                }
-                _ => debug!("Other frame {:?}", frame),
-            }
+    /// Replace the camera's serial number and other identifiers in the Info frame with stable
+    /// pseudonyms, as for `export`.
+    #[arg(long)]
+    anonymize: bool,
+
+    #[arg(short, long)]
+    output: PathBuf,
+
+    file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct SelftestArgs {
+    /// Snapshot to compare with, recorded there if it doesn't exist. Defaults to
+    /// `ginsta-selftest.json` in the corpus.
+    #[arg(long, value_name = "FILE")]
+    snapshot: Option<PathBuf>,
+
+    /// Replace the snapshot with the current results after listing the changes, to accept them.
+    #[arg(long)]
+    update: bool,
+
+    corpus: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct BenchArgs {
+    /// Times to run each stage. The fastest run is reported, as the one other work disturbed
+    /// least.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    runs: u32,
+
+    #[arg(long, value_enum, default_value_t = RowFormat::Table)]
+    format: RowFormat,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// Exit with an error if any file has a problem.
+    #[arg(long)]
+    strict: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum SchemaFormat {
+    /// A JSON object mapping each export to its JSON Schema (draft 2020-12).
+    JsonSchema,
+}
+
+#[derive(Args, Debug)]
+struct SchemaArgs {
+    #[arg(long, value_enum, default_value_t = SchemaFormat::JsonSchema)]
+    format: SchemaFormat,
+
+    /// Write to this file instead of standard output.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum HighlightFormat {
+    Csv,
+    /// CMX3600 edit decision list with one event per highlight.
+    Edl,
+    /// Shell script that cuts each highlight out with ffmpeg.
+    Ffmpeg,
+}
+
+#[derive(Args, Debug)]
+struct DetectionArgs {
+    /// Number of highlights to keep per file, best first.
+    #[arg(long, default_value_t = 10, help_heading = "Detection")]
+    top: usize,
+
+    /// Standard deviations above the clip's average for a moment to count.
+    #[arg(long, default_value_t = 2.0, help_heading = "Detection")]
+    min_score: f64,
+
+    /// Seconds of context added before and after each highlight.
+    #[arg(long, default_value_t = 2.0, help_heading = "Detection")]
+    padding: f64,
+
+    /// Ignore course changes below this speed in m/s.
+    #[arg(long, default_value_t = 2.0, help_heading = "Detection")]
+    min_turn_speed: f64,
+}
+
+impl DetectionArgs {
+    fn options(&self) -> HighlightOptions {
+        HighlightOptions {
+            min_score: self.min_score,
+            padding: self.padding,
+            min_turn_speed: self.min_turn_speed,
+            top: self.top,
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct HighlightArgs {
+    #[arg(long, value_enum, default_value_t = HighlightFormat::Csv)]
+    format: HighlightFormat,
+
+    #[command(flatten)]
+    detection: DetectionArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum MarkerFormat {
+    /// Timeline markers in the EDL layout DaVinci Resolve imports.
+    Edl,
+    /// Marker list in Premiere Pro's CSV layout.
+    Csv,
+}
+
+#[derive(Args, Debug)]
+struct MarkerArgs {
+    #[arg(long, value_enum, default_value_t = MarkerFormat::Edl)]
+    format: MarkerFormat,
+
+    /// Timecode of the start of the timeline in seconds. Resolve starts at 01:00:00:00.
+    #[arg(long, default_value_t = 3600.0)]
+    timeline_start: f64,
+
+    /// Start a new GPS segment when records are more than this many seconds apart.
+    #[arg(long, default_value_t = 5)]
+    segment_gap: u64,
+
+    #[command(flatten)]
+    detection: DetectionArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+/// Unix time of the first video frame, falling back to the first GPS fix for files without a
+/// readable movie header.
+fn video_start(track: Option<&VideoTrack>, telemetry: &Telemetry) -> Option<u64> {
+    track.map(|track| track.creation_time).or(telemetry
+        .gps
+        .iter()
+        .map(|record| record.timestamp)
+        .min())
+}
+
+/// Unix time at which the gyro's millisecond clock read zero, from the Info frame's
+/// `FirstFrameTimestamp`.
+fn camera_epoch(track: Option<&VideoTrack>, telemetry: &Telemetry) -> Option<f64> {
+    let first_frame_timestamp = telemetry.info.as_ref()?.first_frame_timestamp?;
+    Some(video_start(track, telemetry)? as f64 - first_frame_timestamp as f64 / 1000.0)
+}
+
+/// The heart rate in the Heartrate frames of `mmap`, read with `layout`, in time order. Empty,
+/// with a warning, if the file has such frames but no layout or camera clock to read them with.
+fn heart_rate(
+    file_name: &Path,
+    mmap: &[u8],
+    telemetry: &Telemetry,
+    layout: Option<&'static Layout>,
+) -> Result<Vec<HeartRateSample>, Box<dyn std::error::Error>> {
+    let Some((trailer, index)) = read_index(mmap) else {
+        return Ok(Vec::new());
+    };
+    let mut frames = (index.frames.iter())
+        .filter(|frame| frame.frame_type == FrameType::Heartrate)
+        .peekable();
+    if frames.peek().is_none() {
+        return Ok(Vec::new());
+    }
+    let Some(layout) = layout else {
+        warn!(
+            "{} has a Heartrate frame, which has no built-in layout, so heart rate is left out; \
+             give one with --layout",
+            file_name.display()
+        );
+        return Ok(Vec::new());
+    };
+    let Some(epoch) = camera_epoch(parse_video_track(mmap).as_ref(), telemetry) else {
+        warn!(
+            "Can't place the camera clock of {}, so heart rate is left out",
+            file_name.display()
+        );
+        return Ok(Vec::new());
+    };
+    let metadata_start = mmap.len() - trailer.metadata_size as usize;
+    let mut samples = Vec::new();
+    for frame in frames {
+        let start = metadata_start + frame.frame_offset as usize;
+        let Some(bytes) = mmap.get(start..start + frame.frame_size as usize) else {
+            continue;
+        };
+        samples.extend(
+            heart_rate_samples(bytes, layout, epoch)
+                .ok_or("The Heartrate layout needs timestamp and bpm fields")?,
+        );
+    }
+    samples.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    Ok(samples)
+}
+
+/// `camera_epoch` for outputs on a single clock, warning that gyro records are left out without
+/// it.
+fn gyro_epoch(file_name: &Path, mmap: &[u8], telemetry: &Telemetry) -> Option<f64> {
+    let epoch = camera_epoch(parse_video_track(mmap).as_ref(), telemetry);
+    if epoch.is_none() && !telemetry.gyro.is_empty() {
+        warn!(
+            "Can't place the gyro clock of {}, so only GPS is written",
+            file_name.display()
+        );
+    }
+    epoch
+}
+
+/// Periods the camera stood still, using the accelerometer when the gyro clock can be placed.
+fn stationary(mmap: &[u8], telemetry: &Telemetry) -> Vec<Interval> {
+    let track = parse_video_track(mmap);
+    stationary_intervals(
+        &telemetry.gps,
+        &telemetry.gyro,
+        camera_epoch(track.as_ref(), telemetry),
+        &ImuScale::from_info(telemetry.info.as_ref()),
+        &StationaryOptions::default(),
+    )
+}
+
+fn load_gazetteer(path: &Path) -> std::io::Result<Gazetteer> {
+    Ok(Gazetteer::parse_geonames(&std::fs::read_to_string(path)?))
+}
+
+/// Places nearest the first and last GPS records.
+fn track_places(provider: &dyn PlaceProvider, gps: &[GpsRecord]) -> [Option<Place>; 2] {
+    [gps.first(), gps.last()]
+        .map(|record| record.and_then(|r| provider.place(r.latitude.0, r.longitude.0)))
+}
+
+/// Nominal frame rate for timecodes, from the video track's frame count or the Info frame.
+fn frame_rate(track: Option<&VideoTrack>, telemetry: &Telemetry) -> u32 {
+    track
+        .filter(|track| track.duration > 0)
+        .map(|track| {
+            track.presentation_times.len() as f64 * track.timescale as f64 / track.duration as f64
+        })
+        .or(telemetry
+            .info
+            .as_ref()
+            .and_then(|info| info.frame_rate)
+            .map(|rate| rate as f64))
+        .map_or(30, |fps| fps.round() as u32)
+}
+
+/// Detects highlights, placing gyro on the video timeline with the Info frame's
+/// `FirstFrameTimestamp`.
+fn detect_highlights(
+    track: Option<&VideoTrack>,
+    telemetry: &Telemetry,
+    detection: &DetectionArgs,
+) -> Vec<Highlight> {
+    let info = telemetry.info.as_ref();
+    let first_frame_timestamp = info
+        .and_then(|info| info.first_frame_timestamp)
+        .map(|timestamp| timestamp as u64);
+    find_highlights(
+        telemetry,
+        video_start(track, telemetry).map(|start| start as f64),
+        first_frame_timestamp,
+        &ImuScale::from_info(info),
+        &detection.options(),
+    )
+}
+
+/// Non-fatal anomalies met while reading the inputs, counted by kind for the summary
+/// `--anomalies` prints once the command has run.
+static ANOMALIES: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+
+fn count_anomalies(kind: &'static str, count: u64) {
+    if count > 0 {
+        *ANOMALIES.lock().unwrap().entry(kind).or_default() += count;
+    }
+}
+
+/// Prints the anomalies counted during the run to standard error.
+fn report_anomalies(report: AnomalyReport) {
+    let anomalies = ANOMALIES.lock().unwrap();
+    match report {
+        AnomalyReport::Text if !anomalies.is_empty() => {
+            let counts: Vec<String> = (anomalies.iter())
+                .map(|(kind, count)| format!("{kind}={count}"))
+                .collect();
+            eprintln!("anomalies: {}", counts.join(" "));
+        }
+        AnomalyReport::Json => eprintln!("{}", serde_json::json!({ "anomalies": *anomalies })),
+        _ => {}
+    }
+}
+
+/// Set from `--deterministic` before any output is written.
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+
+/// Decimals floats are rounded to with `--deterministic`.
+const DETERMINISTIC_DECIMALS: u32 = 6;
+
+fn deterministic() -> bool {
+    DETERMINISTIC.load(Ordering::Relaxed)
+}
+
+/// `value` as it should be serialized, rounded with `--deterministic`.
+fn rounded<T: ?Sized>(value: &T) -> Rounded<'_, T> {
+    Rounded {
+        value,
+        decimals: deterministic().then_some(DETERMINISTIC_DECIMALS),
+    }
+}
+
+/// Writes `value` as pretty JSON, going through `serde_json::Value` with `--deterministic` so
+/// object keys come out sorted.
+fn write_json(
+    mut out: impl Write,
+    value: &impl Serialize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if deterministic() {
+        serde_json::to_writer_pretty(&mut out, &serde_json::to_value(rounded(value))?)?;
+    } else {
+        serde_json::to_writer_pretty(&mut out, value)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Rows beyond this many are left out of tables, which are for looking at rather than keeping.
+const MAX_TABLE_ROWS: usize = 50;
+
+/// Decimals shown for fractional numbers in tables.
+const TABLE_DECIMALS: u32 = 4;
+
+/// Renders CSV as a table fitting the terminal, with fractional numbers shortened, over-long
+/// cells cut to one line and rows past `MAX_TABLE_ROWS` summarised.
+fn write_table(mut out: impl Write, csv: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = csv::Reader::from_reader(csv);
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .set_content_arrangement(ContentArrangement::Dynamic)
+        .set_header(reader.headers()?);
+    let mut rows = 0;
+    for record in reader.records() {
+        rows += 1;
+        if rows <= MAX_TABLE_ROWS {
+            let mut row = Row::from(record?.iter().map(|cell| match cell.parse::<f64>() {
+                Ok(value) if cell.contains('.') => round(value, TABLE_DECIMALS).to_string(),
+                _ => cell.to_string(),
+            }));
+            row.max_height(1);
+            table.add_row(row);
+        }
+    }
+    writeln!(out, "{table}")?;
+    if rows > MAX_TABLE_ROWS {
+        writeln!(out, "… {} more rows", rows - MAX_TABLE_ROWS)?;
+    }
+    Ok(())
+}
+
+/// CSV output from several files shares a single header row.
+fn csv_writer<W: Write>(out: W, header_written: &mut bool) -> csv::Writer<W> {
+    let writer = csv::WriterBuilder::new()
+        .has_headers(!*header_written)
+        .from_writer(out);
+    *header_written = true;
+    writer
+}
+
+/// Writes a CSV row with the extra columns `scope` asks for.
+fn write_row<W: Write>(
+    writer: &mut csv::Writer<W>,
+    scope: Scope,
+    source: Source,
+    row: impl Serialize,
+) -> csv::Result<()> {
+    match scope {
+        Scope::Record => writer.serialize(rounded(&row)),
+        Scope::Frame => writer.serialize(rounded(&(
+            SegmentColumn {
+                segment: source.segment,
+            },
+            row,
+        ))),
+        Scope::File => writer.serialize(rounded(&(source, row))),
+    }
+}
+
+/// The 1-based GPS segment whose records span `timestamp`.
+fn segment_at(segments: &[&[GpsRecord]], timestamp: f64) -> Option<usize> {
+    segments
+        .iter()
+        .position(|segment| {
+            segment.first().unwrap().timestamp as f64 <= timestamp
+                && timestamp <= segment.last().unwrap().timestamp as f64
+        })
+        .map(|i| i + 1)
+}
+
+/// Reads a file's telemetry, from its full-resolution sibling when it is a low-resolution proxy,
+/// warning where the two disagree.
+fn load(file_name: &Path, time_base: TimeBase) -> std::io::Result<Option<(Mmap, Telemetry)>> {
+    load_with(file_name, time_base, RecordLoading::Collect)
+}
+
+/// Whether loading an Insta360 file decodes its GPS and gyro records, or leaves them to be read
+/// in place from the mapped file with `gps_frames` and `gyro_frames`. Records from other sources
+/// are always decoded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RecordLoading {
+    Collect,
+    Stream,
+}
+
+/// Like `load`, with records decoded or not as `loading` says.
+fn load_with(
+    file_name: &Path,
+    time_base: TimeBase,
+    loading: RecordLoading,
+) -> std::io::Result<Option<(Mmap, Telemetry)>> {
+    if !is_proxy(file_name) {
+        return load_lens(file_name, time_base, loading);
+    }
+    let Some(full) = full_resolution_sibling(file_name) else {
+        warn!(
+            "{} is a low-resolution proxy whose full-resolution file isn't next to it, so its \
+             own metadata is read",
+            file_name.display()
+        );
+        return load_file(file_name, time_base, loading);
+    };
+    log::info!(
+        "Reading {} for its proxy {}",
+        full.display(),
+        file_name.display()
+    );
+    let loaded = load_file(&full, time_base, loading)?;
+    if loaded.is_some()
+        && let Some((full_mmap, full_telemetry)) = insta360_telemetry(&full, time_base)?
+        && let Some((mmap, telemetry)) = insta360_telemetry(file_name, time_base)?
+    {
+        let proxy = Compared::new(&mmap, &telemetry);
+        for difference in telemetry_differences(&proxy, &Compared::new(&full_mmap, &full_telemetry))
+        {
+            count_anomalies("proxy_differences", 1);
+            warn!(
+                "The telemetry of {} differs from that of {}: {difference}",
+                file_name.display(),
+                full.display()
+            );
+        }
+    }
+    Ok(loaded)
+}
+
+/// Loads one lens's file of a dual-stream recording, or the other lens's if it has more complete
+/// telemetry, warning where the two differ.
+fn load_lens(
+    file_name: &Path,
+    time_base: TimeBase,
+    loading: RecordLoading,
+) -> std::io::Result<Option<(Mmap, Telemetry)>> {
+    let Some(partner) = lens_partner(file_name) else {
+        return load_file(file_name, time_base, loading);
+    };
+    let Some((other_mmap, other_telemetry)) = insta360_telemetry(&partner, time_base)? else {
+        return load_file(file_name, time_base, loading);
+    };
+    let other = Compared::new(&other_mmap, &other_telemetry);
+    let own = insta360_telemetry(file_name, time_base)?;
+    let own = (own.as_ref()).map(|(mmap, telemetry)| Compared::new(mmap, telemetry));
+    for difference in (own.iter()).flat_map(|own| telemetry_differences(&other, own)) {
+        count_anomalies("lens_pair_differences", 1);
+        warn!(
+            "The telemetry of {} differs from that of {}: {difference}",
+            partner.display(),
+            file_name.display()
+        );
+    }
+    match choose_lens(own.as_ref(), &other) {
+        LensChoice::Own => load_file(file_name, time_base, loading),
+        LensChoice::OnlyPartner => {
+            log::info!(
+                "Reading {}, as the other lens's {} has no metadata",
+                partner.display(),
+                file_name.display()
+            );
+            load_file(&partner, time_base, loading)
         }
+        LensChoice::MoreCompletePartner => {
+            warn!(
+                "{} has more complete telemetry than {}, so it is read instead",
+                partner.display(),
+                file_name.display()
+            );
+            load_file(&partner, time_base, loading)
+        }
+    }
+}
+
+/// The Insta360 telemetry of `file_name` with GPS times in UTC, if it has any, read without
+/// reporting anomalies for comparing with another file's. Its gyro records are left in the
+/// mapped file, which comes with it, for `Compared` to read in place.
+fn insta360_telemetry(
+    file_name: &Path,
+    time_base: TimeBase,
+) -> std::io::Result<Option<(Mmap, Telemetry)>> {
+    let mmap = unsafe { MmapOptions::new().map(&File::open(file_name)?)? };
+    if metadata_start(&mmap).is_none() {
+        return Ok(None);
     }
+    let mut telemetry = compared_telemetry(&mmap);
+    for record in telemetry.gps.iter_mut() {
+        record.timestamp = time_base.to_utc(record.timestamp);
+    }
+    Ok(Some((mmap, telemetry)))
+}
 
+/// Maps an input file and reads whichever kind of telemetry it holds, with GPS times in UTC.
+fn load_file(
+    file_name: &Path,
+    time_base: TimeBase,
+    loading: RecordLoading,
+) -> std::io::Result<Option<(Mmap, Telemetry)>> {
+    let file = File::open(file_name)?;
+    let mmap = unsafe { MmapOptions::new().map(&file)? };
+
+    let is_srt = file_name
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("srt"));
+    if mmap.starts_with(columnar::MAGIC) {
+        // Written by `--format bin`, with GPS times already in UTC.
+        let Some(tables) = read_tables(&mmap) else {
+            warn!("Truncated or corrupt tables in {}", file_name.display());
+            count_anomalies("unreadable_files", 1);
+            return Ok(None);
+        };
+        let telemetry = read_telemetry_tables(&tables).into_telemetry();
+        return Ok(Some((mmap, telemetry)));
+    }
+    let mut telemetry = if is_srt {
+        read_dji_srt(&String::from_utf8_lossy(&mmap))
+    } else if metadata_start(&mmap).is_some() {
+        let mut without_fix = 0;
+        let telemetry = match loading {
+            RecordLoading::Collect => read_telemetry(&mmap),
+            RecordLoading::Stream => read_telemetry_with(
+                &mmap,
+                |frame| {
+                    without_fix += (gps_record_refs(frame))
+                        .filter(|record| record.fix() == FixStatus::NoFix)
+                        .count();
+                },
+                |_| {},
+            ),
+        };
+        if log::log_enabled!(log::Level::Warn) {
+            let mut stderr = anstream::stderr().lock();
+            for diagnostic in &telemetry.diagnostics {
+                write_diagnostic(&mut stderr, file_name, &mmap, diagnostic)?;
+            }
+        }
+        for diagnostic in &telemetry.diagnostics {
+            count_anomalies(diagnostic.kind(), 1);
+        }
+        for warning in compatibility_warnings(&mmap, &telemetry) {
+            warn!("{}: {warning}", file_name.display());
+            count_anomalies("compatibility_warnings", 1);
+        }
+        if let Some((_, index)) = read_index(&mmap) {
+            let unknown = (index.frames.iter()).filter(|frame| frame.frame_type == FrameType::Raw);
+            count_anomalies("unknown_frame_types", unknown.count() as u64);
+        }
+        without_fix += (telemetry.gps.iter())
+            .filter(|r| r.fix == Some(FixStatus::NoFix))
+            .count();
+        count_anomalies("gps_records_without_fix", without_fix as u64);
+        telemetry
+    } else if let Some(telemetry) = read_gopro_telemetry(&mmap) {
+        telemetry
+    } else if let Some(diagnostic) = stripped_trailer_diagnostic(file_name, &mmap) {
+        // Shown whatever the log level, as there is something to do about it.
+        eprintln!("{}: {diagnostic}", file_name.display());
+        count_anomalies("stripped_trailers", 1);
+        return Ok(None);
+    } else {
+        warn!("No Insta360 or GoPro telemetry in {}", file_name.display());
+        count_anomalies("files_without_telemetry", 1);
+        return Ok(None);
+    };
+    for record in telemetry.gps.iter_mut() {
+        record.timestamp = time_base.to_utc(record.timestamp);
+    }
+    Ok(Some((mmap, telemetry)))
+}
+
+/// Creates `path`, compressed if `compression` is given.
+fn open_output(input: &InputArgs) -> std::io::Result<Box<dyn Write>> {
+    Ok(match (&input.output, input.compression()) {
+        (Some(path), compression) => create_output(path, compression)?,
+        (None, Some(compression)) => Box::new(BufWriter::new(
+            compression.encoder(std::io::stdout().lock())?,
+        )),
+        (None, None) => Box::new(std::io::stdout().lock()),
+    })
+}
+
+/// Like `open_output`, but for text with colours, which are kept only on a terminal.
+fn open_styled_output(input: &InputArgs) -> std::io::Result<Box<dyn Write>> {
+    Ok(match (&input.output, input.compression()) {
+        (None, None) => Box::new(anstream::stdout().lock()),
+        _ => Box::new(anstream::StripStream::new(open_output(input)?)),
+    })
+}
+
+/// Parses the command line, filling in options it doesn't give from the config file.
+fn parse_cli() -> Result<Cli, Box<dyn std::error::Error>> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let command = Cli::command().args_override_self(true);
+    let matches = command.clone().get_matches_from(&args);
+
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => path.clone(),
+        None => match default_config_path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Cli::from_arg_matches(&matches)?),
+        },
+    };
+    let config: toml::Table = std::fs::read_to_string(&path)
+        .map_err(|e| format!("{}: {e}", path.display()))?
+        .parse()
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    let (name, sub_matches) = matches.subcommand().unwrap_or(("export", &matches));
+    let extra = config_args(&config, &command, name, sub_matches)
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+
+    // Ahead of any `--`, after which everything is a file name.
+    let end = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    args.splice(end..end, extra.into_iter().map(OsString::from));
+    Ok(Cli::from_arg_matches(&command.get_matches_from(args))?)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    let cli = parse_cli()?;
+    DETERMINISTIC.store(cli.deterministic, Ordering::Relaxed);
+
+    let result = match cli.command {
+        Some(Command::Export(args)) => export(&args),
+        Some(Command::Highlights(args)) => highlights(&args),
+        Some(Command::Markers(args)) => markers(&args),
+        Some(Command::Info(args)) => info(&args),
+        Some(Command::Inspect(args)) => inspect(&args),
+        Some(Command::Stats(args)) => stats(&args),
+        Some(Command::Laps(args)) => laps(&args),
+        Some(Command::Search(args)) => search(&args),
+        Some(Command::Index(args)) => index(&args),
+        Some(Command::Analyze(Analysis::Imu(args))) => analyze_imu(&args),
+        Some(Command::Calibrate(Calibration::Mag(args))) => calibrate_mag(&args),
+        Some(Command::Verify(args)) => verify(&args),
+        Some(Command::Minimize(args)) => minimize(&args),
+        Some(Command::Edit(args)) => edit(&args),
+        Some(Command::CopyMeta(args)) => copy_meta(&args),
+        Some(Command::Selftest(args)) => selftest(&args),
+        Some(Command::Bench(args)) => bench(&args),
+        Some(Command::Schema(args)) => schema(&args),
+        Some(Command::Watch(args)) => watch(&args),
+        Some(Command::Reframe(args)) => reframe(&args),
+        Some(Command::Replay(args)) => replay(&args),
+        #[cfg(feature = "grpc")]
+        Some(Command::Serve(args)) => serve(&args),
+        #[cfg(feature = "foxglove")]
+        Some(Command::Foxglove(args)) => foxglove(&args),
+        None => export(&cli.export),
+    };
+    report_anomalies(cli.anomalies);
+    result
+}
+
+fn export(args: &ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(template) = &args.output_template {
+        return export_each(args, template);
+    }
+    if args.format == Format::Gpmf && args.input.files.len() != 1 {
+        return Err("--format gpmf takes a single input file".into());
+    }
+    if args.format == Format::Mcap && args.input.files.len() != 1 {
+        return Err("--format mcap takes a single input file".into());
+    }
+    let timed_from_start = [Format::Overlay, Format::Dashware, Format::Racerender];
+    if timed_from_start.contains(&args.format) && args.input.files.len() != 1 {
+        return Err(format!(
+            "--format {} takes a single input file",
+            args.format.to_possible_value().unwrap().get_name()
+        )
+        .into());
+    }
+    let bag_output = match args.format {
+        Format::Rosbag if args.input.files.len() != 1 => {
+            return Err("--format rosbag takes a single input file".into());
+        }
+        Format::Rosbag => Some(
+            (args.input.output.as_deref())
+                .ok_or("--format rosbag writes a directory, so it needs --output")?,
+        ),
+        _ => None,
+    };
+
+    if args.mark_invalid && !matches!(args.format, Format::Csv | Format::Table) {
+        return Err("--mark-invalid adds a CSV column, so it needs --format csv or table".into());
+    }
+    if args.derive_motion.is_some() && !matches!(args.format, Format::Csv | Format::Table) {
+        return Err("--derive-motion adds CSV columns, so it needs --format csv or table".into());
+    }
+    if args.unwrap_track && !matches!(args.format, Format::Csv | Format::Table) {
+        return Err("--unwrap-track adds CSV columns, so it needs --format csv or table".into());
+    }
+    if args.lean && !matches!(args.format, Format::Csv | Format::Table) {
+        return Err("--lean adds a CSV column, so it needs --format csv or table".into());
+    }
+    if args.video_time && !matches!(args.format, Format::Csv | Format::Table | Format::Imu) {
+        return Err(
+            "--video-time adds a CSV column, so it needs --format csv, table or imu".into(),
+        );
+    }
+    if args.g_force && !matches!(args.format, Format::Csv | Format::Table) {
+        return Err("--g-force adds CSV columns, so it needs --format csv or table".into());
+    }
+
+    let gazetteer = match args.tz {
+        TimeZoneArg::Auto => args.gazetteer.as_deref().map(load_gazetteer).transpose()?,
+        _ => None,
+    };
+    if args.split == Some(ActivitySplit::Lap) && args.start_finish.is_none() {
+        return Err("--split lap needs --start-finish".into());
+    }
+    let lap_formats = [
+        Format::Csv,
+        Format::Table,
+        Format::Dashware,
+        Format::Racerender,
+    ];
+    if args.start_finish.is_some()
+        && !lap_formats.contains(&args.format)
+        && args.split != Some(ActivitySplit::Lap)
+    {
+        return Err(
+            "--start-finish adds a lap column, so it needs --format csv, table, dashware or \
+             racerender, or --split lap"
+                .into(),
+        );
+    }
+    let split_output = match args.split {
+        Some(_) if args.format != Format::Gpx => return Err("--split needs --format gpx".into()),
+        Some(_) => Some(
+            (args.input.output.as_deref())
+                .ok_or("--split writes several files, so it needs --output")?,
+        ),
+        None => None,
+    };
+    let mut session = Vec::new();
+    let mut session_heart_rate = Vec::new();
+    let mut registry = LayoutRegistry::default();
+    for path in &args.layout {
+        load_layouts(path, &mut registry)?;
+    }
+    let heart_rate_layout = registry.get(FrameType::Heartrate);
+    let euler_layout = registry.get(FrameType::Euler);
+    let mag_layout = registry.get(FrameType::Magnetic);
+    if args.format == Format::Magnetometer && mag_layout.is_none() {
+        return Err("--format magnetometer needs a --layout for the Magnetic frame".into());
+    }
+    if args.mag_calibration.is_some()
+        && !matches!(args.format, Format::Magnetometer | Format::Heading)
+    {
+        return Err("--mag-calibration needs --format magnetometer or heading".into());
+    }
+    let mag_calibration = args
+        .mag_calibration
+        .as_deref()
+        .map(load_mag_calibration)
+        .transpose()?;
+
+    if args.dry_run && args.format != Format::Gpmf {
+        return Err("--dry-run needs --format gpmf, the only export that rewrites a file".into());
+    }
+    if args.in_place && args.format != Format::Gpmf {
+        return Err("--in-place needs --format gpmf, the only export that rewrites a file".into());
+    }
+    let mut destination: Box<dyn Write> = match split_output.or(bag_output) {
+        _ if args.dry_run => Box::new(std::io::stdout().lock()),
+        Some(_) => Box::new(std::io::sink()),
+        None if args.sidecar || args.in_place => Box::new(std::io::sink()),
+        None => open_output(&args.input)?,
+    };
+    // A table is rendered from the CSV once every row is in.
+    let mut table = Vec::new();
+    let mut out: &mut dyn Write = match args.format {
+        Format::Table => &mut table,
+        _ => &mut destination,
+    };
+    let mut csv_header_written = false;
+    if args.format == Format::Gpx && split_output.is_none() {
+        write_gpx_header(&mut out)?;
+    }
+    // IMU and plain CSV rows are written as each record is read from the mapped file, unless an
+    // option needs the whole stream at once.
+    let whole_stream = args.rebase_time || args.on_time_anomaly.is_some();
+    let gps_columns = args.derive_motion.is_some()
+        || args.unwrap_track
+        || args.lean
+        || args.g_force
+        || args.start_finish.is_some()
+        || args.mark_invalid
+        || args.dem_dir.is_some();
+    let gps_reordered = args.moving_only || args.sort_by_time || args.reverse;
+    let loading = match args.format {
+        _ if whole_stream => RecordLoading::Collect,
+        Format::Imu if args.rate.is_none() => RecordLoading::Stream,
+        Format::Csv if !args.per_frame && !args.sidecar && !gps_columns && !gps_reordered => {
+            RecordLoading::Stream
+        }
+        _ => RecordLoading::Collect,
+    };
+    for file_name in &args.input.files {
+        let Some((mmap, mut telemetry)) = load_with(file_name, args.input.time_base, loading)?
+        else {
+            continue;
+        };
+        if args.anonymize
+            && let Some(info) = telemetry.info.as_mut()
+        {
+            anonymize_info(info);
+        }
+        if args.rebase_time {
+            for adjustment in rebase_telemetry(&mut telemetry, MAX_TIME_JUMP) {
+                count_anomalies("rebased_time_jumps", 1);
+                warn!("{}: {adjustment}", file_name.display());
+            }
+        }
+        if let Some(policy) = args.on_time_anomaly {
+            let dropped = enforce_monotonic_telemetry(&mut telemetry, policy)
+                .map_err(|anomaly| format!("{}: {anomaly}", file_name.display()))?;
+            for (stream, count) in dropped.into_iter().filter(|(_, count)| *count > 0) {
+                count_anomalies("out_of_order_records_dropped", count as u64);
+                warn!(
+                    "Dropped {count} {stream} records with out-of-order timestamps from {}",
+                    file_name.display()
+                );
+            }
+        }
+        let meets_fix = |record: &GpsRecord| args.min_fix.is_none_or(|min| record.meets_fix(min));
+        if !args.mark_invalid {
+            let before = telemetry.gps.len();
+            telemetry.gps.retain(meets_fix);
+            count_anomalies(
+                "gps_records_below_min_fix",
+                (before - telemetry.gps.len()) as u64,
+            );
+        }
+        // Whether each GPS record passed the filters, for `--mark-invalid`.
+        let mut valid: Vec<bool> = telemetry.gps.iter().map(meets_fix).collect();
+        if args.moving_only {
+            let intervals = stationary(&mmap, &telemetry);
+            if args.mark_invalid {
+                mark_stationary(&telemetry.gps, &intervals, &mut valid);
+            } else {
+                retain_moving(&mut telemetry.gps, &intervals);
+            }
+        }
+        if let Some(rate) = args.rate {
+            telemetry.gyro = resample(&telemetry.gyro, rate);
+        }
+        if args.sort_by_time {
+            if args.mark_invalid {
+                sort_marking_repeats(&mut telemetry.gps, &mut valid);
+            } else {
+                sort_by_time(&mut telemetry.gps);
+            }
+        }
+        if args.reverse {
+            telemetry.gps.reverse();
+            valid.reverse();
+        }
+        let mut dem_altitudes = Vec::new();
+        if let Some(dir) = &args.dem_dir {
+            let mut dem = Dem::new(dir);
+            dem_altitudes = (telemetry.gps.iter())
+                .map(|record| dem.elevation(record.latitude.0, record.longitude.0))
+                .collect();
+            let missing = dem_altitudes.iter().filter(|a| a.is_none()).count();
+            if missing > 0 {
+                warn!(
+                    "{missing} GPS records in {} have no elevation in {}",
+                    file_name.display(),
+                    dir.display()
+                );
+            }
+            if args.dem_mode == DemMode::Replace {
+                for (record, altitude) in telemetry.gps.iter_mut().zip(&dem_altitudes) {
+                    if let Some(altitude) = altitude {
+                        record.altitude = Meters(*altitude);
+                    }
+                }
+            }
+        }
+
+        let heart_rate = if args.format == Format::Gpx || args.sidecar {
+            heart_rate(file_name, &mmap, &telemetry, heart_rate_layout)?
+        } else {
+            Vec::new()
+        };
+        if split_output.is_some() {
+            session.append(&mut telemetry.gps);
+            session_heart_rate.extend(heart_rate);
+            continue;
+        }
+        if args.sidecar {
+            write_sidecars(file_name, &mmap, &telemetry, &heart_rate, args.chapter_gap)?;
+            continue;
+        }
+
+        let file = file_name.to_string_lossy();
+        let serial = telemetry
+            .info
+            .as_ref()
+            .and_then(|info| info.serial_number.as_deref());
+        let source = |segment| Source {
+            file: &file,
+            serial,
+            segment,
+        };
+        let segments = split_at_gaps(&telemetry.gps, args.chapter_gap);
+        let derived = match args.derive_motion {
+            Some(window) => derive_motion(&telemetry.gps, window),
+            None => Vec::new(),
+        };
+        let turns = if args.unwrap_track {
+            turning(&telemetry.gps)
+        } else {
+            Vec::new()
+        };
+        let epoch = if args.lean || args.g_force {
+            camera_epoch(parse_video_track(&mmap).as_ref(), &telemetry)
+        } else {
+            None
+        };
+        let scale = ImuScale::from_info(telemetry.info.as_ref());
+        let leans = if args.lean {
+            lean_angles(&telemetry.gps, &telemetry.gyro, epoch, &scale)
+        } else {
+            Vec::new()
+        };
+        let forces = if args.g_force {
+            g_forces(&telemetry.gps, &telemetry.gyro, epoch, &scale)
+        } else {
+            Vec::new()
+        };
+        let clock = (parse_video_track(&mmap).filter(|_| args.video_time))
+            .map(|track| VideoClock::from_telemetry(&track, &telemetry));
+        if args.video_time && clock.is_none() {
+            warn!(
+                "No video track found in {}, so `video_time` is empty",
+                file_name.display()
+            );
+        }
+        let laps = match &args.start_finish {
+            Some(line) => {
+                let crossings = crossings(&telemetry.gps, line, args.lap_width);
+                lap_numbers(telemetry.gps.len(), &crossings)
+            }
+            None => Vec::new(),
+        };
+        let gps_columns: Vec<GpsColumns> = (0..telemetry.gps.len())
+            .map(|i| GpsColumns {
+                derived_speed: derived.get(i).map(|motion| motion.speed),
+                derived_track: derived.get(i).map(|motion| motion.track),
+                unwrapped_track: turns.get(i).map(|turn| turn.unwrapped_track),
+                turn_rate: turns.get(i).map(|turn| turn.turn_rate),
+                lean: leans.get(i).map(|lean| lean.map(|lean| lean.angle)),
+                gps_longitudinal_g: forces.get(i).map(|force| force.gps_longitudinal),
+                gps_lateral_g: forces.get(i).map(|force| force.gps_lateral),
+                accel_longitudinal_g: forces.get(i).map(|force| force.accel_longitudinal),
+                accel_lateral_g: forces.get(i).map(|force| force.accel_lateral),
+                lap: laps.get(i).copied(),
+                video_time: args.video_time.then(|| {
+                    let timestamp = telemetry.gps[i].timestamp as f64;
+                    clock.map(|clock| clock.video_time(timestamp))
+                }),
+                valid: args.mark_invalid.then_some(valid[i]),
+            })
+            .collect();
+
+        match args.format {
+            Format::Csv | Format::Table if args.per_frame => {
+                let Some(track) = parse_video_track(&mmap) else {
+                    warn!("No video track found in {}", file_name.display());
+                    continue;
+                };
+                let euler = match euler_layout {
+                    Some(layout) => file_euler_samples(&mmap, layout)
+                        .ok_or("The Euler layout needs timestamp, yaw, pitch and roll fields")?,
+                    None => Vec::new(),
+                };
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                for row in align_to_frames(&track, &telemetry, &euler) {
+                    let segment = segment_at(&segments, track.creation_time as f64 + row.pts);
+                    write_row(&mut csv_writer, args.scope, source(segment), row)
+                        .expect("Failed to write CSV");
+                }
+            }
+            Format::Csv | Format::Table
+                if args.dem_dir.is_some() && args.dem_mode == DemMode::Column =>
+            {
+                #[derive(Serialize)]
+                struct DemColumn {
+                    dem_altitude: Option<f64>,
+                }
+
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                let rows = telemetry.gps.iter().zip(dem_altitudes).zip(gps_columns);
+                for ((record, dem_altitude), columns) in rows {
+                    let segment = segment_at(&segments, record.timestamp as f64);
+                    let row = (record, DemColumn { dem_altitude }, columns);
+                    write_row(&mut csv_writer, args.scope, source(segment), row)
+                        .expect("Failed to write CSV");
+                }
+            }
+            Format::Csv if loading == RecordLoading::Stream => {
+                // Streamed Insta360 GPS records were left in the file by `load_with`, so are
+                // filtered and split into segments here.
+                let mut below_min_fix = 0;
+                let streamed = (gps_frames(&mmap).into_iter())
+                    .flat_map(gps_record_refs)
+                    .map(|record| GpsRecord {
+                        timestamp: args.input.time_base.to_utc(record.timestamp()),
+                        ..record.to_owned()
+                    })
+                    .filter(|record| {
+                        below_min_fix += u64::from(!meets_fix(record));
+                        meets_fix(record)
+                    });
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                let (mut segment, mut last) = (0, None);
+                for record in telemetry.gps.drain(..).chain(streamed) {
+                    if last
+                        .is_none_or(|last: u64| last.abs_diff(record.timestamp) > args.chapter_gap)
+                    {
+                        segment += 1;
+                    }
+                    last = Some(record.timestamp);
+                    let columns = GpsColumns {
+                        video_time: args
+                            .video_time
+                            .then(|| clock.map(|clock| clock.video_time(record.timestamp as f64))),
+                        ..GpsColumns::default()
+                    };
+                    write_row(
+                        &mut csv_writer,
+                        args.scope,
+                        source(Some(segment)),
+                        (&record, columns),
+                    )
+                    .expect("Failed to write CSV");
+                }
+                count_anomalies("gps_records_below_min_fix", below_min_fix);
+            }
+            Format::Csv | Format::Table => {
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                let mut gps_columns = gps_columns.into_iter();
+                for (i, segment) in segments.iter().enumerate() {
+                    for record in *segment {
+                        let row = (record, gps_columns.next().unwrap());
+                        write_row(&mut csv_writer, args.scope, source(Some(i + 1)), row)
+                            .expect("Failed to write CSV");
+                    }
+                }
+            }
+            Format::Exposure => {
+                let info = telemetry.info.as_ref();
+                let Some(first_frame_timestamp) = info
+                    .and_then(|info| info.first_frame_timestamp)
+                    .map(|timestamp| timestamp as u64)
+                    .or(telemetry.exposure.first().map(|record| record.timestamp))
+                else {
+                    warn!("No exposure records in {}", file_name.display());
+                    continue;
+                };
+                let report = exposure_report(
+                    &telemetry.exposure,
+                    &telemetry.gyro,
+                    first_frame_timestamp,
+                    &ImuScale::from_info(info),
+                    readout_time(info),
+                    ExposureLimits {
+                        blur: args.blur_limit,
+                        skew: args.skew_limit,
+                    },
+                );
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                for row in report {
+                    write_row(&mut csv_writer, args.scope, source(None), row)
+                        .expect("Failed to write CSV");
+                }
+            }
+            Format::Heading => {
+                let track = parse_video_track(&mmap);
+                let Some(camera_epoch) = camera_epoch(track.as_ref(), &telemetry) else {
+                    warn!("No GPS records or gyro timing in {}", file_name.display());
+                    continue;
+                };
+                let mag = match mag_layout {
+                    Some(layout) => file_mag_samples(&mmap, layout).ok_or(MAG_LAYOUT_FIELDS)?,
+                    None => Vec::new(),
+                };
+                let mag: Vec<MagSample> = match &mag_calibration {
+                    Some(calibration) => mag.iter().map(|s| calibration.calibrate(s)).collect(),
+                    None => mag,
+                };
+                let comparison = compare_headings(
+                    &telemetry.gps,
+                    &telemetry.gyro,
+                    &mag,
+                    camera_epoch,
+                    &ImuScale::from_info(telemetry.info.as_ref()),
+                );
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                for row in comparison {
+                    let segment = segment_at(&segments, row.timestamp as f64);
+                    write_row(&mut csv_writer, args.scope, source(segment), row)
+                        .expect("Failed to write CSV");
+                }
+            }
+            Format::Imu => {
+                let scale = ImuScale::from_info(telemetry.info.as_ref());
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                let mut write_record = |record: &GyroRecord| {
+                    let sample = scale.sample(record);
+                    if args.video_time {
+                        let video_time = VideoTimeColumn {
+                            video_time: clock
+                                .and_then(|clock| clock.video_time_ms(record.timestamp as f64)),
+                        };
+                        write_row(
+                            &mut csv_writer,
+                            args.scope,
+                            source(None),
+                            (sample, video_time),
+                        )
+                    } else {
+                        write_row(&mut csv_writer, args.scope, source(None), sample)
+                    }
+                    .expect("Failed to write CSV");
+                };
+                telemetry.gyro.iter().for_each(&mut write_record);
+                // Streamed Insta360 gyro records were left in the file by `load_with`.
+                if loading == RecordLoading::Stream {
+                    for record in gyro_frames(&mmap).into_iter().flat_map(gyro_record_refs) {
+                        write_record(&record.to_owned());
+                    }
+                }
+            }
+            Format::Magnetometer => {
+                let layout = mag_layout.expect("checked before the first file");
+                let samples = file_mag_samples(&mmap, layout).ok_or(MAG_LAYOUT_FIELDS)?;
+                if samples.is_empty() {
+                    warn!("No magnetometer samples in {}", file_name.display());
+                    continue;
+                }
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                for sample in samples {
+                    let sample = match &mag_calibration {
+                        Some(calibration) => calibration.calibrate(&sample),
+                        None => sample,
+                    };
+                    write_row(&mut csv_writer, args.scope, source(None), sample)
+                        .expect("Failed to write CSV");
+                }
+            }
+            Format::Plotjuggler => {
+                let rows = merge_streams(
+                    &telemetry.gps,
+                    &telemetry.gyro,
+                    &telemetry.exposure,
+                    &ImuScale::from_info(telemetry.info.as_ref()),
+                    gyro_epoch(file_name, &mmap, &telemetry),
+                );
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                for row in rows {
+                    let segment = segment_at(&segments, row.time);
+                    write_row(&mut csv_writer, args.scope, source(segment), row)
+                        .expect("Failed to write CSV");
+                }
+            }
+            Format::Bin => {
+                let scale = ImuScale::from_info(telemetry.info.as_ref());
+                let mut bytes = Vec::new();
+                for table in telemetry_tables(&telemetry.gps, &telemetry.gyro, scale) {
+                    table.write(&mut bytes);
+                }
+                out.write_all(&bytes)?;
+            }
+            Format::Mcap => {
+                out.write_all(&telemetry_mcap(
+                    &telemetry.gps,
+                    &telemetry.gyro,
+                    &telemetry.exposure,
+                    &ImuScale::from_info(telemetry.info.as_ref()),
+                    gyro_epoch(file_name, &mmap, &telemetry),
+                ))?;
+            }
+            Format::Rosbag => {
+                #[cfg(feature = "sqlite")]
+                write_rosbag(
+                    bag_output.unwrap(),
+                    &telemetry.gps,
+                    &telemetry.gyro,
+                    &ImuScale::from_info(telemetry.info.as_ref()),
+                    gyro_epoch(file_name, &mmap, &telemetry),
+                )?;
+                #[cfg(not(feature = "sqlite"))]
+                return Err("ROS bags need ginsta built with the `sqlite` feature".into());
+            }
+            Format::Overlay => {
+                let Some(track) = parse_video_track(&mmap) else {
+                    warn!("No video track found in {}", file_name.display());
+                    continue;
+                };
+                let forces = g_forces(
+                    &telemetry.gps,
+                    &telemetry.gyro,
+                    camera_epoch(Some(&track), &telemetry),
+                    &ImuScale::from_info(telemetry.info.as_ref()),
+                );
+                let name = file_name.file_name().unwrap_or_default().to_string_lossy();
+                let bundle = overlay_bundle(&name, &track, &telemetry.gps, &forces);
+                write_json(&mut out, &bundle)?;
+            }
+            Format::Dashware | Format::Racerender => {
+                let forces = g_forces(
+                    &telemetry.gps,
+                    &telemetry.gyro,
+                    camera_epoch(parse_video_track(&mmap).as_ref(), &telemetry),
+                    &ImuScale::from_info(telemetry.info.as_ref()),
+                );
+                let laps = match args.start_finish {
+                    Some(_) => laps,
+                    None => vec![1; telemetry.gps.len()],
+                };
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                if args.format == Format::Dashware {
+                    for row in dashware_rows(&telemetry.gps, &forces, &laps) {
+                        csv_writer.serialize(rounded(&row))?;
+                    }
+                } else {
+                    for row in racerender_rows(&telemetry.gps, &forces, &laps) {
+                        csv_writer.serialize(rounded(&row))?;
+                    }
+                }
+            }
+            Format::Stationary => {
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                for interval in stationary(&mmap, &telemetry) {
+                    let segment = segment_at(&segments, interval.start as f64);
+                    write_row(&mut csv_writer, args.scope, source(segment), interval)
+                        .expect("Failed to write CSV");
+                }
+            }
+            Format::Srt | Format::Ass => {
+                let track = parse_video_track(&mmap);
+                let Some(video_start) = video_start(track.as_ref(), &telemetry) else {
+                    warn!("No GPS records in {}", file_name.display());
+                    continue;
+                };
+                let mut template = args.template.clone();
+                template.time_zone = match args.tz {
+                    TimeZoneArg::Local => deterministic().then_some(Tz::UTC),
+                    TimeZoneArg::Named(zone) => Some(zone),
+                    TimeZoneArg::Auto => {
+                        let provider = gazetteer.as_ref().map(|g| g as &dyn PlaceProvider);
+                        let zone = infer_time_zone(&telemetry.gps, provider);
+                        match zone {
+                            Some(zone) => log::info!("{} is in {zone}", file_name.display()),
+                            None => warn!("No GPS fix in {}", file_name.display()),
+                        }
+                        zone
+                    }
+                };
+                let events = gps_events(&telemetry.gps, &template, video_start as f64);
+                if args.format == Format::Srt {
+                    write_srt(&mut out, &events)?;
+                } else {
+                    let dimension = telemetry.info.as_ref().and_then(|info| info.dimension);
+                    let style = AssStyle {
+                        position: args.position,
+                        font: args.font.clone(),
+                        font_size: args.font_size,
+                        margin: args.margin,
+                        play_res: (
+                            dimension.and_then(|d| d.x).unwrap_or(1920),
+                            dimension.and_then(|d| d.y).unwrap_or(1080),
+                        ),
+                    };
+                    write_ass(&mut out, &events, &style)?;
+                }
+            }
+            Format::Gpx => write_gpx_track(&mut out, &file, &segments, &heart_rate)?,
+            Format::Ffmetadata => {
+                let track = parse_video_track(&mmap);
+                let Some(video_start) = video_start(track.as_ref(), &telemetry) else {
+                    warn!("No GPS records in {}", file_name.display());
+                    continue;
+                };
+
+                let camera_type = telemetry
+                    .info
+                    .as_ref()
+                    .and_then(|i| i.camera_type.as_deref());
+                let tags = metadata_tags(camera_type, video_start, telemetry.gps.first());
+                let chapters = segment_chapters(&segments, video_start as f64);
+                write_ffmetadata(&mut out, &tags, &chapters)?;
+            }
+            Format::Gpmf => {
+                let Some(track) = parse_video_track(&mmap) else {
+                    return Err(format!("No video track found in {}", file_name.display()).into());
+                };
+                let info = telemetry.info.as_ref();
+                let camera = info
+                    .and_then(|info| info.camera_type.clone())
+                    .unwrap_or_else(|| "Insta360".to_string());
+                let first_frame_timestamp = info
+                    .and_then(|info| info.first_frame_timestamp)
+                    .filter(|_| args.gpmf_gyro)
+                    .map(|timestamp| timestamp as u64);
+                let seconds = (track.duration as f64 / track.timescale as f64).ceil() as u64;
+                let samples = samples_per_second(
+                    &camera,
+                    &telemetry.gps,
+                    track.creation_time,
+                    &telemetry.gyro,
+                    first_frame_timestamp,
+                    &ImuScale::from_info(info),
+                    seconds,
+                );
+                let data_track = DataTrack {
+                    handler_type: *b"meta",
+                    handler_name: "GoPro MET".to_string(),
+                    sample_entry: gpmd_sample_entry(),
+                    timescale: 1000,
+                    sample_duration: 1000,
+                    samples,
+                };
+                let mp4_end = metadata_start(&mmap).unwrap_or(mmap.len());
+                let write = |out: &mut dyn Write| {
+                    if args.anonymize {
+                        write_with_data_track(&mut *out, &mmap[..mp4_end], mp4_end, &data_track)?;
+                        out.write_all(&anonymized_trailer(file_name, &mmap, mp4_end))
+                    } else {
+                        write_with_data_track(out, &mmap, mp4_end, &data_track)
+                    }
+                };
+                if args.dry_run {
+                    let layout = data_track_layout(&mmap, mp4_end, &data_track)?;
+                    let trailer =
+                        (args.anonymize).then(|| anonymized_trailer(file_name, &mmap, mp4_end));
+                    write_rewrite_plan(
+                        &mut out,
+                        file_name,
+                        &layout,
+                        data_track.samples.len(),
+                        &mmap,
+                        mp4_end,
+                        trailer.as_deref(),
+                    )?;
+                } else if args.in_place {
+                    let original = unsafe { MmapOptions::new().map(&File::open(file_name)?)? };
+                    let backup = replace_file(file_name, write, |rewritten| {
+                        verify_rewrite(&original, rewritten)
+                    })
+                    .map_err(|e| format!("{}: {e}", file_name.display()))?;
+                    println!(
+                        "{} rewritten, with the original kept as {}",
+                        file_name.display(),
+                        backup.display()
+                    );
+                } else {
+                    write(&mut out)?;
+                }
+            }
+        }
+    }
+    if let (Some(split), Some(output)) = (args.split, split_output) {
+        write_split_gpx(
+            &mut session,
+            &mut session_heart_rate,
+            split,
+            args.start_finish.map(|line| (line, args.lap_width)),
+            args.chapter_gap,
+            output,
+            args.input.compression(),
+        )?;
+    } else if args.format == Format::Gpx {
+        write_gpx_footer(&mut out)?;
+    }
+    out.flush()?;
+    if args.format == Format::Table {
+        write_table(&mut destination, &table)?;
+        destination.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Says that `file_name`, with contents `data`, has no Insta360 metadata, and how to recover it
+/// if it looks like footage that lost its trailer.
+fn no_metadata(file_name: &Path, data: &[u8]) -> String {
+    match stripped_trailer_diagnostic(file_name, data) {
+        Some(diagnostic) => format!("{}: {diagnostic}", file_name.display()),
+        None => format!("No Insta360 metadata in {}", file_name.display()),
+    }
+}
+
+/// Checks that `rewritten` still has the video track, index and telemetry of the file `before`
+/// was mapped from, for edits in place.
+fn verify_rewrite(before: &[u8], rewritten: &Path) -> Result<(), String> {
+    let file = File::open(rewritten).map_err(|e| e.to_string())?;
+    let after = unsafe { MmapOptions::new().map(&file) }.map_err(|e| e.to_string())?;
+    if parse_video_track(before).is_some() && parse_video_track(&after).is_none() {
+        return Err("no video track".to_string());
+    }
+    match (read_index(before), read_index(&after)) {
+        (Some(_), None) => return Err("the metadata index doesn't parse".to_string()),
+        (_, Some((trailer, index))) => {
+            if let Some(problem) = trailer_problems(&after, &trailer, &index).first() {
+                return Err(problem.clone());
+            }
+        }
+        (None, None) => {}
+    }
+    let (telemetry, expected) = (compared_telemetry(&after), compared_telemetry(before));
+    let differences = telemetry_differences(
+        &Compared::new(&after, &telemetry),
+        &Compared::new(before, &expected),
+    );
+    match differences.first() {
+        Some(difference) => Err(difference.clone()),
+        None => Ok(()),
+    }
+}
+
+/// The bytes of `mmap` from `mp4_end` on, the Insta360 metadata, with the identifiers in its Info
+/// frame replaced.
+fn anonymized_trailer(file_name: &Path, mmap: &[u8], mp4_end: usize) -> Vec<u8> {
+    let mut trailer = mmap[mp4_end..].to_vec();
+    let Some((header, index)) = read_index(mmap) else {
+        return trailer;
+    };
+    let metadata_start = mmap.len() - header.metadata_size as usize;
+    for frame in (index.frames.iter()).filter(|frame| frame.frame_type == FrameType::Info) {
+        let start = (metadata_start + frame.frame_offset as usize).saturating_sub(mp4_end);
+        let anonymized = trailer
+            .get_mut(start..start + frame.frame_size as usize)
+            .and_then(anonymize_info_frame);
+        if anonymized.is_none() {
+            warn!(
+                "The Info frame of {} doesn't decode, so identifiers may be left in it",
+                file_name.display()
+            );
+        }
+    }
+    trailer
+}
+
+/// Exports each input on its own, to the file `--output-template` names for it.
+fn export_each(
+    args: &ExportArgs,
+    template: &NameTemplate,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut written = HashSet::new();
+    for file_name in &args.input.files {
+        let Some(output) = templated_output(args, template, file_name, &written)? else {
+            continue;
+        };
+        written.insert(output.clone());
+        let mut export_args = args.clone();
+        export_args.output_template = None;
+        export_args.input.files = vec![file_name.clone()];
+        export_args.input.output = Some(output.clone());
+        export(&export_args)?;
+        println!("{} -> {}", file_name.display(), output.display());
+    }
+    Ok(())
+}
+
+/// Where `--output-template` puts the export of `file_name`, avoiding the paths in `taken`, with
+/// its directory created. `None` for inputs without telemetry.
+fn templated_output(
+    args: &ExportArgs,
+    template: &NameTemplate,
+    file_name: &Path,
+    taken: &HashSet<PathBuf>,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let Some((mmap, telemetry)) = load(file_name, args.input.time_base)? else {
+        return Ok(None);
+    };
+    let mut ext = args.format.extension().to_string();
+    if let Some(compression) = args.input.compress {
+        ext = format!("{ext}.{}", compression.extension());
+    }
+    let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+    let stats = track_stats(
+        &split_at_gaps(&telemetry.gps, args.chapter_gap),
+        ElevationGain::Raw,
+    );
+    let info = telemetry.info.as_ref();
+    let fields = NameFields {
+        stem: &stem,
+        ext: &ext,
+        camera: info.and_then(|info| info.camera_type.as_deref()),
+        serial: info.and_then(|info| info.serial_number.as_deref()),
+        firmware: info.and_then(|info| info.fw_version.as_deref()),
+        start: video_start(parse_video_track(&mmap).as_ref(), &telemetry),
+        stats: (!telemetry.gps.is_empty()).then_some(&stats),
+    };
+    let dir = file_name.parent().unwrap_or(Path::new(""));
+    let output = unique_path(&dir.join(template.render(&fields)), &ext, taken);
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(Some(output))
+}
+
+/// Writes the GPX track, Gyroflow IMU log and catalog entry of `file_name` next to it, for
+/// `--sidecar`.
+fn write_sidecars(
+    file_name: &Path,
+    mmap: &[u8],
+    telemetry: &Telemetry,
+    heart_rate: &[HeartRateSample],
+    gap: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = file_name.file_name().unwrap_or_default().to_string_lossy();
+    let track = parse_video_track(mmap);
+
+    let mut gpx = create_output(&file_name.with_extension("gpx"), None)?;
+    write_gpx_header(&mut gpx)?;
+    let segments = split_at_gaps(&telemetry.gps, gap);
+    write_gpx_track(&mut gpx, &name, &segments, heart_rate)?;
+    write_gpx_footer(&mut gpx)?;
+    gpx.flush()?;
+
+    let info = telemetry.info.as_ref();
+    let header = GcsvHeader {
+        camera: info.and_then(|info| info.camera_type.as_deref()),
+        firmware: info.and_then(|info| info.fw_version.as_deref()),
+        timestamp: video_start(track.as_ref(), telemetry),
+        video_file: &name,
+        frame_readout_ms: readout_time(info).map(|seconds| seconds * 1000.0),
+    };
+    let first_frame_ms = info
+        .and_then(|info| info.first_frame_timestamp)
+        .map(|ms| ms as u64);
+    let mut gcsv = create_output(&file_name.with_extension("gcsv"), None)?;
+    write_gcsv(
+        &mut gcsv,
+        &telemetry.gyro,
+        &ImuScale::from_info(info),
+        first_frame_ms,
+        &header,
+    )?;
+    gcsv.flush()?;
+
+    let entry = catalog_entry(&file_name.to_string_lossy(), telemetry, track.as_ref(), gap);
+    let mut json = create_output(&file_name.with_extension("json"), None)?;
+    write_json(&mut json, &entry)?;
+    json.flush()?;
+    Ok(())
+}
+
+/// Writes the GPS records of all input files as one GPX file per activity, named after `output`
+/// with the activity's start time, its date when splitting by day, or `lap` and its number when
+/// splitting at `start_finish` with the line's width, added to the file stem.
+fn write_split_gpx(
+    session: &mut [GpsRecord],
+    heart_rate: &mut [HeartRateSample],
+    split: ActivitySplit,
+    start_finish: Option<(StartFinish, f64)>,
+    chapter_gap: u64,
+    output: &Path,
+    compression: Option<Compression>,
+) -> std::io::Result<()> {
+    session.sort_by_key(|record| record.timestamp);
+    heart_rate.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    // The label goes before the extension in `trips.gpx.gz` too.
+    let output = match compression {
+        Some(compression) if Compression::from_path(output) == Some(compression) => {
+            output.with_extension("")
+        }
+        _ => output.to_path_buf(),
+    };
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = output
+        .extension()
+        .unwrap_or("gpx".as_ref())
+        .to_string_lossy();
+    let activities: Vec<(String, &[GpsRecord])> = match (split, start_finish) {
+        (ActivitySplit::Lap, Some((line, width))) => {
+            let crossings = crossings(session, &line, width);
+            (split_laps(session, &crossings).into_iter())
+                .map(|(lap, records)| (format!("lap{lap:02}"), records))
+                .collect()
+        }
+        _ => (split_activities(session, split).into_iter())
+            .map(|activity| {
+                let start = DateTime::<Utc>::from_timestamp(activity[0].timestamp as i64, 0)
+                    .unwrap_or_default();
+                let label = match split {
+                    ActivitySplit::Day => start.format("%Y-%m-%d"),
+                    _ => start.format("%Y-%m-%dT%H%M%SZ"),
+                };
+                (label.to_string(), activity)
+            })
+            .collect(),
+    };
+    for (label, activity) in activities {
+        let mut path = output.with_file_name(format!("{stem}-{label}.{extension}"));
+        if let Some(compression) = compression {
+            path.as_mut_os_string()
+                .push(format!(".{}", compression.extension()));
+        }
+        let mut out = create_output(&path, compression)?;
+        write_gpx_header(&mut out)?;
+        let segments = split_at_gaps(activity, chapter_gap);
+        write_gpx_track(&mut out, &label, &segments, heart_rate)?;
+        write_gpx_footer(&mut out)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+fn highlights(args: &HighlightArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = open_output(&args.input)?;
+    let mut csv_header_written = false;
+    for file_name in &args.input.files {
+        let Some((mmap, telemetry)) = load(file_name, args.input.time_base)? else {
+            continue;
+        };
+        let track = parse_video_track(&mmap);
+        let highlights = detect_highlights(track.as_ref(), &telemetry, &args.detection);
+
+        match args.format {
+            HighlightFormat::Csv => {
+                let mut csv_writer = csv_writer(&mut out, &mut csv_header_written);
+                for highlight in highlights {
+                    csv_writer
+                        .serialize(rounded(&highlight))
+                        .expect("Failed to write CSV");
+                }
+            }
+            HighlightFormat::Edl => {
+                let fps = frame_rate(track.as_ref(), &telemetry);
+                let name = file_name.file_name().unwrap_or_default().to_string_lossy();
+                write_edl(&mut out, &name, &name, &highlights, fps)?;
+            }
+            HighlightFormat::Ffmpeg => write_ffmpeg_script(&mut out, file_name, &highlights)?,
+        }
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn markers(args: &MarkerArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = open_output(&args.input)?;
+    for file_name in &args.input.files {
+        let Some((mmap, telemetry)) = load(file_name, args.input.time_base)? else {
+            continue;
+        };
+        let track = parse_video_track(&mmap);
+        let mut markers = highlight_markers(&detect_highlights(
+            track.as_ref(),
+            &telemetry,
+            &args.detection,
+        ));
+        if let Some(video_start) = video_start(track.as_ref(), &telemetry) {
+            let segments = split_at_gaps(&telemetry.gps, args.segment_gap);
+            markers.extend(chapter_markers(&segment_chapters(
+                &segments,
+                video_start as f64,
+            )));
+        }
+        markers.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+        let fps = frame_rate(track.as_ref(), &telemetry);
+        match args.format {
+            MarkerFormat::Edl => {
+                let name = file_name.file_name().unwrap_or_default().to_string_lossy();
+                write_marker_edl(&mut out, &name, &markers, fps, args.timeline_start)?;
+            }
+            MarkerFormat::Csv => write_marker_csv(&mut out, &markers, fps)?,
+        }
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn info(args: &InfoArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.offset_map {
+        return write_offset_maps(&args.input);
+    }
+    let gazetteer = args
+        .annotate_places
+        .as_deref()
+        .map(load_gazetteer)
+        .transpose()?;
+    let anonymize = args.anonymize;
+    let get = &args.get;
+    let offset_sidecar = args.offset_sidecar.as_deref();
+    let args = &args.input;
+    let mut out = open_output(args)?;
+    for file_name in &args.files {
+        let Some((mmap, mut telemetry)) = load(file_name, args.time_base)? else {
+            continue;
+        };
+        if anonymize && let Some(info) = telemetry.info.as_mut() {
+            anonymize_info(info);
+        }
+        if let Some(dir) = offset_sidecar {
+            match telemetry
+                .info
+                .as_ref()
+                .and_then(|info| info.offset.as_deref())
+            {
+                Some(offset) => {
+                    let stem = file_name.file_stem().unwrap_or_default().to_string_lossy();
+                    std::fs::write(dir.join(format!("{stem}.offset")), format!("{offset}\n"))?;
+                }
+                None => warn!("No lens offset in {}", file_name.display()),
+            }
+        }
+        if !get.is_empty() {
+            if telemetry.info.is_none() {
+                warn!("No Info frame in {}", file_name.display());
+            }
+            for path in get {
+                let value = match &telemetry.info {
+                    Some(info) => info_field(info, user_metadata(&mmap), path)?,
+                    None => serde_json::Value::Null,
+                };
+                if args.files.len() > 1 {
+                    write!(out, "{}\t", file_name.display())?;
+                }
+                match value {
+                    serde_json::Value::String(value) => writeln!(out, "{value}")?,
+                    serde_json::Value::Null => writeln!(out)?,
+                    value => writeln!(out, "{value}")?,
+                }
+            }
+            continue;
+        }
+        writeln!(out, "{}", file_name.display())?;
+
+        if let Some((trailer, index)) = read_index(&mmap) {
+            writeln!(out, "  Trailer version: {}", trailer.version_num)?;
+            writeln!(
+                out,
+                "  Metadata: {} bytes at offset {}",
+                trailer.metadata_size,
+                mmap.len() - trailer.metadata_size as usize
+            )?;
+            writeln!(out, "  Trailer entries:")?;
+            for (i, (entry, name)) in trailer.metadata.iter().zip(TRAILER_ENTRY_NAMES).enumerate() {
+                write!(
+                    out,
+                    "    {i}  id {:#06x}  size {:>10}",
+                    entry.id, entry.size
+                )?;
+                match name {
+                    Some(name) => writeln!(out, "  {name}")?,
+                    None => writeln!(out)?,
+                }
+            }
+            writeln!(out, "  Frames:")?;
+            let metadata_start = mmap.len() - trailer.metadata_size as usize;
+            for frame in &index.frames {
+                write!(
+                    out,
+                    "    {:<20} v{}  {:>10} bytes at +{}",
+                    format!("{:?}", frame.frame_type),
+                    frame.frame_version,
+                    frame.frame_size,
+                    frame.frame_offset
+                )?;
+                if let Some(layout) = frame.frame_type.record_layout() {
+                    let (records, trailing) = layout.records_in(frame.frame_size as usize);
+                    write!(out, "  {records:>8} records")?;
+                    let start = metadata_start + frame.frame_offset as usize;
+                    let rate = (mmap.get(start..start + frame.frame_size as usize))
+                        .and_then(|bytes| layout.sample_rate(bytes));
+                    if let Some(rate) = rate {
+                        write!(out, " @ {rate:.0} Hz")?;
+                    }
+                    if trailing > 0 {
+                        write!(out, ", {trailing} bytes over")?;
+                    }
+                }
+                writeln!(out)?;
+            }
+            let problems = trailer_problems(&mmap, &trailer, &index);
+            if problems.is_empty() {
+                writeln!(out, "  Layout: consistent")?;
+            }
+            for problem in problems {
+                writeln!(out, "  Problem: {problem}")?;
+            }
+            for warning in compatibility_warnings(&mmap, &telemetry) {
+                writeln!(out, "  Warning: {warning}")?;
+            }
+        }
+        for diagnostic in &telemetry.diagnostics {
+            writeln!(out, "  Undecoded: {diagnostic}")?;
+        }
+
+        if let Some(info) = &telemetry.info {
+            let field = |value: &Option<String>| value.clone().unwrap_or_else(|| "?".to_string());
+            writeln!(
+                out,
+                "  Camera: {} (serial {}, firmware {})",
+                field(&info.camera_type),
+                field(&info.serial_number),
+                field(&info.fw_version)
+            )?;
+            if let Some(dimension) = info.dimension {
+                writeln!(
+                    out,
+                    "  Resolution: {}x{} at {} fps",
+                    dimension.x.unwrap_or_default(),
+                    dimension.y.unwrap_or_default(),
+                    info.frame_rate.unwrap_or_default()
+                )?;
+            }
+            if let Some(offset) = &info.offset {
+                writeln!(out, "  Lens offset: {offset}")?;
+            }
+        }
+        if let Some(user_metadata) = user_metadata(&mmap) {
+            writeln!(out, "  User metadata: {user_metadata}")?;
+        }
+        if let Some(track) = parse_video_track(&mmap) {
+            let clock = VideoClock::from_telemetry(&track, &telemetry);
+            let start = DateTime::<Utc>::from_timestamp(clock.start as i64, 0).unwrap_or_default();
+            write!(
+                out,
+                "  Video clock: first frame ({:.3} s) at {}",
+                clock.first_pts,
+                start.to_rfc3339_opts(SecondsFormat::Secs, true)
+            )?;
+            if let Some(ms) = clock.first_frame_ms {
+                write!(out, ", camera clock {ms} ms")?;
+            }
+            if clock.speed != 1.0 {
+                write!(out, ", timelapse at {:.1}x", clock.speed)?;
+            }
+            writeln!(out)?;
+        }
+
+        let time = |record: &GpsRecord| {
+            DateTime::<Utc>::from_timestamp(record.timestamp as i64, 0)
+                .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .unwrap_or_default()
+        };
+        match (telemetry.gps.first(), telemetry.gps.last()) {
+            (Some(first), Some(last)) => writeln!(
+                out,
+                "  GPS: {} records, {} to {}",
+                telemetry.gps.len(),
+                time(first),
+                time(last)
+            )?,
+            _ => writeln!(out, "  GPS: none")?,
+        }
+        if let Some(gazetteer) = &gazetteer {
+            let [start, end] = track_places(gazetteer, &telemetry.gps);
+            for (label, place) in [("Start", start), ("End", end)] {
+                if let Some(place) = place {
+                    writeln!(out, "  {label}: {}, {}", place.name, place.country)?;
+                }
+            }
+        }
+        match measured_gyro_rate(&telemetry) {
+            Some(rate) => writeln!(
+                out,
+                "  Gyro: {} records at {rate:.0} Hz",
+                telemetry.gyro.len()
+            )?,
+            None => writeln!(out, "  Gyro: {} records", telemetry.gyro.len())?,
+        }
+        writeln!(out, "  Exposure: {} records", telemetry.exposure.len())?;
+        for project in companion_projects(file_name) {
+            let keyframes = parse_project(&std::fs::read_to_string(&project)?).len();
+            writeln!(
+                out,
+                "  Studio project: {} ({keyframes} keyframes; see `ginsta reframe`)",
+                project.display()
+            )?;
+        }
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn stats(args: &StatsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    #[derive(Serialize)]
+    struct FileColumn<'a> {
+        file: &'a str,
+    }
+
+    #[derive(Serialize)]
+    struct PlaceColumns {
+        start_place: Option<String>,
+        start_country: Option<String>,
+        end_place: Option<String>,
+        end_country: Option<String>,
+    }
+
+    let gazetteer = args
+        .annotate_places
+        .as_deref()
+        .map(load_gazetteer)
+        .transpose()?;
+    let mut out = open_output(&args.input)?;
+    let mut csv_writer = csv::Writer::from_writer(Vec::new());
+    for file_name in &args.input.files {
+        let Some((mmap, mut telemetry)) = load(file_name, args.input.time_base)? else {
+            continue;
+        };
+        if args.moving_only {
+            let intervals = stationary(&mmap, &telemetry);
+            retain_moving(&mut telemetry.gps, &intervals);
+        }
+        let stats = track_stats(
+            &split_at_gaps(&telemetry.gps, args.gap),
+            args.elevation_gain,
+        );
+        let file = FileColumn {
+            file: &file_name.to_string_lossy(),
+        };
+        let lean = args.lean.then(|| {
+            let epoch = camera_epoch(parse_video_track(&mmap).as_ref(), &telemetry);
+            let scale = ImuScale::from_info(telemetry.info.as_ref());
+            lean_stats(&lean_angles(&telemetry.gps, &telemetry.gyro, epoch, &scale))
+        });
+        match &gazetteer {
+            Some(gazetteer) => {
+                let [start, end] = track_places(gazetteer, &telemetry.gps);
+                let places = PlaceColumns {
+                    start_place: start.as_ref().map(|place| place.name.clone()),
+                    start_country: start.map(|place| place.country),
+                    end_place: end.as_ref().map(|place| place.name.clone()),
+                    end_country: end.map(|place| place.country),
+                };
+                match lean {
+                    Some(lean) => csv_writer.serialize(rounded(&(file, stats, lean, places)))?,
+                    None => csv_writer.serialize(rounded(&(file, stats, places)))?,
+                }
+            }
+            None => match lean {
+                Some(lean) => csv_writer.serialize(rounded(&(file, stats, lean)))?,
+                None => csv_writer.serialize(rounded(&(file, stats)))?,
+            },
+        }
+    }
+    let csv = csv_writer.into_inner()?;
+    match args.format {
+        RowFormat::Csv => out.write_all(&csv)?,
+        RowFormat::Table => write_table(&mut out, &csv)?,
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn laps(args: &LapsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    #[derive(Serialize)]
+    struct FileColumn<'a> {
+        file: &'a str,
+    }
+
+    let mut out = open_output(&args.input)?;
+    let mut csv_writer = csv::Writer::from_writer(Vec::new());
+    for file_name in &args.input.files {
+        let Some((_, mut telemetry)) = load(file_name, args.input.time_base)? else {
+            continue;
+        };
+        sort_by_time(&mut telemetry.gps);
+        let crossings = crossings(&telemetry.gps, &args.start_finish, args.lap_width);
+        let file = FileColumn {
+            file: &file_name.to_string_lossy(),
+        };
+        for lap in lap_times(&crossings) {
+            csv_writer.serialize(rounded(&(&file, lap)))?;
+        }
+    }
+    let csv = csv_writer.into_inner()?;
+    match args.format {
+        RowFormat::Csv => out.write_all(&csv)?,
+        RowFormat::Table => write_table(&mut out, &csv)?,
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+const MAG_LAYOUT_FIELDS: &str = "The Magnetic layout needs timestamp, x, y and z fields";
+
+/// Reads a calibration `calibrate mag` wrote.
+fn load_mag_calibration(path: &Path) -> Result<MagCalibration, Box<dyn std::error::Error>> {
+    serde_json::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|e| format!("{}: {e}", path.display()).into())
+}
+
+fn calibrate_mag(args: &CalibrateMagArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut registry = LayoutRegistry::default();
+    for path in &args.layout {
+        load_layouts(path, &mut registry)?;
+    }
+    let layout = (registry.get(FrameType::Magnetic))
+        .ok_or("calibrate mag needs a --layout for the Magnetic frame")?;
+    let mut readings = Vec::new();
+    for file_name in &args.input.files {
+        let file = File::open(file_name)?;
+        let mmap = unsafe { MmapOptions::new().map(&file)? };
+        let samples = file_mag_samples(&mmap, layout).ok_or(MAG_LAYOUT_FIELDS)?;
+        if samples.is_empty() {
+            warn!("No magnetometer samples in {}", file_name.display());
+        }
+        readings.extend(samples.iter().map(MagSample::reading));
+    }
+    let calibration = fit_calibration(&readings).ok_or(
+        "The magnetometer samples don't determine a calibration; record the camera turned \
+         through every orientation",
+    )?;
+    let mut out = open_output(&args.input)?;
+    write_json(&mut out, &calibration)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn analyze_imu(args: &ImuAnalysisArgs) -> Result<(), Box<dyn std::error::Error>> {
+    #[derive(Serialize)]
+    struct Axis<'a> {
+        file: &'a str,
+        sensor: &'a str,
+        axis: &'a str,
+        unit: &'a str,
+    }
+
+    let out = open_output(&args.input)?;
+    let mut csv_writer = csv::Writer::from_writer(out);
+    for file_name in &args.input.files {
+        let Some((_, telemetry)) = load(file_name, args.input.time_base)? else {
+            continue;
+        };
+        if telemetry.gyro.is_empty() {
+            warn!("No gyro records in {}", file_name.display());
+            continue;
+        }
+        let file = file_name.to_string_lossy();
+        let scale = ImuScale::from_info(telemetry.info.as_ref());
+        for noise in imu_noise(&telemetry.gyro, &scale) {
+            let axis = Axis {
+                file: &file,
+                sensor: noise.sensor,
+                axis: noise.axis,
+                unit: noise.unit,
+            };
+            if args.curves {
+                for point in &noise.curve {
+                    csv_writer.serialize(rounded(&(&axis, point)))?;
+                }
+            } else {
+                csv_writer.serialize(rounded(&(&axis, noise.noise)))?;
+            }
+        }
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
+
+fn inspect(args: &InspectArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut registry = LayoutRegistry::default();
+    for path in &args.layout {
+        load_layouts(path, &mut registry)?;
+    }
+    let Some(layout) = registry.get(args.frame) else {
+        return Err(format!("{:?} frames aren't made of fixed-size records", args.frame).into());
+    };
+    let mut out = open_styled_output(&args.input)?;
+    for file_name in &args.input.files {
+        let file = File::open(file_name)?;
+        let mmap = unsafe { MmapOptions::new().map(&file)? };
+        let Some(frames) = frame_ranges(&mmap, args.frame) else {
+            warn!("{}", no_metadata(file_name, &mmap));
+            continue;
+        };
+        writeln!(out, "{}:", file_name.display())?;
+        write_records(
+            &mut out,
+            &mmap,
+            args.frame,
+            layout,
+            &frames,
+            args.skip,
+            args.records,
+        )?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn write_offset_maps(args: &InputArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = open_output(args)?;
+    for file_name in &args.files {
+        let file = File::open(file_name)?;
+        let mmap = unsafe { MmapOptions::new().map(&file)? };
+        let Some((trailer, index)) = read_index(&mmap) else {
+            warn!("{}", no_metadata(file_name, &mmap));
+            continue;
+        };
+        if args.files.len() > 1 {
+            writeln!(out, "# {}", file_name.display())?;
+        }
+        for region in offset_map(mmap.len() as u64, &trailer, &index) {
+            writeln!(
+                out,
+                "{:#010x}\t{:#x}\t{}",
+                region.range.start,
+                region.range.end.saturating_sub(region.range.start),
+                region.name
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn search(args: &SearchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    /// The track's closest point to `--near`.
+    #[derive(Serialize)]
+    struct SearchMatch<'a> {
+        file: &'a str,
+        near_timestamp: Option<u64>,
+        near_latitude: Option<f64>,
+        near_longitude: Option<f64>,
+        /// Metres from `--near`.
+        near_distance: Option<f64>,
+    }
+
+    if args.near.is_none() && args.filter.is_none() {
+        return Err("search needs --near, --where or both".into());
+    }
+    let out = open_output(&args.input)?;
+    let mut csv_writer = csv::Writer::from_writer(out);
+    for file_name in camera_files(&args.input.files, args.recursive)? {
+        let Some((_, telemetry)) = load(&file_name, args.input.time_base)? else {
+            continue;
+        };
+        let stats = track_stats(&split_at_gaps(&telemetry.gps, args.gap), ElevationGain::Raw);
+        if args
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !filter.matches(&stats))
+        {
+            continue;
+        }
+        let mut row = SearchMatch {
+            file: &file_name.to_string_lossy(),
+            near_timestamp: None,
+            near_latitude: None,
+            near_longitude: None,
+            near_distance: None,
+        };
+        if let Some((latitude, longitude)) = args.near {
+            let track = Track::new(telemetry.gps);
+            if !track.intersects(&BoundingBox::around(latitude, longitude, args.radius)) {
+                continue;
+            }
+            match track.nearest_point(latitude, longitude) {
+                Some((record, distance)) if distance <= args.radius => {
+                    row.near_timestamp = Some(record.timestamp);
+                    row.near_latitude = Some(record.latitude.0);
+                    row.near_longitude = Some(record.longitude.0);
+                    row.near_distance = Some(distance);
+                }
+                _ => continue,
+            }
+        }
+        csv_writer.serialize(rounded(&(row, stats)))?;
+    }
+    csv_writer.flush()?;
+
+    Ok(())
+}
+
+fn index(args: &IndexArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut entries = Vec::new();
+    for file_name in camera_files(&args.input.files, args.recursive)? {
+        let Some((mmap, mut telemetry)) = load(&file_name, args.input.time_base)? else {
+            continue;
+        };
+        if args.anonymize
+            && let Some(info) = telemetry.info.as_mut()
+        {
+            anonymize_info(info);
+        }
+        let track = parse_video_track(&mmap);
+        entries.push(catalog_entry(
+            &file_name.to_string_lossy(),
+            &telemetry,
+            track.as_ref(),
+            args.gap,
+        ));
+    }
+
+    let is_sqlite = (args.input.output.as_deref())
+        .and_then(|path| path.extension())
+        .is_some_and(|ext| ext == "sqlite" || ext == "db");
+    if is_sqlite {
+        #[cfg(feature = "sqlite")]
+        return Ok(write_sqlite(
+            args.input.output.as_deref().unwrap(),
+            &entries,
+        )?);
+        #[cfg(not(feature = "sqlite"))]
+        return Err("SQLite catalogs need ginsta built with the `sqlite` feature".into());
+    }
+    let mut out = open_output(&args.input)?;
+    write_json(&mut out, &entries)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+fn minimize(args: &MinimizeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::open(&args.file)?;
+    let mmap = unsafe { MmapOptions::new().map(&file)? };
+    let sample = ginsta::minimize::minimize(&mmap, args.records, args.anonymize)
+        .ok_or_else(|| no_metadata(&args.file, &mmap))?;
+    std::fs::write(&args.output, &sample)?;
+    let telemetry = read_telemetry(&sample);
+    eprintln!(
+        "Wrote {} bytes to {} with {} GPS, {} gyro and {} exposure records",
+        sample.len(),
+        args.output.display(),
+        telemetry.gps.len(),
+        telemetry.gyro.len(),
+        telemetry.exposure.len()
+    );
+    Ok(())
+}
+
+fn edit(args: &EditArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if !args.anonymize
+        && !args.vacuum
+        && args.trailer_version.is_none()
+        && args.user_metadata.is_none()
+    {
+        return Err(
+            "nothing to do: give --anonymize, --user-metadata, --trailer-version or --vacuum"
+                .into(),
+        );
+    }
+    let user_metadata = match &args.user_metadata {
+        Some(path) => {
+            let value: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+            Some(serde_json::to_vec(&value)?)
+        }
+        None => None,
+    };
+    let added: Vec<NewFrame> = (user_metadata.iter())
+        .map(|bytes| NewFrame {
+            frame_type: FrameType::User,
+            version: 1,
+            bytes,
+        })
+        .collect();
+    if let Some(version) = args.trailer_version
+        && !KNOWN_TRAILER_VERSIONS.contains(&version)
+    {
+        warn!(
+            "Trailer version {version} isn't one camera files have been seen with, so other \
+             software may not read the result"
+        );
+    }
+    for file_name in &args.files {
+        if args.anonymize || args.trailer_version.is_some() || !added.is_empty() {
+            let mmap = unsafe { MmapOptions::new().map(&File::open(file_name)?)? };
+            let mut anonymized = false;
+            let appended = amendment(
+                &mmap,
+                args.trailer_version,
+                |frame_type, bytes| {
+                    if !args.anonymize || frame_type != FrameType::Info {
+                        return None;
+                    }
+                    let mut bytes = bytes.to_vec();
+                    let replaced = anonymize_info_frame(&mut bytes).filter(|count| *count > 0);
+                    anonymized |= replaced.is_some();
+                    replaced.map(|_| bytes)
+                },
+                &added,
+            )
+            .ok_or_else(|| no_metadata(file_name, &mmap))?;
+            if appended.is_empty() {
+                println!("{}: nothing to change", file_name.display());
+            } else {
+                append_file(file_name, &appended, |edited| verify_rewrite(&mmap, edited))
+                    .map_err(|e| format!("{}: {e}", file_name.display()))?;
+                let mut changes = Vec::new();
+                if anonymized {
+                    changes.push("the Info frame anonymized".to_string());
+                }
+                if !added.is_empty() {
+                    changes.push("the user metadata".to_string());
+                }
+                if let Some(version) = args.trailer_version {
+                    changes.push(format!("a version {version} trailer"));
+                }
+                println!(
+                    "{}: appended {} bytes with {}",
+                    file_name.display(),
+                    appended.len(),
+                    changes.join(" and ")
+                );
+            }
+        }
+        if args.vacuum {
+            let mmap = unsafe { MmapOptions::new().map(&File::open(file_name)?)? };
+            let superseded = superseded_len(&mmap).ok_or_else(|| no_metadata(file_name, &mmap))?;
+            if superseded == 0 {
+                println!("{}: nothing superseded", file_name.display());
+                continue;
+            }
+            // The trailer version was set by the amendment above.
+            let (metadata_start, metadata) =
+                vacuum(&mmap, None).ok_or_else(|| no_metadata(file_name, &mmap))?;
+            let write = |out: &mut dyn Write| {
+                out.write_all(&mmap[..metadata_start])?;
+                out.write_all(&metadata)
+            };
+            let backup = replace_file(file_name, write, |rewritten| {
+                verify_rewrite(&mmap, rewritten)
+            })
+            .map_err(|e| format!("{}: {e}", file_name.display()))?;
+            println!(
+                "{}: removed {superseded} superseded bytes, with the original kept as {}",
+                file_name.display(),
+                backup.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn copy_meta(args: &CopyMetaArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let source = unsafe { MmapOptions::new().map(&File::open(&args.from)?)? };
+    let metadata = extract(&source, |frame_type| {
+        args.frames.is_empty() || args.frames.contains(&frame_type)
+    })
+    .ok_or_else(|| no_metadata(&args.from, &source))?;
+    let frames = read_index(&metadata).map_or(0, |(_, index)| index.frames.len());
+    if frames == 0 {
+        return Err(format!("No frames to copy from {}", args.from.display()).into());
+    }
+
+    let target = unsafe { MmapOptions::new().map(&File::open(&args.to)?)? };
+    if parse_video_track(&target).is_none() {
+        warn!("{} has no video track", args.to.display());
+    }
+    // Unmapped before the file is rewritten.
+    drop(target);
+    let destination = args.output.as_ref().unwrap_or(&args.to);
+    let copy = copy_metadata(&args.to, &metadata, args.output.as_deref())
+        .map_err(|e| format!("{}: {e}", destination.display()))?;
+    if let MetadataCopy::Replaced(backup) = copy {
+        eprintln!(
+            "Replaced the metadata of {}, with the original kept as {}",
+            args.to.display(),
+            backup.display()
+        );
+    }
+    println!(
+        "{}: copied {frames} frames, {} bytes, from {}",
+        destination.display(),
+        metadata.len(),
+        args.from.display()
+    );
+    Ok(())
+}
+
+fn selftest(args: &SelftestArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_path =
+        (args.snapshot.clone()).unwrap_or_else(|| args.corpus.join("ginsta-selftest.json"));
+    let mut current = Snapshot::new();
+    for file_name in camera_files(std::slice::from_ref(&args.corpus), true)? {
+        let summary = match load_file(&file_name, TimeBase::Utc, RecordLoading::Collect)? {
+            Some((mmap, telemetry)) => summarize(&mmap, &telemetry),
+            None => Summary::default(),
+        };
+        let relative = file_name.strip_prefix(&args.corpus).unwrap_or(&file_name);
+        current.insert(relative.to_string_lossy().into_owned(), summary);
+    }
+    let write_snapshot = || -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(&current).map_err(std::io::Error::other)?;
+        std::fs::write(&snapshot_path, json + "\n")
+    };
+    if !snapshot_path.exists() {
+        write_snapshot()?;
+        eprintln!(
+            "Recorded {} files in {}",
+            current.len(),
+            snapshot_path.display()
+        );
+        return Ok(());
+    }
+
+    let before: Snapshot = serde_json::from_str(&std::fs::read_to_string(&snapshot_path)?)
+        .map_err(|e| format!("{}: {e}", snapshot_path.display()))?;
+    let changes = compare(&before, &current);
+    let mut out = std::io::stdout().lock();
+    for change in &changes {
+        writeln!(out, "{change}")?;
+    }
+    out.flush()?;
+    if args.update {
+        write_snapshot()?;
+        eprintln!("Updated {}", snapshot_path.display());
+    } else if !changes.is_empty() {
+        return Err(format!(
+            "{} changes from {}; accept them with --update",
+            changes.len(),
+            snapshot_path.display()
+        )
+        .into());
+    } else {
+        eprintln!(
+            "{} files decode as recorded in {}",
+            current.len(),
+            snapshot_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// The metadata carries no checksums, so this checks sizes and offsets: the trailer against the
+/// index, each index entry against its frame's own trailer, and each frame's records.
+fn verify(args: &VerifyArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = open_styled_output(&args.input)?;
+    let map = |file_name: &Path| {
+        let file = File::open(file_name)?;
+        unsafe { MmapOptions::new().map(&file) }
+    };
+    let result = verify_files(
+        &args.input.files,
+        args.strict,
+        map,
+        |file_name, mmap, found| {
+            let count = found.count();
+            if count == 0 {
+                return writeln!(out, "{}: ok", file_name.display());
+            }
+            let plural = if count == 1 { "" } else { "s" };
+            writeln!(out, "{}: {count} problem{plural}", file_name.display())?;
+            for problem in &found.problems {
+                writeln!(out, "  {problem}")?;
+            }
+            for diagnostic in &found.diagnostics {
+                write_diagnostic(&mut out, file_name, mmap, diagnostic)?;
+            }
+            Ok(())
+        },
+    );
+    out.flush()?;
+    Ok(result.map_err(|e| e.to_string())?)
+}
+
+fn bench(args: &BenchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    #[derive(Serialize)]
+    struct BenchRow {
+        file: String,
+        stage: &'static str,
+        /// Read for parsing and decoding stages, written for the others.
+        bytes: usize,
+        records: Option<usize>,
+        ms: f64,
+        mb_per_s: f64,
+    }
+
+    let mut out = open_output(&args.input)?;
+    let mut csv_writer = csv::Writer::from_writer(Vec::new());
+    let decimals = deterministic().then_some(DETERMINISTIC_DECIMALS);
+    for file_name in &args.input.files {
+        let file = File::open(file_name)?;
+        let mmap = unsafe { MmapOptions::new().map(&file)? };
+        let stages = match bench_stages(&mmap, args.runs, decimals) {
+            Ok(stages) => stages,
+            Err(BenchError::NoMetadata) => {
+                warn!("{}", no_metadata(file_name, &mmap));
+                continue;
+            }
+            Err(e) => return Err(format!("{}: {e}", file_name.display()).into()),
+        };
+        for stage in stages {
+            let seconds = stage.time.as_secs_f64();
+            csv_writer.serialize(BenchRow {
+                file: file_name.display().to_string(),
+                stage: stage.name,
+                bytes: stage.bytes,
+                records: stage.records,
+                ms: round(seconds * 1000.0, 3),
+                mb_per_s: round(stage.bytes as f64 / seconds.max(1e-9) / 1e6, 1),
+            })?;
+        }
+    }
+    let csv = csv_writer.into_inner()?;
+    match args.format {
+        RowFormat::Csv => out.write_all(&csv)?,
+        RowFormat::Table => write_table(&mut out, &csv)?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn schema(args: &SchemaArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    match args.format {
+        SchemaFormat::JsonSchema => write_json(&mut out, &export_schemas())?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// `paths`, with each directory replaced by the camera files in it.
+fn camera_files(paths: &[PathBuf], recursive: bool) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() {
+                if recursive {
+                    files.extend(camera_files(&[entry], true)?);
+                }
+            } else if is_camera_file(&entry) {
+                files.push(entry);
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn watch(args: &WatchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.export.input.output.is_some() {
+        return Err("watch writes next to each input; --output isn't supported".into());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let mode = if args.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    for dir in &args.export.input.files {
+        watcher.watch(dir, mode)?;
+    }
+
+    let mut settling = Settling::new(Duration::from_secs(args.settle));
+    // The output each input was written to.
+    let mut named: HashMap<PathBuf, PathBuf> = HashMap::new();
+    loop {
+        match receiver.recv_timeout(Duration::from_secs(1)) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    for path in event.paths {
+                        settling.changed(path, Instant::now());
+                    }
+                }
+            }
+            Ok(Err(e)) => warn!("Watch error: {e}"),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        for path in settling.ready(Instant::now()) {
+            if !path.is_file() {
+                continue;
+            }
+            let output = match &args.export.output_template {
+                // A file changed again keeps the name it was given the first time.
+                Some(_) if named.contains_key(&path) => named[&path].clone(),
+                Some(template) => {
+                    match templated_output(&args.export, template, &path, settling.outputs()) {
+                        Ok(Some(output)) => output,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            warn!("{}: {e}", path.display());
+                            continue;
+                        }
+                    }
+                }
+                None => {
+                    let mut output = path.with_extension(args.export.format.extension());
+                    if let Some(compression) = args.export.input.compress {
+                        output
+                            .as_mut_os_string()
+                            .push(format!(".{}", compression.extension()));
+                    }
+                    output
+                }
+            };
+            named.insert(path.clone(), output.clone());
+            let mut export_args = args.export.clone();
+            export_args.output_template = None;
+            export_args.input.files = vec![path.clone()];
+            export_args.input.output = Some(output.clone());
+            settling.written(output.clone());
+            match export(&export_args) {
+                Ok(()) => println!("{} -> {}", path.display(), output.display()),
+                Err(e) => warn!("{}: {e}", path.display()),
+            }
+        }
+    }
+}
+
+fn reframe(args: &ReframeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    #[derive(Serialize)]
+    struct Row<'a> {
+        file: &'a str,
+        time: f64,
+        source: KeyframeSource,
+        yaw: f64,
+        pitch: f64,
+        roll: Option<f64>,
+        fov: Option<f64>,
+    }
+
+    if args.project.is_some() && args.input.files.len() > 1 {
+        return Err("--project applies to a single input".into());
+    }
+    let mut registry = LayoutRegistry::default();
+    for path in &args.layout {
+        load_layouts(path, &mut registry)?;
+    }
+    let anchors_layout = registry.get(FrameType::Anchors);
+
+    let mut out = open_output(&args.input)?;
+    let mut csv_writer = csv::Writer::from_writer(Vec::new());
+    for file_name in &args.input.files {
+        let Some((mmap, telemetry)) = load(file_name, args.input.time_base)? else {
+            continue;
+        };
+        let mut anchors = Vec::new();
+        if let (Some(layout), Some((trailer, index))) = (anchors_layout, read_index(&mmap)) {
+            let first_frame_timestamp =
+                (telemetry.info.as_ref()).and_then(|info| info.first_frame_timestamp);
+            let metadata_start = mmap.len() - trailer.metadata_size as usize;
+            let frames =
+                (index.frames.iter()).filter(|frame| frame.frame_type == FrameType::Anchors);
+            for frame in frames {
+                let Some(first_frame_timestamp) = first_frame_timestamp else {
+                    warn!(
+                        "No first frame timestamp in {}, so its Anchors frame is left out",
+                        file_name.display()
+                    );
+                    break;
+                };
+                let start = metadata_start + frame.frame_offset as usize;
+                let Some(bytes) = mmap.get(start..start + frame.frame_size as usize) else {
+                    continue;
+                };
+                anchors.extend(
+                    anchor_keyframes(bytes, layout, first_frame_timestamp as f64 / 1000.0)
+                        .ok_or("The Anchors layout needs timestamp, yaw and pitch fields")?,
+                );
+            }
+        }
+        let projects = match &args.project {
+            _ if args.no_project => Vec::new(),
+            Some(project) => vec![project.clone()],
+            None => companion_projects(file_name),
+        };
+        let mut project = Vec::new();
+        for path in projects {
+            project.extend(parse_project(&std::fs::read_to_string(path)?));
+        }
+
+        let file = file_name.to_string_lossy();
+        for keyframe in timeline(anchors, project) {
+            csv_writer.serialize(rounded(&Row {
+                file: &file,
+                time: keyframe.time,
+                source: keyframe.source,
+                yaw: keyframe.yaw,
+                pitch: keyframe.pitch,
+                roll: keyframe.roll,
+                fov: keyframe.fov,
+            }))?;
+        }
+    }
+    let csv = csv_writer.into_inner()?;
+    match args.format {
+        RowFormat::Csv => out.write_all(&csv)?,
+        RowFormat::Table => write_table(&mut out, &csv)?,
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn replay(args: &ReplayArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut records = Vec::new();
+    for file_name in &args.files {
+        if let Some((_, telemetry)) = load(file_name, args.time_base)? {
+            records.extend(telemetry.gps);
+        }
+    }
+    sort_by_time(&mut records);
+
+    if let Some(address) = &args.gpsd {
+        let listener = TcpListener::bind(address)?;
+        eprintln!("Serving gpsd clients on {}", listener.local_addr()?);
+        // One client at a time, each from the start of the track.
+        for stream in listener.incoming() {
+            if let Err(e) = serve_gpsd(stream?, &records, args) {
+                warn!("Replay stopped: {e}");
+            }
+        }
+        return Ok(());
+    }
+
+    let socket = match &args.udp {
+        Some(address) => {
+            let address = (address.to_socket_addrs()?.next())
+                .ok_or_else(|| format!("{address} doesn't resolve to an address"))?;
+            let local: SocketAddr = match address {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            let socket = UdpSocket::bind(local)?;
+            socket.connect(address)?;
+            Some(socket)
+        }
+        None => None,
+    };
+    let mut stdout = std::io::stdout().lock();
+    pace(&records, args, |record| {
+        for sentence in [rmc(record), gga(record)] {
+            match &socket {
+                Some(socket) => {
+                    socket.send(sentence.as_bytes())?;
+                }
+                None => {
+                    stdout.write_all(sentence.as_bytes())?;
+                    stdout.flush()?;
+                }
+            }
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Calls `emit` with each record when it's due at the replay speed.
+fn pace(
+    records: &[GpsRecord],
+    args: &ReplayArgs,
+    mut emit: impl FnMut(&GpsRecord) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let start = Instant::now();
+    // Seconds of recording played so far, with long gaps cut short.
+    let mut elapsed = 0;
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            let gap = record.timestamp - records[i - 1].timestamp;
+            elapsed += if gap > args.max_gap { 1 } else { gap };
+        }
+        let due = start + Duration::from_secs_f64(elapsed as f64 / args.speed);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+        emit(record)?;
+    }
+    Ok(())
+}
+
+/// Greets a gpsd client and, once it watches, sends it a report per record. Requests sent
+/// during the replay are answered between records.
+fn serve_gpsd(stream: TcpStream, records: &[GpsRecord], args: &ReplayArgs) -> std::io::Result<()> {
+    use std::io::{BufRead, BufReader, ErrorKind};
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    writer.write_all(gpsd::version().as_bytes())?;
+    let mut session = gpsd::Session::default();
+    let mut line = String::new();
+    // The track starts once the client asks for it.
+    while !session.watching {
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        writer.write_all(session.handle(&line).as_bytes())?;
+        line.clear();
+    }
+    (reader.get_ref()).set_read_timeout(Some(Duration::from_millis(1)))?;
+    pace(records, args, |record| {
+        loop {
+            match reader.read_line(&mut line) {
+                Ok(0) => return Err(ErrorKind::ConnectionAborted.into()),
+                // A partial line stays in `line` until the rest arrives.
+                Ok(_) => {
+                    writer.write_all(session.handle(&line).as_bytes())?;
+                    line.clear();
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if session.watching {
+            writer.write_all(gpsd::tpv(record).as_bytes())?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(feature = "grpc")]
+fn serve(args: &ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use ginsta::grpc::{TelemetryServer, TelemetryService};
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(
+        tonic::transport::Server::builder()
+            .add_service(TelemetryServer::new(TelemetryService))
+            .serve(args.listen),
+    )?;
+    Ok(())
+}
+
+#[cfg(feature = "foxglove")]
+fn foxglove(args: &FoxgloveArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use ginsta::mcap::telemetry_channels;
+
+    let Some((mmap, telemetry)) = load(&args.file, args.time_base)? else {
+        return Err(format!("No telemetry in {}", args.file.display()).into());
+    };
+    let log = telemetry_channels(
+        &telemetry.gps,
+        &telemetry.gyro,
+        &telemetry.exposure,
+        &ImuScale::from_info(telemetry.info.as_ref()),
+        gyro_epoch(&args.file, &mmap, &telemetry),
+    );
+    let listener = std::net::TcpListener::bind(args.listen)?;
+    eprintln!(
+        "Replaying {} on ws://{}",
+        args.file.display(),
+        listener.local_addr()?
+    );
+    // One client at a time, each from the start of the recording.
+    for stream in listener.incoming() {
+        if let Err(e) = ginsta::foxglove::replay(stream?, &log, args.speed) {
+            warn!("Replay stopped: {e}");
+        }
+    }
     Ok(())
 }
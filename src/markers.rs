@@ -0,0 +1,146 @@
+use std::io::Write;
+
+use crate::{
+    ffmetadata::Chapter,
+    highlights::{Highlight, HighlightKind, timecode},
+};
+
+/// A named point or range on the video timeline for import into an editor.
+#[derive(Debug, PartialEq)]
+pub struct Marker {
+    pub start: f64, // Seconds from the start of the video.
+    pub duration: f64,
+    pub name: String,
+    pub comment: String,
+    pub color: &'static str, // A Resolve marker colour name.
+}
+
+pub fn highlight_markers(highlights: &[Highlight]) -> Vec<Marker> {
+    highlights
+        .iter()
+        .map(|highlight| Marker {
+            start: highlight.start,
+            duration: highlight.duration,
+            name: highlight.kind.label().to_string(),
+            comment: format!("peak {:.1}, score {:.1}", highlight.peak, highlight.score),
+            color: match highlight.kind {
+                HighlightKind::Speed => "Red",
+                HighlightKind::Turn => "Yellow",
+                HighlightKind::Rotation => "Purple",
+            },
+        })
+        .collect()
+}
+
+/// Marks the start of each chapter, such as the GPS segments from `segment_chapters`.
+pub fn chapter_markers(chapters: &[Chapter]) -> Vec<Marker> {
+    chapters
+        .iter()
+        .map(|chapter| Marker {
+            start: chapter.start,
+            duration: chapter.end - chapter.start,
+            name: chapter.title.clone(),
+            comment: String::new(),
+            color: "Blue",
+        })
+        .collect()
+}
+
+/// Writes markers in the EDL layout DaVinci Resolve uses for timeline marker import. Each event
+/// is one frame long at the marker's position; the marker's length is carried in `|D:`.
+///
+/// `timeline_start` is the timeline's first timecode in seconds, one hour in Resolve's default.
+pub fn write_marker_edl(
+    mut out: impl Write,
+    title: &str,
+    markers: &[Marker],
+    fps: u32,
+    timeline_start: f64,
+) -> std::io::Result<()> {
+    writeln!(out, "TITLE: {title}")?;
+    writeln!(out, "FCM: NON-DROP FRAME")?;
+    let frame = 1.0 / fps as f64;
+    for (i, marker) in markers.iter().enumerate() {
+        let start = timeline_start + marker.start;
+        let (from, to) = (timecode(start, fps), timecode(start + frame, fps));
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{:03}  001      V     C        {from} {to} {from} {to}  ",
+            i + 1
+        )?;
+        let name = if marker.comment.is_empty() {
+            marker.name.clone()
+        } else {
+            format!("{}: {}", marker.name, marker.comment)
+        };
+        writeln!(
+            out,
+            " |C:ResolveColor{} |M:{} |D:{}",
+            marker.color,
+            name.replace('|', "/"),
+            ((marker.duration * fps as f64).round() as u64).max(1)
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes markers as CSV in the column layout of Premiere Pro's marker export.
+#[cfg(feature = "csv")]
+pub fn write_marker_csv(out: impl Write, markers: &[Marker], fps: u32) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record([
+        "Marker Name",
+        "Description",
+        "In",
+        "Out",
+        "Duration",
+        "Marker Type",
+    ])?;
+    for marker in markers {
+        writer.write_record([
+            marker.name.as_str(),
+            marker.comment.as_str(),
+            &timecode(marker.start, fps),
+            &timecode(marker.start + marker.duration, fps),
+            &timecode(marker.duration, fps),
+            "Comment",
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_markers() {
+        let markers = chapter_markers(&[Chapter {
+            start: 2.5,
+            end: 12.5,
+            title: "GPS segment 1".to_string(),
+        }]);
+
+        let mut edl = Vec::new();
+        write_marker_edl(&mut edl, "clip", &markers, 30, 3600.0).unwrap();
+        assert_eq!(
+            String::from_utf8(edl).unwrap(),
+            "TITLE: clip\nFCM: NON-DROP FRAME\n\n\
+             001  001      V     C        01:00:02:15 01:00:02:16 01:00:02:15 01:00:02:16  \n \
+             |C:ResolveColorBlue |M:GPS segment 1 |D:300\n"
+        );
+
+        #[cfg(feature = "csv")]
+        {
+            let mut csv = Vec::new();
+            write_marker_csv(&mut csv, &markers, 30).unwrap();
+            assert_eq!(
+                String::from_utf8(csv).unwrap(),
+                "Marker Name,Description,In,Out,Duration,Marker Type\n\
+             GPS segment 1,,00:00:02:15,00:00:12:15,00:00:10:00,Comment\n"
+            );
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! MCAP output, the log container read by robotics tools such as Foxglove and PlotJuggler, with
+//! one JSON channel per telemetry stream.
+//!
+//! GPS records go to `/gps` as `foxglove.LocationFix` messages, which Foxglove shows on a map,
+//! with ground speed and course added as extra fields. IMU samples go to `/imu` in rad/s and g,
+//! and exposure times to `/exposure`. Messages are logged at their Unix time in nanoseconds. The
+//! file is unchunked, with a summary section listing the channels and message counts.
+
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::{ExposureRecord, GpsRecord, GyroRecord, imu::ImuScale};
+
+pub const MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_STATISTICS: u8 = 0x0b;
+const OP_DATA_END: u8 = 0x0f;
+
+fn string(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// An opcode, the content length, then the content.
+fn record(out: &mut Vec<u8>, opcode: u8, content: &[u8]) {
+    out.push(opcode);
+    out.extend_from_slice(&(content.len() as u64).to_le_bytes());
+    out.extend_from_slice(content);
+}
+
+pub(crate) struct Channel {
+    pub(crate) topic: String,
+    pub(crate) schema_name: String,
+    pub(crate) schema: Value,
+}
+
+impl Channel {
+    /// The schema and channel records, which share the channel's id.
+    fn write(&self, out: &mut Vec<u8>, id: u16) {
+        let mut schema = Vec::new();
+        schema.extend_from_slice(&id.to_le_bytes());
+        string(&mut schema, &self.schema_name);
+        string(&mut schema, "jsonschema");
+        string(&mut schema, &self.schema.to_string());
+        record(out, OP_SCHEMA, &schema);
+
+        let mut channel = Vec::new();
+        channel.extend_from_slice(&id.to_le_bytes());
+        channel.extend_from_slice(&id.to_le_bytes());
+        string(&mut channel, &self.topic);
+        string(&mut channel, "json");
+        // No metadata.
+        channel.extend_from_slice(&0u32.to_le_bytes());
+        record(out, OP_CHANNEL, &channel);
+    }
+}
+
+/// Collects channels and messages, then writes them as an MCAP file with the messages in time
+/// order.
+#[derive(Default)]
+pub struct McapWriter {
+    pub(crate) channels: Vec<Channel>,
+    /// Log time in nanoseconds, channel id and JSON.
+    pub(crate) messages: Vec<(u64, u16, Vec<u8>)>,
+}
+
+impl McapWriter {
+    /// Adds a channel for JSON messages described by a JSON Schema, returning its id.
+    pub fn add_channel(&mut self, topic: &str, schema_name: &str, schema: Value) -> u16 {
+        self.channels.push(Channel {
+            topic: topic.to_string(),
+            schema_name: schema_name.to_string(),
+            schema,
+        });
+        self.channels.len() as u16
+    }
+
+    pub fn add_message<T: Serialize>(&mut self, channel: u16, log_time: u64, message: &T) {
+        let data = serde_json::to_vec(message).expect("Messages serialize to JSON");
+        self.messages.push((log_time, channel, data));
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.messages.sort_by_key(|(time, _, _)| *time);
+        let mut out = MAGIC.to_vec();
+        let mut header = Vec::new();
+        string(&mut header, "");
+        string(&mut header, concat!("ginsta ", env!("CARGO_PKG_VERSION")));
+        record(&mut out, OP_HEADER, &header);
+
+        for (id, channel) in (1..).zip(&self.channels) {
+            channel.write(&mut out, id);
+        }
+        let mut sequences = vec![0u32; self.channels.len()];
+        for (time, channel, data) in &self.messages {
+            let sequence = &mut sequences[*channel as usize - 1];
+            let mut message = Vec::with_capacity(22 + data.len());
+            message.extend_from_slice(&channel.to_le_bytes());
+            message.extend_from_slice(&sequence.to_le_bytes());
+            // Log and publish time.
+            message.extend_from_slice(&time.to_le_bytes());
+            message.extend_from_slice(&time.to_le_bytes());
+            message.extend_from_slice(data);
+            record(&mut out, OP_MESSAGE, &message);
+            *sequence += 1;
+        }
+        // A zero CRC means none was computed.
+        record(&mut out, OP_DATA_END, &0u32.to_le_bytes());
+
+        let summary_start = out.len() as u64;
+        for (id, channel) in (1..).zip(&self.channels) {
+            channel.write(&mut out, id);
+        }
+        record(&mut out, OP_STATISTICS, &self.statistics(&sequences));
+
+        let mut footer = Vec::new();
+        footer.extend_from_slice(&summary_start.to_le_bytes());
+        // No summary offset section, and no summary CRC.
+        footer.extend_from_slice(&0u64.to_le_bytes());
+        footer.extend_from_slice(&0u32.to_le_bytes());
+        record(&mut out, OP_FOOTER, &footer);
+        out.extend_from_slice(MAGIC);
+        out
+    }
+
+    fn statistics(&self, message_counts: &[u32]) -> Vec<u8> {
+        let mut statistics = Vec::new();
+        statistics.extend_from_slice(&(self.messages.len() as u64).to_le_bytes());
+        statistics.extend_from_slice(&(self.channels.len() as u16).to_le_bytes());
+        statistics.extend_from_slice(&(self.channels.len() as u32).to_le_bytes());
+        // Attachments, metadata records and chunks.
+        statistics.extend_from_slice(&[0; 12]);
+        let first = self.messages.first().map_or(0, |(time, _, _)| *time);
+        let last = self.messages.last().map_or(0, |(time, _, _)| *time);
+        statistics.extend_from_slice(&first.to_le_bytes());
+        statistics.extend_from_slice(&last.to_le_bytes());
+        statistics.extend_from_slice(&(message_counts.len() as u32 * 10).to_le_bytes());
+        for (id, count) in (1u16..).zip(message_counts) {
+            statistics.extend_from_slice(&id.to_le_bytes());
+            statistics.extend_from_slice(&(*count as u64).to_le_bytes());
+        }
+        statistics
+    }
+}
+
+#[derive(Serialize)]
+struct Time {
+    sec: u64,
+    nsec: u32,
+}
+
+#[derive(Serialize)]
+struct LocationFix {
+    timestamp: Time,
+    frame_id: &'static str,
+    latitude: f64,
+    longitude: f64,
+    altitude: f64,
+    position_covariance: [f64; 9],
+    /// Unknown.
+    position_covariance_type: u8,
+    speed: f64,
+    track: f64,
+}
+
+fn number_properties(names: &[&str]) -> Value {
+    Value::Object(
+        (names.iter())
+            .map(|name| (name.to_string(), json!({ "type": "number" })))
+            .collect(),
+    )
+}
+
+fn location_fix_schema() -> Value {
+    let mut properties =
+        number_properties(&["latitude", "longitude", "altitude", "speed", "track"]);
+    properties["timestamp"] = json!({
+        "type": "object",
+        "properties": { "sec": { "type": "integer" }, "nsec": { "type": "integer" } },
+    });
+    properties["frame_id"] = json!({ "type": "string" });
+    properties["position_covariance"] = json!({
+        "type": "array",
+        "items": { "type": "number" },
+        "minItems": 9,
+        "maxItems": 9,
+    });
+    properties["position_covariance_type"] = json!({ "type": "integer" });
+    json!({ "type": "object", "properties": properties })
+}
+
+/// An MCAP file of a recording's streams. See `telemetry_channels`.
+pub fn telemetry_mcap(
+    gps: &[GpsRecord],
+    gyro: &[GyroRecord],
+    exposure: &[ExposureRecord],
+    scale: &ImuScale,
+    camera_epoch: Option<f64>,
+) -> Vec<u8> {
+    telemetry_channels(gps, gyro, exposure, scale, camera_epoch).finish()
+}
+
+/// The channels and messages of a recording's streams. Gyro and exposure records, which use the
+/// camera clock, are placed with `camera_epoch`, the Unix time at which that clock read zero, and
+/// left out without it.
+pub fn telemetry_channels(
+    gps: &[GpsRecord],
+    gyro: &[GyroRecord],
+    exposure: &[ExposureRecord],
+    scale: &ImuScale,
+    camera_epoch: Option<f64>,
+) -> McapWriter {
+    let mut writer = McapWriter::default();
+    let channel = writer.add_channel("/gps", "foxglove.LocationFix", location_fix_schema());
+    for record in gps {
+        let message = LocationFix {
+            timestamp: Time {
+                sec: record.timestamp,
+                nsec: 0,
+            },
+            frame_id: "gps",
+            latitude: record.latitude.0,
+            longitude: record.longitude.0,
+            altitude: record.altitude.0,
+            position_covariance: [0.0; 9],
+            position_covariance_type: 0,
+            speed: record.speed.0,
+            track: record.track.0,
+        };
+        writer.add_message(channel, record.timestamp * 1_000_000_000, &message);
+    }
+
+    if let Some(epoch) = camera_epoch {
+        let log_time = |timestamp: u64| (epoch * 1e9 + timestamp as f64 * 1e6).max(0.0) as u64;
+        let schema = json!({
+            "type": "object",
+            "properties": number_properties(&[
+                "timestamp", "gyro_x", "gyro_y", "gyro_z", "accel_x", "accel_y", "accel_z",
+            ]),
+        });
+        let channel = writer.add_channel("/imu", "ginsta.ImuSample", schema);
+        for record in gyro {
+            writer.add_message(channel, log_time(record.timestamp), &scale.sample(record));
+        }
+        let schema = json!({
+            "type": "object",
+            "properties": number_properties(&["timestamp", "shutterspeed"]),
+        });
+        let channel = writer.add_channel("/exposure", "ginsta.ExposureRecord", schema);
+        for record in exposure {
+            writer.add_message(channel, log_time(record.timestamp), record);
+        }
+    }
+    writer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond, Seconds};
+
+    /// Splits an MCAP file into its records' opcodes and contents.
+    fn records(mut data: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut records = Vec::new();
+        while !data.is_empty() {
+            let len = u64::from_le_bytes(data[1..9].try_into().unwrap()) as usize;
+            records.push((data[0], &data[9..9 + len]));
+            data = &data[9 + len..];
+        }
+        records
+    }
+
+    #[test]
+    fn test_telemetry_mcap() {
+        let gps = [GpsRecord {
+            timestamp: 100,
+            latitude: Degrees(49.0),
+            longitude: Degrees(4.0),
+            speed: MetersPerSecond(2.0),
+            track: Degrees(90.0),
+            altitude: Meters(80.0),
+            fix: None,
+        }];
+        let gyro: Vec<GyroRecord> = [500, 1500]
+            .map(|timestamp| GyroRecord {
+                timestamp,
+                payload: [0; 12],
+            })
+            .into();
+        let exposure = [ExposureRecord {
+            timestamp: 1000,
+            shutterspeed: Seconds(0.001),
+        }];
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        let data = telemetry_mcap(&gps, &gyro, &exposure, &scale, Some(99.0));
+        assert!(data.starts_with(MAGIC) && data.ends_with(MAGIC));
+
+        let mcap_records = records(&data[8..data.len() - 8]);
+        let opcodes: Vec<u8> = mcap_records.iter().map(|(opcode, _)| *opcode).collect();
+        assert_eq!(
+            opcodes,
+            [1, 3, 4, 3, 4, 3, 4, 5, 5, 5, 5, 15, 3, 4, 3, 4, 3, 4, 11, 2]
+        );
+        // Messages in time order: gyro at 99.5 s, then GPS and exposure at 100 s, then gyro.
+        let channels: Vec<u16> = (mcap_records.iter())
+            .filter(|(opcode, _)| *opcode == OP_MESSAGE)
+            .map(|(_, content)| u16::from_le_bytes([content[0], content[1]]))
+            .collect();
+        assert_eq!(channels, [2, 1, 3, 2]);
+        let (_, gps_message) = mcap_records[8];
+        assert_eq!(&gps_message[6..14], &100_000_000_000u64.to_le_bytes());
+        let json: Value = serde_json::from_slice(&gps_message[22..]).unwrap();
+        assert_eq!(
+            (json["latitude"].as_f64(), json["timestamp"]["sec"].as_u64()),
+            (Some(49.0), Some(100))
+        );
+
+        let (_, statistics) = mcap_records[18];
+        assert_eq!(&statistics[..8], &4u64.to_le_bytes());
+
+        // Without a camera epoch only GPS can be placed.
+        let data = telemetry_mcap(&gps, &gyro, &exposure, &scale, None);
+        assert_eq!(records(&data[8..data.len() - 8]).len(), 9);
+    }
+}
@@ -0,0 +1,120 @@
+//! Small samples of camera files for bug reports: the video's boxes without the media data, and
+//! the metadata with each record frame cut to its first records, rebuilt so that the sample
+//! parses like the original.
+//!
+//! Thumbnails are left out. Other frames without a record layout, such as the Info frame, are
+//! kept whole. The trailer keeps the original's version and unknown entries.
+
+use crate::{
+    FrameType,
+    amend::{frame_type_byte, index_and_trailer, push_frame},
+    anonymize::anonymize_info_frame,
+    mp4::box_spans,
+    read_index,
+};
+
+/// A sample of the camera file `data` keeping the first `records` records of each record frame,
+/// with the identifiers in the Info frame replaced when `anonymize` is set. `None` if `data` has
+/// no Insta360 metadata.
+pub fn minimize(data: &[u8], records: usize, anonymize: bool) -> Option<Vec<u8>> {
+    let (trailer, index) = read_index(data)?;
+    let metadata_start = data.len() - trailer.metadata_size as usize;
+
+    // The video's boxes, such as `moov` with its timing, without the media data they point at.
+    let mut out = Vec::new();
+    let movie = &data[..metadata_start];
+    for (offset, header) in box_spans(movie) {
+        if &header.box_type != b"mdat" {
+            out.extend_from_slice(&movie[offset..offset + header.size as usize]);
+        }
+    }
+
+    let mut metadata = Vec::new();
+    let mut entries = Vec::new();
+    for frame in &index.frames {
+        if matches!(
+            frame.frame_type,
+            FrameType::Thumbnail | FrameType::ThumbnailExt
+        ) {
+            continue;
+        }
+        let start = metadata_start + frame.frame_offset as usize;
+        let end = start + frame.frame_size as usize;
+        // Frames that run past the end of the file are left out.
+        let Some(bytes) = data.get(start..end) else {
+            continue;
+        };
+        let mut bytes = bytes.to_vec();
+        if let Some(layout) = frame.frame_type.record_layout() {
+            bytes.truncate(records * layout.size());
+        }
+        if anonymize && frame.frame_type == FrameType::Info {
+            anonymize_info_frame(&mut bytes);
+        }
+        let frame_type = frame_type_byte(data, frame, end);
+        let offset = metadata.len() as u32;
+        push_frame(&mut metadata, &bytes, frame.frame_version, frame_type);
+        entries.push((frame_type, frame.frame_version, bytes.len() as u32, offset));
+    }
+
+    out.extend_from_slice(&metadata);
+    out.extend(index_and_trailer(
+        &trailer,
+        &entries,
+        metadata.len(),
+        trailer.version_num,
+    ));
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_telemetry, tests::build_insv};
+
+    #[test]
+    fn test_minimize() {
+        let gps: Vec<u8> = (0..3u64)
+            .flat_map(|i| {
+                let mut record = (100 + i).to_le_bytes().to_vec();
+                record.extend_from_slice(&[0, 0, b'A']);
+                record.extend_from_slice(&49.25f64.to_le_bytes());
+                record.push(b'N');
+                record.extend_from_slice(&4.03f64.to_le_bytes());
+                record.push(b'E');
+                for value in [5.0f64, 335.0, 86.0] {
+                    record.extend_from_slice(&value.to_le_bytes());
+                }
+                record
+            })
+            .collect();
+        let mut video = b"\0\0\0\x10ftypisom\0\0\0\0".to_vec();
+        video.extend_from_slice(b"\0\0\0\x0cmdatdata");
+        let data = build_insv(
+            &video,
+            &[
+                (FrameType::Thumbnail, 1, vec![0xff; 100]),
+                (FrameType::Gps, 2, gps),
+                (FrameType::Magnetic, 1, vec![1, 2, 3]),
+            ],
+        );
+
+        let sample = minimize(&data, 2, false).unwrap();
+        assert!(sample.starts_with(&video[..16]));
+        assert!(!sample.windows(4).any(|window| window == b"mdat"));
+        let (trailer, index) = read_index(&sample).unwrap();
+        assert_eq!(trailer.version_num, 3);
+        let frames: Vec<(FrameType, u8, u32)> = (index.frames.iter())
+            .map(|frame| (frame.frame_type, frame.frame_version, frame.frame_size))
+            .collect();
+        assert_eq!(
+            frames,
+            [(FrameType::Gps, 2, 2 * 53), (FrameType::Magnetic, 1, 3)]
+        );
+        let telemetry = read_telemetry(&sample);
+        assert_eq!(telemetry.gps.len(), 2);
+        assert!(telemetry.diagnostics.is_empty());
+
+        assert_eq!(minimize(b"not a camera file", 2, false), None);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{FrameType, GPS_RECORD_SIZE, GYRO_RECORD_SIZE, Telemetry, read_index};
+
+/// Gyro samples per second, measured from the camera-clock millisecond timestamps.
+pub fn measured_gyro_rate(telemetry: &Telemetry) -> Option<f64> {
+    let (first, last) = (telemetry.gyro.first()?, telemetry.gyro.last()?);
+    let span = last.timestamp.checked_sub(first.timestamp)? as f64 / 1000.0;
+    (span > 0.0).then(|| (telemetry.gyro.len() - 1) as f64 / span)
+}
+
+/// Reasons the Insta360 metadata in `data` may not decode as this tool expects: a missing camera
+/// model and record sizes that don't divide their frames.
+///
+/// There is no per-model table of gyro rates and checked firmware versions to compare against
+/// until files from each model have been checked, so models and firmware aren't warned about.
+pub fn compatibility_warnings(data: &[u8], telemetry: &Telemetry) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let info = telemetry.info.as_ref();
+    if info.and_then(|info| info.camera_type.as_deref()).is_none() {
+        warnings.push("no camera model in the Info frame".to_string());
+    }
+
+    let frames = read_index(data).map_or_else(Vec::new, |(_, index)| index.frames);
+    for frame in &frames {
+        let record_size = match frame.frame_type {
+            FrameType::Gps => GPS_RECORD_SIZE,
+            FrameType::Gyro => GYRO_RECORD_SIZE,
+            _ => continue,
+        };
+        if !(frame.frame_size as usize).is_multiple_of(record_size) {
+            warnings.push(format!(
+                "{:?} frame v{} is {} bytes, not a multiple of the {record_size} byte record",
+                frame.frame_type, frame.frame_version, frame.frame_size
+            ));
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GyroRecord, insvtools::frames::ExtraMetadata, tests::build_insv};
+
+    #[test]
+    fn test_compatibility_warnings() {
+        let telemetry = Telemetry {
+            info: Some(ExtraMetadata::default()),
+            gyro: (0..11)
+                .map(|i| GyroRecord {
+                    timestamp: 1000 + i * 100,
+                    payload: [0; 12],
+                })
+                .collect(),
+            ..Default::default()
+        };
+        assert_eq!(measured_gyro_rate(&telemetry), Some(10.0));
+
+        let data = build_insv(
+            b"video",
+            &[
+                (FrameType::Gyro, 1, vec![0; 2 * GYRO_RECORD_SIZE]),
+                (FrameType::Gps, 1, vec![0; GPS_RECORD_SIZE + 1]),
+            ],
+        );
+        assert_eq!(
+            compatibility_warnings(&data, &telemetry),
+            [
+                "no camera model in the Info frame",
+                "Gps frame v1 is 54 bytes, not a multiple of the 53 byte record"
+            ]
+        );
+
+        // Models and firmware versions nothing is known about aren't warned about.
+        let telemetry = Telemetry {
+            info: Some(ExtraMetadata {
+                camera_type: Some("Insta360 X9".to_string()),
+                fw_version: Some("v9.9.9".to_string()),
+                ..Default::default()
+            }),
+            ..telemetry
+        };
+        let data = build_insv(b"video", &[(FrameType::Gyro, 1, vec![0; GYRO_RECORD_SIZE])]);
+        assert!(compatibility_warnings(&data, &telemetry).is_empty());
+    }
+}
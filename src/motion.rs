@@ -0,0 +1,166 @@
+//! Speed and course derived from successive GPS positions, as an alternative to the values the
+//! receiver reports, which lag behind changes and wander when moving slowly, and the course
+//! unwrapped into a continuous angle for following turns.
+
+use crate::{
+    GpsRecord,
+    geo::{haversine_distance, initial_bearing},
+    units::{Degrees, DegreesPerSecond, MetersPerSecond},
+};
+
+/// Shortest distance moved across a window for its course to be given, since the bearing
+/// between fixes a few metres apart is mostly noise.
+pub const MIN_TRACK_DISTANCE: f64 = 2.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DerivedMotion {
+    /// Distance along the positions over the time they span.
+    pub speed: Option<MetersPerSecond>,
+    /// Bearing from the first position to the last.
+    pub track: Option<Degrees>,
+}
+
+/// The speed and course at each record, from the records within `window / 2` seconds either side
+/// of it. Records are taken in the order given, so a reversed track gives reversed courses.
+/// Records alone in their window get neither.
+pub fn derive_motion(records: &[GpsRecord], window: f64) -> Vec<DerivedMotion> {
+    let half = window / 2.0;
+    let near =
+        |i: usize, j: usize| records[i].timestamp.abs_diff(records[j].timestamp) as f64 <= half;
+    (0..records.len())
+        .map(|i| {
+            let mut first = i;
+            while first > 0 && near(i, first - 1) {
+                first -= 1;
+            }
+            let mut last = i;
+            while last + 1 < records.len() && near(i, last + 1) {
+                last += 1;
+            }
+            let (a, b) = (&records[first], &records[last]);
+            let elapsed = a.timestamp.abs_diff(b.timestamp) as f64;
+            if elapsed == 0.0 {
+                return DerivedMotion::default();
+            }
+            let distance: f64 = (records[first..=last].windows(2))
+                .map(|pair| {
+                    let (a, b) = (&pair[0], &pair[1]);
+                    haversine_distance(a.latitude.0, a.longitude.0, b.latitude.0, b.longitude.0)
+                })
+                .sum();
+            let moved =
+                haversine_distance(a.latitude.0, a.longitude.0, b.latitude.0, b.longitude.0);
+            DerivedMotion {
+                speed: Some(MetersPerSecond(distance / elapsed)),
+                track: (moved >= MIN_TRACK_DISTANCE).then(|| {
+                    Degrees(initial_bearing(
+                        a.latitude.0,
+                        a.longitude.0,
+                        b.latitude.0,
+                        b.longitude.0,
+                    ))
+                }),
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Turning {
+    /// The reported track with whole turns added or taken away, so it runs on past 360° or below
+    /// 0° instead of jumping, and steps between records stay within 180°.
+    pub unwrapped_track: Degrees,
+    /// How fast the course changes, from the records either side. `None` when they share a
+    /// timestamp.
+    pub turn_rate: Option<DegreesPerSecond>,
+}
+
+/// The unwrapped course and turn rate at each record.
+pub fn turning(records: &[GpsRecord]) -> Vec<Turning> {
+    let mut unwrapped: Vec<f64> = Vec::with_capacity(records.len());
+    for record in records {
+        let track = match unwrapped.last() {
+            Some(last) => last + (record.track.0 - last + 180.0).rem_euclid(360.0) - 180.0,
+            None => record.track.0,
+        };
+        unwrapped.push(track);
+    }
+    (0..records.len())
+        .map(|i| {
+            let (before, after) = (i.saturating_sub(1), (i + 1).min(records.len() - 1));
+            let elapsed = records[after].timestamp as f64 - records[before].timestamp as f64;
+            Turning {
+                unwrapped_track: Degrees(unwrapped[i]),
+                turn_rate: (elapsed != 0.0)
+                    .then(|| DegreesPerSecond((unwrapped[after] - unwrapped[before]) / elapsed)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Meters;
+
+    #[test]
+    fn test_derive_motion() {
+        // Heading north at about 11 m/s, then standing still, after a gap.
+        let record = |timestamp, latitude| GpsRecord {
+            timestamp,
+            latitude: Degrees(latitude),
+            longitude: Degrees(4.0),
+            speed: MetersPerSecond(0.0),
+            track: Degrees(0.0),
+            altitude: Meters(0.0),
+            fix: None,
+        };
+        let records = [
+            record(100, 49.0),
+            record(101, 49.0001),
+            record(102, 49.0002),
+            record(110, 49.0002),
+            record(111, 49.0002),
+            record(120, 49.0002),
+        ];
+        let motion = derive_motion(&records, 2.0);
+        let speed = motion[1].speed.unwrap().0;
+        assert!((speed - 11.12).abs() < 0.01, "{speed}");
+        assert!(motion[1].track.unwrap().0.abs() < 1e-6);
+        assert!(motion[0].speed.is_some());
+        assert_eq!(motion[3].speed, Some(MetersPerSecond(0.0)));
+        assert_eq!(motion[3].track, None);
+        assert_eq!(motion[5], DerivedMotion::default());
+
+        let reversed: Vec<GpsRecord> = (records.iter().rev())
+            .map(|r| record(r.timestamp, r.latitude.0))
+            .collect();
+        let motion = derive_motion(&reversed, 2.0);
+        assert!((motion[4].track.unwrap().0 - 180.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_turning() {
+        // Turning right through north at 20° a second, then back left.
+        let records: Vec<GpsRecord> = [(0, 340.0), (1, 0.0), (2, 20.0), (3, 0.0), (3, 350.0)]
+            .into_iter()
+            .map(|(timestamp, track)| GpsRecord {
+                timestamp,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(10.0),
+                track: Degrees(track),
+                altitude: Meters(0.0),
+                fix: None,
+            })
+            .collect();
+        let turning = turning(&records);
+        let tracks: Vec<f64> = turning.iter().map(|t| t.unwrapped_track.0).collect();
+        assert_eq!(tracks, [340.0, 360.0, 380.0, 360.0, 350.0]);
+        assert_eq!(turning[0].turn_rate, Some(DegreesPerSecond(20.0)));
+        assert_eq!(turning[1].turn_rate, Some(DegreesPerSecond(20.0)));
+        assert_eq!(turning[2].turn_rate, Some(DegreesPerSecond(0.0)));
+        assert_eq!(turning[3].turn_rate, Some(DegreesPerSecond(-30.0)));
+        assert_eq!(turning[4].turn_rate, None);
+    }
+}
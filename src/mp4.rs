@@ -0,0 +1,931 @@
+use std::{
+    cell::Cell,
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+};
+
+use nom::{
+    IResult, Parser,
+    bytes::take,
+    multi::count,
+    number::{be_i32, be_u8, be_u24, be_u32, be_u64},
+};
+
+/// Seconds between the MP4 epoch (1904-01-01) and the Unix epoch.
+pub const MP4_EPOCH_OFFSET: u64 = 2082844800;
+
+#[derive(Debug)]
+pub struct BoxHeader {
+    pub box_type: [u8; 4],
+    pub header_size: usize,
+    pub size: u64,
+}
+
+pub fn parse_box_header(input: &[u8]) -> IResult<&[u8], BoxHeader> {
+    let (rest, (size, box_type)) = (be_u32(), take(4usize)).parse(input)?;
+    let box_type = box_type.try_into().unwrap();
+    match size {
+        // Extends to the end of the file.
+        0 => Ok((
+            rest,
+            BoxHeader {
+                box_type,
+                header_size: 8,
+                size: input.len() as u64,
+            },
+        )),
+        1 => {
+            let (rest, size) = be_u64().parse(rest)?;
+            Ok((
+                rest,
+                BoxHeader {
+                    box_type,
+                    header_size: 16,
+                    size,
+                },
+            ))
+        }
+        size => Ok((
+            rest,
+            BoxHeader {
+                box_type,
+                header_size: 8,
+                size: size as u64,
+            },
+        )),
+    }
+}
+
+/// Iterates over sibling boxes, yielding the offset of each box within `data` with its header.
+/// Stops at the first malformed box, which for Insta360 files is where the metadata trailer
+/// begins.
+pub fn box_spans(data: &[u8]) -> impl Iterator<Item = (usize, BoxHeader)> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let (_, header) = parse_box_header(&data[offset..]).ok()?;
+        if header.size < header.header_size as u64 || header.size > (data.len() - offset) as u64 {
+            return None;
+        }
+        let start = offset;
+        offset += header.size as usize;
+        Some((start, header))
+    })
+}
+
+/// Iterates over sibling boxes, yielding each box type with its payload.
+pub fn boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    box_spans(data).map(|(offset, header)| {
+        let payload = &data[offset + header.header_size..offset + header.size as usize];
+        (header.box_type, payload)
+    })
+}
+
+pub fn find_box<'a>(data: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|(t, _)| t == box_type)
+        .map(|(_, payload)| payload)
+}
+
+/// Creation time and timescale shared by the `mvhd` and `mdhd` boxes.
+#[derive(Debug)]
+pub struct MediaHeader {
+    pub creation_time: u64, // Seconds since 1904-01-01.
+    pub timescale: u32,
+    pub duration: u64,
+}
+
+pub fn parse_media_header(input: &[u8]) -> IResult<&[u8], MediaHeader> {
+    let (rest, (version, _flags)) = (be_u8(), be_u24()).parse(input)?;
+    if version == 1 {
+        let (rest, (creation_time, _, timescale, duration)) =
+            (be_u64(), be_u64(), be_u32(), be_u64()).parse(rest)?;
+        Ok((
+            rest,
+            MediaHeader {
+                creation_time,
+                timescale,
+                duration,
+            },
+        ))
+    } else {
+        let (rest, (creation_time, _, timescale, duration)) =
+            (be_u32(), be_u32(), be_u32(), be_u32()).parse(rest)?;
+        Ok((
+            rest,
+            MediaHeader {
+                creation_time: creation_time as u64,
+                timescale,
+                duration: duration as u64,
+            },
+        ))
+    }
+}
+
+fn parse_handler_type(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (rest, (_, _, handler_type)) = (be_u32(), be_u32(), take(4usize)).parse(input)?;
+    Ok((rest, handler_type))
+}
+
+/// Sample count and delta pairs from `stts`.
+fn parse_time_to_sample(input: &[u8]) -> IResult<&[u8], Vec<(u32, u32)>> {
+    let (rest, (_, entries)) = (be_u32(), be_u32()).parse(input)?;
+    count((be_u32(), be_u32()), entries as usize).parse(rest)
+}
+
+/// Sample count and composition offset pairs from `ctts`.
+fn parse_composition_offsets(input: &[u8]) -> IResult<&[u8], Vec<(u32, i32)>> {
+    let (rest, (_, entries)) = (be_u32(), be_u32()).parse(input)?;
+    count((be_u32(), be_i32()), entries as usize).parse(rest)
+}
+
+#[derive(Debug)]
+pub struct VideoTrack {
+    pub creation_time: u64, // Unix seconds, from the movie header.
+    pub timescale: u32,
+    pub duration: u64,
+    /// Presentation timestamps of each frame in display order, in `timescale` units.
+    pub presentation_times: Vec<u64>,
+}
+
+impl VideoTrack {
+    /// Presentation time of a frame in seconds from the start of the video.
+    pub fn frame_time(&self, frame: usize) -> f64 {
+        self.presentation_times[frame] as f64 / self.timescale as f64
+    }
+}
+
+/// Finds the first video track in an MP4 file and computes its frame presentation times.
+pub fn parse_video_track(data: &[u8]) -> Option<VideoTrack> {
+    let moov = find_box(data, b"moov")?;
+    let (_, movie_header) = parse_media_header(find_box(moov, b"mvhd")?).ok()?;
+
+    let mdia = boxes(moov)
+        .filter(|(t, _)| t == b"trak")
+        .filter_map(|(_, trak)| find_box(trak, b"mdia"))
+        .find(|mdia| {
+            find_box(mdia, b"hdlr")
+                .and_then(|hdlr| parse_handler_type(hdlr).ok())
+                .is_some_and(|(_, handler_type)| handler_type == b"vide")
+        })?;
+    let (_, media_header) = parse_media_header(find_box(mdia, b"mdhd")?).ok()?;
+    let stbl = find_box(find_box(mdia, b"minf")?, b"stbl")?;
+
+    let (_, time_to_sample) = parse_time_to_sample(find_box(stbl, b"stts")?).ok()?;
+    let mut decode_time = 0u64;
+    let mut presentation_times = Vec::new();
+    for (samples, delta) in time_to_sample {
+        for _ in 0..samples {
+            presentation_times.push(decode_time);
+            decode_time += delta as u64;
+        }
+    }
+
+    // Reordered (B-frame) streams carry composition offsets; apply them and sort into display
+    // order.
+    if let Some(ctts) = find_box(stbl, b"ctts") {
+        let (_, offsets) = parse_composition_offsets(ctts).ok()?;
+        let offsets = offsets
+            .into_iter()
+            .flat_map(|(samples, offset)| std::iter::repeat_n(offset, samples as usize));
+        for (time, offset) in presentation_times.iter_mut().zip(offsets) {
+            *time = time.saturating_add_signed(offset as i64);
+        }
+        presentation_times.sort_unstable();
+    }
+
+    Some(VideoTrack {
+        creation_time: movie_header.creation_time.saturating_sub(MP4_EPOCH_OFFSET),
+        timescale: media_header.timescale,
+        duration: media_header.duration,
+        presentation_times,
+    })
+}
+
+/// Sample description types from `stsd`.
+fn parse_sample_entry_types(input: &[u8]) -> Vec<[u8; 4]> {
+    input
+        .get(8..)
+        .map(|entries| boxes(entries).map(|(box_type, _)| box_type).collect())
+        .unwrap_or_default()
+}
+
+/// (first chunk, samples per chunk) pairs from `stsc`.
+fn parse_sample_to_chunk(input: &[u8]) -> IResult<&[u8], Vec<(u32, u32)>> {
+    let (rest, (_, entries)) = (be_u32(), be_u32()).parse(input)?;
+    let (rest, entries) = count((be_u32(), be_u32(), be_u32()), entries as usize).parse(rest)?;
+    Ok((
+        rest,
+        entries
+            .into_iter()
+            .map(|(first_chunk, samples, _)| (first_chunk, samples))
+            .collect(),
+    ))
+}
+
+fn parse_sample_sizes(input: &[u8]) -> IResult<&[u8], Vec<u32>> {
+    let (rest, (_, sample_size, samples)) = (be_u32(), be_u32(), be_u32()).parse(input)?;
+    if sample_size != 0 {
+        return Ok((rest, vec![sample_size; samples as usize]));
+    }
+    count(be_u32(), samples as usize).parse(rest)
+}
+
+fn parse_chunk_offsets(input: &[u8], wide: bool) -> IResult<&[u8], Vec<u64>> {
+    let (rest, (_, entries)) = (be_u32(), be_u32()).parse(input)?;
+    if wide {
+        count(be_u64(), entries as usize).parse(rest)
+    } else {
+        let (rest, offsets) = count(be_u32(), entries as usize).parse(rest)?;
+        Ok((rest, offsets.into_iter().map(u64::from).collect()))
+    }
+}
+
+#[derive(Debug)]
+pub struct Sample<'a> {
+    pub time: u64, // Decode time in the track's timescale.
+    pub duration: u32,
+    pub data: &'a [u8],
+}
+
+#[derive(Debug)]
+pub struct TrackSamples<'a> {
+    pub timescale: u32,
+    pub samples: Vec<Sample<'a>>,
+}
+
+/// Reads every sample of the first track whose sample description is `sample_entry`, e.g.
+/// `gpmd` for GoPro telemetry.
+pub fn find_track_samples<'a>(data: &'a [u8], sample_entry: &[u8; 4]) -> Option<TrackSamples<'a>> {
+    let moov = find_box(data, b"moov")?;
+    let (mdia, stbl) = boxes(moov)
+        .filter(|(t, _)| t == b"trak")
+        .filter_map(|(_, trak)| {
+            let mdia = find_box(trak, b"mdia")?;
+            let stbl = find_box(find_box(mdia, b"minf")?, b"stbl")?;
+            Some((mdia, stbl))
+        })
+        .find(|(_, stbl)| {
+            find_box(stbl, b"stsd")
+                .is_some_and(|stsd| parse_sample_entry_types(stsd).first() == Some(sample_entry))
+        })?;
+    let (_, media_header) = parse_media_header(find_box(mdia, b"mdhd")?).ok()?;
+
+    let (_, sizes) = parse_sample_sizes(find_box(stbl, b"stsz")?).ok()?;
+    let (_, sample_to_chunk) = parse_sample_to_chunk(find_box(stbl, b"stsc")?).ok()?;
+    let (_, chunk_offsets) = match find_box(stbl, b"co64") {
+        Some(co64) => parse_chunk_offsets(co64, true),
+        None => parse_chunk_offsets(find_box(stbl, b"stco")?, false),
+    }
+    .ok()?;
+    let (_, time_to_sample) = parse_time_to_sample(find_box(stbl, b"stts")?).ok()?;
+    let mut durations = time_to_sample
+        .into_iter()
+        .flat_map(|(samples, delta)| std::iter::repeat_n(delta, samples as usize));
+
+    let mut samples = Vec::with_capacity(sizes.len());
+    let mut sizes = sizes.into_iter();
+    let mut time = 0u64;
+    for (chunk, chunk_offset) in chunk_offsets.into_iter().enumerate() {
+        let chunk = chunk as u32 + 1;
+        let samples_in_chunk = sample_to_chunk
+            .iter()
+            .rev()
+            .find(|(first_chunk, _)| *first_chunk <= chunk)
+            .map_or(1, |(_, samples)| *samples);
+        let mut offset = chunk_offset as usize;
+        for _ in 0..samples_in_chunk {
+            let size = sizes.next()? as usize;
+            let duration = durations.next().unwrap_or(0);
+            samples.push(Sample {
+                time,
+                duration,
+                data: data.get(offset..offset + size)?,
+            });
+            offset += size;
+            time += duration as u64;
+        }
+    }
+
+    Some(TrackSamples {
+        timescale: media_header.timescale,
+        samples,
+    })
+}
+
+/// Box types whose payload is made up entirely of child boxes.
+const CONTAINER_TYPES: &[&[u8; 4]] = &[
+    b"moov", b"trak", b"mdia", b"minf", b"stbl", b"edts", b"dinf",
+];
+
+/// An owned box tree, used to rewrite the movie header.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mp4Box {
+    Leaf([u8; 4], Vec<u8>),
+    Container([u8; 4], Vec<Mp4Box>),
+}
+
+impl Mp4Box {
+    pub fn parse_all(data: &[u8]) -> Vec<Mp4Box> {
+        boxes(data)
+            .map(|(box_type, payload)| {
+                if CONTAINER_TYPES.contains(&&box_type) {
+                    Mp4Box::Container(box_type, Mp4Box::parse_all(payload))
+                } else {
+                    Mp4Box::Leaf(box_type, payload.to_vec())
+                }
+            })
+            .collect()
+    }
+
+    /// A leaf box with a full box version/flags header followed by `payload`.
+    pub fn full(box_type: &[u8; 4], version: u8, flags: u32, payload: &[u8]) -> Mp4Box {
+        let mut data = (flags | (version as u32) << 24).to_be_bytes().to_vec();
+        data.extend_from_slice(payload);
+        Mp4Box::Leaf(*box_type, data)
+    }
+
+    pub fn box_type(&self) -> &[u8; 4] {
+        match self {
+            Mp4Box::Leaf(box_type, _) | Mp4Box::Container(box_type, _) => box_type,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        8 + match self {
+            Mp4Box::Leaf(_, data) => data.len() as u64,
+            Mp4Box::Container(_, children) => children.iter().map(Mp4Box::size).sum(),
+        }
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.size() as u32).to_be_bytes());
+        out.extend_from_slice(self.box_type());
+        match self {
+            Mp4Box::Leaf(_, data) => out.extend_from_slice(data),
+            Mp4Box::Container(_, children) => children.iter().for_each(|child| child.write_to(out)),
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Option<&mut Vec<Mp4Box>> {
+        match self {
+            Mp4Box::Container(_, children) => Some(children),
+            Mp4Box::Leaf(..) => None,
+        }
+    }
+
+    /// Calls `f` on this box and every descendant.
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Mp4Box)) {
+        f(self);
+        if let Some(children) = self.children_mut() {
+            children.iter_mut().for_each(|child| child.visit_mut(f));
+        }
+    }
+}
+
+fn be_u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Applies `map` to every chunk offset in an `stco` or `co64` box.
+fn remap_chunk_offsets(mp4_box: &mut Mp4Box, map: &impl Fn(u64) -> u64) {
+    let Mp4Box::Leaf(box_type, data) = mp4_box else {
+        return;
+    };
+    let width = match &*box_type {
+        b"stco" => 4,
+        b"co64" => 8,
+        _ => return,
+    };
+    let entries = be_u32_at(data, 4) as usize;
+    for entry in data[8..].chunks_exact_mut(width).take(entries) {
+        if width == 4 {
+            let offset = map(u32::from_be_bytes(entry.try_into().unwrap()) as u64);
+            entry.copy_from_slice(&(offset as u32).to_be_bytes());
+        } else {
+            let offset = map(u64::from_be_bytes(entry.try_into().unwrap()));
+            entry.copy_from_slice(&offset.to_be_bytes());
+        }
+    }
+}
+
+/// A timed metadata track to add to a movie, with every sample stored in its own chunk.
+#[derive(Debug)]
+pub struct DataTrack {
+    pub handler_type: [u8; 4],
+    pub handler_name: String,
+    /// The single `stsd` entry describing the samples.
+    pub sample_entry: Mp4Box,
+    pub timescale: u32,
+    pub sample_duration: u32,
+    pub samples: Vec<Vec<u8>>,
+}
+
+const IDENTITY_MATRIX: [u32; 9] = [0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x40000000];
+
+fn be_bytes(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+impl DataTrack {
+    fn trak(&self, track_id: u32, movie_timescale: u32, chunk_offsets: &[u64]) -> Mp4Box {
+        let duration = self.samples.len() as u64 * self.sample_duration as u64;
+        let movie_duration = duration * movie_timescale as u64 / self.timescale as u64;
+
+        let mut tkhd = be_bytes(&[0, 0, track_id, 0, movie_duration as u32, 0, 0, 0, 0]);
+        tkhd.extend(be_bytes(&IDENTITY_MATRIX));
+        tkhd.extend(be_bytes(&[0, 0]));
+
+        // Language "und", packed as three five-bit characters.
+        let mut mdhd = be_bytes(&[0, 0, self.timescale, duration as u32]);
+        mdhd.extend_from_slice(&[0x55, 0xc4, 0, 0]);
+
+        let mut hdlr = be_bytes(&[0]);
+        hdlr.extend_from_slice(&self.handler_type);
+        hdlr.extend(be_bytes(&[0, 0, 0]));
+        hdlr.extend_from_slice(self.handler_name.as_bytes());
+        hdlr.push(0);
+
+        let mut dref = be_bytes(&[1]);
+        Mp4Box::full(b"url ", 0, 1, &[]).write_to(&mut dref);
+
+        let mut stsd = be_bytes(&[1]);
+        self.sample_entry.write_to(&mut stsd);
+
+        let sizes: Vec<u32> = self.samples.iter().map(|s| s.len() as u32).collect();
+        let mut stsz = be_bytes(&[0, sizes.len() as u32]);
+        stsz.extend(be_bytes(&sizes));
+
+        let mut co64 = be_bytes(&[chunk_offsets.len() as u32]);
+        co64.extend(chunk_offsets.iter().flat_map(|o| o.to_be_bytes()));
+
+        let stbl = Mp4Box::Container(
+            *b"stbl",
+            vec![
+                Mp4Box::full(b"stsd", 0, 0, &stsd),
+                Mp4Box::full(
+                    b"stts",
+                    0,
+                    0,
+                    &be_bytes(&[1, self.samples.len() as u32, self.sample_duration]),
+                ),
+                Mp4Box::full(b"stsc", 0, 0, &be_bytes(&[1, 1, 1, 1])),
+                Mp4Box::full(b"stsz", 0, 0, &stsz),
+                Mp4Box::full(b"co64", 0, 0, &co64),
+            ],
+        );
+        let minf = Mp4Box::Container(
+            *b"minf",
+            vec![
+                Mp4Box::full(b"nmhd", 0, 0, &[]),
+                Mp4Box::Container(*b"dinf", vec![Mp4Box::full(b"dref", 0, 0, &dref)]),
+                stbl,
+            ],
+        );
+        Mp4Box::Container(
+            *b"trak",
+            vec![
+                Mp4Box::full(b"tkhd", 0, 1, &tkhd),
+                Mp4Box::Container(
+                    *b"mdia",
+                    vec![
+                        Mp4Box::full(b"mdhd", 0, 0, &mdhd),
+                        Mp4Box::full(b"hdlr", 0, 0, &hdlr),
+                        minf,
+                    ],
+                ),
+            ],
+        )
+    }
+}
+
+/// Where `write_with_data_track` puts each part of its output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataTrackLayout {
+    /// The top-level boxes copied unchanged, with their range in the input and where they start
+    /// in the output.
+    pub kept: Vec<([u8; 4], Range<u64>, u64)>,
+    /// The input's `moov`.
+    pub moov: Range<u64>,
+    /// The new `mdat` holding the track's samples, in the output.
+    pub mdat: Range<u64>,
+    /// The rewritten `moov`, in the output.
+    pub new_moov: Range<u64>,
+    /// Chunk offsets of the existing tracks that change because their box moved.
+    pub shifted_chunk_offsets: usize,
+    /// What follows the movie, such as the Insta360 metadata trailer, in the output.
+    pub trailer: Range<u64>,
+}
+
+/// Lays out the output of `write_with_data_track`, returning the rewritten `moov` with it.
+fn layout_data_track(
+    data: &[u8],
+    mp4_end: usize,
+    track: &DataTrack,
+) -> io::Result<(DataTrackLayout, Vec<u8>)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let movie = &data[..mp4_end];
+
+    // Where each retained top-level box will start in the output.
+    let mut kept = Vec::new();
+    let mut moov = None;
+    let mut output_offset = 0u64;
+    for (offset, header) in box_spans(movie) {
+        let end = offset + header.size as usize;
+        if &header.box_type == b"moov" {
+            moov = Some(offset..end);
+        } else {
+            kept.push((header.box_type, offset as u64..end as u64, output_offset));
+            output_offset += header.size;
+        }
+    }
+    let moov_span = moov.ok_or_else(|| invalid("no moov box"))?;
+    let moov_header_size = parse_box_header(&movie[moov_span.start..])
+        .map_err(|_| invalid("bad moov box"))?
+        .1
+        .header_size;
+
+    let mdat_payload_start = output_offset + 8;
+    let mut chunk_offsets = Vec::with_capacity(track.samples.len());
+    let mut sample_offset = mdat_payload_start;
+    for sample in &track.samples {
+        chunk_offsets.push(sample_offset);
+        sample_offset += sample.len() as u64;
+    }
+    let mdat_size = sample_offset - output_offset;
+    if mdat_size > u32::MAX as u64 {
+        return Err(invalid("data track too large"));
+    }
+
+    let mut moov = Mp4Box::Container(
+        *b"moov",
+        Mp4Box::parse_all(&movie[moov_span.start + moov_header_size..moov_span.end]),
+    );
+    let shifted = Cell::new(0);
+    let remap = |offset: u64| {
+        let remapped = kept
+            .iter()
+            .find(|(_, span, _)| span.contains(&offset))
+            .map_or(offset, |(_, span, new_start)| {
+                offset - span.start + new_start
+            });
+        if remapped != offset {
+            shifted.set(shifted.get() + 1);
+        }
+        remapped
+    };
+    moov.visit_mut(&mut |mp4_box| remap_chunk_offsets(mp4_box, &remap));
+
+    let children = moov.children_mut().unwrap();
+    let Some(Mp4Box::Leaf(_, mvhd)) = children.iter_mut().find(|b| b.box_type() == b"mvhd") else {
+        return Err(invalid("no mvhd box"));
+    };
+    let (_, movie_header) = parse_media_header(mvhd).map_err(|_| invalid("bad mvhd box"))?;
+    let next_track_id_at = mvhd.len() - 4;
+    let track_id = be_u32_at(mvhd, next_track_id_at);
+    mvhd[next_track_id_at..].copy_from_slice(&(track_id + 1).to_be_bytes());
+    children.push(track.trak(track_id, movie_header.timescale, &chunk_offsets));
+
+    let mut moov_bytes = Vec::new();
+    moov.write_to(&mut moov_bytes);
+    let moov_end = sample_offset + moov_bytes.len() as u64;
+    let layout = DataTrackLayout {
+        kept,
+        moov: moov_span.start as u64..moov_span.end as u64,
+        mdat: output_offset..sample_offset,
+        new_moov: sample_offset..moov_end,
+        shifted_chunk_offsets: shifted.get(),
+        trailer: moov_end..moov_end + (data.len() - mp4_end) as u64,
+    };
+    Ok((layout, moov_bytes))
+}
+
+/// Where `write_with_data_track` would put each part of its output, without writing it.
+pub fn data_track_layout(
+    data: &[u8],
+    mp4_end: usize,
+    track: &DataTrack,
+) -> io::Result<DataTrackLayout> {
+    Ok(layout_data_track(data, mp4_end, track)?.0)
+}
+
+/// Describes for `--dry-run` how `write_with_data_track` would lay out the copy of `file_name`
+/// with a track of `samples` samples, and the byte ranges of the trailer, `mmap[mp4_end..]`,
+/// that `new_trailer` changes.
+pub fn write_rewrite_plan(
+    out: &mut dyn Write,
+    file_name: &Path,
+    layout: &DataTrackLayout,
+    samples: usize,
+    mmap: &[u8],
+    mp4_end: usize,
+    new_trailer: Option<&[u8]>,
+) -> io::Result<()> {
+    let range = |range: &Range<u64>| format!("{:#x}..{:#x}", range.start, range.end);
+    let at = |start: u64, len: u64| range(&(start..start + len));
+    writeln!(
+        out,
+        "{}: would write {:#x} bytes",
+        file_name.display(),
+        layout.trailer.end
+    )?;
+    for (box_type, span, start) in &layout.kept {
+        writeln!(
+            out,
+            "  {:<8}{} copied to {}",
+            String::from_utf8_lossy(box_type),
+            range(span),
+            at(*start, span.end - span.start)
+        )?;
+    }
+    writeln!(
+        out,
+        "  {:<8}added at {} with {samples} samples",
+        "mdat",
+        range(&layout.mdat)
+    )?;
+    writeln!(
+        out,
+        "  {:<8}{} rewritten at {} with the new track and {} chunk offsets shifted",
+        "moov",
+        range(&layout.moov),
+        range(&layout.new_moov),
+        layout.shifted_chunk_offsets
+    )?;
+    let trailer = &mmap[mp4_end..];
+    if trailer.is_empty() {
+        return Ok(());
+    }
+    // The index locates frames from the end of the file, so it stays valid wherever the trailer
+    // starts.
+    writeln!(
+        out,
+        "  {:<8}{} copied to {}, its index unchanged",
+        "trailer",
+        range(&(mp4_end as u64..mmap.len() as u64)),
+        range(&layout.trailer)
+    )?;
+    let Some(new_trailer) = new_trailer else {
+        return Ok(());
+    };
+    let mut changed: Vec<Range<u64>> = Vec::new();
+    for (i, _) in (trailer.iter().zip(new_trailer).enumerate()).filter(|(_, (a, b))| a != b) {
+        let i = layout.trailer.start + i as u64;
+        match changed.last_mut() {
+            Some(last) if last.end == i => last.end = i + 1,
+            _ => changed.push(i..i + 1),
+        }
+    }
+    for span in &changed {
+        writeln!(out, "  {:<8}{} anonymized", "", range(span))?;
+    }
+    Ok(())
+}
+
+/// Copies the movie in `data[..mp4_end]` to `out` with an extra track, followed by anything after
+/// `mp4_end` (the Insta360 metadata trailer) unchanged.
+///
+/// The track's samples go in a new `mdat` and the rewritten `moov` is moved after it, so existing
+/// chunk offsets are shifted rather than the media data being rewritten.
+pub fn write_with_data_track(
+    mut out: impl Write,
+    data: &[u8],
+    mp4_end: usize,
+    track: &DataTrack,
+) -> io::Result<()> {
+    let (layout, moov) = layout_data_track(data, mp4_end, track)?;
+    for (_, span, _) in &layout.kept {
+        out.write_all(&data[span.start as usize..span.end as usize])?;
+    }
+    out.write_all(&((layout.mdat.end - layout.mdat.start) as u32).to_be_bytes())?;
+    out.write_all(b"mdat")?;
+    for sample in &track.samples {
+        out.write_all(sample)?;
+    }
+    out.write_all(&moov)?;
+    out.write_all(&data[mp4_end..])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(box_type);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn media_header(creation_time: u32, timescale: u32, duration: u32) -> Vec<u8> {
+        [0, creation_time, creation_time, timescale, duration]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect()
+    }
+
+    /// A movie with a video track of the given `stts` entries, followed by garbage standing in
+    /// for the Insta360 metadata trailer.
+    fn movie_with_video_track(stts: &[u32]) -> Vec<u8> {
+        let stts: Vec<u8> = [&[0, stts.len() as u32 / 2][..], stts]
+            .concat()
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        let hdlr = [&[0u8; 8][..], b"vide"].concat();
+        let stbl = mp4_box(b"stbl", &mp4_box(b"stts", &stts));
+        let minf = mp4_box(b"minf", &stbl);
+        let mdia = mp4_box(
+            b"mdia",
+            &[
+                mp4_box(b"mdhd", &media_header(0, 30000, 3003)),
+                mp4_box(b"hdlr", &hdlr),
+                minf,
+            ]
+            .concat(),
+        );
+        let moov = mp4_box(
+            b"moov",
+            &[
+                mp4_box(b"mvhd", &media_header(3835000000, 1000, 100)),
+                mp4_box(b"trak", &mdia),
+            ]
+            .concat(),
+        );
+        [mp4_box(b"ftyp", b"isom"), moov, vec![0xff; 20]].concat()
+    }
+
+    #[test]
+    fn test_parse_video_track() {
+        let file = movie_with_video_track(&[3, 1001]);
+
+        let track = parse_video_track(&file).expect("Failed to find video track");
+        assert_eq!(track.creation_time, 3835000000 - MP4_EPOCH_OFFSET);
+        assert_eq!(track.timescale, 30000);
+        assert_eq!(track.presentation_times, vec![0, 1001, 2002]);
+        assert_eq!(track.frame_time(1), 1001.0 / 30000.0);
+    }
+
+    #[test]
+    fn test_malformed_boxes() {
+        let ftyp = mp4_box(b"ftyp", b"isom");
+        assert_eq!(boxes(&ftyp).count(), 1);
+        // A size smaller than the header, or reaching past the data, ends the boxes.
+        for size in [4u32, 13] {
+            let mut bad = ftyp.clone();
+            bad[..4].copy_from_slice(&size.to_be_bytes());
+            assert_eq!(boxes(&[&ftyp[..], &bad].concat()).count(), 1, "size {size}");
+        }
+        // A 64-bit size must be there, and a zero size runs to the end.
+        assert!(parse_box_header(&[0, 0, 0, 1, b'm', b'd', b'a', b't', 0, 0]).is_err());
+        let (_, header) = parse_box_header(&[0, 0, 0, 0, b'm', b'd', b'a', b't', 1, 2]).unwrap();
+        assert_eq!((header.header_size, header.size), (8, 10));
+        assert!(parse_box_header(&ftyp[..7]).is_err());
+        assert!(parse_media_header(&media_header(0, 1000, 100)[..15]).is_err());
+
+        // A video cut short anywhere has no video track, nor does one whose `stts` claims more
+        // entries than it holds.
+        let file = movie_with_video_track(&[3, 1001]);
+        let moov_end = file.len() - 20;
+        for len in 0..moov_end {
+            assert!(parse_video_track(&file[..len]).is_none(), "{len} bytes");
+        }
+        let stts = file.windows(4).position(|w| w == b"stts").unwrap();
+        let mut overcounted = file.clone();
+        overcounted[stts + 8..stts + 12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(parse_video_track(&overcounted).is_none());
+        assert!(parse_video_track(b"").is_none());
+    }
+
+    /// A movie of one video chunk followed by `trailer`, and where the movie ends.
+    fn movie_with_trailer(trailer: &[u8]) -> (Vec<u8>, usize) {
+        let build = |chunk_offset: u32| {
+            let mut mvhd = media_header(0, 1000, 2000);
+            mvhd.resize(96, 0);
+            mvhd.extend_from_slice(&2u32.to_be_bytes()); // Next track ID.
+            let stco: Vec<u8> = [0u32, 1, chunk_offset]
+                .iter()
+                .flat_map(|v| v.to_be_bytes())
+                .collect();
+            let stbl = mp4_box(b"stbl", &mp4_box(b"stco", &stco));
+            let trak = mp4_box(b"trak", &mp4_box(b"mdia", &mp4_box(b"minf", &stbl)));
+            let moov = mp4_box(b"moov", &[mp4_box(b"mvhd", &mvhd), trak].concat());
+            [mp4_box(b"ftyp", b"isom"), moov, mp4_box(b"mdat", b"FRAME")].concat()
+        };
+        let movie_len = build(0).len();
+        let mut file = build((movie_len - 5) as u32);
+        file.extend_from_slice(trailer);
+        (file, movie_len)
+    }
+
+    fn data_track() -> DataTrack {
+        DataTrack {
+            handler_type: *b"meta",
+            handler_name: "test".to_string(),
+            sample_entry: Mp4Box::Leaf(*b"test", vec![]),
+            timescale: 1000,
+            sample_duration: 1000,
+            samples: vec![b"ONE".to_vec(), b"TWO".to_vec()],
+        }
+    }
+
+    #[test]
+    fn test_write_with_data_track() {
+        let (file, movie_len) = movie_with_trailer(b"TRAILER");
+        let track = data_track();
+        let mut out = Vec::new();
+        write_with_data_track(&mut out, &file, movie_len, &track).unwrap();
+        assert!(out.ends_with(b"TRAILER"));
+
+        let moov = Mp4Box::parse_all(find_box(&out, b"moov").unwrap());
+        let mut offsets = Vec::new();
+        for mut mp4_box in moov {
+            mp4_box.visit_mut(&mut |b| {
+                let width = match b.box_type() {
+                    b"stco" => 4,
+                    b"co64" => 8,
+                    _ => return,
+                };
+                if let Mp4Box::Leaf(_, data) = b {
+                    for entry in data[8..].chunks(width) {
+                        let mut bytes = [0; 8];
+                        bytes[8 - width..].copy_from_slice(entry);
+                        offsets.push(u64::from_be_bytes(bytes) as usize);
+                    }
+                }
+            });
+        }
+        let chunks: Vec<&[u8]> = offsets.iter().map(|o| &out[*o..*o + 3]).collect();
+        assert_eq!(chunks, vec![&b"FRA"[..], b"ONE", b"TWO"]);
+
+        let layout = data_track_layout(&file, movie_len, &track).unwrap();
+        let kept: Vec<(&[u8; 4], u64)> = (layout.kept.iter())
+            .map(|(box_type, _, start)| (box_type, *start))
+            .collect();
+        assert_eq!(kept, [(b"ftyp", 0), (b"mdat", 12)]);
+        assert_eq!(layout.moov.start, 12);
+        assert_eq!(layout.mdat, 25..39);
+        assert_eq!(layout.shifted_chunk_offsets, 1);
+        assert_eq!(layout.new_moov.end, layout.trailer.start);
+        assert_eq!(layout.trailer.end, out.len() as u64);
+    }
+
+    #[test]
+    fn test_write_rewrite_plan() {
+        let (file, movie_len) = movie_with_trailer(b"TRAILER");
+        let track = data_track();
+        let layout = data_track_layout(&file, movie_len, &track).unwrap();
+        let plan = |new_trailer: Option<&[u8]>| {
+            let mut out = Vec::new();
+            let name = Path::new("VID.insv");
+            write_rewrite_plan(&mut out, name, &layout, 2, &file, movie_len, new_trailer).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let trailer = layout.trailer.clone();
+        let expected = format!(
+            "VID.insv: would write {:#x} bytes
+  ftyp    0x0..0xc copied to 0x0..0xc
+  mdat    {:#x}..{:#x} copied to 0xc..0x19
+  mdat    added at 0x19..0x27 with 2 samples
+  moov    0xc..{:#x} rewritten at 0x27..{:#x} with the new track and 1 chunk offsets shifted
+  trailer {:#x}..{:#x} copied to {:#x}..{:#x}, its index unchanged
+",
+            trailer.end,
+            layout.moov.end,
+            movie_len,
+            layout.moov.end,
+            layout.new_moov.end,
+            movie_len,
+            file.len(),
+            trailer.start,
+            trailer.end,
+        );
+        assert_eq!(plan(None), expected);
+
+        // Only the bytes that differ are reported, as ranges in the output.
+        let at = |i: u64| trailer.start + i;
+        assert_eq!(
+            plan(Some(b"TRxxLxx")),
+            format!(
+                "{expected}          {:#x}..{:#x} anonymized\n          {:#x}..{:#x} anonymized\n",
+                at(2),
+                at(4),
+                at(5),
+                at(7)
+            )
+        );
+
+        // Without a trailer there is nothing more to say.
+        let (bare, bare_len) = movie_with_trailer(b"");
+        let bare_layout = data_track_layout(&bare, bare_len, &track).unwrap();
+        let mut out = Vec::new();
+        let name = Path::new("VID.mp4");
+        write_rewrite_plan(&mut out, name, &bare_layout, 2, &bare, bare_len, None).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .ends_with("chunk offsets shifted\n")
+        );
+    }
+}
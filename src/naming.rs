@@ -0,0 +1,194 @@
+//! Output file names built from a template such as `{stem}_{camera}_{date}.{ext}`, for batch
+//! exports that sort their results by camera or day.
+//!
+//! Placeholders are written as in subtitle templates, with `{name:.N}` giving numbers N decimal
+//! places; numbers have none otherwise. Field values are made safe for file names, with path
+//! separators and other reserved characters replaced by `_`, while the template's own text is
+//! kept, so `{date}/{stem}.{ext}` writes into a folder per day. Fields the recording doesn't have
+//! read `unknown`.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    stats::TrackStats,
+    subtitle::{Segment, parse_segments},
+};
+
+/// Names that may appear in an output template placeholder.
+pub const NAME_FIELDS: &[&str] = &[
+    "stem",
+    "ext",
+    "camera",
+    "serial",
+    "firmware",
+    "date",
+    "time",
+    "duration",
+    "distance_km",
+    "max_speed_kmh",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameTemplate {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(NameTemplate {
+            segments: parse_segments(s, NAME_FIELDS)?,
+        })
+    }
+}
+
+/// What a name can be made of, for one input.
+#[derive(Clone, Debug, Default)]
+pub struct NameFields<'a> {
+    /// The input's file name without its extension.
+    pub stem: &'a str,
+    /// The output's extension, without the leading dot.
+    pub ext: &'a str,
+    pub camera: Option<&'a str>,
+    pub serial: Option<&'a str>,
+    pub firmware: Option<&'a str>,
+    /// Unix time the recording started.
+    pub start: Option<u64>,
+    pub stats: Option<&'a TrackStats>,
+}
+
+/// `value` with the characters that can't be in a file name, and whitespace, replaced by `_`.
+fn sanitize(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_whitespace() || c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+impl NameTemplate {
+    pub fn render(&self, fields: &NameFields) -> String {
+        let start =
+            (fields.start).and_then(|start| DateTime::<Utc>::from_timestamp(start as i64, 0));
+        let mut out = String::new();
+        for segment in &self.segments {
+            let (name, precision) = match segment {
+                Segment::Literal(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Segment::Field { name, precision } => (name.as_str(), *precision),
+            };
+            let text = match name {
+                "stem" => Some(fields.stem.to_string()),
+                "ext" => Some(fields.ext.to_string()),
+                "camera" => fields.camera.map(str::to_string),
+                "serial" => fields.serial.map(str::to_string),
+                "firmware" => fields.firmware.map(str::to_string),
+                "date" => start.map(|start| start.format("%Y-%m-%d").to_string()),
+                "time" => start.map(|start| start.format("%H%M%S").to_string()),
+                _ => {
+                    let number = fields.stats.map(|stats| match name {
+                        "duration" => stats.duration.0,
+                        "distance_km" => stats.distance.0 / 1000.0,
+                        "max_speed_kmh" => stats.max_speed.kmh(),
+                        _ => unreachable!("template fields are validated when parsed"),
+                    });
+                    number.map(|number| format!("{number:.0$}", precision.unwrap_or(0)))
+                }
+            };
+            match text.as_deref().map(sanitize) {
+                Some(text) if !text.is_empty() => out.push_str(&text),
+                _ => out.push_str("unknown"),
+            }
+        }
+        out
+    }
+}
+
+/// `path`, or when it's in `taken` the first of `name-2.ext`, `name-3.ext`... that isn't, with
+/// the number before `.ext` when the name ends in it.
+pub fn unique_path(path: &Path, ext: &str, taken: &HashSet<PathBuf>) -> PathBuf {
+    if !taken.contains(path) {
+        return path.to_path_buf();
+    }
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let suffix = format!(".{ext}");
+    let (base, suffix) = match file_name.strip_suffix(&suffix) {
+        Some(base) if !ext.is_empty() => (base, suffix.as_str()),
+        _ => (file_name.as_ref(), ""),
+    };
+    (2..)
+        .map(|n| path.with_file_name(format!("{base}-{n}{suffix}")))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Meters, MetersPerSecond};
+
+    #[test]
+    fn test_render_name() {
+        let template: NameTemplate = "{date}/{stem}_{camera}_{distance_km:.1}km.{ext}"
+            .parse()
+            .unwrap();
+        let stats = TrackStats {
+            distance: Meters(12345.0),
+            max_speed: MetersPerSecond(10.0),
+            ..Default::default()
+        };
+        let fields = NameFields {
+            stem: "VID_20250718_073922_00_001",
+            ext: "gpx",
+            camera: Some("Insta360 X3"),
+            start: Some(1752824362),
+            stats: Some(&stats),
+            ..Default::default()
+        };
+        assert_eq!(
+            template.render(&fields),
+            "2025-07-18/VID_20250718_073922_00_001_Insta360_X3_12.3km.gpx"
+        );
+
+        let template: NameTemplate = "{serial}_{time}_{max_speed_kmh}".parse().unwrap();
+        let fields = NameFields {
+            serial: Some("IAQ/12"),
+            ..fields
+        };
+        assert_eq!(template.render(&fields), "IAQ_12_073922_36");
+        let bare = NameFields::default();
+        assert_eq!(template.render(&bare), "unknown_unknown_unknown");
+
+        assert!("{stem}.{extension}".parse::<NameTemplate>().is_err());
+    }
+
+    #[test]
+    fn test_unique_path() {
+        let mut taken = HashSet::new();
+        let path = Path::new("out/clip.imu.csv");
+        assert_eq!(unique_path(path, "imu.csv", &taken), path);
+        taken.insert(path.to_path_buf());
+        taken.insert(PathBuf::from("out/clip-2.imu.csv"));
+        assert_eq!(
+            unique_path(path, "imu.csv", &taken),
+            Path::new("out/clip-3.imu.csv")
+        );
+        assert_eq!(
+            unique_path(path, "gpx", &taken),
+            Path::new("out/clip.imu.csv-2")
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! NMEA 0183 sentences rebuilt from GPS records, for tools that expect a live receiver.
+//!
+//! Each record becomes an `RMC` sentence, with position, ground speed and course, and a `GGA`
+//! sentence, with altitude. The camera doesn't record satellite counts or dilution of precision,
+//! so those fields are left empty.
+
+use chrono::{DateTime, Utc};
+
+use crate::{FixStatus, GpsRecord};
+
+const KNOTS_PER_METER_PER_SECOND: f64 = 3600.0 / 1852.0;
+
+/// `$`, the body, `*` and the XOR of the body's bytes in hex, then CR LF.
+fn sentence(body: &str) -> String {
+    let checksum = body.bytes().fold(0, |checksum, byte| checksum ^ byte);
+    format!("${body}*{checksum:02X}\r\n")
+}
+
+/// An angle as degrees and decimal minutes, e.g. `4915.0000,N`, with `degree_digits` digits for
+/// the whole degrees.
+fn coordinate(value: f64, degree_digits: usize, positive: char, negative: char) -> String {
+    let hemisphere = if value < 0.0 { negative } else { positive };
+    let value = value.abs();
+    let mut degrees = value.trunc();
+    let mut minutes = ((value - degrees) * 60.0 * 10_000.0).round() / 10_000.0;
+    if minutes >= 60.0 {
+        degrees += 1.0;
+        minutes -= 60.0;
+    }
+    format!(
+        "{degrees:0degree_digits$}{minutes:07.4},{hemisphere}",
+        degrees = degrees as u32
+    )
+}
+
+/// Whether `record` was written without a fix, which RMC marks void and GGA as fix quality 0.
+fn is_void(record: &GpsRecord) -> bool {
+    record.fix == Some(FixStatus::NoFix)
+}
+
+pub fn rmc(record: &GpsRecord) -> String {
+    let time = DateTime::<Utc>::from_timestamp(record.timestamp as i64, 0).unwrap_or_default();
+    let (status, mode) = if is_void(record) {
+        ('V', 'N')
+    } else {
+        ('A', 'A')
+    };
+    sentence(&format!(
+        "GPRMC,{},{status},{},{},{:.1},{:.1},{},,,{mode}",
+        time.format("%H%M%S.00"),
+        coordinate(record.latitude.0, 2, 'N', 'S'),
+        coordinate(record.longitude.0, 3, 'E', 'W'),
+        record.speed.0 * KNOTS_PER_METER_PER_SECOND,
+        record.track.0,
+        time.format("%d%m%y"),
+    ))
+}
+
+pub fn gga(record: &GpsRecord) -> String {
+    let time = DateTime::<Utc>::from_timestamp(record.timestamp as i64, 0).unwrap_or_default();
+    sentence(&format!(
+        "GPGGA,{},{},{},{},,,{:.1},M,,M,,",
+        time.format("%H%M%S.00"),
+        coordinate(record.latitude.0, 2, 'N', 'S'),
+        coordinate(record.longitude.0, 3, 'E', 'W'),
+        if is_void(record) { 0 } else { 1 },
+        record.altitude.0,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_sentences() {
+        let record = GpsRecord {
+            // 2025-07-18 07:39:22 UTC.
+            timestamp: 1752824362,
+            latitude: Degrees(49.25),
+            longitude: Degrees(-4.0305),
+            speed: MetersPerSecond(5.0),
+            track: Degrees(335.0),
+            altitude: Meters(86.0),
+            fix: None,
+        };
+        assert_eq!(
+            rmc(&record),
+            "$GPRMC,073922.00,A,4915.0000,N,00401.8300,W,9.7,335.0,180725,,,A*44\r\n"
+        );
+        assert_eq!(
+            gga(&record),
+            "$GPGGA,073922.00,4915.0000,N,00401.8300,W,1,,,86.0,M,,M,,*7A\r\n"
+        );
+        let void = GpsRecord {
+            fix: Some(FixStatus::NoFix),
+            ..record
+        };
+        assert_eq!(
+            rmc(&void),
+            "$GPRMC,073922.00,V,4915.0000,N,00401.8300,W,9.7,335.0,180725,,,N*5C\r\n"
+        );
+        assert_eq!(
+            gga(&void),
+            "$GPGGA,073922.00,4915.0000,N,00401.8300,W,0,,,86.0,M,,M,,*7B\r\n"
+        );
+        // A minute count rounding up to 60 carries into the degrees.
+        assert_eq!(coordinate(1.999_999_99, 2, 'N', 'S'), "0200.0000,N");
+    }
+}
@@ -0,0 +1,178 @@
+//! A bundle of telemetry sampled at every video frame, for overlay renderers to draw gauges and
+//! maps from without interpolating themselves.
+//!
+//! The JSON shape is a contract: fields may be added, but existing ones keep their names, units
+//! and meaning until `VERSION` changes. A bundle looks like
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "source": "VID_20250718_073922_00_001.insv",
+//!   "frame_rate": 29.97,
+//!   "duration": 60.06,
+//!   "samples": [
+//!     {
+//!       "frame": 0, "time": 0.0, "unix_time": 1752824362.0,
+//!       "latitude": 49.25, "longitude": 4.03, "altitude": 86.0,
+//!       "speed": 5.0, "speed_kmh": 18.0, "heading": 335.0,
+//!       "g_longitudinal": 0.1, "g_lateral": -0.02
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `time` is seconds from the start of the video and `unix_time` the moment it shows. Positions
+//! are in degrees, `altitude` in metres, `speed` in metres per second and `heading` in degrees
+//! clockwise from north. The g fields are in g, forward and right positive, from the
+//! accelerometer where its clock can be placed and the GPS elsewhere. Fields not known at a frame
+//! are `null`.
+
+use serde::Serialize;
+
+use crate::{
+    GpsRecord,
+    align::{bracket, interpolate_gps, lerp},
+    gforce::GForce,
+    mp4::VideoTrack,
+};
+
+/// Version of the bundle's shape.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct OverlayBundle {
+    pub version: u32,
+    /// File name of the video.
+    pub source: String,
+    /// Frames per second, on average.
+    pub frame_rate: Option<f64>,
+    /// Seconds.
+    pub duration: f64,
+    pub samples: Vec<OverlaySample>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct OverlaySample {
+    pub frame: usize,
+    pub time: f64,
+    pub unix_time: f64,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>,
+    pub speed: Option<f64>,
+    pub speed_kmh: Option<f64>,
+    pub heading: Option<f64>,
+    pub g_longitudinal: Option<f64>,
+    pub g_lateral: Option<f64>,
+}
+
+/// Samples `gps` and `forces`, which holds the acceleration at each GPS record, at each frame
+/// of `track`.
+pub fn overlay_bundle(
+    source: &str,
+    track: &VideoTrack,
+    gps: &[GpsRecord],
+    forces: &[GForce],
+) -> OverlayBundle {
+    let duration = track.duration as f64 / track.timescale as f64;
+    let frames = track.presentation_times.len();
+    // Longitudinal and lateral, from the accelerometer where there is one, at each GPS record.
+    let g: Vec<(f64, [Option<f64>; 2])> = (gps.iter().zip(forces))
+        .map(|(record, force)| {
+            let longitudinal = force.accel_longitudinal.or(force.gps_longitudinal);
+            let lateral = force.accel_lateral.or(force.gps_lateral);
+            (record.timestamp as f64, [longitudinal, lateral])
+        })
+        .collect();
+    let g_at = |t: f64, axis: usize| {
+        let (a, b, fraction) = bracket(&g, t, |sample| sample.0)?;
+        Some(lerp(a.1[axis]?, b.1[axis]?, fraction))
+    };
+    let samples = (0..frames)
+        .map(|frame| {
+            let time = track.frame_time(frame);
+            let unix_time = track.creation_time as f64 + time;
+            let record = interpolate_gps(gps, unix_time);
+            OverlaySample {
+                frame,
+                time,
+                unix_time,
+                latitude: record.as_ref().map(|r| r.latitude.0),
+                longitude: record.as_ref().map(|r| r.longitude.0),
+                altitude: record.as_ref().map(|r| r.altitude.0),
+                speed: record.as_ref().map(|r| r.speed.0),
+                speed_kmh: record.as_ref().map(|r| r.speed.kmh()),
+                heading: record.as_ref().map(|r| r.track.0),
+                g_longitudinal: g_at(unix_time, 0),
+                g_lateral: g_at(unix_time, 1),
+            }
+        })
+        .collect();
+    OverlayBundle {
+        version: VERSION,
+        source: source.to_string(),
+        frame_rate: (duration > 0.0).then(|| frames as f64 / duration),
+        duration,
+        samples,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_overlay_bundle() {
+        let track = VideoTrack {
+            creation_time: 1000,
+            timescale: 2,
+            duration: 4,
+            presentation_times: vec![0, 1, 2, 3],
+        };
+        let gps: Vec<GpsRecord> = (0..2)
+            .map(|i| GpsRecord {
+                timestamp: 1000 + i,
+                latitude: Degrees(49.0 + i as f64),
+                longitude: Degrees(4.0),
+                speed: MetersPerSecond(10.0),
+                track: Degrees(90.0),
+                altitude: Meters(100.0),
+                fix: None,
+            })
+            .collect();
+        let forces = [
+            GForce {
+                gps_longitudinal: Some(0.2),
+                accel_longitudinal: Some(0.1),
+                ..Default::default()
+            },
+            GForce {
+                gps_longitudinal: Some(0.4),
+                ..Default::default()
+            },
+        ];
+
+        let bundle = overlay_bundle("VID_1.insv", &track, &gps, &forces);
+        assert_eq!((bundle.frame_rate, bundle.duration), (Some(2.0), 2.0));
+        assert_eq!(
+            bundle.samples[1],
+            OverlaySample {
+                frame: 1,
+                time: 0.5,
+                unix_time: 1000.5,
+                latitude: Some(49.5),
+                longitude: Some(4.0),
+                altitude: Some(100.0),
+                speed: Some(10.0),
+                speed_kmh: Some(36.0),
+                heading: Some(90.0),
+                g_longitudinal: Some(0.25),
+                g_lateral: None,
+            }
+        );
+        assert_eq!(bundle.samples[3].latitude, None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Names and time zones for where a track starts and ends, from an offline gazetteer or any
+//! other source implementing `PlaceProvider`.
+
+use chrono_tz::Tz;
+
+use crate::{GpsRecord, geo::haversine_distance};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Place {
+    pub name: String,
+    /// ISO 3166-1 alpha-2 country code.
+    pub country: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// IANA time zone, such as `Europe/Paris`.
+    pub timezone: Option<Tz>,
+}
+
+/// Looks up the place a position is in or near.
+pub trait PlaceProvider {
+    fn place(&self, latitude: f64, longitude: f64) -> Option<Place>;
+}
+
+/// Places to match positions against, nearest first.
+#[derive(Debug, Default)]
+pub struct Gazetteer {
+    pub places: Vec<Place>,
+    /// Positions further than this many metres from every place have no name.
+    pub max_distance: f64,
+}
+
+pub const DEFAULT_MAX_PLACE_DISTANCE: f64 = 50_000.0;
+
+impl Gazetteer {
+    /// Reads a GeoNames dump such as `cities500.txt`: tab-separated, with the name in the second
+    /// column, latitude and longitude in the fifth and sixth, the country code in the ninth and
+    /// the time zone in the eighteenth. Lines that don't parse are skipped.
+    pub fn parse_geonames(text: &str) -> Self {
+        let places = text
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                Some(Place {
+                    name: fields.get(1)?.to_string(),
+                    country: fields.get(8)?.to_string(),
+                    latitude: fields.get(4)?.parse().ok()?,
+                    longitude: fields.get(5)?.parse().ok()?,
+                    timezone: fields.get(17).and_then(|zone| zone.parse().ok()),
+                })
+            })
+            .collect();
+        Gazetteer {
+            places,
+            max_distance: DEFAULT_MAX_PLACE_DISTANCE,
+        }
+    }
+}
+
+impl PlaceProvider for Gazetteer {
+    fn place(&self, latitude: f64, longitude: f64) -> Option<Place> {
+        self.places
+            .iter()
+            .map(|place| {
+                let distance =
+                    haversine_distance(latitude, longitude, place.latitude, place.longitude);
+                (place, distance)
+            })
+            .filter(|(_, distance)| *distance <= self.max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(place, _)| place.clone())
+    }
+}
+
+/// The nautical time zone for a longitude, a whole number of hours from UTC, for positions no
+/// gazetteer covers. Zones follow borders, so this can be an hour or more off on land.
+pub fn nautical_time_zone(longitude: f64) -> Tz {
+    let hours = (longitude / 15.0).round().clamp(-12.0, 12.0) as i32;
+    // The Etc zones count hours west of Greenwich, so UTC+1 is Etc/GMT-1.
+    let name = match hours {
+        0 => "Etc/GMT".to_string(),
+        hours => format!("Etc/GMT{:+}", -hours),
+    };
+    name.parse().unwrap_or(Tz::UTC)
+}
+
+/// Time zone of the first GPS fix away from null island: the zone of the nearest place in
+/// `provider` that has one, or else the nautical zone.
+pub fn infer_time_zone(records: &[GpsRecord], provider: Option<&dyn PlaceProvider>) -> Option<Tz> {
+    let fix = records
+        .iter()
+        .find(|record| record.latitude.0 != 0.0 || record.longitude.0 != 0.0)?;
+    let (latitude, longitude) = (fix.latitude.0, fix.longitude.0);
+    Some(
+        provider
+            .and_then(|provider| provider.place(latitude, longitude))
+            .and_then(|place| place.timezone)
+            .unwrap_or_else(|| nautical_time_zone(longitude)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gazetteer() {
+        let gazetteer = Gazetteer::parse_geonames(
+            "3030300\tBrest\tBrest\t\t48.39029\t-4.48628\tP\tPPLA3\tFR\t\t\t\t\t\t\t\t\tEurope/Paris\n\
+             2988507\tParis\tParis\t\t48.85341\t2.3488\tP\tPPLC\tFR\n\
+             broken line\n\
+             1\tNowhere\tNowhere\t\tnorth\t2.0\tP\tPPL\tFR\n\
+             2\tZoneless\tZoneless\t\t1.0\t2.0\tP\tPPL\tFR\t\t\t\t\t\t\t\t\tMars/Olympus\n",
+        );
+        assert_eq!(gazetteer.places.len(), 3);
+        assert_eq!(gazetteer.places[2].timezone, None);
+        let place = gazetteer.place(48.4, -4.4).unwrap();
+        assert_eq!(
+            (place.name.as_str(), place.country.as_str()),
+            ("Brest", "FR")
+        );
+        assert_eq!(gazetteer.place(0.0, 0.0), None);
+        assert_eq!(place.timezone, Some(Tz::Europe__Paris));
+
+        assert_eq!(nautical_time_zone(-4.4), Tz::Etc__GMT);
+        assert_eq!(nautical_time_zone(139.7), Tz::Etc__GMTMinus9);
+        assert_eq!(nautical_time_zone(-122.4), Tz::Etc__GMTPlus8);
+    }
+}
@@ -0,0 +1,180 @@
+//! Every stream merged into rows on one time column, the CSV layout PlotJuggler loads without
+//! being told where each stream's timestamps are.
+//!
+//! Columns are named `stream/field`, which PlotJuggler shows as a tree. A row holds the records
+//! of every stream logged at its time and leaves the other streams' columns empty.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{ExposureRecord, GpsRecord, GyroRecord, imu::ImuScale};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct MergedRow {
+    /// Unix time in seconds.
+    pub time: f64,
+    #[cfg_attr(feature = "serde", serde(rename = "gps/latitude"))]
+    pub latitude: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(rename = "gps/longitude"))]
+    pub longitude: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(rename = "gps/speed"))]
+    pub speed: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(rename = "gps/track"))]
+    pub track: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(rename = "gps/altitude"))]
+    pub altitude: Option<f64>,
+    /// In rad/s.
+    #[cfg_attr(feature = "serde", serde(rename = "imu/gyro_x"))]
+    pub gyro_x: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(rename = "imu/gyro_y"))]
+    pub gyro_y: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(rename = "imu/gyro_z"))]
+    pub gyro_z: Option<f64>,
+    /// In g.
+    #[cfg_attr(feature = "serde", serde(rename = "imu/accel_x"))]
+    pub accel_x: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(rename = "imu/accel_y"))]
+    pub accel_y: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(rename = "imu/accel_z"))]
+    pub accel_z: Option<f64>,
+    /// In seconds.
+    #[cfg_attr(feature = "serde", serde(rename = "exposure/shutterspeed"))]
+    pub shutterspeed: Option<f64>,
+}
+
+impl MergedRow {
+    fn has_gps(&self) -> bool {
+        self.latitude.is_some()
+    }
+
+    fn has_imu(&self) -> bool {
+        self.gyro_x.is_some()
+    }
+
+    fn has_exposure(&self) -> bool {
+        self.shutterspeed.is_some()
+    }
+}
+
+/// Merges the streams in time order. Gyro and exposure records, which use the camera clock, are
+/// placed with `camera_epoch`, the Unix time at which that clock read zero, and left out without
+/// it.
+pub fn merge_streams(
+    gps: &[GpsRecord],
+    gyro: &[GyroRecord],
+    exposure: &[ExposureRecord],
+    scale: &ImuScale,
+    camera_epoch: Option<f64>,
+) -> Vec<MergedRow> {
+    enum Record<'a> {
+        Gps(&'a GpsRecord),
+        Gyro(&'a GyroRecord),
+        Exposure(&'a ExposureRecord),
+    }
+    let mut records: Vec<(f64, Record)> = (gps.iter())
+        .map(|record| (record.timestamp as f64, Record::Gps(record)))
+        .collect();
+    if let Some(epoch) = camera_epoch {
+        let time = |timestamp: u64| epoch + timestamp as f64 / 1000.0;
+        records.extend(gyro.iter().map(|r| (time(r.timestamp), Record::Gyro(r))));
+        records.extend((exposure.iter()).map(|r| (time(r.timestamp), Record::Exposure(r))));
+    }
+    records.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    let mut rows: Vec<MergedRow> = Vec::new();
+    for (time, record) in records {
+        // Join the earliest row at the same time that doesn't have this stream yet.
+        let lacks = |row: &MergedRow| match record {
+            Record::Gps(_) => !row.has_gps(),
+            Record::Gyro(_) => !row.has_imu(),
+            Record::Exposure(_) => !row.has_exposure(),
+        };
+        let same_time = rows.iter().rev().take_while(|row| row.time == time).count();
+        let index = match (rows.len() - same_time..rows.len()).find(|&i| lacks(&rows[i])) {
+            Some(index) => index,
+            None => {
+                rows.push(MergedRow {
+                    time,
+                    ..Default::default()
+                });
+                rows.len() - 1
+            }
+        };
+        let row = &mut rows[index];
+        match record {
+            Record::Gps(record) => {
+                row.latitude = Some(record.latitude.0);
+                row.longitude = Some(record.longitude.0);
+                row.speed = Some(record.speed.0);
+                row.track = Some(record.track.0);
+                row.altitude = Some(record.altitude.0);
+            }
+            Record::Gyro(record) => {
+                let sample = scale.sample(record);
+                row.gyro_x = Some(sample.gyro_x);
+                row.gyro_y = Some(sample.gyro_y);
+                row.gyro_z = Some(sample.gyro_z);
+                row.accel_x = Some(sample.accel_x);
+                row.accel_y = Some(sample.accel_y);
+                row.accel_z = Some(sample.accel_z);
+            }
+            Record::Exposure(record) => row.shutterspeed = Some(record.shutterspeed.0),
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond, Seconds};
+
+    #[test]
+    fn test_merge_streams() {
+        let gps = [GpsRecord {
+            timestamp: 100,
+            latitude: Degrees(49.0),
+            longitude: Degrees(4.0),
+            speed: MetersPerSecond(2.0),
+            track: Degrees(90.0),
+            altitude: Meters(80.0),
+            fix: None,
+        }];
+        let gyro: Vec<GyroRecord> = [500, 1000, 1000]
+            .map(|timestamp| GyroRecord {
+                timestamp,
+                payload: [0; 12],
+            })
+            .into();
+        let exposure = [ExposureRecord {
+            timestamp: 1000,
+            shutterspeed: Seconds(0.002),
+        }];
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+
+        let rows = merge_streams(&gps, &gyro, &exposure, &scale, Some(99.0));
+        let times: Vec<f64> = rows.iter().map(|row| row.time).collect();
+        // The second gyro record at 100 s needs a row of its own.
+        assert_eq!(times, [99.5, 100.0, 100.0]);
+        assert!(rows[1].has_gps() && rows[1].has_imu() && rows[1].has_exposure());
+        assert!(!rows[2].has_gps() && rows[2].has_imu());
+
+        let rows = merge_streams(&gps, &gyro, &exposure, &scale, None);
+        assert_eq!(rows.len(), 1);
+        assert!(!rows[0].has_imu());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_columns() {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(MergedRow::default()).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(csv.starts_with("time,gps/latitude,"));
+        assert!(csv.ends_with("exposure/shutterspeed\n0.0,,,,,,,,,,,,\n"));
+    }
+}
@@ -0,0 +1,147 @@
+//! CSV laid out for the overlay tools DashWare and RaceRender, with the column names and units
+//! their importers recognise, so a ginsta export loads without setting up a data profile.
+//!
+//! Both get one row per GPS record with the time in seconds from the first, the lap, position,
+//! altitude, speed, heading and lateral and longitudinal acceleration, with laps numbered by the
+//! caller as `laps::lap_numbers` does. DashWare takes its units from the column names, as in
+//! `Speed (km/h)`; RaceRender reads `X` acceleration as lateral and `Y` as longitudinal.
+
+use serde::Serialize;
+
+use crate::{GpsRecord, gforce::GForce};
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DashwareRow {
+    #[serde(rename = "Time (s)")]
+    pub time: f64,
+    #[serde(rename = "Lap")]
+    pub lap: u32,
+    #[serde(rename = "Latitude (deg)")]
+    pub latitude: f64,
+    #[serde(rename = "Longitude (deg)")]
+    pub longitude: f64,
+    #[serde(rename = "Altitude (m)")]
+    pub altitude: f64,
+    #[serde(rename = "Speed (km/h)")]
+    pub speed: f64,
+    #[serde(rename = "Heading (deg)")]
+    pub heading: f64,
+    #[serde(rename = "Lateral Acceleration (g)")]
+    pub lateral: Option<f64>,
+    #[serde(rename = "Longitudinal Acceleration (g)")]
+    pub longitudinal: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RaceRenderRow {
+    #[serde(rename = "Time")]
+    pub time: f64,
+    #[serde(rename = "Lap")]
+    pub lap: u32,
+    #[serde(rename = "Latitude")]
+    pub latitude: f64,
+    #[serde(rename = "Longitude")]
+    pub longitude: f64,
+    #[serde(rename = "Altitude (m)")]
+    pub altitude: f64,
+    #[serde(rename = "Speed (KPH)")]
+    pub speed: f64,
+    #[serde(rename = "Heading")]
+    pub heading: f64,
+    #[serde(rename = "X Accel (G)")]
+    pub lateral: Option<f64>,
+    #[serde(rename = "Y Accel (G)")]
+    pub longitudinal: Option<f64>,
+}
+
+/// The acceleration of each record, from the accelerometer where there is one.
+fn g(force: Option<&GForce>) -> (Option<f64>, Option<f64>) {
+    let Some(force) = force else {
+        return (None, None);
+    };
+    (
+        force.accel_lateral.or(force.gps_lateral),
+        force.accel_longitudinal.or(force.gps_longitudinal),
+    )
+}
+
+fn seconds_from_start(records: &[GpsRecord], record: &GpsRecord) -> f64 {
+    record.timestamp.saturating_sub(records[0].timestamp) as f64
+}
+
+pub fn dashware_rows(records: &[GpsRecord], forces: &[GForce], laps: &[u32]) -> Vec<DashwareRow> {
+    (records.iter().zip(laps).enumerate())
+        .map(|(i, (record, &lap))| {
+            let (lateral, longitudinal) = g(forces.get(i));
+            DashwareRow {
+                time: seconds_from_start(records, record),
+                lap,
+                latitude: record.latitude.0,
+                longitude: record.longitude.0,
+                altitude: record.altitude.0,
+                speed: record.speed.kmh(),
+                heading: record.track.0,
+                lateral,
+                longitudinal,
+            }
+        })
+        .collect()
+}
+
+pub fn racerender_rows(
+    records: &[GpsRecord],
+    forces: &[GForce],
+    laps: &[u32],
+) -> Vec<RaceRenderRow> {
+    (records.iter().zip(laps).enumerate())
+        .map(|(i, (record, &lap))| {
+            let (lateral, longitudinal) = g(forces.get(i));
+            RaceRenderRow {
+                time: seconds_from_start(records, record),
+                lap,
+                latitude: record.latitude.0,
+                longitude: record.longitude.0,
+                altitude: record.altitude.0,
+                speed: record.speed.kmh(),
+                heading: record.track.0,
+                lateral,
+                longitudinal,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_rows() {
+        let records: Vec<GpsRecord> = (0..7)
+            .map(|i| GpsRecord {
+                timestamp: 100 + i,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(10.0),
+                track: Degrees(0.0),
+                altitude: Meters(5.0),
+                fix: None,
+            })
+            .collect();
+        let laps_run = [1, 1, 1, 1, 2, 2, 3];
+
+        let forces = [GForce {
+            gps_longitudinal: Some(0.1),
+            accel_lateral: Some(-0.2),
+            ..Default::default()
+        }];
+        let rows = racerender_rows(&records, &forces, &laps_run);
+        assert_eq!(rows.len(), 7);
+        assert_eq!((rows[0].speed, rows[0].lateral), (36.0, Some(-0.2)));
+        assert_eq!(rows[0].longitudinal, Some(0.1));
+        assert_eq!((rows[6].time, rows[6].lap, rows[6].lateral), (6.0, 3, None));
+        let rows = dashware_rows(&records, &[], &laps_run);
+        assert_eq!((rows[4].time, rows[4].lap), (4.0, 2));
+    }
+}
@@ -0,0 +1,303 @@
+//! Low-resolution proxies, the `LRV_…` files Insta360 cameras record next to each video for
+//! quick previews. Their metadata can be missing or cut short, so the full-resolution `VID_…`
+//! file they were recorded with is the one to read.
+//!
+//! The two share a date, time and sequence number: `LRV_20250718_073922_11_001.lrv` goes with
+//! `VID_20250718_073922_00_001.insv`, whose `00` marks the file carrying the metadata.
+//!
+//! Dual-lens cameras can also record each lens to its own file, `_00_` and `_10_`, both of which
+//! may carry the metadata. They should agree; where they don't, the more complete one is the one
+//! to read, and to give to stabilisers such as Gyroflow.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    GYRO_RECORD_SIZE, GpsRecordRef, GyroRecordRef, Telemetry, gps_record_refs, gyro_frames,
+    gyro_record_refs, read_telemetry_with,
+};
+
+/// Whether `path` names a proxy, by its `.lrv` extension or `LRV_` prefix.
+pub fn is_proxy(path: &Path) -> bool {
+    let extension = path.extension().unwrap_or_default();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    extension.eq_ignore_ascii_case("lrv") || name.starts_with("LRV_")
+}
+
+/// The names the full-resolution file of the proxy called `name` could have, most likely first.
+fn sibling_names(name: &str) -> Vec<String> {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let Some(rest) = stem.strip_prefix("LRV_") else {
+        return Vec::new();
+    };
+    // Date, time, lens and sequence number.
+    let parts: Vec<&str> = rest.split('_').collect();
+    let [date, time, _, sequence] = parts[..] else {
+        return Vec::new();
+    };
+    ["insv", "mp4"]
+        .iter()
+        .map(|extension| format!("VID_{date}_{time}_00_{sequence}.{extension}"))
+        .collect()
+}
+
+/// The full-resolution file next to the proxy at `path`, if there is one.
+pub fn full_resolution_sibling(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_string_lossy();
+    (sibling_names(&name).into_iter())
+        .map(|sibling| path.with_file_name(sibling))
+        .find(|sibling| sibling.is_file())
+}
+
+/// The file of the other lens of the dual-stream recording at `path`, `_10_` for `_00_` and the
+/// other way round, if it is next to it.
+pub fn lens_partner(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_string_lossy();
+    let (stem, extension) = name.rsplit_once('.')?;
+    let rest = stem.strip_prefix("VID_")?;
+    let parts: Vec<&str> = rest.split('_').collect();
+    let [date, time, lens, sequence] = parts[..] else {
+        return None;
+    };
+    let other = match lens {
+        "00" => "10",
+        "10" => "00",
+        _ => return None,
+    };
+    let partner = path.with_file_name(format!("VID_{date}_{time}_{other}_{sequence}.{extension}"));
+    partner.is_file().then_some(partner)
+}
+
+/// A file's telemetry as it is compared with another's. Its gyro records are read in place from
+/// the mapped file rather than held, as a long recording has hundreds of megabytes of them.
+pub struct Compared<'a> {
+    pub telemetry: &'a Telemetry,
+    /// The whole records of each gyro frame, as `gyro_frames` returns them.
+    pub gyro_frames: Vec<&'a [u8]>,
+}
+
+impl<'a> Compared<'a> {
+    /// The telemetry of the Insta360 file `data`, read without its gyro records, and the gyro
+    /// records left in `data`.
+    pub fn new(data: &'a [u8], telemetry: &'a Telemetry) -> Self {
+        Compared {
+            telemetry,
+            gyro_frames: gyro_frames(data),
+        }
+    }
+
+    fn gyro(&self) -> impl Iterator<Item = GyroRecordRef<'a>> + '_ {
+        self.gyro_frames
+            .iter()
+            .flat_map(|frame| gyro_record_refs(frame))
+    }
+
+    fn gyro_len(&self) -> usize {
+        (self.gyro_frames.iter())
+            .map(|frame| frame.len() / GYRO_RECORD_SIZE)
+            .sum()
+    }
+
+    fn records(&self) -> usize {
+        self.telemetry.gps.len() + self.gyro_len() + self.telemetry.exposure.len()
+    }
+}
+
+/// The telemetry of the Insta360 file `data` as `Compared` needs it, without the gyro records.
+pub fn compared_telemetry(data: &[u8]) -> Telemetry {
+    let mut gps = Vec::new();
+    let mut telemetry = read_telemetry_with(
+        data,
+        |frame| gps.extend(gps_record_refs(frame).map(GpsRecordRef::to_owned)),
+        |_| {},
+    );
+    telemetry.gps = gps;
+    telemetry
+}
+
+/// Whether `a` has more telemetry records than `b`, counting GPS, gyro and exposure together.
+pub fn more_complete(a: &Compared, b: &Compared) -> bool {
+    a.records() > b.records()
+}
+
+/// Which of the two lens files of a dual-stream recording to read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LensChoice {
+    /// The file asked for.
+    Own,
+    /// The other lens's file, as the one asked for has no metadata.
+    OnlyPartner,
+    /// The other lens's file, as its telemetry is more complete.
+    MoreCompletePartner,
+}
+
+/// Chooses between the telemetry of the file asked for, `own` if it has any, and that of the
+/// other lens's file, `partner`. The file asked for is kept unless the other one has more.
+pub fn choose_lens(own: Option<&Compared>, partner: &Compared) -> LensChoice {
+    match own {
+        None => LensChoice::OnlyPartner,
+        Some(own) if more_complete(partner, own) => LensChoice::MoreCompletePartner,
+        Some(_) => LensChoice::Own,
+    }
+}
+
+/// How the telemetry of a proxy or the other lens's file differs from that of the file it goes
+/// with, one line per difference.
+pub fn telemetry_differences(proxy: &Compared, full: &Compared) -> Vec<String> {
+    let mut differences = Vec::new();
+    let counts = [
+        ("GPS", proxy.telemetry.gps.len(), full.telemetry.gps.len()),
+        ("gyro", proxy.gyro_len(), full.gyro_len()),
+        (
+            "exposure",
+            proxy.telemetry.exposure.len(),
+            full.telemetry.exposure.len(),
+        ),
+    ];
+    for (stream, proxy, full) in counts {
+        if proxy != full {
+            differences.push(format!("{proxy} {stream} records against {full}"));
+        }
+    }
+    let span = |telemetry: &Telemetry| {
+        Some((
+            telemetry.gps.first()?.timestamp,
+            telemetry.gps.last()?.timestamp,
+        ))
+    };
+    if let (Some(proxy), Some(full)) = (span(proxy.telemetry), span(full.telemetry))
+        && proxy != full
+    {
+        differences.push(format!(
+            "GPS from {} to {} against {} to {}",
+            proxy.0, proxy.1, full.0, full.1
+        ));
+    }
+    let gyro_differing = (proxy.gyro().zip(full.gyro()))
+        .filter(|(a, b)| a.timestamp() != b.timestamp() || a.payload() != b.payload())
+        .count();
+    if gyro_differing > 0 {
+        differences.push(format!(
+            "{gyro_differing} of the first {} gyro records differ",
+            proxy.gyro_len().min(full.gyro_len())
+        ));
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        FrameType, GpsRecord,
+        tests::build_insv,
+        units::{Degrees, Meters, MetersPerSecond},
+    };
+
+    #[test]
+    fn test_siblings() {
+        assert!(is_proxy(Path::new("LRV_20250718_073922_11_001.lrv")));
+        assert!(is_proxy(Path::new(
+            "/videos/LRV_20250718_073922_01_001.insv"
+        )));
+        assert!(!is_proxy(Path::new("VID_20250718_073922_00_001.insv")));
+        assert_eq!(
+            sibling_names("LRV_20250718_073922_11_001.lrv"),
+            [
+                "VID_20250718_073922_00_001.insv",
+                "VID_20250718_073922_00_001.mp4"
+            ]
+        );
+        assert!(sibling_names("proxy.lrv").is_empty());
+
+        let dir = std::env::temp_dir().join(format!("ginsta-proxy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let proxy = dir.join("LRV_20250718_073922_11_001.lrv");
+        assert_eq!(full_resolution_sibling(&proxy), None);
+        std::fs::write(dir.join("VID_20250718_073922_00_001.insv"), b"").unwrap();
+        assert_eq!(
+            full_resolution_sibling(&proxy),
+            Some(dir.join("VID_20250718_073922_00_001.insv"))
+        );
+
+        let front = dir.join("VID_20250718_073922_00_001.insv");
+        let back = dir.join("VID_20250718_073922_10_001.insv");
+        assert_eq!(lens_partner(&front), None);
+        std::fs::write(&back, b"").unwrap();
+        assert_eq!(lens_partner(&front), Some(back.clone()));
+        assert_eq!(lens_partner(&back), Some(front));
+        assert_eq!(lens_partner(&proxy), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_telemetry_differences() {
+        let record = |timestamp| GpsRecord {
+            timestamp,
+            latitude: Degrees(49.0),
+            longitude: Degrees(4.0),
+            speed: MetersPerSecond(0.0),
+            track: Degrees(0.0),
+            altitude: Meters(0.0),
+            fix: None,
+        };
+        let full = Telemetry {
+            gps: vec![record(100), record(101), record(102)],
+            ..Default::default()
+        };
+        let proxy = Telemetry {
+            gps: vec![record(100), record(101)],
+            ..Default::default()
+        };
+        let compared = |telemetry| Compared {
+            telemetry,
+            gyro_frames: Vec::new(),
+        };
+        let (full, proxy) = (compared(&full), compared(&proxy));
+        assert_eq!(telemetry_differences(&full, &full), Vec::<String>::new());
+        assert_eq!(
+            telemetry_differences(&proxy, &full),
+            [
+                "2 GPS records against 3",
+                "GPS from 100 to 101 against 100 to 102"
+            ]
+        );
+        assert!(more_complete(&full, &proxy));
+        assert!(!more_complete(&full, &full));
+        assert_eq!(choose_lens(None, &proxy), LensChoice::OnlyPartner);
+        assert_eq!(
+            choose_lens(Some(&proxy), &full),
+            LensChoice::MoreCompletePartner
+        );
+        assert_eq!(choose_lens(Some(&full), &proxy), LensChoice::Own);
+        // A tie keeps the file asked for.
+        assert_eq!(choose_lens(Some(&full), &full), LensChoice::Own);
+
+        // Gyro records are compared across frame boundaries, in place in each file.
+        let gyro = |payloads: &[u8]| -> Vec<u8> {
+            (payloads.iter())
+                .flat_map(|&payload| [&5u64.to_le_bytes()[..], &[payload; 12]].concat())
+                .collect()
+        };
+        let front = build_insv(
+            b"video",
+            &[
+                (FrameType::Gyro, 1, gyro(&[1])),
+                (FrameType::Gyro, 1, gyro(&[2])),
+            ],
+        );
+        let back = build_insv(b"video", &[(FrameType::Gyro, 1, gyro(&[1, 3, 4]))]);
+        let telemetry = Telemetry::default();
+        let (front, back) = (
+            Compared::new(&front, &telemetry),
+            Compared::new(&back, &telemetry),
+        );
+        assert_eq!(
+            telemetry_differences(&front, &back),
+            [
+                "2 gyro records against 3",
+                "1 of the first 2 gyro records differ"
+            ]
+        );
+        assert!(more_complete(&back, &front));
+    }
+}
@@ -0,0 +1,300 @@
+//! A small expression language over `TrackStats`, such as
+//! `max_speed_kmh > 60 && duration > 10min`, for picking clips out of an archive.
+//!
+//! Comparisons of a statistic against a number are combined with `&&`, `||`, `!` and
+//! parentheses. Numbers may carry a unit, converted to the statistic's own: `s`, `min` or `h` for
+//! durations in seconds, `m` or `km` for distances in metres. A comparison with a statistic the
+//! track doesn't have, such as the pace of a clip that never moved, is false.
+
+use std::{fmt, str::FromStr};
+
+use crate::stats::TrackStats;
+
+/// Reads one statistic, or `None` where the track doesn't have it.
+type Getter = fn(&TrackStats) -> Option<f64>;
+
+/// The statistics an expression can refer to.
+pub const VARIABLES: &[(&str, Getter)] = &[
+    ("duration", |s| Some(s.duration.0)),
+    ("distance", |s| Some(s.distance.0)),
+    ("distance_km", |s| Some(s.distance.0 / 1000.0)),
+    ("average_speed", |s| Some(s.average_speed.0)),
+    ("average_speed_kmh", |s| Some(s.average_speed.0 * 3.6)),
+    ("max_speed", |s| Some(s.max_speed.0)),
+    ("max_speed_kmh", |s| Some(s.max_speed.0 * 3.6)),
+    ("ascent", |s| Some(s.ascent.0)),
+    ("descent", |s| Some(s.descent.0)),
+    ("pace_min_per_km", |s| s.pace_min_per_km),
+    ("pace_min_per_mi", |s| s.pace_min_per_mi),
+    ("grade_percent", |s| s.grade_percent),
+    ("max_grade_percent", |s| s.max_grade_percent),
+    ("grade_adjusted_pace_min_per_km", |s| {
+        s.grade_adjusted_pace_min_per_km
+    }),
+];
+
+const UNITS: &[(&str, f64)] = &[
+    ("s", 1.0),
+    ("min", 60.0),
+    ("h", 3600.0),
+    ("m", 1.0),
+    ("km", 1000.0),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Compare {
+        /// Index into `VARIABLES`.
+        variable: usize,
+        comparison: Comparison,
+        value: f64,
+    },
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn matches(&self, stats: &TrackStats) -> bool {
+        match self {
+            Expr::Compare {
+                variable,
+                comparison,
+                value,
+            } => (VARIABLES[*variable].1)(stats).is_some_and(|actual| match comparison {
+                Comparison::Less => actual < *value,
+                Comparison::LessOrEqual => actual <= *value,
+                Comparison::Greater => actual > *value,
+                Comparison::GreaterOrEqual => actual >= *value,
+                Comparison::Equal => actual == *value,
+                Comparison::NotEqual => actual != *value,
+            }),
+            Expr::Not(inner) => !inner.matches(stats),
+            Expr::And(a, b) => a.matches(stats) && b.matches(stats),
+            Expr::Or(a, b) => a.matches(stats) || b.matches(stats),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(f64),
+    Comparison(Comparison),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QueryError(String);
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let word_end = |rest: &str, accept: fn(char) -> bool| {
+            rest.find(|c: char| !accept(c)).unwrap_or(rest.len())
+        };
+        let (token, len) = if c.is_ascii_alphabetic() || c == '_' {
+            let len = word_end(rest, |c| c.is_ascii_alphanumeric() || c == '_');
+            (Token::Identifier(rest[..len].to_string()), len)
+        } else if c.is_ascii_digit() || c == '.' {
+            let number_len = word_end(rest, |c| c.is_ascii_digit() || c == '.');
+            let unit_len = word_end(&rest[number_len..], |c| c.is_ascii_alphabetic());
+            let (number, unit) = rest[..number_len + unit_len].split_at(number_len);
+            let number: f64 = number
+                .parse()
+                .map_err(|_| QueryError(format!("invalid number {number:?}")))?;
+            let scale = match unit {
+                "" => 1.0,
+                unit => {
+                    UNITS
+                        .iter()
+                        .find(|(name, _)| *name == unit)
+                        .ok_or_else(|| QueryError(format!("unknown unit {unit:?}")))?
+                        .1
+                }
+            };
+            (Token::Number(number * scale), number_len + unit_len)
+        } else {
+            [
+                ("&&", Token::And),
+                ("||", Token::Or),
+                ("<=", Token::Comparison(Comparison::LessOrEqual)),
+                (">=", Token::Comparison(Comparison::GreaterOrEqual)),
+                ("==", Token::Comparison(Comparison::Equal)),
+                ("!=", Token::Comparison(Comparison::NotEqual)),
+                ("<", Token::Comparison(Comparison::Less)),
+                (">", Token::Comparison(Comparison::Greater)),
+                ("!", Token::Not),
+                ("(", Token::Open),
+                (")", Token::Close),
+            ]
+            .into_iter()
+            .find(|(symbol, _)| rest.starts_with(symbol))
+            .map(|(symbol, token)| (token, symbol.len()))
+            .ok_or_else(|| QueryError(format!("unexpected {c:?}")))?
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, with `!` binding tightest and `||` loosest.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.tokens.get(self.position) == Some(token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                if !self.eat(&Token::Close) {
+                    return Err(QueryError("missing `)`".to_string()));
+                }
+                Ok(expr)
+            }
+            Some(Token::Identifier(name)) => {
+                let variable = VARIABLES
+                    .iter()
+                    .position(|(variable, _)| *variable == name)
+                    .ok_or_else(|| QueryError(format!("unknown statistic {name:?}")))?;
+                let Some(Token::Comparison(comparison)) = self.next() else {
+                    return Err(QueryError(format!("expected a comparison after {name}")));
+                };
+                let Some(Token::Number(value)) = self.next() else {
+                    return Err(QueryError(format!(
+                        "expected a number to compare {name} with"
+                    )));
+                };
+                Ok(Expr::Compare {
+                    variable,
+                    comparison,
+                    value,
+                })
+            }
+            _ => Err(QueryError("expected a comparison, `!` or `(`".to_string())),
+        }
+    }
+}
+
+/// A parsed expression, matched against each clip's statistics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query(Expr);
+
+impl Query {
+    pub fn matches(&self, stats: &TrackStats) -> bool {
+        self.0.matches(stats)
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+        };
+        let expr = parser.or()?;
+        if parser.position < parser.tokens.len() {
+            return Err(QueryError(format!(
+                "unexpected {:?}",
+                parser.tokens[parser.position]
+            )));
+        }
+        Ok(Query(expr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Meters, MetersPerSecond, Seconds};
+
+    #[test]
+    fn test_query() {
+        let stats = TrackStats {
+            duration: Seconds(900.0),
+            distance: Meters(12_000.0),
+            max_speed: MetersPerSecond(20.0),
+            ..Default::default()
+        };
+        let matches = |query: &str| query.parse::<Query>().unwrap().matches(&stats);
+
+        assert!(matches("max_speed_kmh > 60 && duration > 10min"));
+        assert!(!matches("max_speed_kmh > 80 && duration > 10min"));
+        assert!(matches("max_speed_kmh > 80 || distance >= 12km"));
+        assert!(matches("!(duration < 0.25h)"));
+        assert!(matches(
+            "max_speed_kmh > 80 || distance > 1km && duration <= 900s"
+        ));
+        assert!(!matches(
+            "(max_speed_kmh > 80 || distance > 1km) && duration < 900"
+        ));
+        // The track has no pace, so neither comparison holds.
+        assert!(!matches("pace_min_per_km < 5") && !matches("pace_min_per_km >= 5"));
+
+        assert!("speed > 1".parse::<Query>().is_err());
+        assert!("duration > 10 parsecs".parse::<Query>().is_err());
+        assert!("duration > 10lightyears".parse::<Query>().is_err());
+        assert!("(duration > 1".parse::<Query>().is_err());
+        assert!("duration >".parse::<Query>().is_err());
+        assert!("duration > 1 &&".parse::<Query>().is_err());
+    }
+}
@@ -0,0 +1,229 @@
+//! Reframing keyframes, the view direction and field of view chosen for a 360° video over time,
+//! from the two places they are kept: the Anchors frame the camera writes, and the project file
+//! Insta360 Studio saves next to a video once it has been reframed there.
+//!
+//! Neither layout is documented. Project files are read as XML, taking each element whose name
+//! ends in `keyframe` with a `time` attribute in milliseconds and `yaw` and `pitch` attributes
+//! in degrees (`pan` and `tilt` are accepted too), and optional `roll` and `fov`. The Anchors
+//! frame is read through a record layout with fields `timestamp`, `yaw` and `pitch`, and
+//! optionally `roll` and `fov`, since it has no built-in one.
+
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::layout::Layout;
+
+/// Extension of Insta360 Studio project files.
+pub const PROJECT_EXTENSION: &str = "insprj";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "lowercase"))]
+pub enum KeyframeSource {
+    Anchors,
+    Project,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Keyframe {
+    /// Seconds from the first video frame.
+    pub time: f64,
+    pub source: KeyframeSource,
+    /// In degrees.
+    pub yaw: f64,
+    pub pitch: f64,
+    pub roll: Option<f64>,
+    /// Field of view in degrees.
+    pub fov: Option<f64>,
+}
+
+/// Project files next to `video` that belong to it: its name with `.insprj` added or in place
+/// of its extension.
+pub fn companion_projects(video: &Path) -> Vec<PathBuf> {
+    let mut appended = video.as_os_str().to_owned();
+    appended.push(".");
+    appended.push(PROJECT_EXTENSION);
+    let mut candidates = vec![
+        PathBuf::from(appended),
+        video.with_extension(PROJECT_EXTENSION),
+    ];
+    candidates.dedup();
+    candidates.retain(|path| path.is_file());
+    candidates
+}
+
+/// The attributes of the start tag at the beginning of `tag`, which follows its `<`.
+fn attributes(tag: &str) -> Vec<(&str, &str)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start_matches(|c: char| !c.is_whitespace() && c != '>' && c != '/');
+    while let Some((name, value)) = rest.split_once('=') {
+        let name = name.trim();
+        let value = value.trim_start();
+        let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            break;
+        };
+        let Some((value, after)) = value[1..].split_once(quote) else {
+            break;
+        };
+        attributes.push((name, value));
+        if after.trim_start().starts_with(['>', '/']) {
+            break;
+        }
+        rest = after;
+    }
+    attributes
+}
+
+/// The keyframes of a project file, in the order they appear.
+pub fn parse_project(text: &str) -> Vec<Keyframe> {
+    let mut keyframes = Vec::new();
+    for tag in text.split('<').skip(1) {
+        let name = tag.split(|c: char| c.is_whitespace() || c == '>' || c == '/');
+        let is_keyframe = (name.into_iter().next())
+            .is_some_and(|name| name.to_ascii_lowercase().ends_with("keyframe"));
+        if !is_keyframe {
+            continue;
+        }
+        let attributes = attributes(tag);
+        let value = |names: &[&str]| {
+            (attributes.iter())
+                .find(|(name, _)| names.iter().any(|n| name.eq_ignore_ascii_case(n)))
+                .and_then(|(_, value)| value.trim().parse::<f64>().ok())
+        };
+        let (Some(time), Some(yaw), Some(pitch)) = (
+            value(&["time"]),
+            value(&["yaw", "pan"]),
+            value(&["pitch", "tilt"]),
+        ) else {
+            continue;
+        };
+        keyframes.push(Keyframe {
+            time: time / 1000.0,
+            source: KeyframeSource::Project,
+            yaw,
+            pitch,
+            roll: value(&["roll"]),
+            fov: value(&["fov"]),
+        });
+    }
+    keyframes
+}
+
+/// The keyframes in an Anchors frame read through `layout`, whose timestamps count
+/// `ticks_per_second`, 1000 when it doesn't say, on the camera clock. `first_frame_time` is
+/// that clock's reading in seconds at the first video frame. `None` if the layout lacks a
+/// required field.
+pub fn anchor_keyframes(
+    frame: &[u8],
+    layout: &'static Layout,
+    first_frame_time: f64,
+) -> Option<Vec<Keyframe>> {
+    for field in ["timestamp", "yaw", "pitch"] {
+        layout.span(field)?;
+    }
+    let has = |name| layout.span(name).is_some();
+    let ticks_per_second = layout.ticks_per_second.unwrap_or(1000.0);
+    let (records, _) = layout.records_in(frame.len());
+    let keyframes = (0..records)
+        .filter_map(|i| layout.view(&frame[i * layout.size()..]))
+        .map(|record| Keyframe {
+            time: record.value("timestamp").as_f64() / ticks_per_second - first_frame_time,
+            source: KeyframeSource::Anchors,
+            yaw: record.value("yaw").as_f64(),
+            pitch: record.value("pitch").as_f64(),
+            roll: has("roll").then(|| record.value("roll").as_f64()),
+            fov: has("fov").then(|| record.value("fov").as_f64()),
+        })
+        .collect();
+    Some(keyframes)
+}
+
+/// Merges keyframes from both sources into one timeline, ordered by time with the camera's
+/// keyframes first at equal times.
+pub fn timeline(anchors: Vec<Keyframe>, project: Vec<Keyframe>) -> Vec<Keyframe> {
+    let mut keyframes = anchors;
+    keyframes.extend(project);
+    // Stable, so each source keeps its own order at equal times.
+    keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+    keyframes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{Field, FieldKind};
+
+    #[test]
+    fn test_parse_project() {
+        let project = r#"<?xml version="1.0"?>
+            <project><clip file="VID_1.insv"><keyframes>
+              <keyframe time="1500" yaw="90" pitch='-10.5' roll="0" fov="100"/>
+              <KeyFrame time="500" pan="45" tilt="0"></KeyFrame>
+              <keyframe time="oops" yaw="1" pitch="2"/>
+            </keyframes></clip></project>"#;
+        let keyframes = parse_project(project);
+        assert_eq!(keyframes.len(), 2);
+        assert_eq!(
+            keyframes[0],
+            Keyframe {
+                time: 1.5,
+                source: KeyframeSource::Project,
+                yaw: 90.0,
+                pitch: -10.5,
+                roll: Some(0.0),
+                fov: Some(100.0),
+            }
+        );
+        assert_eq!((keyframes[1].time, keyframes[1].yaw), (0.5, 45.0));
+        assert_eq!(keyframes[1].fov, None);
+
+        // A project cut short, or with an unterminated value, keeps the keyframes before it.
+        for (len, expected) in [(project.len() / 2, 1), (0, 0)] {
+            assert_eq!(parse_project(&project[..len]).len(), expected);
+        }
+        assert!(parse_project(r#"<keyframe time="1" yaw="2 pitch="3"/>"#).is_empty());
+        assert!(parse_project("<keyframe").is_empty());
+    }
+
+    #[test]
+    fn test_anchor_keyframes() {
+        static ANCHORS: Layout = Layout {
+            fields: &[
+                Field::new("timestamp", FieldKind::U64),
+                Field::new("yaw", FieldKind::F32),
+                Field::new("pitch", FieldKind::F32),
+            ],
+            ticks_per_second: None,
+        };
+        let frame: Vec<u8> = [(2000u64, 10.0f32, 5.0f32), (3000, 20.0, 0.0)]
+            .iter()
+            .flat_map(|(t, yaw, pitch)| {
+                [
+                    &t.to_le_bytes()[..],
+                    &yaw.to_le_bytes(),
+                    &pitch.to_le_bytes(),
+                ]
+                .concat()
+            })
+            .collect();
+        let anchors = anchor_keyframes(&frame, &ANCHORS, 1.0).unwrap();
+        assert_eq!((anchors[0].time, anchors[0].yaw), (1.0, 10.0));
+        assert_eq!((anchors[1].roll, anchors[1].fov), (None, None));
+        assert_eq!(anchor_keyframes(&frame, &crate::GPS_RECORD, 0.0), None);
+
+        let project = parse_project(r#"<keyframe time="1000" yaw="0" pitch="0"/>"#);
+        let sources: Vec<KeyframeSource> = (timeline(anchors, project).iter())
+            .map(|keyframe| keyframe.source)
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                KeyframeSource::Anchors,
+                KeyframeSource::Project,
+                KeyframeSource::Anchors
+            ]
+        );
+    }
+}
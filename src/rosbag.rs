@@ -0,0 +1,288 @@
+//! ROS 2 bags in the `sqlite3` storage format, for robotics setups carrying the camera as a
+//! payload sensor.
+//!
+//! GPS records become `sensor_msgs/msg/NavSatFix` messages on `/gps/fix` and IMU records
+//! `sensor_msgs/msg/Imu` messages on `/imu`, CDR encoded, in a bag directory holding
+//! `metadata.yaml` and one database.
+
+use std::{io, path::Path};
+
+use crate::{FixStatus, GpsRecord, GyroRecord, imu::ImuScale};
+
+/// Standard gravity, for accelerations in m/s² rather than g.
+const STANDARD_GRAVITY: f64 = 9.80665;
+
+/// Little endian CDR, the ROS 2 wire format: primitives aligned to their size, counting from
+/// after the four byte encapsulation header.
+struct Cdr(Vec<u8>);
+
+impl Cdr {
+    fn new() -> Self {
+        Cdr(vec![0, 1, 0, 0])
+    }
+
+    fn align(&mut self, size: usize) {
+        let padding = (size - (self.0.len() - 4) % size) % size;
+        self.0.resize(self.0.len() + padding, 0);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.align(2);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64s(&mut self, values: &[f64]) {
+        self.align(8);
+        values
+            .iter()
+            .for_each(|value| self.0.extend_from_slice(&value.to_le_bytes()));
+    }
+
+    /// Length including the terminating NUL, then the bytes and the NUL.
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32 + 1);
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(0);
+    }
+
+    /// `std_msgs/Header` with a stamp in nanoseconds.
+    fn header(&mut self, stamp: u64, frame_id: &str) {
+        self.u32((stamp / 1_000_000_000) as u32);
+        self.u32((stamp % 1_000_000_000) as u32);
+        self.string(frame_id);
+    }
+}
+
+/// A covariance matrix flagged as unknown by a first element of -1, as `sensor_msgs/Imu` asks
+/// for a quantity that isn't measured.
+const NOT_MEASURED: [f64; 9] = [-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+
+pub fn nav_sat_fix(record: &GpsRecord) -> Vec<u8> {
+    let mut cdr = Cdr::new();
+    cdr.header(record.timestamp * 1_000_000_000, "gps");
+    // NavSatStatus: STATUS_FIX, or STATUS_NO_FIX for records written without one, and
+    // SERVICE_GPS.
+    let status: i8 = if record.fix == Some(FixStatus::NoFix) {
+        -1
+    } else {
+        0
+    };
+    cdr.u8(status as u8);
+    cdr.u16(1);
+    cdr.f64s(&[record.latitude.0, record.longitude.0, record.altitude.0]);
+    cdr.f64s(&[0.0; 9]);
+    // COVARIANCE_TYPE_UNKNOWN.
+    cdr.u8(0);
+    cdr.0
+}
+
+/// An `Imu` message without orientation, with accelerations converted to m/s².
+pub fn imu(record: &GyroRecord, scale: &ImuScale, stamp: u64) -> Vec<u8> {
+    let mut cdr = Cdr::new();
+    cdr.header(stamp, "imu");
+    // Orientation as an identity quaternion, marked as not measured.
+    cdr.f64s(&[0.0, 0.0, 0.0, 1.0]);
+    cdr.f64s(&NOT_MEASURED);
+    cdr.f64s(&scale.gyro_rad_s(record));
+    cdr.f64s(&[0.0; 9]);
+    cdr.f64s(&scale.accel_g(record).map(|g| g * STANDARD_GRAVITY));
+    cdr.f64s(&[0.0; 9]);
+    cdr.0
+}
+
+struct Topic {
+    name: &'static str,
+    message_type: &'static str,
+    /// Receive time in nanoseconds and CDR data.
+    messages: Vec<(u64, Vec<u8>)>,
+}
+
+/// Writes a bag of the GPS and IMU records into the new directory `path`, named after it. IMU
+/// records, which use the camera clock, are placed with `camera_epoch`, the Unix time at which
+/// that clock read zero, and left out without it.
+pub fn write_rosbag(
+    path: &Path,
+    gps: &[GpsRecord],
+    gyro: &[GyroRecord],
+    scale: &ImuScale,
+    camera_epoch: Option<f64>,
+) -> io::Result<()> {
+    let mut topics = vec![Topic {
+        name: "/gps/fix",
+        message_type: "sensor_msgs/msg/NavSatFix",
+        messages: (gps.iter())
+            .map(|record| (record.timestamp * 1_000_000_000, nav_sat_fix(record)))
+            .collect(),
+    }];
+    if let Some(epoch) = camera_epoch {
+        topics.push(Topic {
+            name: "/imu",
+            message_type: "sensor_msgs/msg/Imu",
+            messages: (gyro.iter())
+                .map(|record| {
+                    let stamp = (epoch * 1e9 + record.timestamp as f64 * 1e6).max(0.0) as u64;
+                    (stamp, imu(record, scale, stamp))
+                })
+                .collect(),
+        });
+    }
+
+    let name = path.file_name().unwrap_or("bag".as_ref()).to_string_lossy();
+    let database = format!("{name}_0.db3");
+    std::fs::create_dir(path)?;
+    write_database(&path.join(&database), &topics).map_err(io::Error::other)?;
+    std::fs::write(path.join("metadata.yaml"), metadata(&topics, &database))
+}
+
+fn write_database(path: &Path, topics: &[Topic]) -> rusqlite::Result<()> {
+    let mut connection = rusqlite::Connection::open(path)?;
+    connection.execute_batch(
+        "CREATE TABLE topics (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            type TEXT NOT NULL,
+            serialization_format TEXT NOT NULL,
+            offered_qos_profiles TEXT NOT NULL
+        );
+        CREATE TABLE messages (
+            id INTEGER PRIMARY KEY,
+            topic_id INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            data BLOB NOT NULL
+        );
+        CREATE INDEX timestamp_idx ON messages (timestamp ASC);",
+    )?;
+    let transaction = connection.transaction()?;
+    {
+        let mut insert_topic =
+            transaction.prepare("INSERT INTO topics VALUES (?1, ?2, ?3, 'cdr', '')")?;
+        let mut insert_message = transaction
+            .prepare("INSERT INTO messages (topic_id, timestamp, data) VALUES (?1, ?2, ?3)")?;
+        for (id, topic) in (1..).zip(topics) {
+            insert_topic.execute(rusqlite::params![id, topic.name, topic.message_type])?;
+            for (timestamp, data) in &topic.messages {
+                insert_message.execute(rusqlite::params![id, *timestamp as i64, data])?;
+            }
+        }
+    }
+    transaction.commit()
+}
+
+/// `metadata.yaml` in version 5 of the format, which ROS 2 Foxy onwards reads.
+fn metadata(topics: &[Topic], database: &str) -> String {
+    let times = || topics.iter().flat_map(|t| t.messages.iter().map(|m| m.0));
+    let start = times().min().unwrap_or(0);
+    let duration = times().max().unwrap_or(0) - start;
+    let count: usize = topics.iter().map(|topic| topic.messages.len()).sum();
+    let mut yaml = format!(
+        "rosbag2_bagfile_information:
+  version: 5
+  storage_identifier: sqlite3
+  duration:
+    nanoseconds: {duration}
+  starting_time:
+    nanoseconds_since_epoch: {start}
+  message_count: {count}
+  topics_with_message_count:
+"
+    );
+    for topic in topics {
+        yaml += &format!(
+            "    - topic_metadata:
+        name: {}
+        type: {}
+        serialization_format: cdr
+        offered_qos_profiles: \"\"
+      message_count: {}
+",
+            topic.name,
+            topic.message_type,
+            topic.messages.len()
+        );
+    }
+    yaml += &format!(
+        "  compression_format: \"\"
+  compression_mode: \"\"
+  relative_file_paths:
+    - {database}
+  files:
+    - path: {database}
+      starting_time:
+        nanoseconds_since_epoch: {start}
+      duration:
+        nanoseconds: {duration}
+      message_count: {count}
+"
+    );
+    yaml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_write_rosbag() {
+        let gps = [GpsRecord {
+            timestamp: 100,
+            latitude: Degrees(49.0),
+            longitude: Degrees(4.0),
+            speed: MetersPerSecond(2.0),
+            track: Degrees(90.0),
+            altitude: Meters(80.0),
+            fix: None,
+        }];
+        let fix = nav_sat_fix(&gps[0]);
+        // Encapsulation, stamp, "gps\0", status and service, three padding bytes, then the
+        // position at offset 24 from the start of the data.
+        assert_eq!(&fix[..12], &[0, 1, 0, 0, 100, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&fix[16..20], b"gps\0");
+        assert_eq!(&fix[28..36], &49.0f64.to_le_bytes());
+        assert_eq!(fix.len(), 4 + 24 + 24 + 72 + 1);
+        let no_fix = GpsRecord {
+            fix: Some(FixStatus::NoFix),
+            ..gps[0]
+        };
+        assert_eq!((fix[20], nav_sat_fix(&no_fix)[20]), (0, 0xff));
+
+        let gyro = [GyroRecord::from_raw(500, [0, 0, 2048, 0, 0, 0])];
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        let imu = imu(&gyro[0], &scale, 0);
+        // Header of 16, orientation and its covariance, angular velocity and its covariance,
+        // then the z acceleration, 1 g.
+        let accel_z = 4 + 16 + 32 + 72 + 24 + 72 + 16;
+        assert_eq!(&imu[accel_z..accel_z + 8], &STANDARD_GRAVITY.to_le_bytes());
+
+        let path = std::env::temp_dir().join(format!("ginsta-bag-{}", std::process::id()));
+        write_rosbag(&path, &gps, &gyro, &scale, Some(99.0)).unwrap();
+        let connection = rusqlite::Connection::open(path.join(format!(
+            "{}_0.db3",
+            path.file_name().unwrap().to_string_lossy()
+        )))
+        .unwrap();
+        let (count, first): (i64, i64) = connection
+            .query_row("SELECT COUNT(*), MIN(timestamp) FROM messages", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((count, first), (2, 99_500_000_000));
+        let metadata = std::fs::read_to_string(path.join("metadata.yaml")).unwrap();
+        assert!(metadata.contains("duration:\n    nanoseconds: 500000000\n"));
+        assert!(metadata.contains("type: sensor_msgs/msg/Imu\n"));
+        assert!(write_rosbag(&path, &gps, &gyro, &scale, None).is_err());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+//! JSON Schemas for the rows of each CSV export, so pipelines can validate what they ingest.
+
+use std::collections::BTreeMap;
+
+use schemars::{Schema, schema_for};
+
+use crate::{
+    GpsRecord, align::FrameTelemetry, exposure::ExposureSecond, heading::HeadingComparison,
+    highlights::Highlight, magnetometer::MagSample, overlay::OverlayBundle, stationary::Interval,
+    stats::TrackStats,
+};
+
+/// Schemas keyed by the export they describe: `gps` for the default CSV, `frame` for
+/// `--per-frame`, `exposure`, `heading`, `magnetometer` and `stationary` for those formats,
+/// `highlight` for `highlights` and `stats` for `stats`, whose rows also start with a `file`
+/// column. `overlay` describes the whole JSON document `--format overlay` writes.
+pub fn export_schemas() -> BTreeMap<&'static str, Schema> {
+    BTreeMap::from([
+        ("gps", schema_for!(GpsRecord)),
+        ("frame", schema_for!(FrameTelemetry)),
+        ("exposure", schema_for!(ExposureSecond)),
+        ("heading", schema_for!(HeadingComparison)),
+        ("highlight", schema_for!(Highlight)),
+        ("magnetometer", schema_for!(MagSample)),
+        ("overlay", schema_for!(OverlayBundle)),
+        ("stationary", schema_for!(Interval)),
+        ("stats", schema_for!(TrackStats)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gps_schema() {
+        let schemas = export_schemas();
+        let gps = serde_json::to_value(&schemas["gps"]).unwrap();
+        let properties = gps["properties"].as_object().unwrap();
+        let columns: Vec<_> = properties.keys().map(String::as_str).collect();
+        assert_eq!(
+            columns,
+            [
+                "altitude",
+                "latitude",
+                "longitude",
+                "speed",
+                "timestamp",
+                "track"
+            ]
+        );
+        assert_eq!(gps["required"].as_array().unwrap().len(), properties.len());
+        assert_eq!(
+            gps["$defs"]["MetersPerSecond"]["description"],
+            "A speed in metres per second."
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use std::str::FromStr;
+
+use crate::GpsRecord;
+
+/// Splits records into runs where consecutive timestamps are at most `max_gap` seconds apart.
+pub fn split_at_gaps(records: &[GpsRecord], max_gap: u64) -> Vec<&[GpsRecord]> {
+    records
+        .chunk_by(|a, b| a.timestamp.abs_diff(b.timestamp) <= max_gap)
+        .collect()
+}
+
+/// Puts records in time order and drops all but the first of any with the same timestamp, so
+/// timestamps strictly increase.
+pub fn sort_by_time(records: &mut Vec<GpsRecord>) {
+    records.sort_by_key(|record| record.timestamp);
+    records.dedup_by_key(|record| record.timestamp);
+}
+
+/// Puts records in time order like `sort_by_time`, but keeps the ones it would drop and marks
+/// them invalid in `valid`, which holds a flag for each record and is reordered along with them.
+/// Only records still valid count as the first at their timestamp.
+pub fn sort_marking_repeats(records: &mut Vec<GpsRecord>, valid: &mut Vec<bool>) {
+    let mut pairs: Vec<(GpsRecord, bool)> = records.drain(..).zip(valid.drain(..)).collect();
+    pairs.sort_by_key(|(record, _)| record.timestamp);
+    let mut last_valid = None;
+    for (record, mut is_valid) in pairs {
+        if is_valid && last_valid == Some(record.timestamp) {
+            is_valid = false;
+        } else if is_valid {
+            last_valid = Some(record.timestamp);
+        }
+        records.push(record);
+        valid.push(is_valid);
+    }
+}
+
+/// How a recording session is divided into activities, each of which goes to its own file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActivitySplit {
+    /// A new activity after GPS records more than this many seconds apart.
+    Gap(u64),
+    /// One activity per UTC day.
+    Day,
+    /// One activity per lap of a circuit. Laps need the start/finish line, so `laps::split_laps`
+    /// splits them and `split_activities` leaves the records whole.
+    Lap,
+}
+
+/// Parses `day`, `lap`, or `gap:` followed by a duration in seconds, optionally suffixed with
+/// `s`, `m` or `h`.
+impl FromStr for ActivitySplit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => return Ok(ActivitySplit::Day),
+            "lap" => return Ok(ActivitySplit::Lap),
+            _ => {}
+        }
+        let duration = s
+            .strip_prefix("gap:")
+            .ok_or_else(|| format!("expected `day`, `lap` or `gap:<duration>`, got {s:?}"))?;
+        let (number, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => duration.split_at(i),
+            None => (duration, "s"),
+        };
+        let unit = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            _ => return Err(format!("unknown duration unit {unit:?}")),
+        };
+        let number: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration {duration:?}"))?;
+        Ok(ActivitySplit::Gap(number * unit))
+    }
+}
+
+/// Splits time-ordered records into activities.
+pub fn split_activities(records: &[GpsRecord], split: ActivitySplit) -> Vec<&[GpsRecord]> {
+    match split {
+        ActivitySplit::Gap(max_gap) => split_at_gaps(records, max_gap),
+        ActivitySplit::Day => records
+            .chunk_by(|a, b| a.timestamp / 86_400 == b.timestamp / 86_400)
+            .collect(),
+        ActivitySplit::Lap => records.chunk_by(|_, _| true).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_split_at_gaps() {
+        let records: Vec<GpsRecord> = [10, 11, 12, 30, 31, 100]
+            .into_iter()
+            .map(|timestamp| GpsRecord {
+                timestamp,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(0.0),
+                track: Degrees(0.0),
+                altitude: Meters(0.0),
+                fix: None,
+            })
+            .collect();
+
+        let lengths: Vec<usize> = split_at_gaps(&records, 5).iter().map(|s| s.len()).collect();
+        assert_eq!(lengths, vec![3, 2, 1]);
+        assert_eq!(split_at_gaps(&records, 100).len(), 1);
+        assert!(split_at_gaps(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn test_sort_by_time() {
+        let records_at = |records: &[(u64, f64)]| -> Vec<GpsRecord> {
+            (records.iter())
+                .map(|&(timestamp, latitude)| GpsRecord {
+                    timestamp,
+                    latitude: Degrees(latitude),
+                    longitude: Degrees(0.0),
+                    speed: MetersPerSecond(0.0),
+                    track: Degrees(0.0),
+                    altitude: Meters(0.0),
+                    fix: None,
+                })
+                .collect()
+        };
+        let mut records = records_at(&[(12, 1.0), (10, 2.0), (12, 3.0), (11, 4.0)]);
+        sort_by_time(&mut records);
+        let order: Vec<(u64, f64)> = records
+            .iter()
+            .map(|r| (r.timestamp, r.latitude.0))
+            .collect();
+        assert_eq!(order, vec![(10, 2.0), (11, 4.0), (12, 1.0)]);
+
+        let mut records = records_at(&[(12, 1.0), (10, 2.0), (12, 3.0), (11, 4.0), (11, 5.0)]);
+        let mut valid = vec![true, true, true, false, true];
+        sort_marking_repeats(&mut records, &mut valid);
+        let order: Vec<(u64, f64, bool)> = (records.iter().zip(valid))
+            .map(|(r, valid)| (r.timestamp, r.latitude.0, valid))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (10, 2.0, true),
+                (11, 4.0, false),
+                (11, 5.0, true),
+                (12, 1.0, true),
+                (12, 3.0, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_activities() {
+        assert_eq!("day".parse(), Ok(ActivitySplit::Day));
+        assert_eq!("lap".parse(), Ok(ActivitySplit::Lap));
+        assert_eq!("gap:90".parse(), Ok(ActivitySplit::Gap(90)));
+        assert_eq!("gap:30m".parse(), Ok(ActivitySplit::Gap(1800)));
+        assert_eq!("gap:2h".parse(), Ok(ActivitySplit::Gap(7200)));
+        assert!("gap:2d".parse::<ActivitySplit>().is_err());
+        assert!("gap:".parse::<ActivitySplit>().is_err());
+        assert!("week".parse::<ActivitySplit>().is_err());
+
+        // Late on one day, just after midnight, then an hour later.
+        let records: Vec<GpsRecord> = [86_390, 86_399, 86_400, 86_410, 90_010]
+            .into_iter()
+            .map(|timestamp| GpsRecord {
+                timestamp,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(0.0),
+                track: Degrees(0.0),
+                altitude: Meters(0.0),
+                fix: None,
+            })
+            .collect();
+        let lengths = |split| -> Vec<usize> {
+            split_activities(&records, split)
+                .iter()
+                .map(|s| s.len())
+                .collect()
+        };
+        assert_eq!(lengths(ActivitySplit::Day), vec![2, 3]);
+        assert_eq!(lengths(ActivitySplit::Gap(1800)), vec![4, 1]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Summaries of how each file in a corpus decodes, saved as a snapshot and compared on later
+//! runs, so that a parser change or a new firmware that decodes differently shows up as a list of
+//! the files and fields that changed.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Telemetry, anonymize::fnv1a, read_index};
+
+/// What decoding one file produced.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Summary {
+    /// Each index entry, as `Gps v1 159 bytes`.
+    pub frames: Vec<String>,
+    pub gps_records: usize,
+    pub gyro_records: usize,
+    pub exposure_records: usize,
+    pub gps_start: Option<u64>,
+    pub gps_end: Option<u64>,
+    pub camera: Option<String>,
+    pub firmware: Option<String>,
+    pub diagnostics: Vec<String>,
+    /// A hash of every decoded record, which changes if any value does.
+    pub digest: String,
+}
+
+/// Summaries by file path relative to the corpus.
+pub type Snapshot = BTreeMap<String, Summary>;
+
+fn digest(telemetry: &Telemetry) -> String {
+    let mut bytes = Vec::new();
+    for record in &telemetry.gps {
+        bytes.extend_from_slice(&record.timestamp.to_le_bytes());
+        for value in [
+            record.latitude.0,
+            record.longitude.0,
+            record.speed.0,
+            record.track.0,
+            record.altitude.0,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    for record in &telemetry.gyro {
+        bytes.extend_from_slice(&record.timestamp.to_le_bytes());
+        bytes.extend_from_slice(&record.payload);
+    }
+    for record in &telemetry.exposure {
+        bytes.extend_from_slice(&record.timestamp.to_le_bytes());
+        bytes.extend_from_slice(&record.shutterspeed.0.to_le_bytes());
+    }
+    format!("{:016x}", fnv1a(&bytes))
+}
+
+/// Summarizes `telemetry`, decoded from the file `data`.
+pub fn summarize(data: &[u8], telemetry: &Telemetry) -> Summary {
+    let frames = (read_index(data).into_iter())
+        .flat_map(|(_, index)| index.frames)
+        .map(|frame| {
+            format!(
+                "{:?} v{} {} bytes",
+                frame.frame_type, frame.frame_version, frame.frame_size
+            )
+        })
+        .collect();
+    #[cfg(feature = "prost")]
+    let (camera, firmware) = match &telemetry.info {
+        Some(info) => (info.camera_type.clone(), info.fw_version.clone()),
+        None => (None, None),
+    };
+    #[cfg(not(feature = "prost"))]
+    let (camera, firmware) = (None, None);
+    Summary {
+        frames,
+        gps_records: telemetry.gps.len(),
+        gyro_records: telemetry.gyro.len(),
+        exposure_records: telemetry.exposure.len(),
+        gps_start: telemetry.gps.first().map(|record| record.timestamp),
+        gps_end: telemetry.gps.last().map(|record| record.timestamp),
+        camera,
+        firmware,
+        diagnostics: (telemetry.diagnostics.iter())
+            .map(|diagnostic| diagnostic.to_string())
+            .collect(),
+        digest: digest(telemetry),
+    }
+}
+
+impl Summary {
+    /// Each field's name and value as text, for comparing.
+    fn fields(&self) -> [(&'static str, String); 10] {
+        [
+            ("frames", self.frames.join(", ")),
+            ("gps_records", self.gps_records.to_string()),
+            ("gyro_records", self.gyro_records.to_string()),
+            ("exposure_records", self.exposure_records.to_string()),
+            ("gps_start", format!("{:?}", self.gps_start)),
+            ("gps_end", format!("{:?}", self.gps_end)),
+            ("camera", format!("{:?}", self.camera)),
+            ("firmware", format!("{:?}", self.firmware)),
+            ("diagnostics", self.diagnostics.join("; ")),
+            ("digest", self.digest.clone()),
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A file not in the snapshot.
+    Added(String),
+    /// A file in the snapshot that is no longer in the corpus.
+    Removed(String),
+    Changed {
+        file: String,
+        field: &'static str,
+        before: String,
+        after: String,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added(file) => write!(f, "{file}: new"),
+            Change::Removed(file) => write!(f, "{file}: missing"),
+            Change::Changed {
+                file,
+                field,
+                before,
+                after,
+            } => write!(f, "{file}: {field} was {before}, now {after}"),
+        }
+    }
+}
+
+/// The differences between a snapshot and the current summaries, by file.
+pub fn compare(before: &Snapshot, after: &Snapshot) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let files: std::collections::BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    for file in files {
+        match (before.get(file), after.get(file)) {
+            (None, Some(_)) => changes.push(Change::Added(file.clone())),
+            (Some(_), None) => changes.push(Change::Removed(file.clone())),
+            (Some(before), Some(after)) => {
+                for ((field, before), (_, after)) in before.fields().into_iter().zip(after.fields())
+                {
+                    if before != after {
+                        changes.push(Change::Changed {
+                            file: file.clone(),
+                            field,
+                            before,
+                            after,
+                        });
+                    }
+                }
+            }
+            (None, None) => unreachable!("the file comes from one of the snapshots"),
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        FrameType, GpsRecord,
+        tests::build_insv,
+        units::{Degrees, Meters, MetersPerSecond},
+    };
+
+    #[test]
+    fn test_compare() {
+        let data = build_insv(b"", &[(FrameType::Magnetic, 2, vec![0; 12])]);
+        let mut telemetry = Telemetry {
+            gps: vec![GpsRecord {
+                timestamp: 100,
+                latitude: Degrees(49.0),
+                longitude: Degrees(4.0),
+                speed: MetersPerSecond(0.0),
+                track: Degrees(0.0),
+                altitude: Meters(0.0),
+                fix: None,
+            }],
+            ..Default::default()
+        };
+        let summary = summarize(&data, &telemetry);
+        assert_eq!(summary.frames, ["Magnetic v2 12 bytes"]);
+        assert_eq!((summary.gps_start, summary.gps_end), (Some(100), Some(100)));
+
+        telemetry.gps[0].altitude = Meters(1.0);
+        let changed = summarize(&data, &telemetry);
+        assert_ne!(changed.digest, summary.digest);
+
+        let before = Snapshot::from([
+            ("a.insv".to_string(), summary.clone()),
+            ("b.insv".to_string(), summary.clone()),
+        ]);
+        let after = Snapshot::from([
+            ("a.insv".to_string(), changed),
+            ("c.insv".to_string(), summary),
+        ]);
+        let changes: Vec<String> = (compare(&before, &after).iter())
+            .map(|change| change.to_string())
+            .collect();
+        assert_eq!(changes.len(), 3);
+        assert!(changes[0].starts_with("a.insv: digest was "));
+        assert_eq!(changes[1..], ["b.insv: missing", "c.insv: new"]);
+        assert!(compare(&before, &before).is_empty());
+    }
+}
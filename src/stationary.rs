@@ -0,0 +1,172 @@
+//! Finds when the camera wasn't going anywhere, so parked time can be left out of averages.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{GpsRecord, GyroRecord, imu::ImuScale};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StationaryOptions {
+    /// Fastest GPS speed in m/s counted as standing still.
+    pub max_speed: f64,
+    /// Largest standard deviation of the acceleration magnitude within a second, in g, counted
+    /// as standing still. Walking with the camera or riding over rough ground exceeds it.
+    pub max_accel_std: f64,
+    /// Shortest stop worth reporting, in seconds.
+    pub min_duration: u64,
+}
+
+impl Default for StationaryOptions {
+    fn default() -> Self {
+        StationaryOptions {
+            max_speed: 0.5,
+            max_accel_std: 0.05,
+            min_duration: 10,
+        }
+    }
+}
+
+/// A stop, from the first to the last stationary GPS record.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Interval {
+    /// Unix time in whole seconds.
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Interval {
+    pub fn contains(&self, timestamp: u64) -> bool {
+        (self.start..=self.end).contains(&timestamp)
+    }
+}
+
+/// Standard deviation of the acceleration magnitude over the gyro records in the second starting
+/// at Unix time `timestamp`, or `None` with fewer than two records in it.
+fn accel_std(
+    gyro: &[GyroRecord],
+    timestamp: u64,
+    camera_epoch: f64,
+    scale: &ImuScale,
+) -> Option<f64> {
+    let start = (timestamp as f64 - camera_epoch) * 1000.0;
+    let first = gyro.partition_point(|record| (record.timestamp as f64) < start);
+    let last = gyro.partition_point(|record| (record.timestamp as f64) < start + 1000.0);
+    let magnitudes: Vec<f64> = gyro[first..last]
+        .iter()
+        .map(|record| {
+            scale
+                .accel_g(record)
+                .iter()
+                .map(|v| v * v)
+                .sum::<f64>()
+                .sqrt()
+        })
+        .collect();
+    if magnitudes.len() < 2 {
+        return None;
+    }
+    let n = magnitudes.len() as f64;
+    let mean = magnitudes.iter().sum::<f64>() / n;
+    Some((magnitudes.iter().map(|m| (m - mean).powi(2)).sum::<f64>() / n).sqrt())
+}
+
+/// Runs of GPS records below `options.max_speed` lasting at least `options.min_duration`. With
+/// `camera_epoch`, the Unix time at which the gyro's millisecond clock read zero, seconds whose
+/// accelerometer varies more than `options.max_accel_std` also end a run, which catches slow
+/// movement the GPS speed misses.
+pub fn stationary_intervals(
+    gps: &[GpsRecord],
+    gyro: &[GyroRecord],
+    camera_epoch: Option<f64>,
+    scale: &ImuScale,
+    options: &StationaryOptions,
+) -> Vec<Interval> {
+    let still = |record: &GpsRecord| {
+        record.speed.0 <= options.max_speed
+            && camera_epoch
+                .and_then(|epoch| accel_std(gyro, record.timestamp, epoch, scale))
+                .is_none_or(|std| std <= options.max_accel_std)
+    };
+    gps.chunk_by(|a, b| still(a) == still(b) && b.timestamp.saturating_sub(a.timestamp) <= 1)
+        .filter(|run| still(&run[0]))
+        .map(|run| Interval {
+            start: run[0].timestamp,
+            end: run[run.len() - 1].timestamp,
+        })
+        .filter(|interval| interval.end - interval.start >= options.min_duration)
+        .collect()
+}
+
+/// Drops the records inside `intervals`, leaving gaps that `split_at_gaps` splits segments at.
+pub fn retain_moving(records: &mut Vec<GpsRecord>, intervals: &[Interval]) {
+    records.retain(|record| is_moving(record, intervals));
+}
+
+/// Marks the records inside `intervals` invalid in `valid`, which holds a flag for each record,
+/// for keeping the records `retain_moving` would drop.
+pub fn mark_stationary(records: &[GpsRecord], intervals: &[Interval], valid: &mut [bool]) {
+    for (record, valid) in records.iter().zip(valid) {
+        *valid &= is_moving(record, intervals);
+    }
+}
+
+fn is_moving(record: &GpsRecord, intervals: &[Interval]) -> bool {
+    !intervals
+        .iter()
+        .any(|interval| interval.contains(record.timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    #[test]
+    fn test_stationary_intervals() {
+        // Moving for 10 seconds, parked for 20, moving again for 10.
+        let mut gps: Vec<GpsRecord> = (0..40)
+            .map(|i| GpsRecord {
+                timestamp: 1000 + i,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(if (10..30).contains(&i) { 0.1 } else { 8.0 }),
+                track: Degrees(0.0),
+                altitude: Meters(0.0),
+                fix: None,
+            })
+            .collect();
+        let scale = ImuScale {
+            gyro_range_dps: 2000.0,
+            accel_range_g: 16.0,
+        };
+        let options = StationaryOptions::default();
+        let intervals = stationary_intervals(&gps, &[], None, &scale, &options);
+        assert_eq!(
+            intervals,
+            [Interval {
+                start: 1010,
+                end: 1029
+            }]
+        );
+
+        // Shaking in one parked second splits the stop into runs too short to report.
+        let gyro: Vec<GyroRecord> = (0..10)
+            .map(|i| {
+                let z: i16 = if i % 2 == 0 { 2048 } else { 4096 };
+                GyroRecord::from_raw(20_000 + i * 100, [0, 0, z, 0, 0, 0])
+            })
+            .collect();
+        let shaken = stationary_intervals(&gps, &gyro, Some(1000.0), &scale, &options);
+        assert!(shaken.is_empty());
+
+        let mut valid = vec![true; gps.len()];
+        mark_stationary(&gps, &intervals, &mut valid);
+        assert_eq!(valid.iter().filter(|valid| **valid).count(), 20);
+        assert!(!valid[10] && !valid[29] && valid[30]);
+
+        retain_moving(&mut gps, &intervals);
+        assert_eq!(gps.len(), 20);
+    }
+}
@@ -0,0 +1,260 @@
+//! Summary statistics of a GPS track, for runners and cyclists using the camera as their logger.
+
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    GpsRecord,
+    geo::haversine_distance,
+    units::{Meters, MetersPerSecond, Seconds},
+};
+
+/// Shortest horizontal distance grade is measured over, so GPS altitude noise between
+/// neighbouring fixes doesn't read as a wall.
+pub const GRADE_DISTANCE: f64 = 50.0;
+
+const METERS_PER_MILE: f64 = 1609.344;
+
+/// Metres `threshold` counts changes from when it doesn't say.
+pub const DEFAULT_THRESHOLD: f64 = 3.0;
+
+/// Seconds `smoothed` averages over when it doesn't say.
+pub const DEFAULT_SMOOTHING: f64 = 10.0;
+
+/// How ascent and descent are summed from GPS altitudes, whose noise of a few metres between
+/// fixes adds up to climbing that never happened when every rise is counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ElevationGain {
+    /// Every rise and fall between fixes.
+    #[default]
+    Raw,
+    /// Only changes of at least this many metres from the last altitude counted, the hysteresis
+    /// fitness watches apply to their altimeters.
+    Threshold(f64),
+    /// Every rise and fall of the altitudes averaged over this many seconds around each fix,
+    /// in the manner of the smoothing Strava applies to GPS altitude.
+    Smoothed(f64),
+}
+
+/// Parses `raw`, `threshold` or `smoothed`, the last two optionally followed by `:` and their
+/// metres or seconds.
+impl FromStr for ElevationGain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => {
+                let value: f64 = (value.parse().ok())
+                    .filter(|value: &f64| value.is_finite() && *value >= 0.0)
+                    .ok_or_else(|| format!("invalid value {value:?}"))?;
+                (name, Some(value))
+            }
+            None => (s, None),
+        };
+        match name {
+            "raw" if value.is_none() => Ok(ElevationGain::Raw),
+            "threshold" => Ok(ElevationGain::Threshold(value.unwrap_or(DEFAULT_THRESHOLD))),
+            "smoothed" => Ok(ElevationGain::Smoothed(value.unwrap_or(DEFAULT_SMOOTHING))),
+            _ => Err(format!(
+                "expected `raw`, `threshold[:<metres>]` or `smoothed[:<seconds>]`, got {s:?}"
+            )),
+        }
+    }
+}
+
+/// Sums the changes of at least `threshold` from the last altitude counted, as ascent and
+/// descent.
+fn sum_changes(altitudes: impl IntoIterator<Item = f64>, threshold: f64) -> (f64, f64) {
+    let (mut ascent, mut descent) = (0.0, 0.0);
+    let mut reference: Option<f64> = None;
+    for altitude in altitudes {
+        let Some(last) = reference else {
+            reference = Some(altitude);
+            continue;
+        };
+        let change = altitude - last;
+        if change.abs() >= threshold {
+            if change > 0.0 {
+                ascent += change;
+            } else {
+                descent -= change;
+            }
+            reference = Some(altitude);
+        }
+    }
+    (ascent, descent)
+}
+
+/// Ascent and descent over a run of records in time order.
+pub fn elevation_change(records: &[GpsRecord], gain: ElevationGain) -> (Meters, Meters) {
+    let altitudes = records.iter().map(|record| record.altitude.0);
+    let (ascent, descent) = match gain {
+        ElevationGain::Raw => sum_changes(altitudes, 0.0),
+        ElevationGain::Threshold(threshold) => sum_changes(altitudes, threshold),
+        ElevationGain::Smoothed(window) => {
+            let half = window / 2.0;
+            let smoothed = records.iter().map(|record| {
+                let near = (records.iter())
+                    .filter(|other| record.timestamp.abs_diff(other.timestamp) as f64 <= half);
+                let (sum, count) = near.fold((0.0, 0), |(sum, count), other| {
+                    (sum + other.altitude.0, count + 1)
+                });
+                sum / count as f64
+            });
+            sum_changes(smoothed, 0.0)
+        }
+    };
+    (Meters(ascent), Meters(descent))
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TrackStats {
+    /// Time covered by GPS segments, leaving out the gaps between them.
+    pub duration: Seconds,
+    pub distance: Meters,
+    pub average_speed: MetersPerSecond,
+    pub max_speed: MetersPerSecond,
+    /// Sum of altitude rises between fixes.
+    pub ascent: Meters,
+    pub descent: Meters,
+    /// Minutes per kilometre at the average speed.
+    pub pace_min_per_km: Option<f64>,
+    /// Minutes per mile at the average speed.
+    pub pace_min_per_mi: Option<f64>,
+    /// Net climb over distance, in percent.
+    pub grade_percent: Option<f64>,
+    /// Steepest climb over at least `GRADE_DISTANCE`, in percent.
+    pub max_grade_percent: Option<f64>,
+    /// Pace on flat ground for the same effort, weighting each stretch by Minetti's energy cost
+    /// of running at its grade.
+    pub grade_adjusted_pace_min_per_km: Option<f64>,
+}
+
+/// Energy cost of running in J/kg/m at `grade` (rise over run), from Minetti et al. (2002),
+/// whose fit holds between -45% and +45%.
+pub fn running_cost(grade: f64) -> f64 {
+    let i = grade.clamp(-0.45, 0.45);
+    155.4 * i.powi(5) - 30.4 * i.powi(4) - 43.3 * i.powi(3) + 46.3 * i.powi(2) + 19.5 * i + 3.6
+}
+
+fn pace(seconds: f64, meters: f64, unit: f64) -> Option<f64> {
+    (meters > 0.0).then(|| seconds / 60.0 / (meters / unit))
+}
+
+/// Statistics over GPS segments such as those from `split_at_gaps`, with ascent and descent
+/// summed as `gain` says.
+pub fn track_stats(segments: &[&[GpsRecord]], gain: ElevationGain) -> TrackStats {
+    let mut stats = TrackStats::default();
+    let mut flat_distance = 0.0;
+    let mut max_grade: Option<f64> = None;
+    for segment in segments {
+        let (Some(first), Some(last)) = (segment.first(), segment.last()) else {
+            continue;
+        };
+        stats.duration.0 += last.timestamp.saturating_sub(first.timestamp) as f64;
+
+        // Grade is measured from `stretch_start` once the track has moved far enough from it.
+        let mut stretch_start = first;
+        let mut stretch_distance = 0.0;
+        for pair in segment.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let step = haversine_distance(a.latitude.0, a.longitude.0, b.latitude.0, b.longitude.0);
+            stats.distance.0 += step;
+            stretch_distance += step;
+            if stretch_distance >= GRADE_DISTANCE {
+                let grade = (b.altitude.0 - stretch_start.altitude.0) / stretch_distance;
+                max_grade = Some(max_grade.map_or(grade, |max| max.max(grade)));
+                flat_distance += stretch_distance * running_cost(grade) / running_cost(0.0);
+                stretch_start = b;
+                stretch_distance = 0.0;
+            }
+        }
+        flat_distance += stretch_distance;
+        let (ascent, descent) = elevation_change(segment, gain);
+        stats.ascent.0 += ascent.0;
+        stats.descent.0 += descent.0;
+        for record in *segment {
+            if record.speed > stats.max_speed {
+                stats.max_speed = record.speed;
+            }
+        }
+    }
+
+    let (seconds, meters) = (stats.duration.0, stats.distance.0);
+    if seconds > 0.0 {
+        stats.average_speed.0 = meters / seconds;
+    }
+    stats.pace_min_per_km = pace(seconds, meters, 1000.0);
+    stats.pace_min_per_mi = pace(seconds, meters, METERS_PER_MILE);
+    stats.grade_percent =
+        (meters > 0.0).then(|| (stats.ascent.0 - stats.descent.0) / meters * 100.0);
+    stats.max_grade_percent = max_grade.map(|grade| grade * 100.0);
+    stats.grade_adjusted_pace_min_per_km = pace(seconds, flat_distance, 1000.0);
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Degrees;
+
+    #[test]
+    fn test_track_stats() {
+        // 10 m north and 1 m up each second: a 10% climb at 10 m/s.
+        let records: Vec<GpsRecord> = (0..=100)
+            .map(|i| GpsRecord {
+                timestamp: 1000 + i,
+                latitude: Degrees(i as f64 * 10.0 / 111_195.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(10.0),
+                track: Degrees(0.0),
+                altitude: Meters(i as f64),
+                fix: None,
+            })
+            .collect();
+        let stats = track_stats(&[&records], ElevationGain::Raw);
+        assert_eq!(stats.duration, Seconds(100.0));
+        assert!((stats.distance.0 - 1000.0).abs() < 1.0);
+        assert!((stats.ascent.0 - 100.0).abs() < 1e-9);
+        assert!((stats.pace_min_per_km.unwrap() - 100.0 / 60.0).abs() < 0.01);
+        assert!((stats.pace_min_per_mi.unwrap() - 1609.344 / 600.0).abs() < 0.01);
+        assert!((stats.grade_percent.unwrap() - 10.0).abs() < 0.1);
+        assert!((stats.max_grade_percent.unwrap() - 10.0).abs() < 0.1);
+        // Climbing costs more than running on the flat, so the adjusted pace is quicker.
+        assert!(stats.grade_adjusted_pace_min_per_km.unwrap() < stats.pace_min_per_km.unwrap());
+
+        assert_eq!(track_stats(&[], ElevationGain::Raw), TrackStats::default());
+    }
+
+    #[test]
+    fn test_elevation_change() {
+        // A 20 m climb with a metre and a half of noise on every other fix.
+        let records: Vec<GpsRecord> = (0..=20)
+            .map(|i| GpsRecord {
+                timestamp: 1000 + i,
+                latitude: Degrees(0.0),
+                longitude: Degrees(0.0),
+                speed: MetersPerSecond(0.0),
+                track: Degrees(0.0),
+                altitude: Meters(i as f64 + if i % 2 == 1 { 1.5 } else { 0.0 }),
+                fix: None,
+            })
+            .collect();
+        let (ascent, descent) = elevation_change(&records, ElevationGain::Raw);
+        assert_eq!((ascent.0, descent.0), (25.0, 5.0));
+        let (ascent, descent) = elevation_change(&records, ElevationGain::Threshold(3.0));
+        assert_eq!((ascent.0, descent.0), (20.5, 0.0));
+        let (ascent, descent) = elevation_change(&records, ElevationGain::Smoothed(4.0));
+        assert!(ascent.0 < 22.0 && descent.0 < 1.0, "{ascent:?} {descent:?}");
+
+        assert_eq!("raw".parse(), Ok(ElevationGain::Raw));
+        assert_eq!("threshold".parse(), Ok(ElevationGain::Threshold(3.0)));
+        assert_eq!("smoothed:30".parse(), Ok(ElevationGain::Smoothed(30.0)));
+        assert!("threshold:-1".parse::<ElevationGain>().is_err());
+        assert!("raw:2".parse::<ElevationGain>().is_err());
+    }
+}
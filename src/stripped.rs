@@ -0,0 +1,117 @@
+//! Recognising Insta360 footage whose metadata is gone, so a file without a trailer can be told
+//! apart from one that never had one.
+//!
+//! Re-encoding or remuxing with tools that only copy the MP4 boxes drops the trailer the camera
+//! appends, but the camera's file name and the `udta` box naming the camera usually survive.
+//! Tools that append their own data keep the trailer but hide it from the signature check at the
+//! end of the file, so the last bytes are searched for it too.
+
+use std::path::Path;
+
+use crate::{SIGNATURE, mp4::find_box};
+
+/// How far from the end of the file to look for a trailer something was appended to.
+const SIGNATURE_SEARCH_LEN: usize = 1 << 20;
+
+/// Whether `name` is one the camera gives its videos: `VID_<date>_<time>_<lens>_<sequence>`,
+/// `PRO_VID_…` or `LRV_…`, with anything after it that editors add.
+fn has_camera_name(name: &str) -> bool {
+    let Some(rest) = ["VID_", "PRO_VID_", "LRV_"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+    else {
+        return false;
+    };
+    let digits =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    let parts: Vec<&str> = rest.splitn(4, '_').collect();
+    let [date, time, lens, sequence] = parts[..] else {
+        return false;
+    };
+    digits(date, 8)
+        && digits(time, 6)
+        && digits(lens, 2)
+        && sequence.get(..3).is_some_and(|s| digits(s, 3))
+}
+
+/// Why the file at `path` with contents `data`, which has no readable Insta360 metadata, looks
+/// like Insta360 footage all the same. Empty when nothing suggests it is.
+pub fn footage_hints(path: &Path, data: &[u8]) -> Vec<String> {
+    let mut hints = Vec::new();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if has_camera_name(&name) {
+        hints.push("its name is an Insta360 camera's".to_string());
+    }
+    let udta = find_box(data, b"moov").and_then(|moov| find_box(moov, b"udta"));
+    if udta.is_some_and(|udta| {
+        (udta.windows(8)).any(|window| window.eq_ignore_ascii_case(b"insta360"))
+    }) {
+        hints.push("its udta box names an Insta360 camera".to_string());
+    }
+    let tail = &data[data.len().saturating_sub(SIGNATURE_SEARCH_LEN)..];
+    if let Some(at) = (tail.windows(SIGNATURE.len())).rposition(|window| window == SIGNATURE) {
+        let after = tail.len() - at - SIGNATURE.len();
+        hints.push(format!(
+            "it has an Insta360 trailer signature {after} bytes before the end"
+        ));
+    }
+    hints
+}
+
+/// What to tell the user about the file at `path`, with contents `data`, that has no readable
+/// Insta360 metadata, when it looks like footage that lost its trailer: why, and how to copy it
+/// back. `None` if it doesn't.
+pub fn stripped_trailer_diagnostic(path: &Path, data: &[u8]) -> Option<String> {
+    let hints = footage_hints(path, data);
+    if hints.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "no readable Insta360 metadata, though {}: the trailer was probably stripped or hidden by \
+         a re-encode or remux; copy it back from the original with `ginsta copy-meta --from \
+         ORIGINAL.insv --to {}`",
+        hints.join(" and "),
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let size = (8 + payload.len()) as u32;
+        [&size.to_be_bytes()[..], box_type, payload].concat()
+    }
+
+    #[test]
+    fn test_footage_hints() {
+        assert!(has_camera_name("VID_20250718_073922_00_001.mp4"));
+        assert!(has_camera_name("PRO_VID_20250718_073922_00_001_edit.mp4"));
+        assert!(has_camera_name("LRV_20250718_073922_11_001.lrv"));
+        assert!(!has_camera_name("VID_2025_073922_00_001.mp4"));
+        assert!(!has_camera_name("holiday.mp4"));
+
+        let plain = mp4_box(b"moov", &mp4_box(b"mvhd", &[0; 100]));
+        assert!(footage_hints(Path::new("holiday.mp4"), &plain).is_empty());
+        assert_eq!(
+            stripped_trailer_diagnostic(Path::new("holiday.mp4"), &plain),
+            None
+        );
+
+        let udta = mp4_box(b"udta", b"\0\0\0\x10\xa9tooInsta360 X3");
+        let tagged = mp4_box(b"moov", &udta);
+        assert_eq!(
+            footage_hints(Path::new("VID_20250718_073922_00_001.mp4"), &tagged),
+            [
+                "its name is an Insta360 camera's",
+                "its udta box names an Insta360 camera"
+            ]
+        );
+
+        let appended = [&plain[..], SIGNATURE, b"tool data"].concat();
+        let diagnostic = stripped_trailer_diagnostic(Path::new("holiday.mp4"), &appended).unwrap();
+        assert!(diagnostic.contains("trailer signature 9 bytes before the end"));
+        assert!(diagnostic.contains("copy-meta --from ORIGINAL.insv --to holiday.mp4"));
+    }
+}
@@ -0,0 +1,382 @@
+use std::{fmt::Write as _, io::Write, str::FromStr};
+
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+#[cfg(feature = "clap")]
+use clap::ValueEnum;
+
+use crate::GpsRecord;
+
+/// Names that may appear in a template placeholder.
+pub const TEMPLATE_FIELDS: &[&str] = &[
+    "speed_ms",
+    "speed_kmh",
+    "speed_mph",
+    "alt_m",
+    "alt_ft",
+    "lat",
+    "lon",
+    "track",
+    "date",
+    "time_utc",
+    "time_local",
+];
+
+pub const DEFAULT_TEMPLATE: &str = "{speed_kmh:.1} km/h  {alt_m:.0} m";
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Segment {
+    Literal(String),
+    Field {
+        name: String,
+        precision: Option<usize>,
+    },
+}
+
+/// A format string such as `{speed_kmh:.1} km/h` rendered once per GPS record.
+///
+/// Placeholders are `{name}` or `{name:.N}` for N decimal places. `{{` and `}}` produce literal
+/// braces and `\n` starts a new line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+    /// Zone for `time_local`; the system's own when `None`.
+    pub time_zone: Option<Tz>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Template {
+            segments: parse_segments(s, TEMPLATE_FIELDS)?,
+            time_zone: None,
+        })
+    }
+}
+
+/// Splits a template into literal text and placeholders, which must be named in `fields`.
+pub(crate) fn parse_segments(s: &str, fields: &[&str]) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '\\' if chars.peek() == Some(&'n') => {
+                chars.next();
+                literal.push('\n');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err(format!("unterminated `{{{placeholder}` in template")),
+                    }
+                }
+                let (name, spec) = placeholder
+                    .split_once(':')
+                    .unwrap_or((placeholder.as_str(), ""));
+                if !fields.contains(&name) {
+                    return Err(format!(
+                        "unknown template field `{name}`, expected one of: {}",
+                        fields.join(", ")
+                    ));
+                }
+                let precision = match spec {
+                    "" => None,
+                    spec => Some(
+                        spec.strip_prefix('.')
+                            .and_then(|p| p.parse().ok())
+                            .ok_or_else(|| format!("invalid format `{spec}` for `{name}`"))?,
+                    ),
+                };
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Field {
+                    name: name.to_string(),
+                    precision,
+                });
+            }
+            '}' => return Err("unmatched `}` in template".to_string()),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+impl Template {
+    pub fn render(&self, record: &GpsRecord) -> String {
+        let time = DateTime::<Utc>::from_timestamp(record.timestamp as i64, 0).unwrap_or_default();
+        let mut out = String::new();
+        for segment in &self.segments {
+            let (name, precision) = match segment {
+                Segment::Literal(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Segment::Field { name, precision } => (name.as_str(), *precision),
+            };
+            let number = match name {
+                "speed_ms" => record.speed.0,
+                "speed_kmh" => record.speed.kmh(),
+                "speed_mph" => record.speed.mph(),
+                "alt_m" => record.altitude.0,
+                "alt_ft" => record.altitude.feet(),
+                "lat" => record.latitude.0,
+                "lon" => record.longitude.0,
+                "track" => record.track.0,
+                "date" => {
+                    write!(out, "{}", time.format("%Y-%m-%d")).unwrap();
+                    continue;
+                }
+                "time_utc" => {
+                    write!(out, "{}", time.format("%H:%M:%S")).unwrap();
+                    continue;
+                }
+                "time_local" => {
+                    match self.time_zone {
+                        Some(zone) => {
+                            write!(out, "{}", time.with_timezone(&zone).format("%H:%M:%S"))
+                        }
+                        None => write!(out, "{}", time.with_timezone(&Local).format("%H:%M:%S")),
+                    }
+                    .unwrap();
+                    continue;
+                }
+                _ => unreachable!("template fields are validated when parsed"),
+            };
+            match precision {
+                Some(precision) => write!(out, "{number:.precision$}").unwrap(),
+                None => write!(out, "{number}").unwrap(),
+            }
+        }
+        out
+    }
+}
+
+/// A piece of overlay text shown between two times, in seconds from the start of the video.
+#[derive(Debug, PartialEq)]
+pub struct SubtitleEvent {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// Turns each GPS record into an event lasting until the next record. `video_start` is the Unix
+/// time of the first video frame.
+pub fn gps_events(
+    records: &[GpsRecord],
+    template: &Template,
+    video_start: f64,
+) -> Vec<SubtitleEvent> {
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            let start = record.timestamp as f64 - video_start;
+            let end = records
+                .get(i + 1)
+                .map_or(start + 1.0, |next| next.timestamp as f64 - video_start);
+            SubtitleEvent {
+                start,
+                end,
+                text: template.render(record),
+            }
+        })
+        .filter(|event| event.end > 0.0 && event.end > event.start)
+        .map(|event| SubtitleEvent {
+            start: event.start.max(0.0),
+            ..event
+        })
+        .collect()
+}
+
+fn split_seconds(seconds: f64, fraction_scale: f64) -> (u64, u64, u64, u64) {
+    let scaled = (seconds * fraction_scale).round() as u64;
+    let fraction_scale = fraction_scale as u64;
+    let whole = scaled / fraction_scale;
+    (
+        whole / 3600,
+        whole / 60 % 60,
+        whole % 60,
+        scaled % fraction_scale,
+    )
+}
+
+fn srt_time(seconds: f64) -> String {
+    let (h, m, s, ms) = split_seconds(seconds, 1000.0);
+    format!("{h:02}:{m:02}:{s:02},{ms:03}")
+}
+
+fn ass_time(seconds: f64) -> String {
+    let (h, m, s, cs) = split_seconds(seconds, 100.0);
+    format!("{h}:{m:02}:{s:02}.{cs:02}")
+}
+
+pub fn write_srt(mut out: impl Write, events: &[SubtitleEvent]) -> std::io::Result<()> {
+    for (i, event) in events.iter().enumerate() {
+        writeln!(out, "{}", i + 1)?;
+        writeln!(out, "{} --> {}", srt_time(event.start), srt_time(event.end))?;
+        writeln!(out, "{}", event.text)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Screen anchor for overlay text, named after the ASS numpad alignment positions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+pub enum Position {
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+    MiddleLeft,
+    Center,
+    MiddleRight,
+    TopLeft,
+    TopCenter,
+    TopRight,
+}
+
+impl Position {
+    fn alignment(self) -> u8 {
+        self as u8 + 1
+    }
+}
+
+#[derive(Debug)]
+pub struct AssStyle {
+    pub position: Position,
+    pub font: String,
+    pub font_size: u32,
+    pub margin: u32,
+    /// Script resolution that font size and margins are relative to.
+    pub play_res: (i32, i32),
+}
+
+pub fn write_ass(
+    mut out: impl Write,
+    events: &[SubtitleEvent],
+    style: &AssStyle,
+) -> std::io::Result<()> {
+    writeln!(out, "[Script Info]")?;
+    writeln!(out, "ScriptType: v4.00+")?;
+    writeln!(out, "PlayResX: {}", style.play_res.0)?;
+    writeln!(out, "PlayResY: {}", style.play_res.1)?;
+    writeln!(out, "WrapStyle: 2")?;
+    writeln!(out)?;
+    writeln!(out, "[V4+ Styles]")?;
+    writeln!(
+        out,
+        "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+         BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+         BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding"
+    )?;
+    writeln!(
+        out,
+        "Style: Telemetry,{},{},&H00FFFFFF,&H000000FF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,1,{},{m},{m},{m},1",
+        style.font,
+        style.font_size,
+        style.position.alignment(),
+        m = style.margin,
+    )?;
+    writeln!(out)?;
+    writeln!(out, "[Events]")?;
+    writeln!(
+        out,
+        "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text"
+    )?;
+    for event in events {
+        writeln!(
+            out,
+            "Dialogue: 0,{},{},Telemetry,,0,0,0,,{}",
+            ass_time(event.start),
+            ass_time(event.end),
+            event.text.replace('\n', "\\N")
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Degrees, Meters, MetersPerSecond};
+
+    fn record(timestamp: u64) -> GpsRecord {
+        GpsRecord {
+            timestamp,
+            latitude: Degrees(-33.5),
+            longitude: Degrees(151.25),
+            speed: MetersPerSecond(10.0),
+            track: Degrees(90.0),
+            altitude: Meters(123.456),
+            fix: None,
+        }
+    }
+
+    #[test]
+    fn test_template() {
+        let template: Template = "{speed_kmh:.1} km/h\\n{alt_m:.0} m {{{time_utc}}}"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            template.render(&record(1752824362)),
+            "36.0 km/h\n123 m {07:39:22}"
+        );
+
+        let mut template: Template = "{time_local}".parse().unwrap();
+        template.time_zone = Some(Tz::Asia__Tokyo);
+        assert_eq!(template.render(&record(1752824362)), "16:39:22");
+
+        assert!("{bogus}".parse::<Template>().is_err());
+        assert!("{alt_m:x}".parse::<Template>().is_err());
+        assert!("alt }".parse::<Template>().is_err());
+        assert_eq!(
+            "{speed".parse::<Template>().unwrap_err(),
+            "unterminated `{speed` in template"
+        );
+        assert!("{speed_kmh:.1".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn test_write_subtitles() {
+        let template: Template = "{lat}".parse().unwrap();
+        let events = gps_events(&[record(99), record(101)], &template, 100.0);
+        assert_eq!(events.len(), 2);
+
+        let mut srt = Vec::new();
+        write_srt(&mut srt, &events).unwrap();
+        assert_eq!(
+            String::from_utf8(srt).unwrap(),
+            "1\n00:00:00,000 --> 00:00:01,000\n-33.5\n\n2\n00:00:01,000 --> 00:00:02,000\n-33.5\n\n"
+        );
+
+        let style = AssStyle {
+            position: Position::TopRight,
+            font: "Arial".to_string(),
+            font_size: 48,
+            margin: 40,
+            play_res: (1920, 1080),
+        };
+        let mut ass = Vec::new();
+        write_ass(&mut ass, &events, &style).unwrap();
+        let ass = String::from_utf8(ass).unwrap();
+        assert!(ass.contains(",9,40,40,40,1\n"));
+        assert!(ass.ends_with("Dialogue: 0,0:00:01.00,0:00:02.00,Telemetry,,0,0,0,,-33.5\n"));
+    }
+}
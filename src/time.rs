@@ -0,0 +1,431 @@
+use std::fmt;
+
+#[cfg(feature = "clap")]
+use clap::ValueEnum;
+
+use crate::Telemetry;
+
+/// The clock that raw GPS timestamps were recorded against.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+pub enum TimeBase {
+    /// Timestamps are already UTC seconds since the Unix epoch.
+    #[default]
+    Utc,
+    /// Timestamps are GPS time expressed as seconds since the Unix epoch, i.e. ahead of UTC by
+    /// the accumulated leap seconds.
+    Gps,
+}
+
+impl TimeBase {
+    /// Converts a raw timestamp in this time base to UTC seconds.
+    pub fn to_utc(self, seconds: u64) -> u64 {
+        match self {
+            TimeBase::Utc => seconds,
+            TimeBase::Gps => gps_to_utc(seconds),
+        }
+    }
+}
+
+/// UTC Unix timestamps at which GPS-UTC increased, paired with the new offset.
+const LEAP_SECONDS: &[(u64, u64)] = &[
+    (362793600, 1),   // 1981-07-01
+    (394329600, 2),   // 1982-07-01
+    (425865600, 3),   // 1983-07-01
+    (489024000, 4),   // 1985-07-01
+    (567993600, 5),   // 1988-01-01
+    (631152000, 6),   // 1990-01-01
+    (662688000, 7),   // 1991-01-01
+    (709948800, 8),   // 1992-07-01
+    (741484800, 9),   // 1993-07-01
+    (773020800, 10),  // 1994-07-01
+    (820454400, 11),  // 1996-01-01
+    (867715200, 12),  // 1997-07-01
+    (915148800, 13),  // 1999-01-01
+    (1136073600, 14), // 2006-01-01
+    (1230768000, 15), // 2009-01-01
+    (1341100800, 16), // 2012-07-01
+    (1435708800, 17), // 2015-07-01
+    (1483228800, 18), // 2017-01-01
+];
+
+/// Returns GPS-UTC in seconds at the given UTC time.
+pub fn leap_seconds_at_utc(utc: u64) -> u64 {
+    LEAP_SECONDS
+        .iter()
+        .rev()
+        .find(|(start, _)| utc >= *start)
+        .map_or(0, |(_, offset)| *offset)
+}
+
+/// Converts GPS seconds (counted from the Unix epoch) to UTC seconds.
+pub fn gps_to_utc(gps: u64) -> u64 {
+    // Leap second boundaries are defined in UTC, so shift each one onto the GPS clock.
+    let offset = LEAP_SECONDS
+        .iter()
+        .rev()
+        .find(|(start, offset)| gps >= start + offset)
+        .map_or(0, |(_, offset)| *offset);
+    gps - offset
+}
+
+/// What to do about a record whose timestamp isn't later than the one before it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+pub enum TimeAnomalyPolicy {
+    /// Fail on the first one.
+    Error,
+    /// Drop it, keeping the records before it.
+    Drop,
+    /// Put the records in time order, then drop repeated timestamps.
+    Resequence,
+}
+
+/// A timestamp that went backwards or repeated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeAnomaly {
+    /// `gps`, `gyro` or `exposure`.
+    pub stream: &'static str,
+    /// Position of the record in its stream.
+    pub index: usize,
+    pub previous: u64,
+    pub timestamp: u64,
+}
+
+impl fmt::Display for TimeAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} record {} has timestamp {} after {}",
+            self.stream, self.index, self.timestamp, self.previous
+        )
+    }
+}
+
+impl std::error::Error for TimeAnomaly {}
+
+/// Makes the timestamps of `records` strictly increase according to `policy`, returning how
+/// many records were dropped.
+pub fn enforce_monotonic<T>(
+    stream: &'static str,
+    records: &mut Vec<T>,
+    timestamp: impl Fn(&T) -> u64,
+    policy: TimeAnomalyPolicy,
+) -> Result<usize, TimeAnomaly> {
+    let len = records.len();
+    match policy {
+        TimeAnomalyPolicy::Error => {
+            if let Some(index) =
+                (1..len).find(|&i| timestamp(&records[i]) <= timestamp(&records[i - 1]))
+            {
+                return Err(TimeAnomaly {
+                    stream,
+                    index,
+                    previous: timestamp(&records[index - 1]),
+                    timestamp: timestamp(&records[index]),
+                });
+            }
+        }
+        TimeAnomalyPolicy::Drop => {
+            let mut last = None;
+            records.retain(|record| {
+                let keep = last.is_none_or(|last| timestamp(record) > last);
+                if keep {
+                    last = Some(timestamp(record));
+                }
+                keep
+            });
+        }
+        TimeAnomalyPolicy::Resequence => {
+            records.sort_by_key(&timestamp);
+            records.dedup_by(|a, b| timestamp(a) == timestamp(b));
+        }
+    }
+    Ok(len - records.len())
+}
+
+/// Applies `enforce_monotonic` to every stream, returning the number of records dropped from
+/// each, or the first anomaly under `TimeAnomalyPolicy::Error`.
+pub fn enforce_monotonic_telemetry(
+    telemetry: &mut Telemetry,
+    policy: TimeAnomalyPolicy,
+) -> Result<[(&'static str, usize); 3], TimeAnomaly> {
+    Ok([
+        (
+            "gps",
+            enforce_monotonic("gps", &mut telemetry.gps, |r| r.timestamp, policy)?,
+        ),
+        (
+            "gyro",
+            enforce_monotonic("gyro", &mut telemetry.gyro, |r| r.timestamp, policy)?,
+        ),
+        (
+            "exposure",
+            enforce_monotonic("exposure", &mut telemetry.exposure, |r| r.timestamp, policy)?,
+        ),
+    ])
+}
+
+/// Seconds in the 1024 weeks after which the week number GPS receivers broadcast rolls over, so
+/// a receiver that misses it reports dates that much too early.
+pub const GPS_WEEK_ROLLOVER: u64 = 1024 * 7 * 86_400;
+
+/// Largest step forward between consecutive timestamps, in seconds, that `rebase_discontinuities`
+/// takes for a pause rather than the clock jumping.
+pub const MAX_TIME_JUMP: u64 = 3600;
+
+/// Why a run of timestamps was moved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Discontinuity {
+    /// The clock was off by whole GPS week rollovers.
+    WeekRollover,
+    /// The time of day wrapped at midnight without the date moving on.
+    Midnight,
+    /// The clock jumped by some other amount, such as when the camera's clock was reset, so the
+    /// run was made to follow on from the one before at the stream's usual rate.
+    ClockReset,
+}
+
+/// A run of records whose timestamps `rebase_discontinuities` moved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeAdjustment {
+    /// `gps`, `gyro` or `exposure`.
+    pub stream: &'static str,
+    /// Position of the run's first record in its stream.
+    pub start: usize,
+    pub len: usize,
+    /// Added to each timestamp in the run, in the stream's units.
+    pub offset: i64,
+    pub cause: Discontinuity,
+}
+
+impl fmt::Display for TimeAdjustment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cause = match self.cause {
+            Discontinuity::WeekRollover => "a GPS week rollover",
+            Discontinuity::Midnight => "the time of day wrapping at midnight",
+            Discontinuity::ClockReset => "a clock reset",
+        };
+        // GPS timestamps are seconds, the others camera-clock milliseconds.
+        let unit = if self.stream == "gps" { "s" } else { "ms" };
+        write!(
+            f,
+            "moved {} records {} to {} by {:+} {unit} for {cause}",
+            self.stream,
+            self.start,
+            self.start + self.len - 1,
+            self.offset
+        )
+    }
+}
+
+/// The offset to add to the later of two runs `gap` apart, and why, for the gap to become a
+/// plausible pause of up to `max_jump`. `per_second` is the number of timestamp units in a
+/// second.
+fn bridge(gap: i64, max_jump: i64, step: i64, per_second: i64) -> (i64, Discontinuity) {
+    let plausible = |offset: i64| (0..=max_jump).contains(&(gap + offset));
+    let rollover = GPS_WEEK_ROLLOVER as i64 * per_second;
+    let rollovers = ((-gap) as f64 / rollover as f64).round() as i64;
+    if rollovers != 0 && plausible(rollovers * rollover) {
+        return (rollovers * rollover, Discontinuity::WeekRollover);
+    }
+    let day = 86_400 * per_second;
+    if plausible(day) {
+        return (day, Discontinuity::Midnight);
+    }
+    (step - gap, Discontinuity::ClockReset)
+}
+
+/// Finds where the timestamps of `records` jump backwards, or forwards by more than `max_jump`,
+/// and moves the runs between the jumps so they join up. The longest run is taken to be right,
+/// except that the runs after a midnight wrap move forward a day. The others are moved by whole
+/// GPS week rollovers or a day when that makes the jump a plausible pause, and otherwise to
+/// follow on at the median step between records. `per_second` is the number of timestamp units
+/// in a second.
+pub fn rebase_discontinuities<T>(
+    stream: &'static str,
+    records: &mut [T],
+    timestamp: impl Fn(&mut T) -> &mut u64,
+    max_jump: u64,
+    per_second: u64,
+) -> Vec<TimeAdjustment> {
+    let times: Vec<i64> = records.iter_mut().map(|r| *timestamp(r) as i64).collect();
+    let max_jump = (max_jump * per_second) as i64;
+    let mut starts = vec![0];
+    starts.extend((1..times.len()).filter(|&i| {
+        let step = times[i] - times[i - 1];
+        step < 0 || step > max_jump
+    }));
+    if starts.len() == 1 {
+        return Vec::new();
+    }
+    let runs: Vec<std::ops::Range<usize>> = (starts.iter().enumerate())
+        .map(|(i, &start)| start..starts.get(i + 1).copied().unwrap_or(times.len()))
+        .collect();
+    let mut steps: Vec<i64> = (runs.iter())
+        .flat_map(|run| times[run.clone()].windows(2).map(|pair| pair[1] - pair[0]))
+        .filter(|step| *step > 0)
+        .collect();
+    steps.sort_unstable();
+    let step = steps
+        .get(steps.len() / 2)
+        .copied()
+        .unwrap_or(per_second as i64);
+    let reference = (0..runs.len())
+        .rev()
+        .max_by_key(|&i| runs[i].len())
+        .unwrap_or(0);
+
+    let mut offsets = vec![0; runs.len()];
+    let mut causes = vec![None; runs.len()];
+    for i in (0..reference).rev() {
+        let gap = (times[runs[i + 1].start] + offsets[i + 1]) - times[runs[i].end - 1];
+        let (offset, cause) = bridge(gap, max_jump, step, per_second as i64);
+        if cause == Discontinuity::Midnight {
+            // It's the runs after midnight whose date didn't move on.
+            for (offset_after, cause_after) in
+                (offsets[i + 1..=reference].iter_mut()).zip(&mut causes[i + 1..=reference])
+            {
+                *offset_after += offset;
+                cause_after.get_or_insert(cause);
+            }
+        } else {
+            (offsets[i], causes[i]) = (-offset, Some(cause));
+        }
+    }
+    for i in reference + 1..runs.len() {
+        let gap = times[runs[i].start] - (times[runs[i - 1].end - 1] + offsets[i - 1]);
+        let (offset, cause) = bridge(gap, max_jump, step, per_second as i64);
+        (offsets[i], causes[i]) = (offset, Some(cause));
+    }
+
+    let mut adjustments = Vec::new();
+    for ((run, offset), cause) in runs.into_iter().zip(offsets).zip(causes) {
+        let Some(cause) = cause.filter(|_| offset != 0) else {
+            continue;
+        };
+        for record in &mut records[run.clone()] {
+            let timestamp = timestamp(record);
+            *timestamp = timestamp.saturating_add_signed(offset);
+        }
+        adjustments.push(TimeAdjustment {
+            stream,
+            start: run.start,
+            len: run.len(),
+            offset,
+            cause,
+        });
+    }
+    adjustments
+}
+
+/// Applies `rebase_discontinuities` to every stream, with GPS timestamps in seconds and the
+/// others in camera-clock milliseconds.
+pub fn rebase_telemetry(telemetry: &mut Telemetry, max_jump: u64) -> Vec<TimeAdjustment> {
+    let mut adjustments =
+        rebase_discontinuities("gps", &mut telemetry.gps, |r| &mut r.timestamp, max_jump, 1);
+    adjustments.extend(rebase_discontinuities(
+        "gyro",
+        &mut telemetry.gyro,
+        |r| &mut r.timestamp,
+        max_jump,
+        1000,
+    ));
+    adjustments.extend(rebase_discontinuities(
+        "exposure",
+        &mut telemetry.exposure,
+        |r| &mut r.timestamp,
+        max_jump,
+        1000,
+    ));
+    adjustments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gps_to_utc() {
+        // 2025-07-18T07:39:22Z, as seen in the bundled insgps sample.
+        assert_eq!(gps_to_utc(1752824362 + 18), 1752824362);
+        assert_eq!(leap_seconds_at_utc(1752824362), 18);
+
+        // Straddling the 2017-01-01 leap second.
+        assert_eq!(gps_to_utc(1483228800 + 17), 1483228800);
+        assert_eq!(gps_to_utc(1483228800 + 18), 1483228800);
+
+        assert_eq!(gps_to_utc(1000), 1000);
+        assert_eq!(TimeBase::Utc.to_utc(1483228800), 1483228800);
+    }
+
+    #[test]
+    fn test_enforce_monotonic() {
+        let records = [10, 11, 11, 9, 12, 14, 13];
+        let fixed = |policy| {
+            let mut records = records.to_vec();
+            enforce_monotonic("gps", &mut records, |&t| t, policy).map(|dropped| (records, dropped))
+        };
+        assert_eq!(
+            fixed(TimeAnomalyPolicy::Error),
+            Err(TimeAnomaly {
+                stream: "gps",
+                index: 2,
+                previous: 11,
+                timestamp: 11
+            })
+        );
+        assert_eq!(
+            fixed(TimeAnomalyPolicy::Drop),
+            Ok((vec![10, 11, 12, 14], 3))
+        );
+        assert_eq!(
+            fixed(TimeAnomalyPolicy::Resequence),
+            Ok((vec![9, 10, 11, 12, 13, 14], 1))
+        );
+        assert_eq!(
+            enforce_monotonic("gps", &mut vec![1, 2, 3], |&t| t, TimeAnomalyPolicy::Error),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn test_rebase_discontinuities() {
+        let rebased = |times: &[u64]| {
+            let mut times = times.to_vec();
+            let adjustments = rebase_discontinuities("gps", &mut times, |t| t, MAX_TIME_JUMP, 1);
+            (times, adjustments)
+        };
+        let (times, adjustments) = rebased(&[100, 101, 102]);
+        assert_eq!((times, adjustments), (vec![100, 101, 102], vec![]));
+
+        // A receiver losing 1024 weeks partway through, after a 30 s pause.
+        let now = 1752824362;
+        let early = now + 33 - GPS_WEEK_ROLLOVER;
+        let (times, adjustments) = rebased(&[now, now + 1, now + 2, early, early + 1]);
+        assert_eq!(times, [now, now + 1, now + 2, now + 33, now + 34]);
+        assert_eq!(
+            adjustments,
+            [TimeAdjustment {
+                stream: "gps",
+                start: 3,
+                len: 2,
+                offset: GPS_WEEK_ROLLOVER as i64,
+                cause: Discontinuity::WeekRollover,
+            }]
+        );
+
+        // The time of day wrapping, and a clock reset to the epoch before a longer run.
+        let (times, adjustments) = rebased(&[86_398, 86_399, 0, 1, 2]);
+        assert_eq!(times, [86_398, 86_399, 86_400, 86_401, 86_402]);
+        assert_eq!(adjustments[0].cause, Discontinuity::Midnight);
+        let (times, adjustments) = rebased(&[5, 6, now, now + 1, now + 2]);
+        assert_eq!(times, [now - 2, now - 1, now, now + 1, now + 2]);
+        assert_eq!(adjustments[0].offset, now as i64 - 7);
+        assert_eq!(adjustments[0].cause, Discontinuity::ClockReset);
+        assert_eq!(
+            adjustments[0].to_string(),
+            "moved gps records 0 to 1 by +1752824355 s for a clock reset"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! A GPS track to look positions up in, for applications such as geotagging photos or drawing
+//! overlays that need a position at arbitrary times, or searching clips for where they went.
+
+use rstar::{RTree, primitives::GeomWithData};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    GpsRecord,
+    align::{interpolate_gps, lerp, lerp_degrees},
+    geo::{EARTH_RADIUS_M, haversine_distance},
+    units::{Degrees, Meters, MetersPerSecond},
+};
+
+/// A latitude and longitude range in degrees. Boxes crossing the antimeridian aren't supported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BoundingBox {
+    pub min_latitude: f64,
+    pub min_longitude: f64,
+    pub max_latitude: f64,
+    pub max_longitude: f64,
+}
+
+impl BoundingBox {
+    /// The smallest box around every record, or `None` without any.
+    pub fn of(records: &[GpsRecord]) -> Option<Self> {
+        records.iter().fold(None, |bounds: Option<BoundingBox>, r| {
+            let (latitude, longitude) = (r.latitude.0, r.longitude.0);
+            Some(match bounds {
+                None => BoundingBox {
+                    min_latitude: latitude,
+                    min_longitude: longitude,
+                    max_latitude: latitude,
+                    max_longitude: longitude,
+                },
+                Some(b) => BoundingBox {
+                    min_latitude: b.min_latitude.min(latitude),
+                    min_longitude: b.min_longitude.min(longitude),
+                    max_latitude: b.max_latitude.max(latitude),
+                    max_longitude: b.max_longitude.max(longitude),
+                },
+            })
+        })
+    }
+
+    /// The box around every point within `radius` metres of a position.
+    pub fn around(latitude: f64, longitude: f64, radius: f64) -> Self {
+        let latitude_delta = (radius / EARTH_RADIUS_M).to_degrees();
+        let longitude_delta = match latitude.to_radians().cos() * EARTH_RADIUS_M {
+            scale if scale > radius => (radius / scale).to_degrees(),
+            _ => 180.0,
+        };
+        BoundingBox {
+            min_latitude: (latitude - latitude_delta).max(-90.0),
+            min_longitude: (longitude - longitude_delta).max(-180.0),
+            max_latitude: (latitude + latitude_delta).min(90.0),
+            max_longitude: (longitude + longitude_delta).min(180.0),
+        }
+    }
+
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        (self.min_latitude..=self.max_latitude).contains(&latitude)
+            && (self.min_longitude..=self.max_longitude).contains(&longitude)
+    }
+
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min_latitude <= other.max_latitude
+            && other.min_latitude <= self.max_latitude
+            && self.min_longitude <= other.max_longitude
+            && other.min_longitude <= self.max_longitude
+    }
+}
+
+/// A position as a point on the unit sphere, so straight-line distances in the tree order
+/// positions the same way as great-circle distances.
+fn unit_vector(latitude: f64, longitude: f64) -> [f64; 3] {
+    let (phi, lambda) = (latitude.to_radians(), longitude.to_radians());
+    [
+        phi.cos() * lambda.cos(),
+        phi.cos() * lambda.sin(),
+        phi.sin(),
+    ]
+}
+
+/// What `Track::position_at` returns for times before the first record or after the last.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Extrapolation {
+    /// Nothing.
+    #[default]
+    None,
+    /// The first or last record.
+    Clamp,
+    /// Continue along the line through the first or last two records, up to this many seconds
+    /// beyond the track.
+    Linear(f64),
+}
+
+#[derive(Debug, Default)]
+pub struct Track {
+    records: Vec<GpsRecord>,
+    /// Indices into `records` by position.
+    index: RTree<GeomWithData<[f64; 3], usize>>,
+    bounding_box: Option<BoundingBox>,
+    pub extrapolation: Extrapolation,
+}
+
+impl Track {
+    /// Builds a track from records in any order.
+    pub fn new(mut records: Vec<GpsRecord>) -> Self {
+        records.sort_by_key(|record| record.timestamp);
+        let points = (records.iter().enumerate())
+            .map(|(i, r)| GeomWithData::new(unit_vector(r.latitude.0, r.longitude.0), i))
+            .collect();
+        let bounding_box = BoundingBox::of(&records);
+        Track {
+            records,
+            index: RTree::bulk_load(points),
+            bounding_box,
+            extrapolation: Extrapolation::None,
+        }
+    }
+
+    pub fn records(&self) -> &[GpsRecord] {
+        &self.records
+    }
+
+    /// The smallest box around every record, or `None` for an empty track.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        self.bounding_box
+    }
+
+    /// Whether the track's bounding box overlaps `other`, a quick test before `nearest_point`.
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.bounding_box.is_some_and(|b| b.intersects(other))
+    }
+
+    /// The record closest to a position, and its distance in metres.
+    pub fn nearest_point(&self, latitude: f64, longitude: f64) -> Option<(&GpsRecord, f64)> {
+        let nearest = self
+            .index
+            .nearest_neighbor(&unit_vector(latitude, longitude))?;
+        let record = &self.records[nearest.data];
+        let distance =
+            haversine_distance(latitude, longitude, record.latitude.0, record.longitude.0);
+        Some((record, distance))
+    }
+
+    /// Records within `radius` metres of a position, in time order.
+    pub fn points_within(&self, latitude: f64, longitude: f64, radius: f64) -> Vec<&GpsRecord> {
+        // The chord subtending `radius` on the unit sphere.
+        let chord = 2.0
+            * (radius / EARTH_RADIUS_M / 2.0)
+                .min(core::f64::consts::FRAC_PI_2)
+                .sin();
+        let mut indices: Vec<usize> = self
+            .index
+            .locate_within_distance(unit_vector(latitude, longitude), chord * chord)
+            .map(|point| point.data)
+            .collect();
+        indices.sort_unstable();
+        indices.into_iter().map(|i| &self.records[i]).collect()
+    }
+
+    /// The position at Unix time `timestamp`, interpolated between the records either side of it
+    /// and extrapolated beyond them according to `self.extrapolation`.
+    pub fn position_at(&self, timestamp: f64) -> Option<GpsRecord> {
+        let (first, last) = (self.records.first()?, self.records.last()?);
+        if (first.timestamp as f64..=last.timestamp as f64).contains(&timestamp) {
+            return interpolate_gps(&self.records, timestamp);
+        }
+        let before = timestamp < first.timestamp as f64;
+        let end = if before { first } else { last };
+        match self.extrapolation {
+            Extrapolation::None => None,
+            Extrapolation::Clamp => Some(between(end, end, 0.0, timestamp)),
+            Extrapolation::Linear(max_seconds) => {
+                if (timestamp - end.timestamp as f64).abs() > max_seconds {
+                    return None;
+                }
+                let n = self.records.len();
+                let (a, b) = match n {
+                    1 => (first, first),
+                    _ if before => (first, &self.records[1]),
+                    _ => (&self.records[n - 2], last),
+                };
+                let span = (b.timestamp - a.timestamp) as f64;
+                let fraction = if span > 0.0 {
+                    (timestamp - a.timestamp as f64) / span
+                } else {
+                    0.0
+                };
+                Some(between(a, b, fraction, timestamp))
+            }
+        }
+    }
+}
+
+/// The point `fraction` of the way from `a` to `b`, which may lie outside them.
+fn between(a: &GpsRecord, b: &GpsRecord, fraction: f64, timestamp: f64) -> GpsRecord {
+    GpsRecord {
+        timestamp: timestamp.max(0.0) as u64,
+        latitude: Degrees(lerp(a.latitude.0, b.latitude.0, fraction)),
+        longitude: Degrees(lerp(a.longitude.0, b.longitude.0, fraction)),
+        speed: MetersPerSecond(lerp(a.speed.0, b.speed.0, fraction)),
+        track: Degrees(lerp_degrees(a.track.0, b.track.0, fraction)),
+        altitude: Meters(lerp(a.altitude.0, b.altitude.0, fraction)),
+        fix: a.fix.min(b.fix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_at() {
+        let record = |timestamp, latitude| GpsRecord {
+            timestamp,
+            latitude: Degrees(latitude),
+            longitude: Degrees(4.0),
+            speed: MetersPerSecond(5.0),
+            track: Degrees(0.0),
+            altitude: Meters(100.0),
+            fix: None,
+        };
+        let mut track = Track::new(vec![record(20, 49.2), record(10, 49.1), record(30, 49.4)]);
+        let latitude = |track: &Track, t| track.position_at(t).map(|r| r.latitude.0);
+        assert_eq!(track.records()[0].timestamp, 10);
+
+        assert!((latitude(&track, 15.0).unwrap() - 49.15).abs() < 1e-9);
+        assert_eq!(latitude(&track, 30.0), Some(49.4));
+        assert_eq!(latitude(&track, 9.0), None);
+
+        track.extrapolation = Extrapolation::Clamp;
+        assert_eq!(latitude(&track, 0.0), Some(49.1));
+        assert_eq!(latitude(&track, 100.0), Some(49.4));
+
+        track.extrapolation = Extrapolation::Linear(5.0);
+        assert!((latitude(&track, 35.0).unwrap() - 49.5).abs() < 1e-9);
+        assert!((latitude(&track, 5.0).unwrap() - 49.05).abs() < 1e-9);
+        assert_eq!(latitude(&track, 36.0), None);
+
+        assert!(Track::default().position_at(0.0).is_none());
+    }
+
+    #[test]
+    fn test_spatial_queries() {
+        // North along a meridian in 0.001° (about 111 m) steps.
+        let track = Track::new(
+            (0..10)
+                .map(|i| GpsRecord {
+                    timestamp: 100 + i,
+                    latitude: Degrees(49.0 + i as f64 * 0.001),
+                    longitude: Degrees(4.0),
+                    speed: MetersPerSecond(0.0),
+                    track: Degrees(0.0),
+                    altitude: Meters(0.0),
+                    fix: None,
+                })
+                .collect(),
+        );
+
+        let (record, distance) = track.nearest_point(49.0042, 4.001).unwrap();
+        assert_eq!(record.timestamp, 104);
+        assert!((distance - 76.3).abs() < 0.1, "{distance}");
+
+        let near: Vec<u64> = (track.points_within(49.005, 4.0, 150.0).iter())
+            .map(|r| r.timestamp)
+            .collect();
+        assert_eq!(near, vec![104, 105, 106]);
+
+        let query = BoundingBox::around(49.005, 4.01, 200.0);
+        assert!(query.contains(49.005, 4.011));
+        assert!(!track.intersects(&query));
+        assert!(track.intersects(&BoundingBox::around(49.005, 4.01, 800.0)));
+        assert!(Track::default().nearest_point(0.0, 0.0).is_none());
+    }
+}
@@ -0,0 +1,52 @@
+//! Units for record fields. Each serializes as a bare number, so CSV and JSON columns stay
+//! plain values while the type says what they measure.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+macro_rules! unit {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+        pub struct $name(pub f64);
+    };
+}
+
+unit!(
+    /// An angle in degrees: latitude, longitude or a bearing clockwise from north.
+    Degrees
+);
+unit!(
+    /// An angular rate in degrees per second, positive clockwise for a bearing.
+    DegreesPerSecond
+);
+unit!(
+    /// A distance or altitude in metres.
+    Meters
+);
+unit!(
+    /// A speed in metres per second.
+    MetersPerSecond
+);
+unit!(
+    /// A duration in seconds.
+    Seconds
+);
+
+impl Meters {
+    pub fn feet(self) -> f64 {
+        self.0 * 3.28084
+    }
+}
+
+impl MetersPerSecond {
+    pub fn kmh(self) -> f64 {
+        self.0 * 3.6
+    }
+
+    pub fn mph(self) -> f64 {
+        self.0 * 2.236936
+    }
+}
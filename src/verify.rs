@@ -0,0 +1,140 @@
+//! The checks behind `ginsta verify`: the trailer cross-checked against the index frame and the
+//! frames it points to, and whatever decoding the records had to skip.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Diagnostic, oversized_metadata, read_index, read_telemetry,
+    stripped::stripped_trailer_diagnostic, trailer_problems,
+};
+
+/// What is wrong with a file, if anything.
+#[derive(Debug, Default, PartialEq)]
+pub struct Verification {
+    /// Inconsistencies between the trailer, the index and the frames, or why there's no readable
+    /// metadata at all.
+    pub problems: Vec<String>,
+    /// Parts of the metadata that couldn't be decoded.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Verification {
+    /// Checks the file at `path` with contents `data`.
+    pub fn of(path: &Path, data: &[u8]) -> Verification {
+        match read_index(data) {
+            Some((trailer, index)) => Verification {
+                problems: trailer_problems(data, &trailer, &index),
+                diagnostics: read_telemetry(data).diagnostics,
+            },
+            None => Verification {
+                problems: vec![
+                    (oversized_metadata(data, data.len() as u64).map(|metadata_size| {
+                        format!(
+                            "metadata size {metadata_size} doesn't fit in a {} byte file",
+                            data.len()
+                        )
+                    }))
+                    .or_else(|| stripped_trailer_diagnostic(path, data))
+                    .unwrap_or_else(|| "no readable Insta360 metadata".to_string()),
+                ],
+                diagnostics: Vec::new(),
+            },
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.problems.len() + self.diagnostics.len()
+    }
+}
+
+/// Checks each of `files`, whose contents `read` returns, and passes each file's result to
+/// `report` in turn. With `strict`, fails once they're all reported if any has a problem.
+pub fn verify_files<D: AsRef<[u8]>>(
+    files: &[PathBuf],
+    strict: bool,
+    mut read: impl FnMut(&Path) -> io::Result<D>,
+    mut report: impl FnMut(&Path, &[u8], &Verification) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut failed = 0;
+    for path in files {
+        let data = read(path)?;
+        let verification = Verification::of(path, data.as_ref());
+        if verification.count() > 0 {
+            failed += 1;
+        }
+        report(path, data.as_ref(), &verification)?;
+    }
+    if strict && failed > 0 {
+        return Err(io::Error::other(format!(
+            "{failed} of {} files failed verification",
+            files.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FrameType, HEADER_SIZE, tests::build_insv};
+
+    #[test]
+    fn test_verify_files() {
+        let good = build_insv(b"video", &[(FrameType::Gyro, 1, vec![0; 40])]);
+        // The gyro frame's own trailer claims 20 bytes where the index has 40.
+        let mut mismatched = good.clone();
+        mismatched[5 + 40 + 2..5 + 40 + 6].copy_from_slice(&20i32.to_le_bytes());
+        let files = [PathBuf::from("good.insv"), PathBuf::from("mismatched.insv")];
+        let read = |path: &Path| {
+            Ok(match path.to_str() {
+                Some("good.insv") => good.clone(),
+                _ => mismatched.clone(),
+            })
+        };
+
+        let mut reported = Vec::new();
+        let report = |path: &Path, _: &[u8], verification: &Verification| {
+            reported.push((path.to_path_buf(), verification.problems.clone()));
+            Ok(())
+        };
+        verify_files(&files, false, read, report).unwrap();
+        assert_eq!(
+            reported,
+            [
+                (files[0].clone(), Vec::new()),
+                (
+                    files[1].clone(),
+                    vec![
+                        "Gyro frame at offset 0 has trailer Gyro v1 (20 bytes), expected v1 (40 \
+                         bytes)"
+                            .to_string()
+                    ]
+                ),
+            ]
+        );
+
+        let mut reported = 0;
+        let error = verify_files(&files, true, read, |_, _, _| {
+            reported += 1;
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(error.to_string(), "1 of 2 files failed verification");
+        assert_eq!(reported, 2);
+        assert!(verify_files(&files[..1], true, read, |_, _, _| Ok(())).is_ok());
+
+        let mut oversized = good.clone();
+        let at = oversized.len() - HEADER_SIZE as usize + 38;
+        oversized[at..at + 4].copy_from_slice(&0xFFFFFF00u32.to_le_bytes());
+        assert_eq!(
+            Verification::of(Path::new("oversized.insv"), &oversized).problems,
+            [format!(
+                "metadata size 4294967040 doesn't fit in a {} byte file",
+                oversized.len()
+            )]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Deciding which files `ginsta watch` exports and when: camera files, once they've stopped
+//! changing long enough to have been copied completely.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Files `watch` picks up: Insta360 and other MP4 video, and DJI subtitles.
+pub fn is_camera_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ["insv", "mp4", "srt"].contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Camera files that changed, held back until none of them has changed for `settle`.
+#[derive(Debug)]
+pub struct Settling {
+    settle: Duration,
+    /// Files still being copied, with the time of their latest change.
+    pending: HashMap<PathBuf, Instant>,
+    /// Outputs, which can look like inputs, e.g. subtitles or the GPMF copy.
+    written: HashSet<PathBuf>,
+}
+
+impl Settling {
+    pub fn new(settle: Duration) -> Settling {
+        Settling {
+            settle,
+            pending: HashMap::new(),
+            written: HashSet::new(),
+        }
+    }
+
+    /// Notes that `path` changed at `now`, restarting its wait, unless it isn't a camera file or
+    /// was written by `watch` itself.
+    pub fn changed(&mut self, path: PathBuf, now: Instant) {
+        if is_camera_file(&path) && !self.written.contains(&path) {
+            self.pending.insert(path, now);
+        }
+    }
+
+    /// Records an output, so its own changes aren't taken for a new file.
+    pub fn written(&mut self, output: PathBuf) {
+        self.written.insert(output);
+    }
+
+    /// The outputs recorded so far.
+    pub fn outputs(&self) -> &HashSet<PathBuf> {
+        &self.written
+    }
+
+    /// The files that haven't changed for `settle` at `now`, in path order. They stop being
+    /// pending until they change again.
+    pub fn ready(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut ready: Vec<PathBuf> = (self.pending.iter())
+            .filter(|(_, changed)| now.saturating_duration_since(**changed) >= self.settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        ready.sort();
+        for path in &ready {
+            self.pending.remove(path);
+        }
+        ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_camera_file() {
+        assert!(is_camera_file(Path::new("VID_20250718_073922_00_001.insv")));
+        assert!(is_camera_file(Path::new("/card/DJI_0001.SRT")));
+        assert!(is_camera_file(Path::new("clip.MP4")));
+        assert!(!is_camera_file(Path::new("VID_20250718_073922_00_001.csv")));
+        assert!(!is_camera_file(Path::new("LRV_20250718_073922_01_001.lrv")));
+        assert!(!is_camera_file(Path::new("insv")));
+    }
+
+    #[test]
+    fn test_settling() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let mut settling = Settling::new(Duration::from_secs(5));
+
+        settling.changed("a.insv".into(), at(0));
+        settling.changed("notes.txt".into(), at(0));
+        settling.changed("b.insv".into(), at(1));
+        // Still being copied: each change restarts the wait.
+        settling.changed("a.insv".into(), at(3));
+        assert!(settling.ready(at(5)).is_empty());
+        assert_eq!(settling.ready(at(6)), [PathBuf::from("b.insv")]);
+        assert_eq!(settling.ready(at(8)), [PathBuf::from("a.insv")]);
+        assert!(settling.ready(at(20)).is_empty());
+
+        // Outputs are never picked up, though they look like camera files.
+        settling.written("a.srt".into());
+        settling.changed("a.srt".into(), at(20));
+        settling.changed("a.insv".into(), at(20));
+        assert_eq!(settling.ready(at(30)), [PathBuf::from("a.insv")]);
+        assert!(settling.outputs().contains(Path::new("a.srt")));
+    }
+}